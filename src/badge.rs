@@ -0,0 +1,111 @@
+use regex::Regex;
+
+use command::Command;
+
+use error::{Error, Result};
+
+/// A colored tag shown before the commands declaring it (`badges = ["PROD"]`) or matching its pattern:
+///
+/// ```toml
+/// [[badges]]
+/// text = "PROD"
+/// color = "red"
+/// pattern = "--context prod"
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BadgeConfig {
+    pub text: String,
+    #[serde(default)]
+    pub color: BadgeColor,
+    /// a regular expression the templates are matched against
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BadgeColor {
+    Red,
+    Yellow,
+    Green,
+    Blue,
+    Magenta,
+    Cyan,
+    #[default]
+    Grey
+}
+
+/// The badges of the configuration, their patterns compiled
+#[derive(Debug, Default)]
+pub struct Badges {
+    badges: Vec<(BadgeConfig, Option<Regex>)>
+}
+
+impl Badges {
+    /// Along with the errors of the invalid patterns, whose badges are then shown on the commands declaring them only
+    pub fn compile(configs: &[BadgeConfig]) -> (Badges, Vec<Error>) {
+        let mut badges = Vec::new();
+        let mut errors = Vec::new();
+        for config in configs {
+            let regex = match config.pattern.as_ref().map(|pattern| compile(pattern)).transpose() {
+                Ok(regex) => regex,
+                Err(e) => {
+                    errors.push(e);
+                    None
+                }
+            };
+            badges.push((config.clone(), regex));
+        }
+        (Badges { badges }, errors)
+    }
+
+    /// Those it declares then those it matches, in the order of the configuration, each once;
+    /// a badge it declares but the configuration doesn't is grey
+    pub fn of<'a>(self: &'a Badges, command: &'a Command) -> Vec<(&'a str, BadgeColor)> {
+        let mut badges: Vec<(&str, BadgeColor)> = command.badges.iter().map(|text| {
+            let color = self.badges.iter().find(|(config, _)| config.text == *text).map_or(BadgeColor::default(), |(config, _)| config.color);
+            (text.as_str(), color)
+        }).collect();
+        for (config, regex) in &self.badges {
+            let matching = regex.as_ref().is_some_and(|regex| regex.is_match(command.cmd.template()));
+            if matching && !badges.iter().any(|&(text, _)| text == config.text) {
+                badges.push((&config.text, config.color));
+            }
+        }
+        badges
+    }
+}
+
+fn compile(pattern: &str) -> Result<Regex> {
+    Regex::new(pattern).map_err(|e| Error::ConfigParse {
+        path: None,
+        line: None,
+        message: format!("invalid badge pattern `{}`: {}", pattern, e)
+    })
+}
+
+#[test]
+fn badging_commands() {
+    use command::Placeholders;
+
+    let badges = Badges::compile(&[
+        BadgeConfig { text: "PROD".to_owned(), color: BadgeColor::Red, pattern: Some("--context prod".to_owned()) },
+        BadgeConfig { text: "slow".to_owned(), color: BadgeColor::Yellow, pattern: None }
+    ]).0;
+    let command = |cmd: &str, badges: &[&str]| Command {
+        cmd: Placeholders::parse(cmd).unwrap(),
+        badges: badges.iter().map(|&badge| badge.to_owned()).collect(),
+        ..Default::default()
+    };
+
+    assert_eq!(badges.of(&command("kubectl --context prod get pods", &[])), vec![("PROD", BadgeColor::Red)]);
+    assert_eq!(badges.of(&command("kubectl --context prod rollout restart {deploy}", &["slow", "PROD"])),
+               vec![("slow", BadgeColor::Yellow), ("PROD", BadgeColor::Red)]);
+    assert_eq!(badges.of(&command("./backup.sh", &["nightly"])), vec![("nightly", BadgeColor::Grey)]);
+    assert!(badges.of(&command("kubectl --context dev get pods", &[])).is_empty());
+
+    let (invalid, errors) = Badges::compile(&[BadgeConfig { text: "x".to_owned(), color: BadgeColor::Red, pattern: Some("(".to_owned()) }]);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(invalid.of(&command("(", &["x"])), vec![("x", BadgeColor::Red)]);
+    assert!(invalid.of(&command("(", &[])).is_empty());
+}
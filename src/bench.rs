@@ -0,0 +1,60 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+use command::{Command, Commands, Placeholders};
+use render::{Renderer, TerminalRenderer, Theme};
+use screen::Screen;
+use suggestion::Suggestion;
+
+use error::Result;
+
+const TOOLS: [&str; 8] = ["git", "docker", "nix", "kube", "cargo", "ssh", "systemctl", "net"];
+
+/// Commands sharing keywords such as `git12` or `docker3`, so that prefixes match plenty of them
+pub fn synthetic_commands(count: usize) -> Vec<Command> {
+    let keyword = |i: usize| format!("{}{}", TOOLS[i % TOOLS.len()], i / TOOLS.len() % 50);
+
+    (0..count).map(|i| Command {
+        cmd: Placeholders::parse(&format!("{} --option-{} {{value}}", TOOLS[i % TOOLS.len()], i)).unwrap(),
+        description: Some(format!("Synthetic command #{}", i)),
+        keywords: vec![keyword(i), keyword(i * 7 + 3), keyword(i * 13 + 5)],
+        ..Default::default()
+    }).collect()
+}
+
+/// Type the query in one key at a time, timing the suggestion then the rendering of the screen
+pub fn run<W: Write>(out: &mut W, count: usize, query: &str, runs: u32) -> Result<()> {
+    let commands = Commands::new(synthetic_commands(count));
+    let mut screen = Screen::new();
+    let mut renderer = TerminalRenderer::new(io::sink(), Theme::monochrome());
+
+    writeln!(out, "{} commands, {} keywords, average of {} runs", count, commands.kwd2cmd.len(), runs)?;
+    writeln!(out, "{:<20} {:>8} {:>14} {:>12}", "query", "matches", "suggest (µs)", "render (µs)")?;
+
+    let chars: Vec<char> = query.chars().collect();
+    for end in 1..=chars.len() {
+        let typed: String = chars[..end].iter().collect();
+        let mut suggest_time = Duration::default();
+        let mut render_time = Duration::default();
+        let mut matches = 0;
+
+        for _ in 0..runs {
+            let started = Instant::now();
+            let suggestion = Suggestion::from_query(&commands, &typed);
+            suggest_time += started.elapsed();
+            matches = suggestion.commands.len();
+
+            let started = Instant::now();
+            screen.set_suggestion(suggestion);
+            renderer.render(&screen.frame())?;
+            render_time += started.elapsed();
+        }
+
+        writeln!(out, "{:<20} {:>8} {:>14} {:>12}",
+                 format!("{:?}", typed), matches, micros(suggest_time / runs), micros(render_time / runs))?;
+    }
+    Ok(())
+}
+
+fn micros(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000 + u64::from(duration.subsec_micros())
+}
@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use exec;
+use history;
+use system;
+
+use error::{Error, Result};
+
+/// The `[completion_cache]` table of the configuration, e.g. `[completion_cache] ttl_secs = 300`,
+/// 0 turning the cache off
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CacheConfig {
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64
+}
+
+/// Where the values listed by the completion commands are kept for a while, for each directory,
+/// by default `~/.local/share/smart-runner/completions.json`: filling the same placeholder in again
+/// doesn't wait for e.g. `kubectl get namespaces`
+pub struct CompletionCache {
+    pub path: PathBuf,
+    /// where the completion commands run
+    pub cwd: String,
+    pub ttl: Duration
+}
+
+/// The values of each completion command, by directory
+type Cached = BTreeMap<String, BTreeMap<String, Listed>>;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Listed {
+    /// in seconds since the epoch
+    at: u64,
+    values: Vec<String>
+}
+
+impl Default for CacheConfig {
+    fn default() -> CacheConfig {
+        CacheConfig { ttl_secs: default_ttl_secs() }
+    }
+}
+
+fn default_ttl_secs() -> u64 {
+    60
+}
+
+impl CompletionCache {
+    pub fn new(path: PathBuf, cwd: String, ttl: Duration) -> CompletionCache {
+        CompletionCache { path, cwd, ttl }
+    }
+
+    /// For the current directory, unless the cache is turned off
+    pub fn open(config: &CacheConfig) -> Result<Option<CompletionCache>> {
+        if config.ttl_secs == 0 {
+            return Ok(None);
+        }
+        let path = ::dirs::data_dir().ok_or(Error::NoDirectory("data"))?.join("smart-runner").join("completions.json");
+        let cwd = ::std::env::current_dir()?.to_string_lossy().into_owned();
+        Ok(Some(CompletionCache::new(path, cwd, Duration::from_secs(config.ttl_secs))))
+    }
+
+    /// The values the completion command lists, run unless it did lately
+    pub fn lines(self: &CompletionCache, provider: &str) -> Result<Vec<String>> {
+        let now = history::now();
+        if let Some(values) = self.get(provider, now) {
+            return Ok(values);
+        }
+        self.refresh(provider)
+    }
+
+    /// The values the completion command lists, run whether it did lately or not, e.g. on F5
+    pub fn refresh(self: &CompletionCache, provider: &str) -> Result<Vec<String>> {
+        let values = exec::lines(provider)?;
+        // unsaved, the values get listed by running the command again next time
+        let _ = self.put(provider, &values, history::now());
+        Ok(values)
+    }
+
+    /// Those listed in the directory less than `ttl` ago
+    pub fn get(self: &CompletionCache, provider: &str, now: u64) -> Option<Vec<String>> {
+        let mut cached = self.load().ok()?;
+        cached.get_mut(&self.cwd)
+            .and_then(|listed| listed.remove(provider))
+            .filter(|listed| now.saturating_sub(listed.at) < self.ttl.as_secs())
+            .map(|listed| listed.values)
+    }
+
+    /// Dropping those expired meanwhile, whatever the directory. Locked, for the entries of other pickers,
+    /// or of the other completion commands running, not to get lost between reading the cache and renaming
+    /// the rewritten one over it.
+    pub fn put(self: &CompletionCache, provider: &str, values: &[String], now: u64) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let lock = self.path.with_extension("json.lock");
+        let _lock = system::lock(&lock).map_err(|error| Error::File { path: lock, error })?;
+        // rather than failing for good
+        let mut cached = self.load().unwrap_or_default();
+        for listed in cached.values_mut() {
+            listed.retain(|_, listed| now.saturating_sub(listed.at) < self.ttl.as_secs());
+        }
+        cached.retain(|_, listed| !listed.is_empty());
+        cached.entry(self.cwd.clone()).or_default()
+            .insert(provider.to_owned(), Listed { at: now, values: values.to_vec() });
+
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, ::serde_json::to_string(&cached)?).map_err(|error| Error::File { path: tmp.clone(), error })?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    fn load(self: &CompletionCache) -> Result<Cached> {
+        if !self.path.exists() {
+            return Ok(Cached::new());
+        }
+        let file = File::open(&self.path).map_err(|error| Error::File { path: self.path.clone(), error })?;
+        Ok(::serde_json::from_reader(file)?)
+    }
+}
+
+#[test]
+fn caching_completions() {
+    let path = ::std::env::temp_dir().join(format!("smart-runner-completions-{}.json", ::std::process::id()));
+    let cache = CompletionCache::new(path.clone(), "/work".to_owned(), Duration::from_secs(60));
+    let values = vec!["main".to_owned(), "dev".to_owned()];
+    cache.put("git branch", &values, 1000).unwrap();
+
+    assert_eq!(cache.get("git branch", 1059), Some(values.clone()));
+    assert_eq!(cache.get("git branch", 1060), None);
+    assert_eq!(cache.get("git tag", 1000), None);
+    let elsewhere = CompletionCache::new(path.clone(), "/other".to_owned(), Duration::from_secs(60));
+    assert_eq!(elsewhere.get("git branch", 1000), None);
+
+    elsewhere.put("git tag", &[], 1070).unwrap();
+    assert_eq!(cache.get("git branch", 1000), None);
+    assert_eq!(elsewhere.get("git tag", 1070), Some(Vec::new()));
+
+    fs::write(&path, "{").unwrap();
+    assert_eq!(cache.get("git branch", 1000), None);
+    cache.put("git branch", &values, 1000).unwrap();
+    assert_eq!(cache.get("git branch", 1000), Some(values));
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(path.with_extension("json.lock")).unwrap();
+}
+
+#[test]
+fn caching_from_several_pickers() {
+    let path = ::std::env::temp_dir().join(format!("smart-runner-completions-concurrent-{}.json", ::std::process::id()));
+    let threads: Vec<_> = (0..4).map(|i| {
+        let path = path.clone();
+        ::std::thread::spawn(move || for j in 0..25 {
+            // a cache each, as in pickers of their own
+            let cache = CompletionCache::new(path.clone(), "/work".to_owned(), Duration::from_secs(60));
+            cache.put(&format!("list {} {}", i, j), &[], 1000).unwrap();
+        })
+    }).collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    let cache = CompletionCache::new(path.clone(), "/work".to_owned(), Duration::from_secs(60));
+    let cached = cache.load();
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(path.with_extension("json.lock")).unwrap();
+    assert_eq!(cached.unwrap()["/work"].len(), 100);
+}
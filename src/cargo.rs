@@ -0,0 +1,94 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use command::Command;
+use source::{self, CommandSource};
+
+use error::{Error, Result};
+
+const TASKS: &[(&str, &str)] = &[
+    ("build", "Compile the package"),
+    ("check", "Check the package for errors, without building it"),
+    ("test", "Run the tests"),
+    ("clippy", "Lint the package"),
+    ("bench", "Run the benchmarks"),
+    ("run", "Run the binary of the package")
+];
+
+/// Within a Rust project, the common cargo tasks and the aliases of its `.cargo/config.toml` files
+pub struct Cargo {
+    pub dir: PathBuf
+}
+
+impl CommandSource for Cargo {
+    /// Nothing outside of a Rust project
+    fn load(self: &Cargo) -> Result<Vec<Command>> {
+        if !self.dir.ancestors().any(|dir| dir.join("Cargo.toml").is_file()) {
+            return Ok(Vec::new());
+        }
+
+        let mut tasks: Vec<(String, Option<String>)> = TASKS.iter()
+            .map(|&(task, description)| (task.to_owned(), Some(description.to_owned())))
+            .collect();
+        for path in config_files(&self.dir) {
+            let content = fs::read_to_string(&path).map_err(|error| Error::File { path: path.clone(), error })?;
+            for (alias, expansion) in aliases(&content).map_err(|e| Error::ConfigParse { path: Some(path.clone()), line: None, message: e.to_string() })? {
+                // the closest definition wins, like with cargo
+                if !tasks.iter().any(|task| task.0 == alias) {
+                    tasks.push((alias, Some(expansion)));
+                }
+            }
+        }
+        Ok(source::named_commands("cargo", tasks))
+    }
+}
+
+/// From the closest to the farthest, then the one of the cargo home
+fn config_files(dir: &Path) -> Vec<PathBuf> {
+    let cargo_home = ::std::env::var_os("CARGO_HOME").map(PathBuf::from)
+        .or_else(|| ::dirs::home_dir().map(|home| home.join(".cargo")));
+
+    dir.ancestors().map(|dir| dir.join(".cargo"))
+        .chain(cargo_home)
+        .flat_map(|dir| vec![dir.join("config.toml"), dir.join("config")])
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+/// The `[alias]` table, e.g. `b = "build"` or `rr = ["run", "--release"]`
+fn aliases(content: &str) -> Result<Vec<(String, String)>> {
+    let config: ::toml::Value = ::toml::from_str(content)?;
+    let table = match config.get("alias").and_then(|aliases| aliases.as_table()) {
+        Some(table) => table,
+        None => return Ok(Vec::new())
+    };
+
+    Ok(table.iter()
+        .filter_map(|(alias, expansion)| {
+            let expansion = match *expansion {
+                ::toml::Value::String(ref s) => s.clone(),
+                ::toml::Value::Array(ref words) => words.iter().filter_map(|w| w.as_str()).collect::<Vec<_>>().join(" "),
+                _ => return None
+            };
+            Some((alias.clone(), format!("cargo {}", expansion)))
+        })
+        .collect())
+}
+
+#[test]
+fn reading_aliases() {
+    let aliases = aliases(r#"
+        [build]
+        jobs = 4
+
+        [alias]
+        b = "build"
+        rr = ["run", "--release"]
+    "#).unwrap();
+
+    assert_eq!(aliases, vec![
+        ("b".to_owned(), "cargo build".to_owned()),
+        ("rr".to_owned(), "cargo run --release".to_owned())
+    ]);
+    assert!(self::aliases("[build]\njobs = 4\n").unwrap().is_empty());
+}
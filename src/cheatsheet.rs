@@ -0,0 +1,88 @@
+use std::io::Write;
+use config::Config;
+
+use error::Result;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em auto; max-width: 60em; color: #222; }
+input { width: 100%; padding: .5em; font-size: 1.1em; box-sizing: border-box; }
+table { width: 100%; border-collapse: collapse; margin-top: 1em; }
+td { padding: .4em; border-bottom: 1px solid #ddd; vertical-align: top; }
+code { background: #f4f4f4; padding: .1em .3em; }
+.keyword { display: inline-block; margin: .1em; padding: .1em .4em; border-radius: .3em;
+           background: #e0e8f8; cursor: pointer; }
+";
+
+/// Keep the rows having every typed word as a keyword prefix; clicking a keyword types it in
+const SCRIPT: &str = "
+var filter = document.getElementById('filter');
+function update() {
+  var words = filter.value.toLowerCase().split(/\\s+/).filter(function (w) { return w; });
+  document.querySelectorAll('tbody tr').forEach(function (row) {
+    var keywords = row.dataset.keywords.split(' ');
+    row.hidden = !words.every(function (w) {
+      return keywords.some(function (kw) { return kw.indexOf(w) === 0; });
+    });
+  });
+}
+filter.addEventListener('input', update);
+document.querySelectorAll('.keyword').forEach(function (kw) {
+  kw.addEventListener('click', function () {
+    filter.value = (filter.value.trim() + ' ' + kw.textContent).trim();
+    update();
+  });
+});
+";
+
+/// A standalone page listing the commands, filterable by keyword
+pub fn write<W: Write>(out: &mut W, config: &Config) -> Result<()> {
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Smart Runner cheatsheet</title>")?;
+    writeln!(out, "<style>{}</style>\n</head>\n<body>", STYLE)?;
+    writeln!(out, "<h1>Cheatsheet</h1>")?;
+    writeln!(out, "<input id=\"filter\" placeholder=\"Filter by keywords\" autofocus>")?;
+    writeln!(out, "<table>\n<tbody>")?;
+
+    for entry in &config.commands {
+        let keywords = entry.keywords.iter().map(|kw| escape(&kw.to_lowercase())).collect::<Vec<_>>();
+        writeln!(out, "<tr data-keywords=\"{}\">", keywords.join(" "))?;
+        writeln!(out, "<td><code>{}</code></td>", escape(&entry.cmd))?;
+        writeln!(out, "<td>{}</td>", entry.description.as_ref().map_or(String::new(), |d| escape(d)))?;
+        write!(out, "<td>")?;
+        for kw in &entry.keywords {
+            write!(out, "<span class=\"keyword\">{}</span>", escape(kw))?;
+        }
+        writeln!(out, "</td>\n</tr>")?;
+    }
+
+    writeln!(out, "</tbody>\n</table>")?;
+    writeln!(out, "<script>{}</script>\n</body>\n</html>", SCRIPT)?;
+    Ok(())
+}
+
+fn escape(text: &str) -> String {
+    text.chars().map(|c| match c {
+        '&'  => "&amp;".to_owned(),
+        '<'  => "&lt;".to_owned(),
+        '>'  => "&gt;".to_owned(),
+        '"'  => "&quot;".to_owned(),
+        '\'' => "&#39;".to_owned(),
+        c    => c.to_string()
+    }).collect()
+}
+
+#[test]
+fn escaping_commands() {
+    let config = Config::parse(r#"
+        [[commands]]
+        cmd = "ls <dir> | grep \"a&b\""
+        keywords = ["ls", "Files"]
+    "#).unwrap();
+
+    let mut out = Vec::new();
+    write(&mut out, &config).unwrap();
+    let html = String::from_utf8(out).unwrap();
+
+    assert!(html.contains("<code>ls &lt;dir&gt; | grep &quot;a&amp;b&quot;</code>"));
+    assert!(html.contains("<tr data-keywords=\"ls files\">"));
+}
@@ -0,0 +1,875 @@
+use clap::{App, AppSettings, Arg, ArgMatches, Shell, SubCommand};
+use itertools::Itertools;
+use std::fs::File;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::mem;
+use std::process;
+use std::slice;
+use std::time::Duration;
+
+use std::sync::Arc;
+use smart_runner::command::{self, Command, Commands, Selection};
+use smart_runner::config::{self, CommandEntry, Config, Location, Resolution, Saved};
+use smart_runner::daemon::{Daemon, Library};
+use smart_runner::{bench, cheatsheet, doctor, exec, keywords, pane, rpc, search, tutorial};
+use smart_runner::render::Theme;
+use smart_runner::runner::{self, RunnerBuilder};
+use smart_runner::history::{self, History, Record};
+use smart_runner::frecency::{Context, Frecency};
+use smart_runner::stats::Stats;
+use smart_runner::values::ValueStore;
+use smart_runner::macros::{MacroStore, QueryMacro};
+use smart_runner::pack::PackDir;
+use smart_runner::source::{CommandSource, ConfigFile};
+use smart_runner::session::{Captured, OutputStore, SessionStore};
+
+use smart_runner::error::{Error, Result};
+
+pub fn build_cli() -> App<'static, 'static> {
+    build_cli_with(&[], &[])
+}
+
+/// Profiles and keywords are only listed when generating the shell completions
+fn build_cli_with<'a>(profiles: &[&'a str], keywords: &[&'a str]) -> App<'a, 'a> {
+    App::new("smart-runner")
+        .version(crate_version!())
+        .about("Search through a bunch of command line tools and their gazillion of options")
+        .setting(AppSettings::VersionlessSubcommands)
+        .arg(Arg::with_name("config")
+            .long("config")
+            .value_name("FILE")
+            .global(true)
+            .help("Read the commands from this file instead of ~/.config/smart-runner/config.toml"))
+        .arg(with_values(profiles, Arg::with_name("profile")
+            .long("profile")
+            .value_name("NAME")
+            .global(true)
+            .conflicts_with("config")
+            .help("Read the commands from ~/.config/smart-runner/profiles/<NAME>.toml")))
+        .arg(Arg::with_name("no-color")
+            .long("no-color")
+            .global(true)
+            .help("Do not use colors in the interface"))
+        .arg(Arg::with_name("safe")
+            .long("safe")
+            .global(true)
+            .help("Run nothing: hide the dangerous commands, skip the sources and completions running programs, \
+                   print the URLs rather than opening them and refuse `exec`, e.g. for a demo or on a production machine"))
+        .args(&run_args(keywords))
+        .subcommand(SubCommand::with_name("run")
+            .about("Pick a command interactively (default when no subcommand is given)")
+            .args(&run_args(keywords)))
+        .subcommand(SubCommand::with_name("add")
+            .about("Add a command to the configuration")
+            .arg(Arg::with_name("cmd")
+                .value_name("COMMAND")
+                .required(true)
+                .help("The command, with placeholders written as `{name}`"))
+            .arg(Arg::with_name("id")
+                .long("id")
+                .value_name("ID")
+                .help("A stable name for the command"))
+            .arg(Arg::with_name("description")
+                .short("d")
+                .long("description")
+                .value_name("TEXT"))
+            .arg(with_values(keywords, Arg::with_name("keywords")
+                .short("k")
+                .long("keywords")
+                .value_name("KEYWORDS")
+                .use_delimiter(true)
+                .help("Comma separated keywords"))))
+        .subcommand(SubCommand::with_name("list")
+            .about("List the configured commands"))
+        .subcommand(SubCommand::with_name("import")
+            .about("Add the commands of another configuration file, skipping the ones already present")
+            .arg(Arg::with_name("file")
+                .value_name("FILE")
+                .required(true)))
+        .subcommand(SubCommand::with_name("export")
+            .about("Print the configured commands as TOML, or as an HTML cheatsheet")
+            .arg(Arg::with_name("format")
+                .long("format")
+                .value_name("FORMAT")
+                .possible_values(&ExportFormat::variants())
+                .case_insensitive(true)
+                .default_value("toml"))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .help("Write to this file instead of stdout")))
+        .subcommand(SubCommand::with_name("sync")
+            .about("Synchronise the configuration directory with its git remote"))
+        .subcommand(SubCommand::with_name("completions")
+            .about("Print the completion script of a shell (including the profiles and keywords)")
+            .arg(Arg::with_name("shell")
+                .value_name("SHELL")
+                .required(true)
+                .possible_values(&Shell::variants())))
+        .subcommand(SubCommand::with_name("exec")
+            .about("Run the command with this id, exiting with its status")
+            .arg(Arg::with_name("id")
+                .value_name("ID")
+                .required(true))
+            .arg(Arg::with_name("set")
+                .long("set")
+                .value_name("NAME=VALUE")
+                .multiple(true)
+                .number_of_values(1)
+                .help("The value of a placeholder"))
+            .arg(Arg::with_name("dry-run")
+                .long("dry-run")
+                .short("n")
+                .help("Print the command, along with its pipe, instead of running it"))
+            .arg(Arg::with_name("pane")
+                .long("pane")
+                .short("p")
+                .help("Stream its output into a pane, to scroll (Up/Down), search (/) or follow (f) it until q"))
+            .arg(Arg::with_name("capture")
+                .long("capture")
+                .short("c")
+                .help("Keep its output, for the placeholders completing from `@output` to pick among its lines")))
+        .subcommand(SubCommand::with_name("preset")
+            .about("Name the values of the placeholders of the command with this id, to pick them with Alt-<N>")
+            .arg(Arg::with_name("id")
+                .value_name("ID")
+                .required(true))
+            .arg(Arg::with_name("name")
+                .value_name("NAME")
+                .required(true)
+                .help("e.g. `prod`, replacing the preset of that name"))
+            .arg(Arg::with_name("set")
+                .long("set")
+                .value_name("NAME=VALUE")
+                .multiple(true)
+                .number_of_values(1)
+                .help("The value of a placeholder")))
+        .subcommand(SubCommand::with_name("macro")
+            .about("Save a filter under a name, to bring it back with `@<NAME>` in the picker or `--query-macro <NAME>`")
+            .arg(Arg::with_name("name")
+                .value_name("NAME")
+                .required(true)
+                .help("e.g. `triage`, replacing the macro of that name"))
+            .arg(Arg::with_name("query")
+                .value_name("QUERY")
+                .multiple(true)
+                .help("The input, by default that of the picker when last canceled along with its keywords"))
+            .arg(Arg::with_name("keywords")
+                .long("keywords")
+                .short("k")
+                .value_name("KEYWORDS")
+                .use_delimiter(true)
+                .help("The comma separated keywords")))
+        .subcommand(SubCommand::with_name("search")
+            .about("Print the commands the most relevant to a few words, along with their score")
+            .arg(Arg::with_name("words")
+                .value_name("WORDS")
+                .required(true)
+                .multiple(true))
+            .arg(Arg::with_name("top")
+                .long("top")
+                .value_name("N")
+                .default_value("10")
+                .help("How many commands to print")))
+        .subcommand(SubCommand::with_name("stats")
+            .about("Report how the commands and keywords are used, according to the history")
+            .arg(Arg::with_name("top")
+                .long("top")
+                .value_name("N")
+                .default_value("10")
+                .help("How many commands and keywords to list"))
+            .arg(Arg::with_name("keywords")
+                .long("keywords")
+                .help("Report how each keyword of the commands is used instead: how often, which ones never are, \
+                       and the commands only reachable via those")))
+        .subcommand(SubCommand::with_name("daemon")
+            .about("Keep the commands loaded, those of the providers included, for the picker to start right away")
+            .arg(Arg::with_name("refresh")
+                .long("refresh")
+                .value_name("SECONDS")
+                .default_value("300")
+                .help("Load the commands again past that age, as well as when the configuration file changes")))
+        .subcommand(SubCommand::with_name("retag")
+            .about("Mark commands of the configuration in the picker, then add keywords to them or remove some at once")
+            .setting(AppSettings::AllowLeadingHyphen)
+            .arg(Arg::with_name("edits")
+                .value_name("EDITS")
+                .multiple(true)
+                .allow_hyphen_values(true)
+                .help("e.g. `+k8s -kube`, asked for once the commands are marked when missing")))
+        .subcommand(SubCommand::with_name("tutorial")
+            .about("Learn how to pick commands, step by step, on harmless ones"))
+        .subcommand(SubCommand::with_name("rpc")
+            .about("Answer JSON-RPC queries read from stdin, one per line, for editors to match the commands"))
+        .subcommand(SubCommand::with_name("doctor")
+            .about("Check the configuration and the terminal, exiting with status 1 upon errors"))
+        .subcommand(SubCommand::with_name("history")
+            .about("Show or maintain the history of the selected and executed commands")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("list")
+                .about("Print the records of the history, oldest first, tab separated"))
+            .subcommand(SubCommand::with_name("prune")
+                .about("Drop the old records of the history")
+                .arg(Arg::with_name("older-than")
+                    .long("older-than")
+                    .value_name("AGE")
+                    .required(true)
+                    .help("Drop what's older than this, e.g. `90d` (s, m, h, d or w)"))))
+        .subcommand(SubCommand::with_name("pack")
+            .about("Install command packs from the registry of the configuration into commands.d/")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("install")
+                .about("Install the latest version of a pack, or pin the one given")
+                .arg(Arg::with_name("name")
+                    .value_name("NAME[@VERSION]")
+                    .required(true)
+                    .help("e.g. `docker`, or `docker@1.2.0` to keep that version")))
+            .subcommand(SubCommand::with_name("update")
+                .about("Bring the packs installed to their latest version, but the pinned ones")))
+        .subcommand(SubCommand::with_name("bench")
+            .about("Time the suggestions and the rendering for each key of a query, on synthetic commands")
+            .setting(AppSettings::Hidden)
+            .arg(Arg::with_name("commands")
+                .long("commands")
+                .value_name("N")
+                .default_value("10000"))
+            .arg(Arg::with_name("query")
+                .long("query")
+                .value_name("QUERY")
+                .default_value("git1 ssh"))
+            .arg(Arg::with_name("runs")
+                .long("runs")
+                .value_name("N")
+                .default_value("20")))
+}
+
+fn with_values<'a>(values: &[&'a str], arg: Arg<'a, 'a>) -> Arg<'a, 'a> {
+    if values.is_empty() { arg } else { arg.possible_values(values) }
+}
+
+arg_enum! {
+    #[derive(Debug, PartialEq)]
+    pub enum Output { Plain, Lines, Json }
+}
+
+arg_enum! {
+    #[derive(Debug, PartialEq)]
+    pub enum ExportFormat { Toml, Html }
+}
+
+/// Shared by the bare invocation and `run`
+fn run_args<'a>(keywords: &[&'a str]) -> Vec<Arg<'a, 'a>> {
+    vec![
+        Arg::with_name("query")
+            .value_name("QUERY")
+            .multiple(true)
+            .help("Start with this query, as if it had been typed in (e.g. `nix store`)"),
+        with_values(keywords, Arg::with_name("keywords")
+            .long("keywords")
+            .short("k")
+            .value_name("KEYWORDS")
+            .use_delimiter(true)
+            .help("Start with these comma separated keywords already validated")),
+        Arg::with_name("filter")
+            .long("filter")
+            .help("Print the commands matching the query instead of showing the interface"),
+        Arg::with_name("stdin")
+            .long("stdin")
+            .help("Pick among the lines read from stdin rather than the configured commands"),
+        Arg::with_name("delimiter")
+            .long("delimiter")
+            .value_name("DELIMITER")
+            .requires("stdin")
+            .help("Read the lines as `keyword,keyword<DELIMITER>description<DELIMITER>command`"),
+        Arg::with_name("select-1")
+            .long("select-1")
+            .short("1")
+            .help("Print the command right away when the query matches only one"),
+        Arg::with_name("exit-0")
+            .long("exit-0")
+            .short("0")
+            .help("Exit right away (with status 1) when the query matches no command"),
+        Arg::with_name("out")
+            .long("out")
+            .value_name("PATH")
+            .help("Write the selected command into this file rather than on stdout, e.g. `/dev/fd/3`"),
+        Arg::with_name("annotate")
+            .long("annotate")
+            .help("Follow the selected command with its description as a shell comment, ` # <description>`"),
+        Arg::with_name("print0")
+            .long("print0")
+            .help("End the printed commands with NUL rather than a new line"),
+        Arg::with_name("output")
+            .long("output")
+            .value_name("FORMAT")
+            .possible_values(&Output::variants())
+            .case_insensitive(true)
+            .default_value("plain")
+            .help("How to print the selected command (or the matching ones with --filter), \
+                   `lines` printing the chained ones on a line each rather than joined with &&"),
+        Arg::with_name("resume")
+            .long("resume")
+            .conflicts_with_all(&["query", "keywords", "stdin", "query-macro"])
+            .help("Reopen the picker where it was left when last canceled"),
+        Arg::with_name("query-macro")
+            .long("query-macro")
+            .value_name("NAME")
+            .help("Start with the keywords and the input saved under this name, see the `macro` subcommand"),
+        Arg::with_name("multi")
+            .long("multi")
+            .short("m")
+            .help("Mark several commands with Tab to chain them, in the order they were marked"),
+        Arg::with_name("record")
+            .long("record")
+            .value_name("PATH")
+            .help("Write the keys typed in the picker into this file, with their timing, e.g. for a bug report \
+                   (the values typed into the placeholders included)"),
+        Arg::with_name("replay")
+            .long("replay")
+            .value_name("PATH")
+            .help("Type the keys recorded with --record in again, with the same timing, e.g. for a demo"),
+        Arg::with_name("announce-fd")
+            .long("announce-fd")
+            .value_name("FD")
+            .help("Write the command selected and its description on a plain line to this file descriptor \
+                   whenever the selection changes, e.g. for a screen reader to announce it (`3>/tmp/announces`)"),
+        Arg::with_name("passthrough")
+            .value_name("ARGS")
+            .multiple(true)
+            .last(true)
+            .help("Append these arguments to the selected command, or fill its trailing placeholder in with them \
+                   (e.g. `-- -n kube-system`)")
+    ]
+}
+
+/// Global arguments may be given either before or after the subcommand
+pub fn global_value<'a>(matches: &'a ArgMatches, name: &str) -> Option<&'a str> {
+    matches.subcommand().1
+        .and_then(|sub| sub.value_of(name))
+        .or_else(|| matches.value_of(name))
+}
+
+pub fn global_flag(matches: &ArgMatches, name: &str) -> bool {
+    matches.is_present(name) || matches.subcommand().1.is_some_and(|sub| sub.is_present(name))
+}
+
+pub fn add(location: &Location, matches: &ArgMatches) -> Result<()> {
+    let mut entry = CommandEntry {
+        id: matches.value_of("id").map(str::to_owned),
+        cmd: matches.value_of("cmd").unwrap().to_owned(),
+        description: matches.value_of("description").map(str::to_owned),
+        keywords: matches.values_of("keywords")
+            .map_or(Vec::new(), |kws| kws.map(str::to_owned).collect()),
+        ..Default::default()
+    };
+    entry.to_command()?; // validate before writing anything
+    if entry.keywords.is_empty() {
+        entry.keywords = keywords::suggest(&entry.cmd, entry.description.as_deref());
+        if !entry.keywords.is_empty() {
+            eprintln!("Keywords: {} (derived from the command, pick others with -k)", entry.keywords.join(", "));
+        }
+    }
+
+    location.append(&[entry])?;
+    eprintln!("Added to {}", location.path.display());
+    Ok(())
+}
+
+pub fn list(location: &Location) -> Result<()> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for cmd in location.load()?.commands {
+        writeln!(out, "{}\t{}\t{}",
+                 cmd.cmd,
+                 cmd.description.unwrap_or_default(),
+                 cmd.keywords.join(","))?;
+    }
+    Ok(())
+}
+
+pub fn import(location: &Location, matches: &ArgMatches) -> Result<()> {
+    let imported = Config::read(Path::new(matches.value_of("file").unwrap()))?;
+    let existing = location.load_existing()?;
+
+    let new_entries: Vec<CommandEntry> = imported.commands.into_iter()
+        .filter(|entry| !existing.commands.iter().any(|e| e.cmd == entry.cmd))
+        .collect();
+    for entry in &new_entries {
+        entry.to_command()?;
+    }
+
+    location.append(&new_entries)?;
+    eprintln!("Imported {} command(s) into {}", new_entries.len(), location.path.display());
+    Ok(())
+}
+
+pub fn export(location: &Location, matches: &ArgMatches) -> Result<()> {
+    let config = location.load()?;
+    let mut out: Box<dyn Write> = match matches.value_of("output") {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout())
+    };
+
+    match value_t!(matches, "format", ExportFormat)? {
+        ExportFormat::Toml => out.write_all(::toml::to_string(&config)?.as_bytes())?,
+        ExportFormat::Html => cheatsheet::write(&mut out, &config)?
+    }
+    Ok(())
+}
+
+/// Pull then push the configuration directory, committing local changes in between
+pub fn sync(location: &Location) -> Result<()> {
+    let dir = location.dir();
+    if !dir.join(".git").exists() {
+        return Err(Error::Process {
+            program: "git".to_owned(),
+            message: format!("{} is not a git repository", dir.display())
+        });
+    }
+
+    let git = |args: &[&str]| -> Result<process::Output> {
+        let output = process::Command::new("git").arg("-C").arg(dir).args(args).output()?;
+        if output.status.success() {
+            Ok(output)
+        } else {
+            Err(Error::Process {
+                program: "git".to_owned(),
+                message: format!("`git {}` failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim())
+            })
+        }
+    };
+
+    if !git(&["status", "--porcelain"])?.stdout.is_empty() {
+        git(&["add", "--all"])?;
+        git(&["commit", "--message", "smart-runner sync"])?;
+    }
+    git(&["pull", "--rebase"])?;
+    git(&["push"])?;
+    Ok(())
+}
+
+pub fn completions(location: &Location, matches: &ArgMatches) -> Result<()> {
+    let shell = value_t!(matches, "shell", Shell)?;
+    let profiles = config::profiles()?;
+    let keywords: Vec<String> = location.load()?.commands.into_iter()
+        .flat_map(|cmd| cmd.keywords)
+        .unique()
+        .sorted();
+
+    let profiles: Vec<&str> = profiles.iter().map(String::as_str).collect();
+    let keywords: Vec<&str> = keywords.iter().map(String::as_str).collect();
+    build_cli_with(&profiles, &keywords)
+        .gen_completions_to("smart-runner", shell, &mut io::stdout());
+    Ok(())
+}
+
+pub fn doctor(location: &Location) -> Result<i32> {
+    let findings = doctor::diagnose(location);
+    for finding in &findings {
+        println!("{}", finding);
+    }
+    Ok(if findings.iter().any(doctor::Finding::is_error) { 1 } else { 0 })
+}
+
+pub fn daemon(location: &Location, matches: &ArgMatches) -> Result<()> {
+    let refresh = Duration::from_secs(value_t!(matches, "refresh", u64)?);
+    Daemon::new(location.clone(), refresh)?.serve()
+}
+
+/// Each step in a picker of its own, the instructions below the commands, again until it's done as asked.
+/// The picked commands are run, what they print being shown along the next instructions.
+pub fn tutorial(location: &Location, theme: Theme) -> Result<()> {
+    let mut news: Vec<String> = vec!["Welcome! Let's pick a few commands.".to_owned()];
+    for (i, step) in tutorial::STEPS.iter().enumerate() {
+        loop {
+            let mut hint = mem::take(&mut news);
+            hint.push(format!("Step {}/{}:", i + 1, tutorial::STEPS.len()));
+            hint.extend(step.instructions.iter().map(|line| format!("  {}", line)));
+
+            let mut runner = RunnerBuilder::new(Commands::new(tutorial::sandbox())).theme(theme).hint(hint).build()?;
+            let picked = runner.run()?;
+            let keywords = runner.valid_keywords();
+            drop(runner); // out of raw mode
+
+            match step.check(picked.as_ref(), &keywords) {
+                Ok(()) => {
+                    if let Some(selection) = picked {
+                        let cmd = selection.interpolated();
+                        news.push(format!("Well done! `{}` printed:", cmd));
+                        news.extend(exec::lines(&cmd)?.into_iter().map(|line| format!("  {}", line)));
+                    }
+                    break;
+                },
+                Err(what) => news.push(format!("{} Let's try again.", what))
+            }
+        }
+    }
+    eprintln!("That's it! Add commands of your own with `smart-runner add`, or in {}", location.path.display());
+    Ok(())
+}
+
+/// The keywords of the marked commands (all the entries with their `cmd`) get edited in the file
+pub fn retag(location: &Location, matches: &ArgMatches, theme: Theme, safe: bool) -> Result<()> {
+    // checked before marking anything
+    let retag = match matches.values_of("edits") {
+        Some(mut edits) => Some(keywords::Retag::parse(&edits.join(" "))?),
+        None => None
+    };
+    // to tell whether it changes meanwhile
+    let loaded = location.read()?;
+    let entries = Config::parse_at(&loaded, &location.path)?.commands;
+    let commands = entries.iter().map(CommandEntry::to_command).collect::<Result<Vec<_>>>()?;
+    let hint = vec!["Mark the commands to retag with Tab, then press Enter (right away for the highlighted one only)".to_owned()];
+    let mut runner = RunnerBuilder::new(Commands::new(commands))
+        .theme(theme)
+        .multi(true)
+        .keep_placeholders(true)
+        .safe(safe)
+        .hint(hint)
+        .build()?;
+    let picked = runner.run()?;
+    let chained = runner.picked().to_vec();
+    drop(runner); // out of raw mode
+    let marked: Vec<Selection> = match picked {
+        Some(selection) => chained.into_iter().chain(Some(selection)).collect(),
+        None => return Ok(())
+    };
+
+    let retag = match retag {
+        Some(retag) => retag,
+        None => {
+            eprint!("Keywords to add (+KEYWORD) or remove (-KEYWORD) for {} command(s): ", marked.len());
+            let mut line = String::new();
+            io::stdin().read_line(&mut line)?;
+            keywords::Retag::parse(&line)?
+        }
+    };
+    let cmds: Vec<String> = marked.iter().map(|selection| selection.command.cmd.template().to_owned()).collect();
+    match location.retag(&loaded, &cmds, &retag, || resolve_conflict(&location.path))? {
+        Saved::Written { retagged } => eprintln!("Retagged {} command(s) in {}", retagged, location.path.display()),
+        Saved::Discarded => eprintln!("Left {} as it is now", location.path.display()),
+        Saved::Merged { retagged, missing } => {
+            eprintln!("Retagged {} command(s) in {}", retagged, location.path.display());
+            for cmd in missing {
+                eprintln!("  not `{}`, no longer in it", cmd);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Asked on stderr, the file having changed since it was loaded, e.g. edited by hand or synced meanwhile
+fn resolve_conflict(path: &Path) -> Result<Resolution> {
+    loop {
+        eprint!("{} changed since it was loaded: [k]eep mine (losing those changes), [t]ake theirs (losing the retagging) \
+                 or [m]erge (retagging the same commands in it as it is now)? ", path.display());
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line)? == 0 {
+            // no one to ask: left alone
+            return Ok(Resolution::TakeTheirs);
+        }
+        match line.trim() {
+            "k" => return Ok(Resolution::KeepMine),
+            "t" => return Ok(Resolution::TakeTheirs),
+            "m" => return Ok(Resolution::Merge),
+            _ => ()
+        }
+    }
+}
+
+/// With the library of the daemon when it runs
+pub fn rpc(location: &Location) -> Result<()> {
+    let library = Library::fetch(location).or_else(|_| Library::load(location))?;
+    let commands = library.indexed()?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        if let Some(response) = rpc::answer(&commands, &line?) {
+            writeln!(out, "{}", response)?;
+            out.flush()?;
+        }
+    }
+    Ok(())
+}
+
+pub fn exec(location: &Location, matches: &ArgMatches, theme: Theme) -> Result<i32> {
+    let config = location.load()?;
+    let command = command_by_id(location, matches.value_of("id").unwrap())?;
+
+    let assignments: Vec<&str> = matches.values_of("set").map_or(Vec::new(), |values| values.collect());
+    let selection = Selection::with_named_values(Arc::new(command), &assignments)?;
+
+    // the exit status being that of the pipe, if any
+    let piped = match selection.command.pipe {
+        Some(ref pipe) => format!("{} | {}", selection.interpolated(), pipe),
+        None => selection.interpolated()
+    };
+    if matches.is_present("dry-run") {
+        println!("{}", piped);
+        return Ok(0);
+    }
+
+    let ignore = config.history.ignore_rules()?;
+    let (status, output) = if selection.command.is_url() {
+        exec::open_url(&selection.url())?;
+        (0, None)
+    } else if matches.is_present("pane") && runner::has_terminal(false) {
+        let (status, lines) = pane::stream(&piped, theme)?;
+        (status, Some(lines))
+    } else if matches.is_present("capture") {
+        let (status, lines) = exec::execute_captured(&piped)?;
+        (status, Some(lines))
+    } else {
+        (selection.execute()?, None)
+    };
+    if let Some(lines) = output {
+        // for the placeholders of the next command to pick among its lines, see `session::LAST_OUTPUT`
+        if let Err(e) = OutputStore::open().and_then(|store| store.save(&Captured { cmd: selection.interpolated(), lines })) {
+            eprintln!("Cannot save the output of the command: {}", e);
+        }
+    }
+    if ignore.allows(&selection) {
+        if let Err(e) = History::open().and_then(|history| history.record(&Record::executed(&selection, status))) {
+            eprintln!("Cannot record the command in the history: {}", e);
+        }
+    }
+    Ok(status)
+}
+
+/// Among those of the configuration file and of the packs
+fn command_by_id(location: &Location, id: &str) -> Result<Command> {
+    ConfigFile { location: location.clone() }.load()?.into_iter()
+        .find(|cmd| cmd.id.as_ref().is_some_and(|cmd_id| cmd_id == id))
+        .ok_or_else(|| Error::UnknownId(id.to_owned()))
+}
+
+pub fn preset(location: &Location, matches: &ArgMatches) -> Result<()> {
+    let command = command_by_id(location, matches.value_of("id").unwrap())?;
+
+    let assignments: Vec<&str> = matches.values_of("set").map_or(Vec::new(), |values| values.collect());
+    let selection = Selection::with_named_values(Arc::new(command), &assignments)?;
+
+    let store = ValueStore::open()?;
+    let mut presets = store.presets()?;
+    presets.set(selection.command.cmd.template(), matches.value_of("name").unwrap(), &selection.values);
+    store.save_presets(&presets)
+}
+
+pub fn save_macro(matches: &ArgMatches) -> Result<()> {
+    let keywords: Vec<String> = matches.values_of("keywords").map_or(Vec::new(), |kws| kws.map(str::to_owned).collect());
+    let input = matches.values_of("query").map_or(String::new(), |mut words| words.join(" "));
+    let query_macro = if keywords.is_empty() && input.is_empty() {
+        // the filter just left
+        let session = SessionStore::open()?.load()?
+            .ok_or_else(|| Error::InvalidInput("No filter to save: give a query or keywords, or cancel the picker on it first".to_owned()))?;
+        QueryMacro { keywords: session.keywords, input: session.input }
+    } else {
+        QueryMacro { keywords, input }
+    };
+    let name = matches.value_of("name").unwrap();
+    MacroStore::open()?.save(name, query_macro)?;
+    eprintln!("Type `@{}` then Space in the picker to bring it back", name);
+    Ok(())
+}
+
+pub fn history(matches: &ArgMatches) -> Result<()> {
+    match matches.subcommand() {
+        ("list", _) => {
+            let stdout = io::stdout();
+            history::print(&mut stdout.lock(), &History::open()?.records()?)?;
+        },
+        ("prune", Some(m)) => {
+            let older_than = history::parse_age(m.value_of("older-than").unwrap())?;
+            let pruned = History::open()?.prune(older_than)?;
+            println!("{} record(s) dropped", pruned);
+        },
+        _ => ()
+    }
+    Ok(())
+}
+
+pub fn pack(location: &Location, matches: &ArgMatches) -> Result<()> {
+    let index = location.load()?.packs.index()?;
+    let dir = PackDir::of(location);
+    match matches.subcommand() {
+        ("install", Some(m)) => {
+            let name = m.value_of("name").unwrap();
+            let (name, version) = match name.split_once('@') {
+                Some((name, version)) => (name, Some(version)),
+                None => (name, None)
+            };
+            let release = index.release(name, version)?;
+            dir.install(release, version.is_some())?;
+            println!("Installed {} {} into {}", release.name, release.version, dir.path.display());
+        },
+        ("update", _) => {
+            let outdated = dir.outdated(&index)?;
+            for release in &outdated {
+                match dir.install(release, false) {
+                    Ok(()) => println!("Updated {} to {}", release.name, release.version),
+                    Err(e) => eprintln!("Cannot update {} to {}: {}", release.name, release.version, e)
+                }
+            }
+            for name in dir.unlisted(&index)? {
+                eprintln!("The registry no longer lists {}: left as it is", name);
+            }
+            if outdated.is_empty() {
+                println!("The packs are up to date");
+            }
+        },
+        _ => ()
+    }
+    Ok(())
+}
+
+/// Exit with status 1 when nothing is relevant, like grep
+pub fn search(location: &Location, matches: &ArgMatches) -> Result<i32> {
+    let top = value_t!(matches, "top", usize)?;
+    let query = matches.values_of("words").unwrap().join(" ");
+    let commands = Commands::new(location.load()?.commands()?);
+
+    let mut ranked = search::rank(&commands, &query);
+    ranked.truncate(top);
+
+    let stdout = io::stdout();
+    search::print(&mut stdout.lock(), &ranked)?;
+    Ok(if ranked.is_empty() { 1 } else { 0 })
+}
+
+pub fn bench(matches: &ArgMatches) -> Result<()> {
+    let count = value_t!(matches, "commands", usize)?;
+    let runs = value_t!(matches, "runs", u32)?;
+    let stdout = io::stdout();
+    bench::run(&mut stdout.lock(), count, matches.value_of("query").unwrap(), runs.max(1))
+}
+
+pub fn stats(location: &Location, matches: &ArgMatches) -> Result<()> {
+    let top = value_t!(matches, "top", usize)?;
+    let records = History::open()?.records()?;
+    let config = location.load()?;
+    let frecency = Frecency::compute(&records, &config.frecency, &Context::current(), history::now());
+    let stats = Stats::compute(&records, &config.commands, &frecency);
+
+    let stdout = io::stdout();
+    if matches.is_present("keywords") {
+        stats.print_keywords(&mut stdout.lock(), top)
+    } else {
+        stats.print(&mut stdout.lock(), top)
+    }
+}
+
+/// How the commands are printed, on stdout unless `--out` tells otherwise
+pub struct Printer {
+    pub output: Output,
+    /// end the commands with NUL rather than a new line, for `xargs -0`
+    pub print0: bool,
+    /// follow the selected commands with their description, as a shell comment
+    pub annotate: bool,
+    /// e.g. `/dev/fd/3`, for wrappers keeping stdout for something else
+    pub out: Option<PathBuf>
+}
+
+impl Printer {
+    pub fn from_matches(matches: &ArgMatches) -> Result<Printer> {
+        Ok(Printer {
+            output: value_t!(matches, "output", Output)?,
+            print0: matches.is_present("print0"),
+            annotate: matches.is_present("annotate"),
+            out: matches.value_of("out").map(PathBuf::from)
+        })
+    }
+
+    /// One per line or as a JSON array
+    pub fn print_commands(self: &Printer, commands: &[Arc<Command>]) -> Result<()> {
+        match self.output {
+            Output::Plain | Output::Lines =>
+                self.write(&commands.iter().map(|cmd| format!("{}{}", cmd.cmd, self.terminator())).collect::<String>()),
+            Output::Json => {
+                let json: Vec<_> = commands.iter().map(|cmd| json!({
+                    "cmd": cmd.cmd.to_string(),
+                    "description": cmd.description,
+                    "keywords": cmd.keywords
+                })).collect();
+                self.write(&format!("{}{}", ::serde_json::to_string_pretty(&json)?, self.terminator()))
+            }
+        }
+    }
+
+    pub fn print_selection(self: &Printer, selection: &Selection) -> Result<()> {
+        match self.output {
+            Output::Plain | Output::Lines => self.write(&format!("{}{}{}",
+                selection.interpolated(), self.annotation(slice::from_ref(selection)), self.terminator())),
+            Output::Json => self.write(&format!("{}{}", selection_json(selection), self.terminator()))
+        }
+    }
+
+    /// The chained commands: joined with `&&`, one per line or as a JSON array
+    pub fn print_selections(self: &Printer, selections: &[Selection]) -> Result<()> {
+        match self.output {
+            Output::Plain => {
+                let cmds: Vec<String> = selections.iter().map(Selection::interpolated).collect();
+                self.write(&format!("{}{}{}", cmds.join(" && "), self.annotation(selections), self.terminator()))
+            },
+            Output::Lines => self.write(&selections.iter()
+                .map(|selection| format!("{}{}{}",
+                    selection.interpolated(), self.annotation(slice::from_ref(selection)), self.terminator()))
+                .collect::<String>()),
+            Output::Json => {
+                let json: Vec<_> = selections.iter().map(selection_json).collect();
+                self.write(&format!("{}{}", ::serde_json::Value::Array(json), self.terminator()))
+            }
+        }
+    }
+
+    /// When nothing has been selected
+    pub fn print_nothing(self: &Printer) -> Result<()> {
+        if self.print0 {
+            return Ok(());
+        }
+        self.write("\n") // needed when piped with read cmd
+    }
+
+    /// All at once, on stdout or into the file given with `--out`
+    fn write(self: &Printer, text: &str) -> Result<()> {
+        match self.out {
+            Some(ref path) => {
+                let mut file = File::create(path).map_err(|error| Error::File { path: path.clone(), error })?;
+                file.write_all(text.as_bytes()).map_err(|error| Error::File { path: path.clone(), error })
+            },
+            None => {
+                let stdout = io::stdout();
+                let mut out = stdout.lock();
+                out.write_all(text.as_bytes())?;
+                Ok(out.flush()?)
+            }
+        }
+    }
+
+    /// Nothing without `--annotate`, see `command::annotation`
+    fn annotation(self: &Printer, selections: &[Selection]) -> String {
+        if self.annotate { command::annotation(selections) } else { String::new() }
+    }
+
+    fn terminator(self: &Printer) -> char {
+        if self.print0 { '\0' } else { '\n' }
+    }
+}
+
+fn selection_json(selection: &Selection) -> ::serde_json::Value {
+    let cmd = &selection.command;
+    let placeholders: Vec<_> = cmd.cmd.names().iter().zip(&selection.values)
+        .map(|(name, value)| json!({ "name": name, "value": value }))
+        .collect();
+
+    json!({
+        "id": cmd.id,
+        "cmd": selection.interpolated(),
+        "template": cmd.cmd.to_string(),
+        "placeholders": placeholders,
+        "keywords": cmd.keywords,
+        "description": cmd.description
+    })
+}
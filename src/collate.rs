@@ -0,0 +1,68 @@
+use std::cmp::Ordering;
+
+/// Compare like a person would: `item2` before `item10`, ignoring the case and the accents of
+/// the Latin letters (`école` between `ecole` and `ecrire`). Strings which only differ by those
+/// are then compared as is, so that the order is total.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut i, mut j) = (0, 0);
+    loop {
+        let ordering = match (a[i..].chars().next(), b[j..].chars().next()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (m, n) = (digits_end(a, i), digits_end(b, j));
+                let ordering = compare_numbers(&a[i..m], &b[j..n]);
+                i = m;
+                j = n;
+                ordering
+            },
+            (Some(x), Some(y)) => {
+                i += x.len_utf8();
+                j += y.len_utf8();
+                if x == y { Ordering::Equal } else { fold(x).cmp(&fold(y)) }
+            }
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+fn digits_end(s: &str, start: usize) -> usize {
+    s[start..].find(|c: char| !c.is_ascii_digit()).map_or(s.len(), |idx| start + idx)
+}
+
+/// By value, whatever their length: the leading zeros don't count
+fn compare_numbers(a: &str, b: &str) -> Ordering {
+    let (a, b) = (a.trim_start_matches('0'), b.trim_start_matches('0'));
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+/// The lowercase letter without its accent
+fn fold(c: char) -> char {
+    match c.to_lowercase().next().unwrap_or(c) {
+        'à'..='å' | 'ā' | 'ă' | 'ą' => 'a',
+        'ç' | 'ć' | 'č' => 'c',
+        'ď' => 'd',
+        'è'..='ë' | 'ē' | 'ė' | 'ę' | 'ě' => 'e',
+        'ì'..='ï' | 'ī' | 'į' => 'i',
+        'ł' => 'l',
+        'ñ' | 'ń' | 'ň' => 'n',
+        'ò'..='ö' | 'ø' | 'ō' | 'ő' => 'o',
+        'ř' => 'r',
+        'ś' | 'š' => 's',
+        'ť' => 't',
+        'ù'..='ü' | 'ū' | 'ů' | 'ű' => 'u',
+        'ý' | 'ÿ' => 'y',
+        'ź' | 'ż' | 'ž' => 'z',
+        c => c
+    }
+}
+
+#[test]
+fn comparing_naturally() {
+    let mut items = vec!["item10", "Item2", "item2", "item02b", "école", "ecrire", "ecole", "item", "zebra", "Éclair"];
+    items.sort_by(|a, b| natural_cmp(a, b));
+    assert_eq!(items, vec!["Éclair", "ecole", "école", "ecrire", "item", "Item2", "item2", "item02b", "item10", "zebra"]);
+}
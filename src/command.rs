@@ -1,21 +1,64 @@
 
-use std::rc::Rc;
-use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::Bound;
 use std::cmp::Ordering;
+use std::fmt;
+use std::mem;
+use std::time::Duration;
 use itertools::Itertools;
+use source::CommandSource;
+use collate::natural_cmp;
+use exec;
+use frecency::Frecency;
+use function::Function;
+use history::Outcomes;
+use keys::parse_hotkey;
+use termion::event::Key;
 
-type Result<T> = ::std::result::Result<T, Box<::std::error::Error>>;
+use error::{Error, Result};
 
-#[derive(Clone, Hash, Eq, PartialEq, Debug)]
+/// The programs running the command given as argument, skipped when telling which program a command line runs
+pub const WRAPPERS: &[&str] = &["sudo", "env", "nohup", "time", "exec", "command", "nice"];
+
+#[derive(Clone, Hash, Eq, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize), serde(default))]
 pub struct Command {
+    pub id: Option<String>,
     pub cmd: Placeholders,
     pub description: Option<String>,
     pub keywords: Vec<String>, // TODO should be a Set
+    /// For some placeholders, a shell command listing the values to pick among, one per line
+    #[cfg_attr(feature = "serialize", serde(skip_serializing_if = "BTreeMap::is_empty"))]
+    pub completions: BTreeMap<String, String>,
+    /// A key accepting the command right away in the picker, e.g. `F2` (see `keys::parse_hotkey`)
+    #[cfg_attr(feature = "serialize", serde(skip_serializing_if = "Option::is_none"))]
+    pub hotkey: Option<String>,
+    /// Why the command is dangerous, see `danger::DangerRules`
+    #[cfg_attr(feature = "serialize", serde(skip_serializing_if = "Option::is_none"))]
+    pub danger: Option<String>,
+    /// Why the command cannot run here, e.g. `needs kubectl`: it's greyed out
+    #[cfg_attr(feature = "serialize", serde(skip_serializing_if = "Option::is_none"))]
+    pub unavailable: Option<String>,
+    /// Where it comes from, e.g. `Makefile`: the commands of several groups are listed under headers
+    #[cfg_attr(feature = "serialize", serde(skip_serializing_if = "Option::is_none"))]
+    pub group: Option<String>,
+    /// A shell command its output goes through when it's run, e.g. `jq '.items[].metadata.name'`
+    #[cfg_attr(feature = "serialize", serde(skip_serializing_if = "Option::is_none"))]
+    pub pipe: Option<String>,
+    /// The id of the command to use instead, this one being deprecated
+    #[cfg_attr(feature = "serialize", serde(skip_serializing_if = "Option::is_none"))]
+    pub replaced_by: Option<String>,
+    /// The texts of the badges shown before it, see `badge::Badges`
+    #[cfg_attr(feature = "serialize", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub badges: Vec<String>
 }
 
+/// By template then description, the numbers being compared by value (see `natural_cmp`)
 impl Ord for Command {
     fn cmp(&self, other: &Command) -> Ordering {
-        self.cmd.original.cmp(&other.cmd.original)
+        natural_cmp(&self.cmd.original, &other.cmd.original)
+            .then_with(|| natural_cmp(self.some_description(), other.some_description()))
     }
 }
 
@@ -29,73 +72,503 @@ impl PartialOrd for Command {
 pub struct Placeholders {
     original: String,
     cmd_chunks: Vec<String>,
-    names: Vec<String>
+    names: Vec<String>,
+    /// the functions called, in the order of the template
+    calls: Vec<Call>
+}
+
+/// A function called within a chunk, e.g. `{uuid()}`, which the chunk keeps
+#[derive(Clone, Hash, Eq, PartialEq, Debug)]
+struct Call {
+    chunk: usize,
+    /// where its braces are in the chunk, in bytes
+    start: usize,
+    end: usize,
+    function: Function
 }
 
+/// As the original template, e.g. `"nix-env -q '.*{name}.*'"`
+#[cfg(feature = "serialize")]
+impl ::serde::Serialize for Placeholders {
+    fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.original)
+    }
+}
 
+#[cfg(feature = "serialize")]
+impl<'de> ::serde::Deserialize<'de> for Placeholders {
+    fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> ::std::result::Result<Placeholders, D::Error> {
+        let template = String::deserialize(deserializer)?;
+        Placeholders::parse(&template).map_err(::serde::de::Error::custom)
+    }
+}
 
 impl Placeholders {
 
-    /// Syntax is: `my-command {placeholder name} -i {other}`
+    /// Syntax is: `my-command {placeholder name} -i {other}`, a `}` on its own being mere text.
+    /// There's always one more chunk than placeholders, possibly empty.
+    /// The built-in functions are called likewise, e.g. `{uuid()}` or `{=2*1024}`, but aren't placeholders: see `Function`.
     pub fn parse(cmd: &str) -> Result<Placeholders> {
-        use ::regex::{Regex};
-
-        // TODO do not rebuild this for every command
-        let regex = Regex::new(r"([^{]*)(\{([^}]*?)\})?")?;
+        let error = |column: usize, message: String| Err(Error::PlaceholderSyntax {
+            cmd: cmd.to_owned(),
+            column: Some(column),
+            message
+        });
 
         let mut placeholders = Placeholders {
             original: cmd.to_owned(),
             ..Default::default()
         };
+        let mut chunk = String::new();
+        // the column of the `{` of the placeholder being read, and its name so far
+        let mut opened: Option<(usize, String)> = None;
 
-        for capture in regex.captures_iter(cmd) {
-            if let Some(cmd) = capture.get(1) {
-                placeholders.cmd_chunks.push(cmd.as_str().to_owned())
-            };
-            if let Some(name) = capture.get(3) {
-                placeholders.names.push(name.as_str().to_owned())
+        for (idx, c) in cmd.chars().enumerate() {
+            let column = idx + 1;
+            opened = match (opened, c) {
+                (None, '{') => Some((column, String::new())),
+                (None, c) => {
+                    chunk.push(c);
+                    None
+                },
+                (Some((start, _)), '{') =>
+                    return error(column, format!("nested placeholder, within the one opened at column {}", start)),
+                (Some((start, name)), '}') => {
+                    match Function::parse(&name) {
+                        Some(Ok(function)) => {
+                            let begin = chunk.len();
+                            chunk.push_str(&format!("{{{}}}", name));
+                            placeholders.calls.push(Call { chunk: placeholders.cmd_chunks.len(), start: begin, end: chunk.len(), function });
+                        },
+                        Some(Err(message)) => return error(start, message),
+                        None => {
+                            placeholders.cmd_chunks.push(mem::take(&mut chunk));
+                            placeholders.names.push(name);
+                        }
+                    }
+                    None
+                },
+                (Some((start, mut name)), c) => {
+                    name.push(c);
+                    Some((start, name))
+                }
             };
         }
 
+        if let Some((start, _)) = opened {
+            return error(start, "unclosed placeholder".to_owned());
+        }
+        placeholders.cmd_chunks.push(chunk);
         Ok(placeholders)
     }
 
-    pub fn interpolate(self: &Placeholders, values: Vec<String>) -> String {
-        self.cmd_chunks.iter().interleave(values.iter()).join("")
+    /// Without placeholders, even if it contains braces, e.g. a command already interpolated
+    pub fn literal(cmd: &str) -> Placeholders {
+        Placeholders { original: cmd.to_owned(), cmd_chunks: vec![cmd.to_owned()], names: Vec::new(), calls: Vec::new() }
+    }
+
+    /// With the values of the placeholders, and the results of the functions it calls, see `evaluate`
+    pub fn interpolate(self: &Placeholders, values: &[String], results: &[String]) -> String {
+        let mut calls = self.calls.iter().zip(results).peekable();
+        let chunks: Vec<String> = self.cmd_chunks.iter().enumerate().map(|(idx, chunk)| {
+            let mut interpolated = String::with_capacity(chunk.len());
+            let mut from = 0;
+            while let Some((call, result)) = calls.next_if(|(call, _)| call.chunk == idx) {
+                interpolated.push_str(&chunk[from..call.start]);
+                interpolated.push_str(result);
+                from = call.end;
+            }
+            interpolated.push_str(&chunk[from..]);
+            interpolated
+        }).collect();
+        chunks.iter().interleave(values.iter()).join("")
+    }
+
+    /// Call its functions, e.g. drawing a new UUID each time
+    pub fn evaluate(self: &Placeholders) -> Vec<String> {
+        self.calls.iter().map(|call| call.function.evaluate()).collect()
+    }
+
+    /// Whether nothing follows the last placeholder, e.g. `kubectl get pods {args}`
+    pub fn ends_with_placeholder(self: &Placeholders) -> bool {
+        !self.names.is_empty() && self.cmd_chunks.last().is_some_and(String::is_empty)
+    }
+
+    /// As written in the configuration
+    pub fn template(self: &Placeholders) -> &str {
+        &self.original
+    }
+
+    /// The text around the placeholders
+    pub fn chunks(self: &Placeholders) -> &[String] {
+        &self.cmd_chunks
+    }
+
+    /// Empty for the anonymous placeholders (`{}`)
+    pub fn names(self: &Placeholders) -> &[String] {
+        &self.names
+    }
+}
+
+impl fmt::Display for Placeholders {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.original)
     }
 }
 
 impl Command {
 
-    pub fn some_description<'a>(self: &'a Command) -> &'a str {
+    /// Its template is a URL, e.g. `https://grafana.example.com/d/{dashboard}`: opened in the browser rather than run
+    pub fn is_url(self: &Command) -> bool {
+        let template = self.cmd.template().trim_start().to_ascii_lowercase();
+        template.starts_with("http://") || template.starts_with("https://")
+    }
+
+    pub fn some_description(self: &Command) -> &str {
         self.description.as_ref().map_or("", String::as_str)
     }
+
+    /// Along with the same command listed by another source, e.g. `make build` configured and found in the Makefile:
+    /// their keywords together, the longer description, and the settings of the other one this one lacks
+    pub fn merged(self: &Command, other: &Command) -> Command {
+        let mut merged = self.clone();
+        merged.keywords.extend(other.keywords.iter().filter(|kw| !self.keywords.contains(kw)).cloned());
+        if other.some_description().len() > self.some_description().len() {
+            merged.description = other.description.clone();
+        }
+        for (name, provider) in &other.completions {
+            merged.completions.entry(name.clone()).or_insert_with(|| provider.clone());
+        }
+        merged.badges.extend(other.badges.iter().filter(|badge| !self.badges.contains(badge)).cloned());
+        merged.id = merged.id.or_else(|| other.id.clone());
+        merged.hotkey = merged.hotkey.or_else(|| other.hotkey.clone());
+        merged.danger = merged.danger.or_else(|| other.danger.clone());
+        merged.pipe = merged.pipe.or_else(|| other.pipe.clone());
+        merged.replaced_by = merged.replaced_by.or_else(|| other.replaced_by.clone());
+        merged
+    }
+}
+
+/// A command along with the values of its placeholders
+#[derive(Clone, Debug)]
+pub struct Selection {
+    pub command: Arc<Command>,
+    pub values: Vec<String>,
+    /// how long it took to type the values in
+    pub fill_in_time: Option<Duration>,
+    /// appended verbatim, e.g. the arguments given after `--`
+    pub arguments: Option<String>,
+    /// whether the arguments given after `--` filled its trailing placeholder in, see `pass_through`
+    pub passed_through: bool,
+    /// what the functions of its template returned, called once as it got picked
+    pub results: Vec<String>
+}
+
+impl Selection {
+    /// For a command without placeholders
+    pub fn new(command: Arc<Command>) -> Selection {
+        let results = command.cmd.evaluate();
+        Selection { command, values: Vec::new(), fill_in_time: None, arguments: None, passed_through: false, results }
+    }
+
+    /// Fill the placeholders in from their names, as given by `name=value`
+    pub fn with_named_values(command: Arc<Command>, assignments: &[&str]) -> Result<Selection> {
+        let mut named_values = HashMap::new();
+        for assignment in assignments {
+            match assignment.find('=') {
+                Some(idx) => named_values.insert(&assignment[..idx], assignment[idx + 1..].to_owned()),
+                None => return Err(Error::InvalidInput(format!("Expecting `name=value`, got `{}`", assignment)))
+            };
+        }
+
+        let mut values = Vec::new();
+        for name in command.cmd.names() {
+            match named_values.get(name.as_str()) {
+                Some(value) => values.push(value.clone()),
+                None if name.is_empty() => return Err(Error::PlaceholderSyntax {
+                    cmd: command.cmd.to_string(),
+                    column: None,
+                    message: "anonymous placeholders cannot be set".to_owned()
+                }),
+                None => return Err(Error::MissingValue(name.clone()))
+            }
+        }
+
+        Ok(Selection { values, ..Selection::new(command) })
+    }
+
+    /// Append the arguments, unless they filled its trailing placeholder in, see `RunnerBuilder::passthrough`
+    pub fn pass_through(self: &mut Selection, arguments: &str) {
+        if !self.passed_through {
+            self.arguments = Some(arguments.to_owned());
+        }
+    }
+
+    /// Run it with the user's shell, through its pipe if any, returning its exit status
+    pub fn execute(self: &Selection) -> Result<i32> {
+        match self.command.pipe {
+            Some(ref pipe) => exec::execute_through(&self.interpolated(), pipe),
+            None => exec::execute(&self.interpolated())
+        }
+    }
+
+    /// As a URL to open, its values percent-encoded, e.g. for `&` or a space to stay part of a query parameter
+    pub fn url(self: &Selection) -> String {
+        let encoded = |values: &[String]| values.iter().map(|value| exec::percent_encode(value)).collect::<Vec<_>>();
+        self.command.cmd.interpolate(&encoded(&self.values), &encoded(&self.results))
+    }
+
+    pub fn interpolated(self: &Selection) -> String {
+        let interpolated = self.command.cmd.interpolate(&self.values, &self.results);
+        match self.arguments {
+            Some(ref arguments) => format!("{} {}", interpolated, arguments),
+            None => interpolated
+        }
+    }
+}
+
+/// ` # <description>`, a shell comment following the selected commands, those of chained commands being
+/// separated by `;`: on a single line, and nothing when none has a description
+pub fn annotation(selections: &[Selection]) -> String {
+    let descriptions: Vec<String> = selections.iter()
+        .map(|selection| selection.command.some_description().split_whitespace().join(" "))
+        .filter(|description| !description.is_empty())
+        .collect();
+    if descriptions.is_empty() {
+        return String::new();
+    }
+    format!(" # {}", descriptions.join("; "))
 }
 
+#[derive(Default)]
 pub struct Commands {
-    pub commands: Vec<Rc<Command>>,
-    pub kwd2cmd: HashMap<String, HashSet<Rc<Command>>>
+    pub commands: Vec<Arc<Command>>,
+    /// the commands of each keyword, sorted to look the prefixes up
+    pub kwd2cmd: BTreeMap<String, CommandSet>,
+    /// how the suggestions are ordered, the most frecent first
+    pub frecency: Frecency,
+    /// the last use and the count of uses of each template, to list the commands in other orders
+    pub recency: Frecency,
+    pub frequency: Frecency,
+    /// how much the match weighs against the frecency, see `FrecencyConfig::relevance_weight`
+    pub relevance_weight: f64,
+    /// how the last executions of each template went, see `history::outcomes`
+    pub outcomes: HashMap<String, Outcomes>,
+    /// the place of each command once sorted, to break the ties of frecency without comparing them
+    ranks: Vec<usize>,
+    /// the index of each template, a command listed by several sources being merged into one
+    templates: HashMap<String, usize>,
+    /// the commands as listed, before being merged, along with the index of the source which loaded them
+    /// if it may load them again, see `Commands::replace`
+    parts: Vec<(Option<usize>, Arc<Command>)>,
+    /// the command of each hotkey, the last one declaring it winning
+    pub hotkeys: HashMap<Key, usize>
 }
 
 impl Commands {
     pub fn new(vec_commands: Vec<Command>) -> Commands {
-        let commands: Vec<Rc<Command>> = vec_commands.into_iter().map(|cmd| Rc::new(cmd)).collect();
-        Commands::new_rc(commands)
+        let commands: Vec<Arc<Command>> = vec_commands.into_iter().map(Arc::new).collect();
+        Commands::new_arc(commands)
+    }
+
+    pub fn new_arc(commands: Vec<Arc<Command>>) -> Commands {
+        let mut all = Commands::default();
+        all.extend_arc(commands);
+        all
+    }
+
+    /// Add the commands, e.g. as a source gets loaded
+    pub fn extend(self: &mut Commands, commands: Vec<Command>) {
+        self.extend_arc(commands.into_iter().map(Arc::new).collect())
+    }
+
+    /// Swap the commands the source of that index loaded before for those it loaded again, be they merged
+    /// with those of other sources since
+    pub fn replace(self: &mut Commands, source: usize, new: Vec<Command>) {
+        self.parts.retain(|&(origin, _)| origin != Some(source));
+        self.parts.extend(new.into_iter().map(|cmd| (Some(source), Arc::new(cmd))));
+        self.rebuild();
+    }
+
+    /// Keep the commands satisfying the predicate only, e.g. the harmless ones with `--safe`: one merged
+    /// from several sources goes away altogether when it doesn't
+    pub fn retain<F: FnMut(&Command) -> bool>(self: &mut Commands, mut keep: F) {
+        let dropped: HashSet<String> = self.commands.iter()
+            .filter(|cmd| !keep(cmd))
+            .map(|cmd| cmd.cmd.template().to_owned())
+            .collect();
+        self.parts.retain(|(_, cmd)| !dropped.contains(cmd.cmd.template()));
+        self.rebuild();
     }
 
-    pub fn new_rc(commands: Vec<Rc<Command>>) -> Commands {
-        let mut kwd2cmd: HashMap<String, HashSet<Rc<Command>>> = HashMap::new();
-        for cmd in &commands {
-            for kw in &cmd.keywords {
-                let set = kwd2cmd.entry(kw.clone()).or_insert(HashSet::new());
-                set.insert(cmd.clone());
+    fn rebuild(self: &mut Commands) {
+        let parts = mem::take(&mut self.parts);
+        self.commands.clear();
+        self.kwd2cmd.clear();
+        self.hotkeys.clear();
+        self.templates.clear();
+        for (origin, cmd) in parts {
+            self.add(origin, cmd);
+        }
+        self.sort();
+    }
+
+    fn extend_arc(self: &mut Commands, commands: Vec<Arc<Command>>) {
+        for cmd in commands {
+            self.add(None, cmd);
+        }
+        self.sort();
+    }
+
+    /// Merged with the command of the same template, if any
+    fn add(self: &mut Commands, origin: Option<usize>, cmd: Arc<Command>) {
+        self.parts.push((origin, cmd.clone()));
+        match self.templates.get(cmd.cmd.template()).cloned() {
+            Some(idx) => {
+                let existing = self.commands[idx].clone();
+                let merged = existing.merged(&cmd);
+                let keywords: Vec<String> = merged.keywords.iter().filter(|kw| !existing.keywords.contains(kw)).cloned().collect();
+                self.index(idx, &keywords, merged.hotkey.as_ref().filter(|_| existing.hotkey.is_none()));
+                self.commands[idx] = Arc::new(merged);
+            },
+            None => {
+                let idx = self.commands.len();
+                self.index(idx, &cmd.keywords, cmd.hotkey.as_ref());
+                self.templates.insert(cmd.cmd.template().to_owned(), idx);
+                self.commands.push(cmd);
             }
         }
+    }
 
-        Commands { commands, kwd2cmd }
+    fn sort(self: &mut Commands) {
+        let mut order: Vec<usize> = (0..self.commands.len()).collect();
+        order.sort_by(|&a, &b| self.commands[a].cmp(&self.commands[b]));
+        self.ranks = vec![0; order.len()];
+        for (rank, idx) in order.into_iter().enumerate() {
+            self.ranks[idx] = rank;
+        }
+    }
+
+    /// Find the command of that index by these keywords and this hotkey
+    fn index(self: &mut Commands, idx: usize, keywords: &[String], hotkey: Option<&String>) {
+        for kw in keywords {
+            self.kwd2cmd.entry(kw.clone()).or_default().insert(idx);
+        }
+        if let Some(key) = hotkey.and_then(|name| parse_hotkey(name).ok()) {
+            self.hotkeys.insert(key, idx);
+        }
+    }
+
+    /// Where the command of that index comes once all of them are sorted, see `Ord for Command`
+    pub fn rank(self: &Commands, idx: usize) -> usize {
+        self.ranks[idx]
+    }
+
+    /// The keywords starting with that prefix, in order
+    pub fn keywords_starting_with<'a>(self: &'a Commands, prefix: &'a str) -> impl Iterator<Item = (&'a String, &'a CommandSet)> {
+        self.kwd2cmd.range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(move |&(kw, _)| kw.starts_with(prefix))
+    }
+
+    /// All the commands of the sources, in order
+    pub fn from_sources(sources: &[Box<dyn CommandSource>]) -> Result<Commands> {
+        let mut commands = Vec::new();
+        for source in sources {
+            commands.extend(source.load()?);
+        }
+        Ok(Commands::new(commands))
+    }
+}
+
+/// Indexes of `Commands::commands`, a bit each: intersecting those of thousands of commands
+/// takes a few microseconds
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CommandSet {
+    bits: Vec<u64>
+}
+
+impl CommandSet {
+    /// The indexes up to `len`, excluded
+    pub fn full(len: usize) -> CommandSet {
+        let mut bits = vec![!0; len / 64];
+        let rest = len % 64;
+        if rest > 0 {
+            bits.push((1 << rest) - 1);
+        }
+        CommandSet { bits }
+    }
+
+    pub fn insert(self: &mut CommandSet, idx: usize) {
+        if idx / 64 >= self.bits.len() {
+            self.bits.resize(idx / 64 + 1, 0);
+        }
+        self.bits[idx / 64] |= 1 << (idx % 64);
+    }
+
+    pub fn contains(self: &CommandSet, idx: usize) -> bool {
+        self.bits.get(idx / 64).is_some_and(|word| word & (1 << (idx % 64)) != 0)
+    }
+
+    pub fn len(self: &CommandSet) -> usize {
+        self.bits.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(self: &CommandSet) -> bool {
+        self.bits.iter().all(|&word| word == 0)
+    }
+
+    pub fn union_with(self: &mut CommandSet, other: &CommandSet) {
+        if other.bits.len() > self.bits.len() {
+            self.bits.resize(other.bits.len(), 0);
+        }
+        for (word, other) in self.bits.iter_mut().zip(&other.bits) {
+            *word |= other;
+        }
+    }
+
+    pub fn intersect_with(self: &mut CommandSet, other: &CommandSet) {
+        self.bits.truncate(other.bits.len());
+        for (word, other) in self.bits.iter_mut().zip(&other.bits) {
+            *word &= other;
+        }
+    }
+
+    /// In increasing order
+    pub fn iter<'a>(self: &'a CommandSet) -> impl Iterator<Item = usize> + 'a {
+        self.bits.iter().enumerate().flat_map(|(i, &word)| (0..64)
+            .filter(move |bit| word & (1 << bit) != 0)
+            .map(move |bit| i * 64 + bit))
+    }
+}
+
+/// A command with those keywords, for the tests of the modules listing commands
+#[cfg(test)]
+pub fn command(cmd: &str, keywords: &[&str]) -> Command {
+    Command {
+        cmd: Placeholders::parse(cmd).unwrap(),
+        keywords: keywords.iter().map(|kw| kw.to_string()).collect(),
+        ..Default::default()
     }
 }
 
+#[test]
+fn combining_command_sets() {
+    let mut set = CommandSet::default();
+    set.insert(3);
+    set.insert(70);
+    assert_eq!(set.len(), 2);
+    assert!(set.contains(70) && !set.contains(4) && !set.contains(200));
+
+    let mut other = CommandSet::full(66);
+    assert_eq!(other.len(), 66);
+    other.intersect_with(&set);
+    assert_eq!(other.iter().collect::<Vec<_>>(), vec![3]);
+
+    other.union_with(&set);
+    other.insert(130);
+    assert_eq!(other.iter().collect::<Vec<_>>(), vec![3, 70, 130]);
+}
+
 #[test]
 fn parsing_placeholders_name_and_no_name() {
     let ph = Placeholders::parse("nix-env -q '.*{}.*'{name} blabla").unwrap();
@@ -103,9 +576,166 @@ fn parsing_placeholders_name_and_no_name() {
     assert_eq!(ph.names, vec!["", "name"]);
 }
 
+#[test]
+fn parsing_placeholders() {
+    let parsed = |cmd: &str| {
+        let ph = Placeholders::parse(cmd).unwrap();
+        (ph.cmd_chunks, ph.names)
+    };
+    let strings = |strs: &[&str]| strs.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+    assert_eq!(parsed(""), (strings(&[""]), strings(&[])));
+    assert_eq!(parsed("ls -l"), (strings(&["ls -l"]), strings(&[])));
+    assert_eq!(parsed("ls {dir}"), (strings(&["ls ", ""]), strings(&["dir"])));
+    assert_eq!(parsed("{cmd} --help"), (strings(&["", " --help"]), strings(&["cmd"])));
+    assert_eq!(parsed("{a}{b}"), (strings(&["", "", ""]), strings(&["a", "b"])));
+    assert_eq!(parsed("echo {} {the name}"), (strings(&["echo ", " ", ""]), strings(&["", "the name"])));
+    assert_eq!(parsed("echo } {x}}"), (strings(&["echo } ", "}"]), strings(&["x"])));
+    assert_eq!(parsed("écho {nom}"), (strings(&["écho ", ""]), strings(&["nom"])));
+}
+
+#[test]
+fn reporting_placeholder_syntax_errors() {
+    let error = |cmd: &str| match Placeholders::parse(cmd) {
+        Err(Error::PlaceholderSyntax { column: Some(column), message, .. }) => (column, message),
+        other => panic!("unexpected {:?}", other)
+    };
+
+    assert_eq!(error("ls {dir"), (4, "unclosed placeholder".to_owned()));
+    assert_eq!(error("{"), (1, "unclosed placeholder".to_owned()));
+    assert_eq!(error("cp {src} {dst"), (10, "unclosed placeholder".to_owned()));
+    assert_eq!(error("échö {a{b}}"), (8, "nested placeholder, within the one opened at column 6".to_owned()));
+    assert_eq!(Placeholders::parse("ls {dir").unwrap_err().to_string(), "`ls {dir` at column 4: unclosed placeholder");
+}
+
+#[test]
+fn setting_named_values() {
+    let cmd = Arc::new(Command { cmd: Placeholders::parse("cp {src} {dst}.bak{src}").unwrap(), ..Default::default() });
+    let selection = Selection::with_named_values(cmd.clone(), &["src=a", "dst=b=c"]).unwrap();
+    assert_eq!(selection.interpolated(), "cp a b=c.baka");
+
+    assert!(Selection::with_named_values(cmd.clone(), &["src=a"]).is_err());
+    assert!(Selection::with_named_values(cmd, &["src"]).is_err());
+}
+
 #[test]
 fn interpolating_placeholders() {
     let ph = Placeholders::parse("nix-env -q '.*{}.*'{name} blabla").unwrap();
-    assert_eq!(ph.interpolate(vec!["stuff".to_owned(), "more-stuff".to_owned()]), "nix-env -q '.*stuff.*'more-stuff blabla");
+    assert_eq!(ph.interpolate(&["stuff".to_owned(), "more-stuff".to_owned()], &[]), "nix-env -q '.*stuff.*'more-stuff blabla");
+
+    let ph = Placeholders::parse("docker run --name {image}-{rand(4)} {image} --host {hostname()}").unwrap();
+    assert_eq!(ph.names(), &["image", "image"]);
+    assert_eq!(ph.chunks(), &["docker run --name ", "-{rand(4)} ", " --host {hostname()}"]);
+    assert_eq!(ph.evaluate().iter().map(String::len).next(), Some(4));
+    assert_eq!(ph.interpolate(&["nginx".to_owned(), "nginx".to_owned()], &["ab12".to_owned(), "box".to_owned()]),
+               "docker run --name nginx-ab12 nginx --host box");
+    assert!(Placeholders::parse("touch {rand(x)}").is_err());
+    let ph = Placeholders::parse("truncate -s {=2M + 512} {file}").unwrap();
+    assert_eq!(ph.interpolate(&["disk.img".to_owned()], &ph.evaluate()), "truncate -s 2097664 disk.img");
+    assert!(Placeholders::parse("head -c {=1/0}").unwrap_err().to_string().contains("division by zero"));
+}
+
+
+#[cfg(feature = "serialize")]
+#[test]
+fn serializing_commands() {
+    let json = r#"{"cmd":"cp {src} {dst}","keywords":["cp"]}"#;
+    let command: Command = ::serde_json::from_str(json).unwrap();
+    assert_eq!(command.cmd.names(), &["src", "dst"]);
+    assert_eq!(::serde_json::to_string(&command).unwrap(),
+               r#"{"id":null,"cmd":"cp {src} {dst}","description":null,"keywords":["cp"]}"#);
+
+    assert!(::serde_json::from_str::<Command>(r#"{"cmd":"cp {src"}"#).is_err());
 }
 
+#[test]
+fn telling_urls_apart() {
+    let command = |cmd: &str| Command { cmd: Placeholders::parse(cmd).unwrap(), ..Default::default() };
+    assert!(command("https://grafana.example.com/d/{dashboard}").is_url());
+    assert!(command("HTTP://wiki/runbooks").is_url());
+    assert!(!command("curl https://example.com").is_url());
+
+    let mut selection = Selection::new(Arc::new(command("https://grafana.example.com/explore?q={query}")));
+    selection.values.push("rate(x[5m]) & y".to_owned());
+    assert_eq!(selection.url(), "https://grafana.example.com/explore?q=rate%28x%5B5m%5D%29%20%26%20y");
+}
+
+#[test]
+fn passing_arguments_through() {
+    let command = Arc::new(Command { cmd: Placeholders::parse("echo {text}").unwrap(), ..Default::default() });
+
+    let mut typed = Selection { values: vec!["-n".to_owned()], ..Selection::new(command.clone()) };
+    typed.pass_through("-n");
+    assert_eq!(typed.interpolated(), "echo -n -n");
+
+    let mut filled_in = Selection { values: vec!["-n".to_owned()], passed_through: true, ..Selection::new(command) };
+    filled_in.pass_through("-n");
+    assert_eq!(filled_in.interpolated(), "echo -n");
+}
+
+#[test]
+fn annotating_selections() {
+    let selection = |cmd: &str, description: Option<&str>| Selection::new(Arc::new(Command {
+        cmd: Placeholders::parse(cmd).unwrap(),
+        description: description.map(str::to_owned),
+        ..Default::default()
+    }));
+    let ls = selection("ls -la", Some("List the files"));
+    assert_eq!(annotation(::std::slice::from_ref(&ls)), " # List the files");
+    assert_eq!(annotation(&[selection("df", Some("Disk\nusage,  per  filesystem"))]), " # Disk usage, per filesystem");
+    assert_eq!(annotation(&[selection("pwd", None)]), "");
+    assert_eq!(annotation(&[ls, selection("pwd", Some(" ")), selection("du -sh", Some("Disk usage"))]),
+               " # List the files; Disk usage");
+}
+
+#[test]
+fn retaining_commands() {
+    let command = |cmd: &str, danger: Option<&str>| Command {
+        cmd: Placeholders::parse(cmd).unwrap(),
+        keywords: vec!["files".to_owned()],
+        danger: danger.map(str::to_owned),
+        ..Default::default()
+    };
+    let mut commands = Commands::new(vec![command("rm -rf {dir}", Some("deletes recursively")), command("ls", None)]);
+    commands.retain(|command| command.danger.is_none());
+
+    assert_eq!(commands.commands.iter().map(|cmd| cmd.cmd.to_string()).collect::<Vec<_>>(), vec!["ls"]);
+    assert!(commands.kwd2cmd["files"].contains(0) && commands.kwd2cmd["files"].len() == 1);
+}
+
+#[test]
+fn merging_commands_across_sources() {
+    let command = |cmd: &str, description: Option<&str>, keywords: &[&str]| Command {
+        cmd: Placeholders::parse(cmd).unwrap(),
+        description: description.map(str::to_owned),
+        keywords: keywords.iter().map(|kw| kw.to_string()).collect(),
+        ..Default::default()
+    };
+    let mut commands = Commands::new(vec![
+        Command { hotkey: Some("F2".to_owned()), ..command("make build", Some("Build"), &["make", "build"]) },
+        command("ls", None, &["ls"])
+    ]);
+    commands.extend(vec![
+        Command { group: Some("Makefile".to_owned()), ..command("make build", Some("Build the binaries"), &["make", "compile"]) },
+        command("ls", None, &["files"])
+    ]);
+
+    assert_eq!(commands.commands.len(), 2);
+    assert_eq!(*commands.commands[0], Command {
+        hotkey: Some("F2".to_owned()),
+        ..command("make build", Some("Build the binaries"), &["make", "build", "compile"])
+    });
+    assert!(commands.kwd2cmd["compile"].contains(0) && commands.kwd2cmd["files"].contains(1));
+    assert_eq!(commands.hotkeys[&Key::F(2)], 0);
+
+    // a source loaded again, whose command got merged with one configured
+    commands.replace(1, vec![command("make build", None, &["make", "targets"]), command("make test", None, &["make"])]);
+    commands.replace(1, vec![Command { hotkey: Some("F3".to_owned()), ..command("make test", None, &["make", "check"]) }]);
+    assert_eq!(commands.commands.len(), 3);
+    assert!(!commands.kwd2cmd.contains_key("targets"));
+    assert_eq!(commands.commands[0].keywords, vec!["make", "build", "compile"]);
+    assert_eq!((commands.commands[2].cmd.template(), commands.hotkeys[&Key::F(3)]), ("make test", 2));
+    commands.replace(1, Vec::new());
+    assert_eq!(commands.commands.len(), 2);
+    assert!(!commands.kwd2cmd.contains_key("check") && !commands.hotkeys.contains_key(&Key::F(3)));
+}
@@ -0,0 +1,749 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use badge::BadgeConfig;
+use cache::CacheConfig;
+use command::{Command, Placeholders};
+use danger::DangerConfig;
+use frecency::FrecencyConfig;
+use keys::parse_hotkey;
+use history::HistoryConfig;
+use keywords::{DeriveKeywords, Retag};
+use pack::PacksConfig;
+use source::{Provider, SourcesConfig};
+use state::StartupMode;
+use system;
+
+use error::{Error, Result};
+
+/// Where the commands are stored, e.g. `~/.config/smart-runner/config.toml`:
+///
+/// ```toml
+/// [[commands]]
+/// cmd = "nix-env -q '.*{name}.*'"
+/// description = "Search a Nix package by name"
+/// keywords = ["nix", "search", "package"]
+/// ```
+#[derive(Clone)]
+pub struct Location {
+    pub path: PathBuf,
+    /// false when the file has been picked with `--config` or `--profile`
+    pub is_default: bool
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Config {
+    #[serde(default)]
+    pub commands: Vec<CommandEntry>,
+    /// how the history orders the commands, e.g. `[frecency] half_life_days = 14`
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub frecency: FrecencyConfig,
+    /// what's kept out of the history, e.g. `[history] ignore = ["^vault "]`
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub history: HistoryConfig,
+    /// the executables contributing commands, see `source::Provider`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub providers: Vec<Provider>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub sources: SourcesConfig,
+    /// keywords validating several at once, e.g. `[bundles] k8s-debug = ["kubernetes", "pods", "logs"]`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub bundles: BTreeMap<String, Vec<String>>,
+    /// what flags the dangerous commands, e.g. `[danger] patterns = ["^kubectl delete"]`
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub danger: DangerConfig,
+    /// what becomes of the commands which cannot run here, e.g. `unavailable = "grey"`
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub unavailable: Unavailable,
+    /// keywords for the commands without any, e.g. `[derive_keywords] enabled = true`
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub derive_keywords: DeriveKeywords,
+    /// where `pack install` fetches the command packs from, e.g. `[packs] registry = "https://..."`
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub packs: PacksConfig,
+    /// how long the values of the completion commands are kept, e.g. `[completion_cache] ttl_secs = 300`
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub completion_cache: CacheConfig,
+    /// the colored tags shown before the commands, see `badge::BadgeConfig`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub badges: Vec<BadgeConfig>,
+    /// what the picker lists before anything is typed in, e.g. `[startup] mode = "history"`
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub startup: StartupConfig
+}
+
+/// E.g. `[startup] mode = "favorites"` for the commands used most, or `list_on_empty = false` with thousands of them
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct StartupConfig {
+    #[serde(default)]
+    pub mode: StartupMode,
+    /// all the commands listed while nothing is typed in, right from the start (true) or never (false),
+    /// rather than once something got typed in and erased
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub list_on_empty: Option<bool>
+}
+
+/// The commands whose `requires_bin` are not all on the PATH are either hidden or greyed out, with a note
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Unavailable {
+    #[default]
+    Hide,
+    Grey
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct CommandEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub cmd: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    /// e.g. `completions = { branch = "git branch --format='%(refname:short)'" }`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub completions: BTreeMap<String, String>,
+    /// e.g. `hotkey = "F2"`, accepting the command right away in the picker
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hotkey: Option<String>,
+    /// not to flag the command as dangerous, see `Config::danger`
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub allow_danger: bool,
+    /// e.g. `requires_bin = ["kubectl"]`: the programs to find on the PATH, see `Config::unavailable`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requires_bin: Vec<String>,
+    /// e.g. `platform = ["macos"]`: the operating systems (`linux`, `macos`...) or architectures (`x86_64`, `aarch64`...)
+    /// it's listed on, or both as in `linux-aarch64`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub platform: Vec<String>,
+    /// e.g. `hosts = ["workstation", "build-*"]`: the host names it's listed on, `*` standing for any chars
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hosts: Vec<String>,
+    /// e.g. `users = ["root"]`: the users it's listed for, `*` standing for any chars
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub users: Vec<String>,
+    /// e.g. `group = "deploy"`: the header it's listed under, when the commands listed come from several groups
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// e.g. `pipe = "jq '.items[].metadata.name'"`: what its output goes through when it's run (by `exec`),
+    /// rather than printed for the shell
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pipe: Option<String>,
+    /// e.g. `replaced_by = "deploy-v2"`: the id of the command to use instead, this one being deprecated:
+    /// it's struck through, and picking it offers the other one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replaced_by: Option<String>,
+    /// e.g. `badges = ["PROD"]`: the tags shown before it, colored as the `[[badges]]` of that text say
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub badges: Vec<String>
+}
+
+/// What to do when saving over a file which changed on disk since it was loaded, see `Location::retag`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resolution {
+    /// Write over it anyway, the changes made meanwhile being lost
+    KeepMine,
+    /// Leave it as it is now, the edits being lost
+    TakeTheirs,
+    /// Make the edits on it as it is now, to the entries of the same `cmd`
+    Merge
+}
+
+/// How the edits got saved, along with how many entries they changed
+#[derive(Debug, PartialEq, Eq)]
+pub enum Saved {
+    Written { retagged: usize },
+    /// the file having changed meanwhile
+    Discarded,
+    /// into the file as it is now, but for the commands no longer in it
+    Merged { retagged: usize, missing: Vec<String> }
+}
+
+/// What the conditions of the commands are evaluated against (`platform`, `hosts` and `users`)
+#[derive(Debug, Clone)]
+pub struct Machine {
+    pub os: &'static str,
+    pub arch: &'static str,
+    pub host: Option<String>,
+    pub user: Option<String>
+}
+
+impl Location {
+    pub fn new(config: Option<&str>, profile: Option<&str>) -> Result<Location> {
+        match (config, profile) {
+            (Some(path), _) => Ok(Location { path: PathBuf::from(path), is_default: false }),
+            (None, Some(name)) => Ok(Location {
+                path: config_dir()?.join("profiles").join(format!("{}.toml", name)),
+                is_default: false
+            }),
+            (None, None) => Ok(Location { path: config_dir()?.join("config.toml"), is_default: true })
+        }
+    }
+
+    pub fn dir(self: &Location) -> &Path {
+        self.path.parent().unwrap_or_else(|| Path::new("."))
+    }
+
+    /// Fall back on a few sample commands until the default file gets created
+    pub fn load(self: &Location) -> Result<Config> {
+        if self.is_default && !self.path.exists() {
+            Ok(Config::sample())
+        } else {
+            Config::read(&self.path)
+        }
+    }
+
+    /// Append the entries at the end of the file, preserving its formatting and comments
+    pub fn append(self: &Location, entries: &[CommandEntry]) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        for entry in entries {
+            let block = ::toml::to_string(&Config { commands: vec![entry.clone()], ..Default::default() })?;
+            write!(file, "\n{}", block)?;
+        }
+        Ok(())
+    }
+
+    /// What's in the file, empty if there's none yet, to tell later whether it changed meanwhile
+    pub fn read(self: &Location) -> Result<String> {
+        if !self.path.exists() {
+            return Ok(String::new());
+        }
+        fs::read_to_string(&self.path).map_err(|error| Error::File { path: self.path.clone(), error })
+    }
+
+    /// Retag the entries of these commands in the content loaded, preserving the rest of the file.
+    /// When the file changed since, `resolve` tells what to do rather than silently writing over the changes:
+    /// merging, the entries as they are now get retagged, their keywords edited meanwhile included
+    pub fn retag<F>(self: &Location, loaded: &str, cmds: &[String], retag: &Retag, resolve: F) -> Result<Saved>
+            where F: FnOnce() -> Result<Resolution> {
+        let current = self.read()?;
+        let resolution = if current == loaded { Resolution::KeepMine } else { resolve()? };
+        let content = match resolution {
+            Resolution::KeepMine => loaded,
+            Resolution::TakeTheirs => return Ok(Saved::Discarded),
+            Resolution::Merge => &current
+        };
+        let entries = Config::parse_at(content, &self.path)?.commands;
+        let mut keywords = BTreeMap::new();
+        for (idx, entry) in entries.iter().enumerate().filter(|(_, entry)| cmds.contains(&entry.cmd)) {
+            let mut kws = entry.keywords.clone();
+            if retag.apply(&mut kws) {
+                keywords.insert(idx, kws);
+            }
+        }
+        fs::write(&self.path, with_keywords(content, &keywords))?;
+
+        let retagged = keywords.len();
+        Ok(match resolution {
+            Resolution::Merge => Saved::Merged {
+                retagged,
+                missing: cmds.iter().filter(|cmd| !entries.iter().any(|entry| entry.cmd == **cmd)).cloned().collect()
+            },
+            _ => Saved::Written { retagged }
+        })
+    }
+
+    /// Like `load` but without the sample commands: what's actually in the file
+    pub fn load_existing(self: &Location) -> Result<Config> {
+        if self.path.exists() {
+            Config::read(&self.path)
+        } else {
+            Ok(Config::default())
+        }
+    }
+}
+
+impl Config {
+    pub fn read(path: &Path) -> Result<Config> {
+        let mut content = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut content))
+            .map_err(|error| Error::File { path: path.to_owned(), error })?;
+
+        Config::parse_at(&content, path)
+    }
+
+    /// That of the file at this path, its errors telling it
+    pub fn parse_at(content: &str, path: &Path) -> Result<Config> {
+        Config::parse(content).map_err(|e| match e {
+            Error::ConfigParse { line, message, .. } => Error::ConfigParse { path: Some(path.to_owned()), line, message },
+            e => e
+        })
+    }
+
+    pub fn parse(content: &str) -> Result<Config> {
+        Ok(::toml::from_str(content)?)
+    }
+
+    /// Flagging the dangerous ones, and leaving out those which cannot run here (unless greyed out)
+    pub fn commands(self: &Config) -> Result<Vec<Command>> {
+        let machine = Machine::current();
+        Ok(self.entries()?.into_iter()
+            .filter_map(|(entry, command)| entry.on(&machine, self.unavailable, command))
+            .collect())
+    }
+
+    /// Every command along with its entry, the dangerous ones flagged, whatever machine they are listed on:
+    /// see `CommandEntry::on`, e.g. for the daemon to leave it to the picker
+    pub fn entries(self: &Config) -> Result<Vec<(&CommandEntry, Command)>> {
+        let rules = self.danger.rules();
+        let mut commands = Vec::new();
+        for entry in &self.commands {
+            let mut command = entry.to_command()?;
+            if !entry.allow_danger {
+                command.danger = rules.check(&entry.cmd).map(str::to_owned);
+            }
+            commands.push(command);
+        }
+        self.derive_keywords.apply(&mut commands);
+        Ok(self.commands.iter().zip(commands).collect())
+    }
+
+    fn sample() -> Config {
+        let entry = |cmd: &str, description: &str, keywords: &[&str]| CommandEntry {
+            id: None,
+            cmd: cmd.to_owned(),
+            description: Some(description.to_owned()),
+            keywords: keywords.iter().map(|&kw| kw.to_owned()).collect(),
+            ..Default::default()
+        };
+
+        Config {
+            commands: vec![
+                entry("nix-env -q '.*{name}.*'", "Search a Nix package by name", &["nix", "search", "package"]),
+                entry("du -sh /nix/store", "Show the size of the Nix store", &["nix", "store", "size"]),
+                entry("sudo shutdown -h now", "Shut the system down", &["hardware", "shutdown"])
+            ],
+            ..Default::default()
+        }
+    }
+}
+
+impl CommandEntry {
+    pub fn to_command(self: &CommandEntry) -> Result<Command> {
+        if let Some(ref hotkey) = self.hotkey {
+            parse_hotkey(hotkey)?;
+        }
+        Ok(Command {
+            id: self.id.clone(),
+            cmd: Placeholders::parse(&self.cmd)?,
+            description: self.description.clone(),
+            keywords: self.keywords.clone(),
+            completions: self.completions.clone(),
+            hotkey: self.hotkey.clone(),
+            danger: None,
+            unavailable: None,
+            group: self.group.clone(),
+            pipe: self.pipe.clone(),
+            replaced_by: self.replaced_by.clone(),
+            badges: self.badges.clone()
+        })
+    }
+
+    /// Whether it's listed at all on that machine, each condition being met by one of its values
+    pub fn applies_to(self: &CommandEntry, machine: &Machine) -> bool {
+        let met = |values: &[String], matching: &dyn Fn(&str) -> bool| values.is_empty() || values.iter().any(|value| matching(value));
+        met(&self.platform, &|platform| machine.has_platform(platform))
+            && met(&self.hosts, &|pattern| machine.has_host(pattern))
+            && met(&self.users, &|pattern| machine.user.as_ref().is_some_and(|user| system::matches_glob(pattern, user)))
+    }
+
+    /// The command as listed on that machine, greyed out when it cannot run there:
+    /// None when it is not listed at all, or hidden as `unavailable` says
+    pub fn on(self: &CommandEntry, machine: &Machine, unavailable: Unavailable, mut command: Command) -> Option<Command> {
+        if !self.applies_to(machine) {
+            return None;
+        }
+        command.unavailable = self.unavailable();
+        if command.unavailable.is_some() && unavailable == Unavailable::Hide {
+            return None;
+        }
+        Some(command)
+    }
+
+    /// Why it cannot run here, e.g. `needs kubectl`
+    pub fn unavailable(self: &CommandEntry) -> Option<String> {
+        let missing: Vec<&str> = self.requires_bin.iter()
+            .filter(|bin| system::find_executable(bin).is_none())
+            .map(String::as_str)
+            .collect();
+        if missing.is_empty() { None } else { Some(format!("needs {}", missing.join(", "))) }
+    }
+}
+
+/// The names of the files in the profiles directory
+pub fn profiles() -> Result<Vec<String>> {
+    let dir = config_dir()?.join("profiles");
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "toml") {
+            if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                names.push(name.to_owned());
+            }
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// The line of each `[[commands]]` table, to locate the entries of a file
+pub fn command_lines(content: &str) -> Vec<usize> {
+    let lines: Vec<&str> = content.lines().collect();
+    statements(content).into_iter()
+        .filter(|&(first, _)| is_command_table(lines[first]))
+        .map(|(first, _)| first + 1)
+        .collect()
+}
+
+/// The `keywords` of the entries replaced, be their array on several lines, or added below `[[commands]]`
+fn with_keywords(content: &str, keywords: &BTreeMap<usize, Vec<String>>) -> String {
+    let lines: Vec<&str> = content.lines().collect();
+    let statements = statements(content);
+    let text = |(first, last): (usize, usize)| lines[first..=last].iter().map(|&line| line.to_owned());
+    let mut rewritten: Vec<String> = Vec::new();
+    let mut entries = 0;
+    let mut s = 0;
+    while s < statements.len() {
+        let (first, _) = statements[s];
+        rewritten.extend(text(statements[s]));
+        s += 1;
+        if !is_command_table(lines[first]) {
+            continue;
+        }
+        entries += 1;
+        let kws = match keywords.get(&(entries - 1)) {
+            Some(kws) => kws,
+            None => continue
+        };
+        // up to the next table
+        let end = (s..statements.len()).find(|&t| lines[statements[t].0].trim_start().starts_with('[')).unwrap_or(statements.len());
+        let line = format!("keywords = {}", ::toml::Value::from(kws.clone()));
+        let entry = &statements[s..end];
+        match entry.iter().position(|&(first, _)| is_key(lines[first], "keywords")) {
+            Some(replaced) => {
+                rewritten.extend(entry[..replaced].iter().flat_map(|&statement| text(statement)));
+                rewritten.push(line);
+                rewritten.extend(entry[replaced + 1..].iter().flat_map(|&statement| text(statement)));
+            },
+            None => {
+                rewritten.push(line);
+                rewritten.extend(entry.iter().flat_map(|&statement| text(statement)));
+            }
+        }
+        s = end;
+    }
+    rewritten.join("\n") + if content.ends_with('\n') { "\n" } else { "" }
+}
+
+/// The first and last lines of each statement of the file: a table header, a key along with its value,
+/// be it a string or an array on several lines, or else a blank or comment line. Brackets and `#` within
+/// strings, and `[[commands]]` within a multi-line string, are told apart
+fn statements(content: &str) -> Vec<(usize, usize)> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Within { Code, Comment, Basic, Literal, MultiLineBasic, MultiLineLiteral }
+
+    let bytes = content.as_bytes();
+    let mut statements = Vec::new();
+    let (mut within, mut depth, mut first, mut line) = (Within::Code, 0usize, 0, 0);
+    let mut i = 0;
+    while i < bytes.len() {
+        let rest = &bytes[i..];
+        match (within, bytes[i]) {
+            (_, b'\n') => {
+                if let Within::Comment | Within::Basic | Within::Literal = within {
+                    within = Within::Code;
+                }
+                if within == Within::Code && depth == 0 {
+                    statements.push((first, line));
+                    first = line + 1;
+                }
+                line += 1;
+            },
+            (Within::Code, b'#') => within = Within::Comment,
+            (Within::Code, b'"') if rest.starts_with(b"\"\"\"") => {
+                within = Within::MultiLineBasic;
+                i += 2;
+            },
+            (Within::Code, b'"') => within = Within::Basic,
+            (Within::Code, b'\'') if rest.starts_with(b"'''") => {
+                within = Within::MultiLineLiteral;
+                i += 2;
+            },
+            (Within::Code, b'\'') => within = Within::Literal,
+            (Within::Code, b'[') => depth += 1,
+            (Within::Code, b']') => depth = depth.saturating_sub(1),
+            (Within::Basic, b'\\') | (Within::MultiLineBasic, b'\\') if rest.get(1) != Some(&b'\n') => i += 1,
+            (Within::Basic, b'"') | (Within::Literal, b'\'') => within = Within::Code,
+            (Within::MultiLineBasic, b'"') if rest.starts_with(b"\"\"\"") => {
+                within = Within::Code;
+                i += 2;
+            },
+            (Within::MultiLineLiteral, b'\'') if rest.starts_with(b"'''") => {
+                within = Within::Code;
+                i += 2;
+            },
+            _ => ()
+        }
+        i += 1;
+    }
+    // the last line, unless ended by a new line, or whatever is left unclosed
+    let count = content.lines().count();
+    if first < count {
+        statements.push((first, count - 1));
+    }
+    statements
+}
+
+fn is_command_table(line: &str) -> bool {
+    line.trim_start().starts_with("[[commands]]")
+}
+
+fn is_key(line: &str, key: &str) -> bool {
+    line.trim_start().strip_prefix(key).is_some_and(|rest| rest.trim_start().starts_with('='))
+}
+
+impl Machine {
+    pub fn current() -> Machine {
+        Machine { os: env::consts::OS, arch: env::consts::ARCH, host: system::hostname(), user: system::username() }
+    }
+
+    fn has_platform(self: &Machine, platform: &str) -> bool {
+        platform == self.os || platform == self.arch || platform == format!("{}-{}", self.os, self.arch)
+    }
+
+    /// Either the full name or its first label, e.g. `build-1` for `build-1.example.com`
+    fn has_host(self: &Machine, pattern: &str) -> bool {
+        self.host.as_ref().is_some_and(|host| {
+            system::matches_glob(pattern, host) || host.split('.').next().is_some_and(|short| system::matches_glob(pattern, short))
+        })
+    }
+}
+
+fn is_default<T: Default + PartialEq>(table: &T) -> bool {
+    *table == T::default()
+}
+
+fn config_dir() -> Result<PathBuf> {
+    ::dirs::config_dir()
+        .map(|dir| dir.join("smart-runner"))
+        .ok_or(Error::NoDirectory("configuration"))
+}
+
+#[test]
+fn parsing_config() {
+    let config = Config::parse(r#"
+        [[commands]]
+        cmd = "du -sh /nix/store"
+        description = "Show the size of the Nix store"
+        keywords = ["nix", "store"]
+
+        [[commands]]
+        cmd = "sudo shutdown -h now"
+    "#).unwrap();
+
+    assert_eq!(config.commands.len(), 2);
+    assert_eq!(config.commands[0].keywords, vec!["nix", "store"]);
+    assert_eq!(config.commands[1].description, None);
+    assert_eq!(config.commands[1].keywords, Vec::<String>::new());
+    assert_eq!(config.frecency, FrecencyConfig::default());
+    assert!(config.bundles.is_empty());
+}
+
+#[test]
+fn flagging_dangerous_commands() {
+    let config = Config::parse(r#"
+        [[commands]]
+        cmd = "sudo rm -rf /"
+
+        [[commands]]
+        cmd = "chmod 777 {dir}"
+        allow_danger = true
+    "#).unwrap();
+
+    let commands = config.commands().unwrap();
+    assert_eq!(commands[0].danger, Some("removes everything from the root".to_owned()));
+    assert_eq!(commands[1].danger, None);
+}
+
+#[test]
+fn requiring_binaries() {
+    let toml = r#"
+        [[commands]]
+        cmd = "kubectl get pods"
+        requires_bin = ["sh", "surely-not-installed"]
+
+        [[commands]]
+        cmd = "ls"
+        requires_bin = ["sh"]
+    "#;
+    let commands = Config::parse(toml).unwrap().commands().unwrap();
+    assert_eq!(commands.len(), 1);
+    assert_eq!(commands[0].unavailable, None);
+
+    let commands = Config::parse(&format!("unavailable = \"grey\"\n{}", toml)).unwrap().commands().unwrap();
+    assert_eq!(commands[0].unavailable, Some("needs surely-not-installed".to_owned()));
+}
+
+#[test]
+fn filtering_platforms() {
+    let mac = Machine { os: "macos", arch: "aarch64", host: None, user: None };
+    assert!(mac.has_platform("macos"));
+    assert!(mac.has_platform("aarch64"));
+    assert!(mac.has_platform("macos-aarch64"));
+    assert!(!mac.has_platform("linux-aarch64"));
+
+    let commands = Config::parse(r#"
+        [[commands]]
+        cmd = "xclip -selection clipboard"
+        platform = ["plan9"]
+
+        [[commands]]
+        cmd = "ls"
+    "#).unwrap().commands().unwrap();
+    assert_eq!(commands.len(), 1);
+}
+
+#[test]
+fn filtering_hosts_and_users() {
+    let machine = Machine { os: "linux", arch: "x86_64", host: Some("build-12.example.com".to_owned()), user: Some("root".to_owned()) };
+    let entry = |hosts: &[&str], users: &[&str]| CommandEntry {
+        hosts: hosts.iter().map(|&host| host.to_owned()).collect(),
+        users: users.iter().map(|&user| user.to_owned()).collect(),
+        ..Default::default()
+    };
+    assert!(entry(&[], &[]).applies_to(&machine));
+    assert!(entry(&["workstation", "build-*"], &["root"]).applies_to(&machine));
+    assert!(entry(&["*.example.com"], &[]).applies_to(&machine));
+    assert!(!entry(&["workstation"], &[]).applies_to(&machine));
+    assert!(!entry(&["build-*"], &["admin", "r*t*x"]).applies_to(&machine));
+
+    assert!(system::matches_glob("b*-*2", "build-12"));
+    assert!(!system::matches_glob("b*-*3", "build-12"));
+    assert!(system::matches_glob("build-?2", "build-12"));
+}
+
+#[test]
+fn parsing_bundles() {
+    let config = Config::parse(r#"
+        [bundles]
+        k8s-debug = ["kubernetes", "pods", "logs"]
+    "#).unwrap();
+
+    assert_eq!(config.bundles["k8s-debug"], vec!["kubernetes", "pods", "logs"]);
+}
+
+#[test]
+fn parsing_the_startup() {
+    let config = Config::parse("[startup]\nmode = \"favorites\"\nlist_on_empty = false\n").unwrap();
+    assert_eq!(config.startup, StartupConfig { mode: StartupMode::Favorites, list_on_empty: Some(false) });
+
+    assert_eq!(Config::parse("").unwrap().startup, StartupConfig::default());
+    assert!(Config::parse("[startup]\nmode = \"recent\"\n").is_err());
+}
+
+#[test]
+fn reporting_the_line_of_syntax_errors() {
+    match Config::parse("[[commands]]\ncmd = \"ls\"\nkeywords = [\"ls\" \"du\"]\n") {
+        Err(Error::ConfigParse { line, .. }) => assert_eq!(line, Some(3)),
+        other => panic!("unexpected {:?}", other)
+    }
+}
+
+#[test]
+fn rejecting_a_half_life_which_is_not_positive() {
+    assert_eq!(Config::parse("[frecency]\nhalf_life_days = 14\n").unwrap().frecency.half_life_days, 14.0);
+    for days in ["0", "0.0", "-7", "nan", "inf"] {
+        match Config::parse(&format!("[frecency]\nhalf_life_days = {}\n", days)) {
+            Err(Error::ConfigParse { message, .. }) => assert!(message.contains("half_life_days"), "{}", message),
+            other => panic!("unexpected {:?} for {}", other, days)
+        }
+    }
+}
+
+#[test]
+fn setting_keywords() {
+    let content = "# mine\n[[commands]]\ncmd = \"ls\"\nkeywords = [\n  \"ls\",\n]\n\n[[commands]]\ncmd = \"du\"\n\n[[commands]]\ncmd = \"df\"\nkeywords = [\"df\"]\n";
+    let mut keywords = BTreeMap::new();
+    keywords.insert(0, vec!["files".to_owned()]);
+    keywords.insert(1, vec!["disk".to_owned(), "size".to_owned()]);
+
+    let rewritten = with_keywords(content, &keywords);
+    assert_eq!(rewritten, "# mine\n[[commands]]\ncmd = \"ls\"\nkeywords = [\"files\"]\n\n[[commands]]\nkeywords = [\"disk\", \"size\"]\ncmd = \"du\"\n\n[[commands]]\ncmd = \"df\"\nkeywords = [\"df\"]\n");
+    let entries = Config::parse(&rewritten).unwrap().commands;
+    assert_eq!(entries[1].keywords, vec!["disk", "size"]);
+}
+
+#[test]
+fn setting_keywords_past_strings_and_comments() {
+    let content = "[[commands]]\ncmd = '''\ncat <<EOF\n[[commands]]\nEOF'''\n# keywords = [\"old\"]\nkeywords = [\"a]b\", # the ] of a string\n  \"c\"]\n\
+                   description = \"after [the] keywords\"\n\n[[commands]]\ncmd = \"ls\"\n";
+    assert_eq!(command_lines(content), vec![1, 11]);
+    let mut keywords = BTreeMap::new();
+    keywords.insert(0, vec!["x]".to_owned()]);
+    keywords.insert(1, vec!["files".to_owned()]);
+
+    let rewritten = with_keywords(content, &keywords);
+    assert_eq!(rewritten, "[[commands]]\ncmd = '''\ncat <<EOF\n[[commands]]\nEOF'''\n# keywords = [\"old\"]\nkeywords = [\"x]\"]\n\
+                           description = \"after [the] keywords\"\n\n[[commands]]\nkeywords = [\"files\"]\ncmd = \"ls\"\n");
+    let entries = Config::parse(&rewritten).unwrap().commands;
+    assert_eq!(entries.iter().map(|entry| entry.keywords.clone()).collect::<Vec<_>>(), vec![vec!["x]"], vec!["files"]]);
+    assert_eq!(entries[0].description, Some("after [the] keywords".to_owned()));
+}
+
+#[test]
+fn resolving_conflicts_on_save() {
+    let path = ::std::env::temp_dir().join(format!("smart-runner-retag-{}.toml", ::std::process::id()));
+    let location = Location { path: path.clone(), is_default: false };
+    let loaded = "[[commands]]\ncmd = \"ls\"\n\n[[commands]]\ncmd = \"du\"\n\n[[commands]]\ncmd = \"df\"\n";
+    // `ls` listed twice and tagged meanwhile
+    let theirs = "[[commands]]\ncmd = \"pwd\"\n\n[[commands]]\ncmd = \"ls\"\nkeywords = [\"list\"]\n\n\
+                  [[commands]]\ncmd = \"du\"\n\n[[commands]]\ncmd = \"ls\"\n";
+    let cmds = vec!["ls".to_owned(), "df".to_owned()];
+    let retag = Retag { add: vec!["files".to_owned()], remove: Vec::new() };
+    let resolving = |resolution: Resolution| {
+        fs::write(&path, theirs).unwrap();
+        let saved = location.retag(loaded, &cmds, &retag, || Ok(resolution)).unwrap();
+        (saved, Config::read(&path).unwrap().commands.into_iter().map(|entry| entry.keywords).collect::<Vec<_>>())
+    };
+    let kws = |kws: &[&str]| kws.iter().map(|&kw| kw.to_owned()).collect::<Vec<_>>();
+
+    assert_eq!(resolving(Resolution::TakeTheirs), (Saved::Discarded, vec![kws(&[]), kws(&["list"]), kws(&[]), kws(&[])]));
+    assert_eq!(resolving(Resolution::Merge), (Saved::Merged { retagged: 2, missing: vec!["df".to_owned()] },
+                                             vec![kws(&[]), kws(&["list", "files"]), kws(&[]), kws(&["files"])]));
+    assert_eq!(resolving(Resolution::KeepMine), (Saved::Written { retagged: 2 }, vec![kws(&["files"]), kws(&[]), kws(&["files"])]));
+
+    fs::write(&path, loaded).unwrap();
+    let saved = location.retag(loaded, &cmds, &retag, || panic!("no conflict"));
+    fs::remove_file(&path).unwrap();
+    assert_eq!(saved.unwrap(), Saved::Written { retagged: 2 });
+}
+
+#[test]
+fn appending_preserves_existing_content() {
+    let path = ::std::env::temp_dir().join(format!("smart-runner-append-{}.toml", ::std::process::id()));
+    fs::write(&path, "# my commands\n").unwrap();
+    let location = Location { path: path.clone(), is_default: false };
+
+    location.append(&[CommandEntry {
+        cmd: "ls -la".to_owned(),
+        keywords: vec!["ls".to_owned()],
+        ..Default::default()
+    }]).unwrap();
+
+    let content = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(content.starts_with("# my commands\n"));
+    assert_eq!(Config::parse(&content).unwrap().commands[0].cmd, "ls -la");
+}
@@ -0,0 +1,414 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use command::{Command, Commands};
+use config::{CommandEntry, Config, Location, Machine};
+use doctor;
+use frecency::{Context, Frecency};
+use history::{self, History};
+use pack::{PackDir, Packs};
+use rpc;
+use source;
+
+use error::{Error, Result};
+
+/// How long the picker waits for the daemon before loading the commands itself
+const TIMEOUT: Duration = Duration::from_secs(2);
+/// How long the daemon waits for the next request of an editor before hanging up, for it to connect again
+const IDLE: Duration = Duration::from_secs(600);
+
+/// What the daemon keeps loaded for the picker to start right away
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Library {
+    /// The configuration, without its commands
+    pub config: Config,
+    /// How many issues `doctor` reports with the commands of the configuration
+    pub issues: usize,
+    /// Those of the configuration then those of the providers
+    commands: Vec<LibraryEntry>,
+    /// How many of them come from the configuration and its packs, whose ids are to be unique
+    #[serde(default)]
+    configured: usize
+}
+
+/// A command as sent over the socket, as it is written in the configuration: along with the conditions
+/// of its entry (`requires_bin`, `platform`, `hosts` and `users`), which hold or not where the picker runs
+#[derive(Serialize, Deserialize, Debug)]
+struct LibraryEntry {
+    #[serde(flatten)]
+    entry: CommandEntry,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    danger: Option<String>
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Request {
+    Library
+}
+
+/// Keeps the library of a configuration file loaded, serving it over a Unix socket (see `socket_path`):
+/// to the picker, which sends `"library"` and gets the `Library` back, and to editors, which send
+/// JSON-RPC requests (see `rpc::answer`) one line after the other, on a thread of their own
+pub struct Daemon {
+    location: Location,
+    /// Past that age, the library gets loaded again, e.g. for the commands of the providers to be up to date
+    refresh: Duration,
+    loaded: Arc<Mutex<Loaded>>,
+    /// Whether the library is being loaded again, on a thread of its own
+    reloading: Arc<AtomicBool>
+}
+
+struct Loaded {
+    /// The library as answered, one line of JSON
+    answer: Arc<String>,
+    /// The same commands, indexed for the JSON-RPC queries
+    commands: Arc<Commands>,
+    at: Instant,
+    modified: Option<SystemTime>
+}
+
+impl Library {
+    /// Those of the configuration file and of its providers, the failing providers being skipped
+    pub fn load(location: &Location) -> Result<Library> {
+        let mut config = location.load()?;
+        let issues = doctor::lint(&config.commands, &doctor::locations(location, config.commands.len())).len();
+        let danger = config.danger.rules();
+        let packs = Packs { dir: PackDir::of(location), danger: danger.clone(), unavailable: config.unavailable };
+        let mut entries: Vec<(CommandEntry, Command)> = config.entries()?.into_iter()
+            .map(|(entry, command)| (entry.clone(), command))
+            .chain(packs.entries())
+            .collect();
+        let configured = entries.len();
+        for provider in &config.providers {
+            // their conditions are checked by the picker, as those of the configuration
+            match provider.entries() {
+                Ok(loaded) => {
+                    let (provided, mut loaded): (Vec<CommandEntry>, Vec<Command>) = loaded.into_iter().unzip();
+                    config.derive_keywords.apply(&mut loaded);
+                    danger.flag(&mut loaded);
+                    entries.extend(provided.into_iter().zip(loaded))
+                },
+                Err(e) => eprintln!("Cannot load commands: {}", e)
+            }
+        }
+        config.commands.clear();
+        let commands = entries.iter().map(|(entry, command)| LibraryEntry::new(entry, command)).collect();
+        Ok(Library { config, issues, commands, configured })
+    }
+
+    /// From the daemon serving that configuration file, if any
+    pub fn fetch(location: &Location) -> Result<Library> {
+        let stream = UnixStream::connect(socket_path(location)?)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        writeln!(&stream, "{}", ::serde_json::to_string(&Request::Library)?)?;
+
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+        Ok(::serde_json::from_str(&line)?)
+    }
+
+    /// Ready to be queried, the most frecent first (as of the whole history)
+    pub fn indexed(self: &Library) -> Result<Commands> {
+        let mut commands = Commands::new(self.commands()?);
+        let records = History::open().and_then(|history| history.records()).unwrap_or_default();
+        commands.frecency = Frecency::compute(&records, &self.config.frecency, &Context::current(), history::now());
+        commands.relevance_weight = self.config.frecency.relevance_weight;
+        Ok(commands)
+    }
+
+    /// Those listed on this machine, whichever the daemon runs on
+    pub fn commands(self: &Library) -> Result<Vec<Command>> {
+        let machine = Machine::current();
+        let mut commands = Vec::new();
+        let mut configured = 0;
+        for (idx, library_entry) in self.commands.iter().enumerate() {
+            let mut command = library_entry.entry.to_command()?;
+            command.danger = library_entry.danger.clone();
+            if let Some(command) = library_entry.entry.on(&machine, self.config.unavailable, command) {
+                configured += usize::from(idx < self.configured);
+                commands.push(command);
+            }
+        }
+        source::unique_ids(&commands[..configured])?;
+        Ok(commands)
+    }
+}
+
+impl LibraryEntry {
+    /// With the conditions of its entry, if it has one
+    fn new(entry: &CommandEntry, command: &Command) -> LibraryEntry {
+        LibraryEntry {
+            entry: CommandEntry {
+                id: command.id.clone(),
+                cmd: command.cmd.template().to_owned(),
+                description: command.description.clone(),
+                keywords: command.keywords.clone(),
+                completions: command.completions.clone(),
+                hotkey: command.hotkey.clone(),
+                allow_danger: false,
+                requires_bin: entry.requires_bin.clone(),
+                platform: entry.platform.clone(),
+                hosts: entry.hosts.clone(),
+                users: entry.users.clone(),
+                group: command.group.clone(),
+                pipe: command.pipe.clone(),
+                replaced_by: command.replaced_by.clone(),
+                badges: command.badges.clone()
+            },
+            danger: command.danger.clone()
+        }
+    }
+}
+
+impl Daemon {
+    pub fn new(location: Location, refresh: Duration) -> Result<Daemon> {
+        let loaded = Loaded::load(&location)?;
+        Ok(Daemon { location, refresh, loaded: Arc::new(Mutex::new(loaded)), reloading: Arc::new(AtomicBool::new(false)) })
+    }
+
+    /// Until killed
+    pub fn serve(self: Daemon) -> Result<()> {
+        let path = socket_path(&self.location)?;
+        if UnixStream::connect(&path).is_ok() {
+            return Err(Error::InvalidInput(format!("A daemon already serves {} on {}", self.location.path.display(), path.display())));
+        }
+        // left over by a daemon which got killed
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let listener = UnixListener::bind(&path).map_err(|error| Error::File { path: path.clone(), error })?;
+        eprintln!("Serving {} on {}", self.location.path.display(), path.display());
+
+        let daemon = Arc::new(self);
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let daemon = daemon.clone();
+            thread::spawn(move || if let Err(e) = daemon.answer(stream) {
+                eprintln!("Cannot answer: {}", e);
+            });
+        }
+        Ok(())
+    }
+
+    /// The first request is to come right away, not to keep a thread for a client that never writes
+    fn answer(self: &Daemon, stream: UnixStream) -> Result<()> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let mut reader = BufReader::new(&stream);
+        let mut line = String::new();
+        if !read_request(&mut reader, &mut line)? {
+            return Ok(());
+        }
+        if let Ok(Request::Library) = ::serde_json::from_str(&line) {
+            let (answer, _) = self.current();
+            writeln!(&stream, "{}", answer)?;
+            return Ok(());
+        }
+
+        // an editor, until it hangs up or stays idle
+        stream.set_read_timeout(Some(IDLE))?;
+        loop {
+            let (_, commands) = self.current();
+            if let Some(response) = rpc::answer(&commands, &line) {
+                writeln!(&stream, "{}", response)?;
+            }
+            line.clear();
+            if !read_request(&mut reader, &mut line)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Loaded again in the background when the configuration file changed or the library is too old,
+    /// the previous one being answered meanwhile: running the providers may take longer than the picker waits
+    fn current(self: &Daemon) -> (Arc<String>, Arc<Commands>) {
+        let loaded = self.loaded.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let stale = loaded.at.elapsed() >= self.refresh || modified(&self.location) != loaded.modified;
+        if stale && !self.reloading.swap(true, Ordering::SeqCst) {
+            let (location, shared, reloading) = (self.location.clone(), self.loaded.clone(), self.reloading.clone());
+            thread::spawn(move || {
+                let reloaded = Loaded::load(&location);
+                let mut loaded = shared.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                match reloaded {
+                    Ok(reloaded) => *loaded = reloaded,
+                    Err(e) => {
+                        eprintln!("Cannot load the commands again: {}", e);
+                        // not before the next change
+                        loaded.at = Instant::now();
+                        loaded.modified = modified(&location);
+                    }
+                }
+                reloading.store(false, Ordering::SeqCst);
+            });
+        }
+        (loaded.answer.clone(), loaded.commands.clone())
+    }
+}
+
+impl Loaded {
+    fn load(location: &Location) -> Result<Loaded> {
+        let modified = modified(location);
+        let library = Library::load(location)?;
+        Ok(Loaded {
+            answer: Arc::new(::serde_json::to_string(&library)?),
+            commands: Arc::new(library.indexed()?),
+            at: Instant::now(),
+            modified
+        })
+    }
+}
+
+/// Whether a line was read, rather than the client hanging up or keeping quiet past the read timeout
+fn read_request<B: BufRead>(reader: &mut B, line: &mut String) -> io::Result<bool> {
+    match reader.read_line(line) {
+        Ok(read) => Ok(read > 0),
+        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => Ok(false),
+        Err(e) => Err(e)
+    }
+}
+
+fn modified(location: &Location) -> Option<SystemTime> {
+    fs::metadata(&location.path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// One per configuration file, in the runtime directory (or else the data directory),
+/// e.g. `/run/user/1000/smart-runner/daemon-0123456789abcdef.sock`
+pub fn socket_path(location: &Location) -> Result<PathBuf> {
+    let mut hasher = DefaultHasher::new();
+    location.path.hash(&mut hasher);
+    ::dirs::runtime_dir().or_else(::dirs::data_dir)
+        .map(|dir| dir.join("smart-runner").join(format!("daemon-{:016x}.sock", hasher.finish())))
+        .ok_or(Error::NoDirectory("runtime"))
+}
+
+#[test]
+fn hanging_up_on_a_quiet_client() {
+    let (client, server) = UnixStream::pair().unwrap();
+    server.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+    let mut line = String::new();
+    assert!(!read_request(&mut BufReader::new(&server), &mut line).unwrap());
+
+    writeln!(&client, "{{}}").unwrap();
+    assert!(read_request(&mut BufReader::new(&server), &mut line).unwrap());
+    assert_eq!(line, "{}\n");
+    drop(client);
+    line.clear();
+    assert!(!read_request(&mut BufReader::new(&server), &mut line).unwrap());
+}
+
+#[test]
+fn sending_the_library() {
+    let location = Location { path: ::std::env::temp_dir().join(format!("smart-runner-daemon-{}.toml", ::std::process::id())), is_default: false };
+    fs::write(&location.path, "[bundles]\ngit-all = [\"git\"]\n\n[[commands]]\ncmd = \"chmod 777 {dir}\"\nkeywords = [\"perms\"]\n").unwrap();
+
+    let library = Library::load(&location).unwrap();
+    let sent: Library = ::serde_json::from_str(&::serde_json::to_string(&library).unwrap()).unwrap();
+    fs::remove_file(&location.path).unwrap();
+
+    assert_eq!(sent.config.bundles.len(), 1);
+    assert!(sent.config.commands.is_empty());
+    let commands = sent.commands().unwrap();
+    assert_eq!(commands.len(), 1);
+    assert_eq!(commands[0].cmd.names(), &["dir".to_owned()]);
+    assert_eq!(commands[0].danger, Some("lets anyone write and run the files".to_owned()));
+}
+
+#[test]
+fn flagging_the_commands_of_the_providers() {
+    let location = Location { path: ::std::env::temp_dir().join(format!("smart-runner-flagging-{}.toml", ::std::process::id())), is_default: false };
+    fs::write(&location.path, "[danger]\npatterns = [\"(\", \"^kubectl delete\"]\n\n[[providers]]\nprogram = \"sh\"\n\
+                               args = [\"-c\", \"echo '{\\\"commands\\\": [{\\\"cmd\\\": \\\"kubectl delete pod {pod}\\\"}]}'\"]\n").unwrap();
+
+    let library = Library::load(&location).unwrap();
+    fs::remove_file(&location.path).unwrap();
+    assert_eq!(library.commands().unwrap()[0].danger, Some("matches `^kubectl delete`".to_owned()));
+}
+
+#[test]
+fn leaving_the_programs_required_to_the_picker() {
+    let location = Location { path: ::std::env::temp_dir().join(format!("smart-runner-requires-{}.toml", ::std::process::id())), is_default: false };
+    fs::write(&location.path, "[[commands]]\ncmd = \"kubectl get pods\"\nkeywords = [\"k8s\"]\nrequires_bin = [\"smart-runner-no-such-program\"]\n").unwrap();
+
+    let library = Library::load(&location).unwrap();
+    fs::remove_file(&location.path).unwrap();
+    let mut sent: Library = ::serde_json::from_str(&::serde_json::to_string(&library).unwrap()).unwrap();
+    assert_eq!(sent.commands[0].entry.requires_bin, vec!["smart-runner-no-such-program"]);
+    assert!(sent.commands().unwrap().is_empty());
+
+    sent.config.unavailable = ::config::Unavailable::Grey;
+    assert_eq!(sent.commands().unwrap()[0].unavailable, Some("needs smart-runner-no-such-program".to_owned()));
+}
+
+#[test]
+fn leaving_the_programs_provided_commands_require_to_the_picker() {
+    let location = Location { path: ::std::env::temp_dir().join(format!("smart-runner-provided-requires-{}.toml", ::std::process::id())), is_default: false };
+    fs::write(&location.path, "[[providers]]\nprogram = \"sh\"\nargs = [\"-c\", \"echo '{\\\"commands\\\": [{\\\"cmd\\\": \\\"kubectl get pods\\\", \
+                               \\\"requires_bin\\\": [\\\"smart-runner-no-such-program\\\"]}]}'\"]\n").unwrap();
+
+    let library = Library::load(&location).unwrap();
+    fs::remove_file(&location.path).unwrap();
+    let sent: Library = ::serde_json::from_str(&::serde_json::to_string(&library).unwrap()).unwrap();
+    assert_eq!(sent.commands[0].entry.requires_bin, vec!["smart-runner-no-such-program"]);
+    assert!(sent.commands().unwrap().is_empty());
+}
+
+#[test]
+fn leaving_the_platforms_to_the_picker() {
+    let location = Location { path: ::std::env::temp_dir().join(format!("smart-runner-platforms-{}.toml", ::std::process::id())), is_default: false };
+    fs::write(&location.path, format!("[[commands]]\nid = \"browse\"\ncmd = \"open .\"\nkeywords = [\"files\"]\nplatform = [\"plan9\"]\n\n\
+                                       [[commands]]\nid = \"browse\"\ncmd = \"ls\"\nkeywords = [\"files\"]\nplatform = [\"{}\"]\n", ::std::env::consts::OS)).unwrap();
+
+    let library = Library::load(&location).unwrap();
+    fs::remove_file(&location.path).unwrap();
+    let sent: Library = ::serde_json::from_str(&::serde_json::to_string(&library).unwrap()).unwrap();
+    assert_eq!(sent.commands.len(), 2);
+    assert_eq!(sent.commands[0].entry.platform, vec!["plan9"]);
+    assert_eq!(sent.commands().unwrap().iter().map(|command| command.cmd.template()).collect::<Vec<_>>(), vec!["ls"]);
+}
+
+#[test]
+fn leaving_the_hosts_and_users_to_the_picker() {
+    let location = Location { path: ::std::env::temp_dir().join(format!("smart-runner-hosts-{}.toml", ::std::process::id())), is_default: false };
+    fs::write(&location.path, "[[commands]]\ncmd = \"reboot\"\nkeywords = [\"power\"]\nhosts = [\"build-*\"]\nusers = [\"smart-runner-no-such-user\"]\n\n\
+                               [[commands]]\ncmd = \"uptime\"\nkeywords = [\"power\"]\n").unwrap();
+
+    let library = Library::load(&location).unwrap();
+    fs::remove_file(&location.path).unwrap();
+    let sent: Library = ::serde_json::from_str(&::serde_json::to_string(&library).unwrap()).unwrap();
+    assert_eq!(sent.commands.len(), 2);
+    assert_eq!((&sent.commands[0].entry.hosts[..], &sent.commands[0].entry.users[..]),
+               (&["build-*".to_owned()][..], &["smart-runner-no-such-user".to_owned()][..]));
+    assert_eq!(sent.commands().unwrap().iter().map(|command| command.cmd.template()).collect::<Vec<_>>(), vec!["uptime"]);
+}
+
+#[test]
+fn loading_the_library_again_in_the_background() {
+    let location = Location { path: ::std::env::temp_dir().join(format!("smart-runner-reload-{}.toml", ::std::process::id())), is_default: false };
+    fs::write(&location.path, "[[commands]]\ncmd = \"ls\"\nkeywords = [\"files\"]\n").unwrap();
+    let daemon = Daemon::new(location.clone(), Duration::from_secs(3600)).unwrap();
+
+    thread::sleep(Duration::from_millis(20));
+    fs::write(&location.path, "[[commands]]\ncmd = \"du\"\nkeywords = [\"disk\"]\n").unwrap();
+    let (answer, _) = daemon.current();
+    assert!(answer.contains("\"ls\""));
+
+    let reloaded = (0..200).any(|_| {
+        thread::sleep(Duration::from_millis(10));
+        daemon.current().0.contains("\"du\"")
+    });
+    fs::remove_file(&location.path).unwrap();
+    assert!(reloaded);
+}
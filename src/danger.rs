@@ -0,0 +1,109 @@
+use regex::Regex;
+
+use command::Command;
+
+use error::{Error, Result};
+
+/// What the built-in rules flag, and why
+const BUILTIN: &[(&str, &str)] = &[
+    (r"\brm\s+(-\w+\s+)*-\w*[rR]\w*\s+(-\S+\s+)*/\*?(\s|$)", "removes everything from the root"),
+    (r"\bchmod\s+(-\w+\s+)*0?777\b", "lets anyone write and run the files"),
+    (r#"\beval\s[^'"]*\{"#, "evaluates an unquoted placeholder")
+];
+
+/// The `[danger]` table of the configuration
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DangerConfig {
+    /// Whether the built-in rules apply: `rm -rf /`, `chmod 777` and unquoted placeholders in `eval`
+    #[serde(default = "default_builtin")]
+    pub builtin: bool,
+    /// Regular expressions flagging more templates, e.g. `["^kubectl delete"]`
+    #[serde(default)]
+    pub patterns: Vec<String>
+}
+
+/// What makes a command dangerous, see `DangerConfig`
+#[derive(Debug, Default, Clone)]
+pub struct DangerRules {
+    rules: Vec<(Regex, String)>
+}
+
+impl Default for DangerConfig {
+    fn default() -> DangerConfig {
+        DangerConfig { builtin: true, patterns: Vec::new() }
+    }
+}
+
+fn default_builtin() -> bool {
+    true
+}
+
+impl DangerConfig {
+    /// The invalid patterns being skipped, see `DangerConfig::errors`
+    pub fn rules(self: &DangerConfig) -> DangerRules {
+        let mut rules = Vec::new();
+        if self.builtin {
+            for &(pattern, reason) in BUILTIN {
+                rules.push((Regex::new(pattern).expect("valid built-in pattern"), reason.to_owned()));
+            }
+        }
+        for pattern in &self.patterns {
+            if let Ok(regex) = compile(pattern) {
+                rules.push((regex, format!("matches `{}`", pattern)));
+            }
+        }
+        DangerRules { rules }
+    }
+
+    /// Why the invalid patterns are
+    pub fn errors(self: &DangerConfig) -> Vec<Error> {
+        self.patterns.iter().filter_map(|pattern| compile(pattern).err()).collect()
+    }
+}
+
+fn compile(pattern: &str) -> Result<Regex> {
+    Regex::new(pattern).map_err(|e| Error::ConfigParse {
+        path: None,
+        line: None,
+        message: format!("invalid danger pattern `{}`: {}", pattern, e)
+    })
+}
+
+impl DangerRules {
+    /// Those of the commands not flagged yet, e.g. as a source loads them
+    pub fn flag(self: &DangerRules, commands: &mut [Command]) {
+        for command in commands.iter_mut().filter(|command| command.danger.is_none()) {
+            command.danger = self.check(command.cmd.template()).map(str::to_owned);
+        }
+    }
+
+    /// Why the template is dangerous, according to the first rule it matches
+    pub fn check(self: &DangerRules, template: &str) -> Option<&str> {
+        self.rules.iter()
+            .find(|(regex, _)| regex.is_match(template))
+            .map(|(_, reason)| reason.as_str())
+    }
+}
+
+#[test]
+fn flagging_dangerous_commands() {
+    let rules = DangerConfig { patterns: vec!["^kubectl delete".to_owned()], ..Default::default() }.rules();
+
+    assert_eq!(rules.check("sudo rm -rf /"), Some("removes everything from the root"));
+    assert_eq!(rules.check("rm -fr /*"), Some("removes everything from the root"));
+    assert_eq!(rules.check("chmod -R 777 {dir}"), Some("lets anyone write and run the files"));
+    assert_eq!(rules.check("eval {cmd}"), Some("evaluates an unquoted placeholder"));
+    assert_eq!(rules.check("kubectl delete pod {pod}"), Some("matches `^kubectl delete`"));
+
+    assert_eq!(rules.check("rm -rf /tmp/build"), None);
+    assert_eq!(rules.check("chmod 755 {file}"), None);
+    assert_eq!(rules.check("eval \"$(ssh-agent)\" {key}"), None);
+
+    let builtin_off = DangerConfig { builtin: false, patterns: Vec::new() }.rules();
+    assert_eq!(builtin_off.check("rm -rf /"), None);
+
+    // the other patterns still apply
+    let invalid = DangerConfig { patterns: vec!["(".to_owned(), "^kubectl delete".to_owned()], ..Default::default() };
+    assert_eq!(invalid.errors().len(), 1);
+    assert_eq!(invalid.rules().check("kubectl delete pod {pod}"), Some("matches `^kubectl delete`"));
+}
@@ -0,0 +1,313 @@
+use std::collections::{BTreeSet, HashMap};
+use std::env;
+use std::fs;
+use std::io::stderr;
+use std::fmt;
+
+use badge::Badges;
+use command::WRAPPERS;
+use config::{self, CommandEntry, Config, Location, Machine};
+use keywords;
+use source::Provider;
+use system;
+
+pub enum Level { Info, Warning, Error }
+
+pub struct Finding {
+    pub level: Level,
+    pub message: String,
+    /// What to do about it
+    pub hint: Option<String>
+}
+
+// Commands provided by the shell
+const BUILTINS: &[&str] = &[
+    "cd", "echo", "export", "source", ".", "alias", "set", "unset", "type", "test", "[",
+    "eval", "read", "printf", "pwd", "exit", "true", "false", "ulimit", "umask", "wait",
+    "jobs", "fg", "bg", "history", "for", "if", "while", "case", "functions", "builtin"];
+
+pub fn diagnose(location: &Location) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    match location.load() {
+        Ok(config) => {
+            findings.push(Finding::info(format!("{} command(s) read from {}",
+                                                config.commands.len(), location.path.display())));
+            for entry in &config.commands {
+                if let Err(e) = entry.to_command() {
+                    findings.push(Finding::error(format!("Invalid command `{}`: {}", entry.cmd, e), None));
+                }
+            }
+            if let Err(e) = config.history.ignore_rules() {
+                findings.push(Finding::error(format!("{}", e), Some("fix the regular expression".to_owned())));
+            }
+            findings.extend(providers(&config.providers));
+            findings.extend(unknown_completions(&config.commands));
+            findings.extend(duplicate_ids(&config.commands));
+            findings.extend(unknown_replacements(&config.commands));
+            findings.extend(lint(&config.commands, &locations(location, config.commands.len())));
+            findings.extend(missing_programs(&config.commands));
+            findings.extend(dangers(&config));
+            findings.extend(Badges::compile(&config.badges).1.into_iter()
+                .map(|e| Finding::error(format!("{}", e), Some("fix the regular expression".to_owned()))));
+        },
+        Err(e) => findings.push(Finding::error(
+            format!("Cannot read the configuration: {}", e),
+            Some("fix the syntax error, or pick another file with --config".to_owned())))
+    }
+
+    findings.extend(terminal());
+    findings
+}
+
+fn providers(providers: &[Provider]) -> Vec<Finding> {
+    providers.iter().map(|provider| match provider.entries() {
+        Ok(commands) => Finding::info(format!("{} command(s) provided by {}", commands.len(), provider.program)),
+        Err(e) => Finding::error(
+            format!("Cannot load the commands of a provider: {}", e),
+            Some("it must print `{\"commands\": [...]}` on stdout and exit with status 0".to_owned()))
+    }).collect()
+}
+
+fn duplicate_ids(entries: &[CommandEntry]) -> Vec<Finding> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for id in entries.iter().filter_map(|entry| entry.id.as_ref()) {
+        *counts.entry(id).or_insert(0) += 1;
+    }
+
+    let mut duplicates: Vec<&str> = counts.into_iter().filter(|&(_, n)| n > 1).map(|(id, _)| id).collect();
+    duplicates.sort();
+    duplicates.into_iter().map(|id| Finding::error(
+        format!("Several commands have the id `{}`", id),
+        Some("ids must be unique, rename all but one".to_owned())
+    )).collect()
+}
+
+/// Where the entries of the file are, e.g. `config.toml:12`
+pub fn locations(location: &Location, count: usize) -> Vec<String> {
+    let lines = fs::read_to_string(&location.path).map(|content| config::command_lines(&content)).unwrap_or_default();
+    (0..count).map(|idx| match lines.get(idx) {
+        // when the entries are written otherwise, e.g. as an inline array, the lines don't match them
+        Some(line) if lines.len() == count => format!("{}:{}", location.path.display(), line),
+        _ => format!("command #{} of {}", idx + 1, location.path.display())
+    }).collect()
+}
+
+/// The duplicate commands, those without keywords, and those which keywords cannot tell apart
+pub fn lint(entries: &[CommandEntry], locations: &[String]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut commands: HashMap<String, usize> = HashMap::new();
+    let mut keyword_sets: HashMap<BTreeSet<&str>, usize> = HashMap::new();
+
+    for (idx, entry) in entries.iter().enumerate() {
+        let cmd = entry.cmd.split_whitespace().collect::<Vec<_>>().join(" ");
+        if let Some(&first) = commands.get(&cmd) {
+            findings.push(Finding::warning(
+                format!("`{}` ({}) is already defined at {}", entry.cmd, locations[idx], locations[first]),
+                Some("remove one of them, merging their keywords".to_owned())));
+            continue;
+        }
+        commands.insert(cmd, idx);
+
+        let suggested = keywords::suggest(&entry.cmd, entry.description.as_deref());
+        if entry.keywords.is_empty() {
+            let hint = match suggested.is_empty() {
+                true => "it is only listed until something is typed, give it keywords".to_owned(),
+                false => format!("it is only listed until something is typed, give it keywords, e.g. `keywords = [{}]`",
+                                 suggested.iter().map(|kw| format!("{:?}", kw)).collect::<Vec<_>>().join(", "))
+            };
+            findings.push(Finding::warning(format!("`{}` ({}) has no keywords", entry.cmd, locations[idx]), Some(hint)));
+            continue;
+        }
+        let keywords: BTreeSet<&str> = entry.keywords.iter().map(String::as_str).collect();
+        match keyword_sets.get(&keywords) {
+            Some(&other) => findings.push(Finding::warning(
+                format!("`{}` ({}) has the same keywords as `{}` ({})",
+                        entry.cmd, locations[idx], entries[other].cmd, locations[other]),
+                Some(match suggested.iter().find(|kw| !keywords.contains(kw.as_str())) {
+                    Some(keyword) => format!("only the arrows tell them apart, give either another keyword, e.g. `{}`", keyword),
+                    None => "only the arrows tell them apart, give either another keyword".to_owned()
+                }))),
+            None => { keyword_sets.insert(keywords, idx); }
+        }
+    }
+    findings
+}
+
+fn unknown_completions(entries: &[CommandEntry]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for entry in entries {
+        if let Ok(command) = entry.to_command() {
+            for name in entry.completions.keys().filter(|name| !command.cmd.names().contains(name)) {
+                findings.push(Finding::warning(
+                    format!("`{}` lists the values of `{{{}}}`, which is not one of its placeholders", entry.cmd, name),
+                    Some("rename the completion after the placeholder".to_owned())));
+            }
+        }
+    }
+    findings
+}
+
+/// The deprecated commands pointing at no other one
+fn unknown_replacements(entries: &[CommandEntry]) -> Vec<Finding> {
+    entries.iter()
+        .filter_map(|entry| entry.replaced_by.as_ref().map(|id| (entry, id)))
+        .filter(|&(_, id)| !entries.iter().any(|other| other.id.as_ref() == Some(id)))
+        .map(|(entry, id)| Finding::warning(
+            format!("`{}` is replaced by `{}`, which is the id of no command", entry.cmd, id),
+            Some("point `replaced_by` at the id of the command replacing it".to_owned())))
+        .collect()
+}
+
+fn dangers(config: &Config) -> Vec<Finding> {
+    let rules = config.danger.rules();
+    let invalid = config.danger.errors().into_iter()
+        .map(|e| Finding::error(format!("{}", e), Some("fix the regular expression".to_owned())));
+    let flagged = config.commands.iter()
+        .filter(|entry| !entry.allow_danger)
+        .filter_map(|entry| rules.check(&entry.cmd).map(|reason| Finding::warning(
+            format!("`{}` {}", entry.cmd, reason),
+            Some("double check it, or set `allow_danger = true` on it".to_owned()))));
+    invalid.chain(flagged).collect()
+}
+
+fn missing_programs(entries: &[CommandEntry]) -> Vec<Finding> {
+    let machine = Machine::current();
+    entries.iter()
+        .filter(|entry| entry.applies_to(&machine))
+        .filter_map(|entry| program(&entry.cmd).map(|prog| (entry, prog)))
+        // missing on purpose when required
+        .filter(|&(entry, prog)| !entry.requires_bin.iter().any(|bin| bin == prog))
+        .filter(|&(_, prog)| system::find_executable(prog).is_none())
+        .map(|(entry, prog)| Finding::warning(
+            format!("`{}` is not installed (used by `{}`)", prog, entry.cmd),
+            Some("install it, or check that it is on the PATH".to_owned())))
+        .collect()
+}
+
+/// The program a command line runs, unless it's provided by the shell or is a placeholder
+fn program(cmd: &str) -> Option<&str> {
+    cmd.split_whitespace()
+        .find(|word| !WRAPPERS.contains(word) && !is_assignment(word))
+        .filter(|word| !BUILTINS.contains(word) && !word.contains('{'))
+}
+
+fn is_assignment(word: &str) -> bool {
+    word.find('=').is_some_and(|idx| idx > 0)
+}
+
+fn terminal() -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    if !::termion::is_tty(&stderr()) {
+        findings.push(Finding::error(
+            "stderr is not a terminal, the interface cannot be shown".to_owned(),
+            Some("only redirect stdout, or use --filter".to_owned())));
+    }
+    match system::terminal_size() {
+        Some((width, height)) => findings.push(Finding::info(format!("Terminal size: {}x{}", width, height))),
+        None => findings.push(Finding::warning("Cannot get the terminal size".to_owned(), None))
+    }
+    match env::var("TERM") {
+        Ok(ref term) if term != "dumb" => findings.push(Finding::info(format!("TERM={}", term))),
+        _ => findings.push(Finding::warning(
+            "TERM is not set, or set to a terminal without cursor movements".to_owned(),
+            Some("set TERM to match your terminal emulator (e.g. xterm-256color)".to_owned())))
+    }
+    findings
+}
+
+impl Finding {
+    fn info(message: String) -> Finding {
+        Finding { level: Level::Info, message, hint: None }
+    }
+
+    fn warning(message: String, hint: Option<String>) -> Finding {
+        Finding { level: Level::Warning, message, hint }
+    }
+
+    fn error(message: String, hint: Option<String>) -> Finding {
+        Finding { level: Level::Error, message, hint }
+    }
+
+    pub fn is_error(self: &Finding) -> bool {
+        matches!(self.level, Level::Error)
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let level = match self.level {
+            Level::Info => "ok",
+            Level::Warning => "warning",
+            Level::Error => "error"
+        };
+        write!(f, "[{}] {}", level, self.message)?;
+        if let Some(ref hint) = self.hint {
+            write!(f, "\n        -> {}", hint)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: Option<&str>, cmd: &str) -> CommandEntry {
+        CommandEntry {
+            id: id.map(str::to_owned),
+            cmd: cmd.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    fn keyworded(cmd: &str, keywords: &[&str]) -> CommandEntry {
+        CommandEntry { keywords: keywords.iter().map(|&kw| kw.to_owned()).collect(), ..entry(None, cmd) }
+    }
+
+    #[test]
+    fn finding_the_program() {
+        assert_eq!(program("du -sh /nix/store"), Some("du"));
+        assert_eq!(program("sudo LANG=C shutdown -h now"), Some("shutdown"));
+        assert_eq!(program("cd /tmp && ls"), None);
+        assert_eq!(program("{editor} file"), None);
+    }
+
+    #[test]
+    fn detecting_duplicate_ids() {
+        let findings = duplicate_ids(&[
+            entry(Some("deploy"), "make deploy"),
+            entry(None, "ls"),
+            entry(Some("deploy"), "make deploy-prod"),
+            entry(Some("build"), "make")]);
+
+        assert_eq!(findings.len(), 1);
+        assert!(findings[0].message.contains("`deploy`"));
+
+        let replaced = |cmd: &str, id: &str| CommandEntry { replaced_by: Some(id.to_owned()), ..entry(None, cmd) };
+        let findings = unknown_replacements(&[replaced("make old", "deploy"), replaced("make older", "gone"), entry(Some("deploy"), "make deploy")]);
+        assert_eq!(findings.len(), 1);
+        assert!(findings[0].message.contains("`gone`"));
+    }
+
+    #[test]
+    fn linting_the_commands() {
+        let entries = [
+            keyworded("make  deploy", &["make", "deploy"]),
+            keyworded("make deploy", &["deploy"]),
+            keyworded("ls", &[]),
+            keyworded("make deploy-prod", &["deploy", "make"]),
+            keyworded("make", &["make"])];
+        let locations: Vec<String> = (1..6).map(|line| format!("config.toml:{}", line * 5)).collect();
+
+        let findings = lint(&entries, &locations);
+        let messages: Vec<&str> = findings.iter().map(|finding| finding.message.as_str()).collect();
+        assert_eq!(messages, vec![
+            "`make deploy` (config.toml:10) is already defined at config.toml:5",
+            "`ls` (config.toml:15) has no keywords",
+            "`make deploy-prod` (config.toml:20) has the same keywords as `make  deploy` (config.toml:5)"]);
+        assert_eq!(findings[1].hint.as_deref(), Some("it is only listed until something is typed, give it keywords, e.g. `keywords = [\"ls\"]`"));
+        assert_eq!(findings[2].hint.as_deref(), Some("only the arrows tell them apart, give either another keyword, e.g. `deploy-prod`"));
+    }
+}
@@ -0,0 +1,96 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// Reading or writing that file
+    File { path: PathBuf, error: io::Error },
+    /// Setting the terminal up, e.g. in raw mode
+    Terminal(io::Error),
+    /// The line is known for syntax errors
+    ConfigParse { path: Option<PathBuf>, line: Option<usize>, message: String },
+    /// The column is that of the faulty brace, when there's one
+    PlaceholderSyntax { cmd: String, column: Option<usize>, message: String },
+    /// No value given to this placeholder
+    MissingValue(String),
+    UnknownId(String),
+    /// A malformed line of the standard input, or a malformed argument
+    InvalidInput(String),
+    /// Running this program failed
+    Process { program: String, message: String },
+    /// The configuration or the data directory
+    NoDirectory(&'static str),
+    Json(::serde_json::Error),
+    Toml(::toml::ser::Error),
+    Usage(::clap::Error)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "{}", e),
+            Error::File { ref path, ref error } => write!(f, "{}: {}", path.display(), error),
+            Error::Terminal(ref e) => write!(f, "Cannot set the terminal up: {}", e),
+            Error::ConfigParse { path: Some(ref path), ref message, .. } => write!(f, "{}: {}", path.display(), message),
+            Error::ConfigParse { path: None, ref message, .. } => write!(f, "{}", message),
+            Error::PlaceholderSyntax { ref cmd, column: Some(column), ref message } =>
+                write!(f, "`{}` at column {}: {}", cmd, column, message),
+            Error::PlaceholderSyntax { ref cmd, column: None, ref message } => write!(f, "`{}`: {}", cmd, message),
+            Error::MissingValue(ref name) => write!(f, "Missing a value for `{}`", name),
+            Error::UnknownId(ref id) => write!(f, "No command has the id `{}`", id),
+            Error::InvalidInput(ref message) => write!(f, "{}", message),
+            Error::Process { ref program, ref message } => write!(f, "{}: {}", program, message),
+            Error::NoDirectory(which) => write!(f, "Cannot locate the {} directory", which),
+            Error::Json(ref e) => write!(f, "{}", e),
+            Error::Toml(ref e) => write!(f, "{}", e),
+            Error::Usage(ref e) => write!(f, "{}", e.message)
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref e) | Error::File { error: ref e, .. } | Error::Terminal(ref e) => Some(e),
+            Error::Json(ref e) => Some(e),
+            Error::Toml(ref e) => Some(e),
+            Error::Usage(ref e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<::serde_json::Error> for Error {
+    fn from(e: ::serde_json::Error) -> Error {
+        Error::Json(e)
+    }
+}
+
+impl From<::toml::ser::Error> for Error {
+    fn from(e: ::toml::ser::Error) -> Error {
+        Error::Toml(e)
+    }
+}
+
+impl From<::toml::de::Error> for Error {
+    fn from(e: ::toml::de::Error) -> Error {
+        Error::ConfigParse { path: None, line: e.line_col().map(|(line, _)| line + 1), message: e.to_string() }
+    }
+}
+
+impl From<::clap::Error> for Error {
+    fn from(e: ::clap::Error) -> Error {
+        Error::Usage(e)
+    }
+}
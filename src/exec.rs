@@ -0,0 +1,217 @@
+use std::env;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+
+use error::{Error, Result};
+
+/// Run the command line with the user's shell, returning its exit status
+pub fn execute(cmd: &str) -> Result<i32> {
+    let status = process::Command::new(shell())
+        .arg("-c")
+        .arg(cmd)
+        .status()
+        .map_err(|e| Error::Process { program: shell(), message: format!("cannot run `{}`: {}", cmd, e) })?;
+
+    Ok(exit_code(status))
+}
+
+/// Run the command line with the user's shell, its output going through the `pipe` command line,
+/// returning the exit status of the command, or else of the pipe when the command succeeds
+pub fn execute_through(cmd: &str, pipe: &str) -> Result<i32> {
+    let spawn_error = |cmd: &str, e: ::std::io::Error| Error::Process { program: shell(), message: format!("cannot run `{}`: {}", cmd, e) };
+    let mut command = process::Command::new(shell())
+        .arg("-c")
+        .arg(cmd)
+        .stdout(process::Stdio::piped())
+        .spawn()
+        .map_err(|e| spawn_error(cmd, e))?;
+    let output = command.stdout.take().expect("the output is piped");
+    let filtered = process::Command::new(shell())
+        .arg("-c")
+        .arg(pipe)
+        .stdin(output)
+        .status();
+
+    let status = command.wait().map_err(|e| spawn_error(cmd, e))?;
+    let filtered = filtered.map_err(|e| spawn_error(pipe, e))?;
+    // killed by SIGPIPE when the pipe stops reading, as `head` does
+    let piped = status.success() || status.signal() == Some(::libc::SIGPIPE);
+    Ok(if piped { exit_code(filtered) } else { exit_code(status) })
+}
+
+/// Run the command line with the user's shell, printing its output as it comes, returning its exit status
+/// and the lines it printed; as its output is piped, it may print it otherwise, e.g. without colors
+pub fn execute_captured(cmd: &str) -> Result<(i32, Vec<String>)> {
+    let mut child = process::Command::new(shell())
+        .arg("-c")
+        .arg(cmd)
+        .stdout(process::Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::Process { program: shell(), message: format!("cannot run `{}`: {}", cmd, e) })?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut lines = Vec::new();
+    for line in BufReader::new(child.stdout.take().expect("the output is piped")).split(b'\n') {
+        let line = line?;
+        out.write_all(&line)?;
+        out.write_all(b"\n")?;
+        lines.push(String::from_utf8_lossy(&line).into_owned());
+    }
+    Ok((wait(&mut child)?, lines))
+}
+
+/// Run the command line with the user's shell, its output and errors piped, e.g. to stream them,
+/// in a process group of its own for `kill` to stop its pipeline as a whole
+pub fn spawn(cmd: &str) -> Result<process::Child> {
+    process::Command::new(shell())
+        .arg("-c")
+        .arg(cmd)
+        .process_group(0)
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::Process { program: shell(), message: format!("cannot run `{}`: {}", cmd, e) })
+}
+
+/// A spawned command, along with the commands of its pipeline, e.g. `kubectl logs -f web` in
+/// `kubectl logs -f web | jq .`, the shell running them not passing the signal on
+pub fn kill(child: &process::Child) {
+    unsafe { ::libc::killpg(child.id() as ::libc::pid_t, ::libc::SIGKILL) };
+}
+
+/// The exit status of a spawned command, once done
+pub fn wait(child: &mut process::Child) -> Result<i32> {
+    Ok(exit_code(child.wait()?))
+}
+
+/// The non-empty lines the command line prints, e.g. the values of a placeholder
+pub fn lines(cmd: &str) -> Result<Vec<String>> {
+    let output = process::Command::new(shell())
+        .arg("-c")
+        .arg(cmd)
+        .stdin(process::Stdio::null())
+        .output()
+        .map_err(|e| Error::Process { program: shell(), message: format!("cannot run `{}`: {}", cmd, e) })?;
+
+    if !output.status.success() {
+        return Err(Error::Process {
+            program: shell(),
+            message: format!("`{}` failed: {}", cmd, String::from_utf8_lossy(&output.stderr).trim())
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_owned)
+        .collect())
+}
+
+/// In the browser, with `open` on macOS and `xdg-open` elsewhere
+pub fn open_url(url: &str) -> Result<()> {
+    let program = if cfg!(target_os = "macos") { "open" } else { "xdg-open" };
+    stdout(process::Command::new(program).arg(url)).map(|_| ())
+}
+
+/// What the program is about: its tldr page, or else its `man -f` summary
+pub fn documentation(program: &str) -> Result<Vec<String>> {
+    let page = stdout(process::Command::new("tldr").arg(program).env("NO_COLOR", "1"))
+        .or_else(|_| stdout(process::Command::new("man").arg("-f").arg(program)))?;
+
+    Ok(page.lines()
+        .map(str::trim_end)
+        .filter(|line| !line.trim().is_empty())
+        .map(str::to_owned)
+        .collect())
+}
+
+/// What the program prints, provided it succeeds
+fn stdout(command: &mut process::Command) -> Result<String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command.stdin(process::Stdio::null()).output()
+        .map_err(|e| Error::Process { program: program.clone(), message: e.to_string() })?;
+
+    if !output.status.success() {
+        return Err(Error::Process { program, message: String::from_utf8_lossy(&output.stderr).trim().to_owned() });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// As the shell reads it back: between single quotes unless made of safe chars only,
+/// e.g. the arguments given after `--`, already split and unquoted by the shell
+pub fn quote(arg: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_=+.,/:@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        return arg.to_owned();
+    }
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// Fit to be part of a URL: every byte but the unreserved chars (`A-Z`, `a-z`, `0-9`, `-`, `.`, `_` and `~`) as `%XX`
+pub fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for &byte in value.as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+pub fn shell() -> String {
+    env::var("SHELL").ok().filter(|shell| !shell.is_empty()).unwrap_or_else(|| "sh".to_owned())
+}
+
+#[test]
+fn listing_lines() {
+    assert_eq!(lines("printf 'a\\n\\n b \\n'").unwrap(), vec!["a", "b"]);
+    assert!(lines("exit 1").is_err());
+}
+
+#[test]
+fn quoting_arguments() {
+    assert_eq!(quote("app=web"), "app=web");
+    assert_eq!(quote("app=web x; touch gotcha"), "'app=web x; touch gotcha'");
+    assert_eq!(quote(""), "''");
+    let args = ["-l", "app=web x;echo $HOME", "it's", "*"];
+    let quoted: Vec<String> = args.iter().map(|arg| quote(arg)).collect();
+    assert_eq!(lines(&format!("printf '%s\\n' {}", quoted.join(" "))).unwrap(), args);
+}
+
+#[test]
+fn percent_encoding() {
+    assert_eq!(percent_encode("web-1.prod_eu~"), "web-1.prod_eu~");
+    assert_eq!(percent_encode("a&b=c d/é"), "a%26b%3Dc%20d%2F%C3%A9");
+}
+
+#[test]
+fn killing_a_pipeline() {
+    let mut child = spawn("sleep 30 | sleep 30").unwrap();
+    let group = child.id() as ::libc::pid_t;
+    kill(&child);
+    assert_eq!(wait(&mut child).unwrap(), 128 + ::libc::SIGKILL);
+    let gone = (0..100).any(|_| {
+        ::std::thread::sleep(::std::time::Duration::from_millis(10));
+        (unsafe { ::libc::killpg(group, 0) }) != 0
+    });
+    assert!(gone);
+}
+
+#[test]
+fn executing_through_a_pipe() {
+    let path = env::temp_dir().join(format!("smart-runner-pipe-{}.txt", process::id()));
+    let pipe = format!("tr a-z A-Z > {}", path.display());
+    assert_eq!(execute_through("echo hello", &pipe).unwrap(), 0);
+    assert_eq!(::std::fs::read_to_string(&path).unwrap(), "HELLO\n");
+    assert_eq!(execute_through("echo hello; exit 3", &pipe).unwrap(), 3);
+    assert_eq!(execute_through("echo hello", "exit 4").unwrap(), 4);
+    ::std::fs::remove_file(&path).unwrap();
+}
+
+/// Like shells do, 128 + the signal number when the command was killed
+fn exit_code(status: process::ExitStatus) -> i32 {
+    status.code().or_else(|| status.signal().map(|signal| 128 + signal)).unwrap_or(1)
+}
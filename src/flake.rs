@@ -0,0 +1,88 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use command::{Command, Placeholders};
+use source::{self, CommandSource};
+use system;
+
+use error::Result;
+
+/// The apps and development shells of the `flake.nix` of a directory, as `nix run .#<app>` and
+/// `nix develop .#<shell>` (merely `nix run` and `nix develop` for the default ones),
+/// discovered with `nix flake show --json`
+pub struct Flake {
+    pub dir: PathBuf
+}
+
+impl CommandSource for Flake {
+    /// Nothing without a `flake.nix`, or without nix
+    fn load(self: &Flake) -> Result<Vec<Command>> {
+        if !self.dir.join("flake.nix").is_file() || system::find_executable("nix").is_none() {
+            return Ok(Vec::new());
+        }
+        let output = source::program_output("nix", &["flake", "show", "--json"], Some(&self.dir))?;
+        let show: ::serde_json::Value = ::serde_json::from_str(&output)?;
+        Ok(flake_commands(&show))
+    }
+
+    fn runs_commands(self: &Flake) -> bool {
+        true
+    }
+}
+
+fn flake_commands(show: &::serde_json::Value) -> Vec<Command> {
+    let mut commands = Vec::new();
+    for app in outputs(show, "apps") {
+        let (cmd, description) = if app == "default" {
+            ("nix run".to_owned(), "Run the app of the flake".to_owned())
+        } else {
+            (format!("nix run .#{}", app), format!("Run the {} app of the flake", app))
+        };
+        commands.push(Command {
+            cmd: Placeholders::literal(&cmd),
+            description: Some(description),
+            keywords: vec!["nix".to_owned(), "flake".to_owned(), "run".to_owned(), app],
+            ..Default::default()
+        });
+    }
+    for shell in outputs(show, "devShells") {
+        let (cmd, description) = if shell == "default" {
+            ("nix develop".to_owned(), "Enter the development shell of the flake".to_owned())
+        } else {
+            (format!("nix develop .#{}", shell), format!("Enter the {} development shell of the flake", shell))
+        };
+        commands.push(Command {
+            cmd: Placeholders::literal(&cmd),
+            description: Some(description),
+            keywords: vec!["nix".to_owned(), "flake".to_owned(), "develop".to_owned(), shell],
+            ..Default::default()
+        });
+    }
+    commands
+}
+
+/// The names of the outputs of that kind, for any system, e.g. `{"apps": {"x86_64-linux": {"default": {}}}}`
+fn outputs(show: &::serde_json::Value, kind: &str) -> BTreeSet<String> {
+    show.get(kind)
+        .and_then(|systems| systems.as_object())
+        .map_or(BTreeSet::new(), |systems| systems.values()
+            .filter_map(|outputs| outputs.as_object())
+            .flat_map(|outputs| outputs.keys().cloned())
+            .collect())
+}
+
+#[test]
+fn listing_apps_and_shells() {
+    let show = ::serde_json::from_str(r#"{
+        "apps": {"x86_64-linux": {"default": {"type": "app"}, "serve": {"type": "app"}}, "aarch64-darwin": {"serve": {}}},
+        "devShells": {"x86_64-linux": {"default": {}, "ci": {}}},
+        "packages": {"x86_64-linux": {"default": {}}}
+    }"#).unwrap();
+
+    assert_eq!(source::listed(&flake_commands(&show)), vec![
+        "nix run # Run the app of the flake",
+        "nix run .#serve # Run the serve app of the flake",
+        "nix develop .#ci # Enter the ci development shell of the flake",
+        "nix develop # Enter the development shell of the flake"
+    ]);
+}
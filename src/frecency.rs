@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use history::Record;
+
+/// Which of the records count, according to where they were made
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Bucket {
+    /// all of them
+    #[default]
+    Global,
+    /// those made in the current directory
+    Directory,
+    /// those made on the current host
+    Host
+}
+
+/// The `[frecency]` table of the configuration
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FrecencyConfig {
+    /// how long it takes for a use to count half as much, more than 0
+    #[serde(default = "default_half_life_days", deserialize_with = "half_life_days")]
+    pub half_life_days: f64,
+    #[serde(default)]
+    pub bucket: Bucket,
+    /// how much the keyword the input matches weighs against the frecency when listing the commands,
+    /// from 0 (the most frecent first) to 1 (the closest match first), see `Suggestion::from_input_in_order`
+    #[serde(default)]
+    pub relevance_weight: f64
+}
+
+/// Where the runner is started from
+#[derive(Debug, Clone, Default)]
+pub struct Context {
+    pub cwd: Option<String>,
+    pub host: Option<String>
+}
+
+/// The frecency of each template: every use counts 1, decaying exponentially with its age
+#[derive(Debug, Clone, Default)]
+pub struct Frecency {
+    scores: HashMap<String, f64>
+}
+
+impl Default for FrecencyConfig {
+    fn default() -> FrecencyConfig {
+        FrecencyConfig { half_life_days: default_half_life_days(), bucket: Bucket::default(), relevance_weight: 0.0 }
+    }
+}
+
+fn default_half_life_days() -> f64 {
+    7.0
+}
+
+/// With no half-life, the uses of right now would score NaN, and with a negative one the old uses would count more
+fn half_life_days<'de, D: ::serde::Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    use serde::Deserialize;
+    use serde::de::Error;
+
+    let days = f64::deserialize(deserializer)?;
+    if !days.is_finite() || days <= 0.0 {
+        return Err(D::Error::custom(format!("half_life_days must be a positive number of days, got {}", days)));
+    }
+    Ok(days)
+}
+
+impl Context {
+    pub fn current() -> Context {
+        Context {
+            cwd: ::std::env::current_dir().ok().map(|dir| dir.to_string_lossy().into_owned()),
+            host: ::system::hostname()
+        }
+    }
+
+    fn contains(self: &Context, record: &Record, bucket: Bucket) -> bool {
+        match bucket {
+            Bucket::Global    => true,
+            Bucket::Directory => self.cwd.is_some() && record.cwd == self.cwd,
+            Bucket::Host      => self.host.is_some() && record.host == self.host
+        }
+    }
+}
+
+impl Frecency {
+    /// `now` in seconds since the epoch, like the timestamps of the records
+    pub fn compute(records: &[Record], config: &FrecencyConfig, context: &Context, now: u64) -> Frecency {
+        let half_life = config.half_life_days * 24.0 * 3600.0;
+        let mut scores = HashMap::new();
+        for record in records.iter().filter(|record| context.contains(record, config.bucket)) {
+            let age = now.saturating_sub(record.timestamp) as f64;
+            *scores.entry(record.template.clone()).or_insert(0.0) += 0.5f64.powf(age / half_life);
+        }
+        Frecency { scores }
+    }
+
+    /// The timestamp of the last use of each template, to list the most recent first
+    pub fn last_uses(records: &[Record], config: &FrecencyConfig, context: &Context) -> Frecency {
+        let mut scores = HashMap::new();
+        for record in records.iter().filter(|record| context.contains(record, config.bucket)) {
+            let last = scores.entry(record.template.clone()).or_insert(0.0);
+            *last = f64::max(*last, record.timestamp as f64);
+        }
+        Frecency { scores }
+    }
+
+    /// How many times each template got used, however long ago
+    pub fn uses(records: &[Record], config: &FrecencyConfig, context: &Context) -> Frecency {
+        let mut scores = HashMap::new();
+        for record in records.iter().filter(|record| context.contains(record, config.bucket)) {
+            *scores.entry(record.template.clone()).or_insert(0.0) += 1.0;
+        }
+        Frecency { scores }
+    }
+
+    /// E.g. the timestamps of the last uses, to list the most recent first
+    pub fn from_scores(scores: HashMap<String, f64>) -> Frecency {
+        Frecency { scores }
+    }
+
+    /// 0 for the templates never used
+    pub fn score(self: &Frecency, template: &str) -> f64 {
+        self.scores.get(template).cloned().unwrap_or(0.0)
+    }
+
+    /// Highest first
+    pub fn ranked(self: &Frecency) -> Vec<(String, f64)> {
+        let mut ranked: Vec<(String, f64)> = self.scores.iter().map(|(t, &score)| (t.clone(), score)).collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        ranked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 24 * 3600;
+
+    fn record(template: &str, timestamp: u64, cwd: &str) -> Record {
+        Record {
+            timestamp,
+            template: template.to_owned(),
+            cmd: template.to_owned(),
+            cwd: Some(cwd.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn decaying_with_age() {
+        let records = vec![record("ls", 0, "/"), record("ls", 0, "/"), record("du", 14 * DAY, "/")];
+        let frecency = Frecency::compute(&records, &FrecencyConfig::default(), &Context::default(), 14 * DAY);
+
+        assert_eq!(frecency.score("ls"), 0.5);
+        assert_eq!(frecency.score("du"), 1.0);
+        assert_eq!(frecency.score("df"), 0.0);
+        assert_eq!(frecency.ranked()[0].0, "du");
+
+        let last_uses = Frecency::last_uses(&records, &FrecencyConfig::default(), &Context::default());
+        assert_eq!(last_uses.ranked()[0], ("du".to_owned(), (14 * DAY) as f64));
+        let uses = Frecency::uses(&records, &FrecencyConfig::default(), &Context::default());
+        assert_eq!(uses.ranked()[0], ("ls".to_owned(), 2.0));
+    }
+
+    #[test]
+    fn bucketing_by_directory() {
+        let records = vec![record("ls", 0, "/tmp"), record("du", 0, "/home")];
+        let config = FrecencyConfig { bucket: Bucket::Directory, ..Default::default() };
+        let context = Context { cwd: Some("/tmp".to_owned()), host: None };
+        let frecency = Frecency::compute(&records, &config, &context, 0);
+
+        assert_eq!(frecency.score("ls"), 1.0);
+        assert_eq!(frecency.score("du"), 0.0);
+    }
+}
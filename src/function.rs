@@ -0,0 +1,179 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+use system;
+
+/// How many chars `rand(N)` draws at most
+const MAX_RAND: usize = 1024;
+
+/// The chars `rand(N)` draws from
+const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+
+/// A built-in function a template calls, e.g. `touch /tmp/scratch-{rand(8)}`: it's not filled in,
+/// but evaluated once the command is picked
+#[derive(Clone, Copy, Hash, Eq, PartialEq, Debug)]
+pub enum Function {
+    /// `uuid()`, a random (version 4) UUID
+    Uuid,
+    /// `rand(N)`, N random lowercase letters and digits
+    Rand(usize),
+    /// `hostname()`, that of the machine, empty if unknown
+    Hostname,
+    /// `=4G/512`, an integer expression: `+`, `-`, `*`, `/`, `%` and parentheses, the `K`, `M`, `G` and `T`
+    /// suffixes multiplying by powers of 1024, e.g. for `dd bs=1M count={=4G/1M}`
+    Arithmetic(i64)
+}
+
+impl Function {
+    /// None unless it calls one of the functions, `{file(s)}` remaining a mere placeholder
+    pub fn parse(call: &str) -> Option<::std::result::Result<Function, String>> {
+        if let Some(expression) = call.strip_prefix('=') {
+            return Some(arithmetic(expression).map(Function::Arithmetic));
+        }
+        let open = call.find('(')?;
+        let name = &call[..open];
+        let arguments = call[open + 1..].strip_suffix(')')?.trim();
+        Some(match (name, arguments) {
+            ("uuid", "") => Ok(Function::Uuid),
+            ("hostname", "") => Ok(Function::Hostname),
+            ("uuid", _) | ("hostname", _) => Err(format!("`{}()` takes no arguments", name)),
+            ("rand", count) => match count.parse::<usize>() {
+                Ok(count) if count > 0 && count <= MAX_RAND => Ok(Function::Rand(count)),
+                _ => Err(format!("`rand(N)` draws from 1 to {} chars, got `{}`", MAX_RAND, count))
+            },
+            _ => return None
+        })
+    }
+
+    pub fn evaluate(self: Function) -> String {
+        match self {
+            Function::Uuid => {
+                let mut bytes = system::random_bytes(16);
+                bytes[6] = (bytes[6] & 0x0f) | 0x40;
+                bytes[8] = (bytes[8] & 0x3f) | 0x80;
+                let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+                format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+            },
+            Function::Rand(count) => {
+                let mut chars = String::with_capacity(count);
+                while chars.len() < count {
+                    // the bytes past the last multiple of the alphabet's length would favor its first chars
+                    let limit = 256 - 256 % ALPHABET.len();
+                    chars.extend(system::random_bytes(count).into_iter()
+                        .filter(|&byte| (byte as usize) < limit)
+                        .map(|byte| ALPHABET[byte as usize % ALPHABET.len()] as char)
+                        .take(count - chars.len()));
+                }
+                chars
+            },
+            Function::Hostname => system::hostname().unwrap_or_default(),
+            Function::Arithmetic(value) => value.to_string()
+        }
+    }
+}
+
+fn arithmetic(expression: &str) -> ::std::result::Result<i64, String> {
+    let mut chars = expression.chars().peekable();
+    let value = sum(&mut chars)?;
+    match next(&mut chars) {
+        None => Ok(value),
+        Some(c) => Err(format!("unexpected `{}` in `{}`", c, expression))
+    }
+}
+
+/// The next char but whitespace
+fn next(chars: &mut Peekable<Chars>) -> Option<char> {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    chars.next()
+}
+
+fn peek(chars: &mut Peekable<Chars>) -> Option<char> {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    chars.peek().cloned()
+}
+
+fn sum(chars: &mut Peekable<Chars>) -> ::std::result::Result<i64, String> {
+    let mut value = product(chars)?;
+    while let Some(op) = peek(chars).filter(|&c| c == '+' || c == '-') {
+        chars.next();
+        let operand = product(chars)?;
+        value = if op == '+' { value.checked_add(operand) } else { value.checked_sub(operand) }.ok_or_else(overflow)?;
+    }
+    Ok(value)
+}
+
+fn product(chars: &mut Peekable<Chars>) -> ::std::result::Result<i64, String> {
+    let mut value = factor(chars)?;
+    while let Some(op) = peek(chars).filter(|&c| c == '*' || c == '/' || c == '%') {
+        chars.next();
+        let operand = factor(chars)?;
+        if op != '*' && operand == 0 {
+            return Err("division by zero".to_owned());
+        }
+        value = match op {
+            '*' => value.checked_mul(operand),
+            '/' => value.checked_div(operand),
+            _ => value.checked_rem(operand)
+        }.ok_or_else(overflow)?;
+    }
+    Ok(value)
+}
+
+fn factor(chars: &mut Peekable<Chars>) -> ::std::result::Result<i64, String> {
+    match next(chars) {
+        Some('-') => factor(chars)?.checked_neg().ok_or_else(overflow),
+        Some('(') => {
+            let value = sum(chars)?;
+            match next(chars) {
+                Some(')') => Ok(value),
+                _ => Err("unclosed parenthesis".to_owned())
+            }
+        },
+        Some(digit) if digit.is_ascii_digit() => {
+            let mut digits = digit.to_string();
+            while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                digits.push(digit);
+            }
+            let value: i64 = digits.parse().map_err(|_| overflow())?;
+            let unit = match chars.peek().map(char::to_ascii_uppercase) {
+                Some('K') => 1 << 10,
+                Some('M') => 1 << 20,
+                Some('G') => 1 << 30,
+                Some('T') => 1 << 40,
+                _ => return Ok(value)
+            };
+            chars.next();
+            value.checked_mul(unit).ok_or_else(overflow)
+        },
+        Some(c) => Err(format!("expecting a number, got `{}`", c)),
+        None => Err("expecting a number".to_owned())
+    }
+}
+
+fn overflow() -> String {
+    "out of the range of 64-bit integers".to_owned()
+}
+
+#[test]
+fn calling_functions() {
+    assert_eq!(Function::parse("uuid()"), Some(Ok(Function::Uuid)));
+    assert_eq!(Function::parse("rand( 8 )"), Some(Ok(Function::Rand(8))));
+    assert!(Function::parse("rand(0)").unwrap().is_err());
+    assert!(Function::parse("hostname(x)").unwrap().is_err());
+    assert_eq!(Function::parse("file(s)"), None);
+    assert_eq!(Function::parse("uuid"), None);
+    assert_eq!(Function::parse("=2*1024"), Some(Ok(Function::Arithmetic(2048))));
+    assert_eq!(Function::parse("= 4G / (1024 - 1k/2) + 10 % 3"), Some(Ok(Function::Arithmetic(8 * 1024 * 1024 + 1))));
+    assert_eq!(Function::parse("=-2m"), Some(Ok(Function::Arithmetic(-2 * 1024 * 1024))));
+    for invalid in &["=", "=1/0", "=(1", "=2x", "=8T*8T*8T", "=1 2"] {
+        assert!(Function::parse(invalid).unwrap().is_err(), "{}", invalid);
+    }
+    assert_eq!(Function::Arithmetic(2048).evaluate(), "2048");
+
+    let uuid = Function::Uuid.evaluate();
+    assert_eq!(uuid.len(), 36);
+    assert_eq!((&uuid[14..15], uuid.matches('-').count()), ("4", 4));
+    assert_ne!(uuid, Function::Uuid.evaluate());
+    let rand = Function::Rand(40).evaluate();
+    assert!(rand.len() == 40 && rand.bytes().all(|byte| ALPHABET.contains(&byte)));
+}
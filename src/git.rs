@@ -0,0 +1,55 @@
+use std::process;
+
+use command::Command;
+use source::{self, CommandSource};
+use system;
+
+use error::{Error, Result};
+
+/// The git aliases, as `git <alias>` described by what they stand for
+pub struct GitAliases;
+
+impl CommandSource for GitAliases {
+    /// Nothing without git
+    fn load(self: &GitAliases) -> Result<Vec<Command>> {
+        if system::find_executable("git").is_none() {
+            return Ok(Vec::new());
+        }
+        let output = process::Command::new("git")
+            .args(["config", "--get-regexp", "^alias\\."])
+            .stdin(process::Stdio::null())
+            .output()
+            .map_err(|e| Error::Process { program: "git".to_owned(), message: e.to_string() })?;
+
+        // exits with status 1 when there's no alias
+        Ok(commands(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    fn runs_commands(self: &GitAliases) -> bool {
+        true
+    }
+}
+
+/// `git <alias>` described by what it stands for
+fn commands(output: &str) -> Vec<Command> {
+    source::named_commands("git", aliases(output).into_iter().map(|(alias, expansion)| (alias, Some(expansion))))
+}
+
+/// Lines such as `alias.lg log --graph --oneline`
+fn aliases(output: &str) -> Vec<(String, String)> {
+    output.lines()
+        .filter_map(|line| line.strip_prefix("alias."))
+        .map(|line| match line.find(' ') {
+            Some(idx) => (line[..idx].to_owned(), line[idx + 1..].trim().to_owned()),
+            None => (line.to_owned(), String::new())
+        })
+        .collect()
+}
+
+#[test]
+fn parsing_aliases() {
+    assert_eq!(source::listed(&commands("alias.lg log --graph --oneline\nalias.st status\n")), vec![
+        "git lg # log --graph --oneline",
+        "git st # status"
+    ]);
+}
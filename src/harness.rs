@@ -0,0 +1,121 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use command::Selection;
+use error::Result;
+use keys::parse_keys;
+use render::{Frame, HeadlessRenderer};
+use runner::RunnerBuilder;
+use state::Effect;
+
+/// How a key script ended
+#[derive(Debug)]
+pub struct Simulation {
+    /// false when the keys ran out before a command got picked or the runner got canceled
+    pub done: bool,
+    pub selection: Option<Selection>,
+    /// What was last drawn
+    pub frame: Frame
+}
+
+/// Type the script in, e.g. `nix se<Tab><Enter>` (see `keys::parse_keys`), against a headless renderer
+pub fn simulate(builder: RunnerBuilder, script: &str) -> Result<Simulation> {
+    let mut runner = builder.build_with_renderer(HeadlessRenderer::default())?;
+    let effect = runner.simulate(parse_keys(script)?)?;
+    let frame = runner.renderer().frame.clone();
+
+    Ok(match effect {
+        Effect::Done(selection) => Simulation { done: true, selection, frame },
+        Effect::Continue => Simulation { done: false, selection: None, frame }
+    })
+}
+
+/// Compare a snapshot (see `Frame::snapshot`) with the golden file at that path, which gets written
+/// instead when `UPDATE_SNAPSHOTS` is set, e.g. after changing the layout on purpose: a missing one fails
+pub fn assert_snapshot(path: &Path, snapshot: &str) {
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).unwrap();
+        }
+        fs::write(path, format!("{}\n", snapshot)).unwrap();
+        return;
+    }
+    let golden = fs::read_to_string(path).unwrap_or_else(|e| {
+        panic!("Cannot read {} ({}), run the tests with UPDATE_SNAPSHOTS=1 to write it:\n{}", path.display(), e, snapshot)
+    });
+    assert!(golden.trim_end_matches('\n') == snapshot,
+            "{} differs, run the tests with UPDATE_SNAPSHOTS=1 if that's intended:\n{}\n--- expected:\n{}",
+            path.display(), snapshot, golden);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use command::{Command, Commands, Placeholders};
+    use runner::{Action, Keybindings};
+    use termion::event::Key;
+
+    fn builder() -> RunnerBuilder {
+        let command = |cmd: &str, description: &str, keywords: &[&str]| Command {
+            cmd: Placeholders::parse(cmd).unwrap(),
+            description: Some(description.to_owned()),
+            keywords: keywords.iter().map(|kw| kw.to_string()).collect(),
+            ..Default::default()
+        };
+        RunnerBuilder::new(Commands::new(vec![
+            command("nix-env -q '.*{name}.*'", "Search a package", &["nix", "search", "package"]),
+            command("du -sh /nix/store", "Size of the store", &["nix", "store", "size"]),
+            command("sudo shutdown -h now", "Shut down", &["hardware", "shutdown"])
+        ]))
+    }
+
+    #[test]
+    fn picking_then_filling_in() {
+        let simulation = simulate(builder(), "nix se<Tab><Enter>hello<Enter>").unwrap();
+        assert!(simulation.done);
+        assert_eq!(simulation.selection.unwrap().interpolated(), "nix-env -q '.*hello.*'");
+    }
+
+    #[test]
+    fn showing_the_matching_commands() {
+        let simulation = simulate(builder(), "nix <Down>").unwrap();
+        assert!(!simulation.done);
+        assert_eq!(simulation.frame.prompt.text(), "> ¹nix ");
+        assert_eq!(simulation.frame.text().lines().skip(2).collect::<Vec<_>>(), vec![
+            "  du -sh /nix/store Size of the store",
+            "* nix-env -q '.*{name}.*' Search a package"
+        ]);
+    }
+
+    #[test]
+    fn snapshots_of_the_screen() {
+        let golden = |name: &str| Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("snapshots").join(format!("{}.txt", name));
+        let many = (1..=30).map(|i| Command {
+            cmd: Placeholders::parse(&format!("echo {} and a rather long line going past the width of the screen", i)).unwrap(),
+            keywords: vec!["echo".to_owned()],
+            ..Default::default()
+        }).collect();
+
+        let cases = vec![
+            ("empty", builder(), ""),
+            ("filtered", builder(), "sto"),
+            ("chips", builder().hint(vec!["A hint below the commands".to_owned()]), "nix <Down>"),
+            ("fill_in", builder(), "nix se<Tab><Enter>hel"),
+            ("overflow", RunnerBuilder::new(Commands::new(many)), "echo <Up><Up><Up>")
+        ];
+        for (name, builder, script) in cases {
+            let frame = simulate(builder, script).unwrap().frame;
+            assert_snapshot(&golden(name), &frame.snapshot(40, 10));
+        }
+    }
+
+    #[test]
+    fn rebinding_keys() {
+        let keybindings = Keybindings::default().unbind(Key::Char('q')).bind(Key::Esc, Action::Cancel);
+        let simulation = simulate(builder().keybindings(keybindings), "q<Esc>").unwrap();
+        assert!(simulation.done);
+        assert!(simulation.selection.is_none());
+        assert_eq!(simulation.frame.prompt.text(), "> q");
+    }
+}
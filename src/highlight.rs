@@ -0,0 +1,147 @@
+use render::{Span, Style};
+
+/// The chars ending a word after which a program is run: pipes, lists, subshells
+const OPERATORS: &[char] = &['|', '&', ';', '(', ')', '`'];
+
+/// Tells the programs, the flags, the strings and the placeholders of a command line apart, to color them.
+/// It's no shell parser: the first word after an operator is taken for a program, words starting
+/// with a `-` for flags. Placeholders are `{name}` (or `{{name}}`, as in the tldr pages) unless
+/// `placeholders` is false, e.g. for a command already interpolated, its braces then being mere text.
+pub fn spans(line: &str, placeholders: bool) -> Vec<Span> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut spans = Vec::new();
+    // whether the next word runs a program
+    let mut program = true;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            let end = run(&chars, i, char::is_whitespace);
+            push(&mut spans, &chars[i..end], Style::Normal);
+            i = end;
+        } else if OPERATORS.contains(&c) {
+            push(&mut spans, &chars[i..=i], Style::Normal);
+            program = true;
+            i += 1;
+        } else if c == '<' || c == '>' {
+            // `2>&1` redirects, its `&` running nothing
+            let end = run(&chars, i, |c| c == '<' || c == '>' || c == '&');
+            push(&mut spans, &chars[i..end], Style::Normal);
+            i = end;
+        } else if c == '#' {
+            push(&mut spans, &chars[i..], Style::Description);
+            i = chars.len();
+        } else if c == '\'' || c == '"' {
+            let end = closing_quote(&chars, i);
+            with_placeholders(&mut spans, &chars[i..end], placeholders, Style::Quoted);
+            program = false;
+            i = end;
+        } else if c == '{' && placeholders {
+            let end = closing_braces(&chars, i);
+            push(&mut spans, &chars[i..end], Style::Placeholder);
+            program = false;
+            i = end;
+        } else {
+            let end = run(&chars, i, |c| !(c.is_whitespace() || OPERATORS.contains(&c) || "<>'\"".contains(c) || (c == '{' && placeholders)));
+            let word = &chars[i..end];
+            let style = if c == '-' {
+                Style::Flag
+            } else if program && !word.contains(&'=') {
+                program = false;
+                Style::Program
+            } else {
+                Style::Normal
+            };
+            push(&mut spans, word, style);
+            i = end;
+        }
+    }
+    spans
+}
+
+/// A URL, which runs nothing: only its placeholders stand out
+pub fn url(line: &str, placeholders: bool) -> Vec<Span> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut spans = Vec::new();
+    with_placeholders(&mut spans, &chars, placeholders, Style::Normal);
+    spans
+}
+
+/// A string or a URL, its placeholders standing out
+fn with_placeholders(spans: &mut Vec<Span>, chars: &[char], placeholders: bool, style: Style) {
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == '{' && placeholders {
+            let end = closing_braces(chars, i);
+            push(spans, &chars[i..end], Style::Placeholder);
+            i = end;
+        } else {
+            let end = run(chars, i, |c| c != '{' || !placeholders);
+            push(spans, &chars[i..end], style);
+            i = end;
+        }
+    }
+}
+
+/// The end of the chars from `start` matching
+fn run<F: Fn(char) -> bool>(chars: &[char], start: usize, matching: F) -> usize {
+    chars[start..].iter().position(|&c| !matching(c)).map_or(chars.len(), |n| start + n)
+}
+
+/// Past the quote closing the one at `start`, or the end of an unclosed string
+fn closing_quote(chars: &[char], start: usize) -> usize {
+    let quote = chars[start];
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if quote == '"' => i += 2,
+            c if c == quote => return i + 1,
+            _ => i += 1
+        }
+    }
+    chars.len()
+}
+
+/// Past as many `}` as there are `{` at `start`
+fn closing_braces(chars: &[char], start: usize) -> usize {
+    let opening = run(chars, start, |c| c == '{') - start;
+    let name_end = run(chars, start + opening, |c| c != '}');
+    let closing = run(chars, name_end, |c| c == '}');
+    closing.min(name_end + opening)
+}
+
+/// Merged with the previous span if it has the same style
+fn push(spans: &mut Vec<Span>, chars: &[char], style: Style) {
+    let text: String = chars.iter().collect();
+    match spans.last_mut() {
+        Some(ref mut last) if last.style == style => last.text.push_str(&text),
+        _ => spans.push(Span::new(text, style))
+    }
+}
+
+#[test]
+fn highlighting_commands() {
+    let styles = |line: &str, placeholders: bool| spans(line, placeholders).into_iter()
+        .map(|span| (span.text, span.style))
+        .collect::<Vec<_>>();
+    let s = |text: &str, style: Style| (text.to_owned(), style);
+
+    assert_eq!(styles("git log --oneline | grep -i '{pattern}' >{file} # recent", true), vec![
+        s("git", Style::Program), s(" log ", Style::Normal), s("--oneline", Style::Flag), s(" | ", Style::Normal),
+        s("grep", Style::Program), s(" ", Style::Normal), s("-i", Style::Flag), s(" ", Style::Normal),
+        s("'", Style::Quoted), s("{pattern}", Style::Placeholder), s("'", Style::Quoted), s(" >", Style::Normal),
+        s("{file}", Style::Placeholder), s(" ", Style::Normal), s("# recent", Style::Description)
+    ]);
+    assert_eq!(styles("LC_ALL=C awk \"{print \\\"$1\\\"}\" 2>&1", false), vec![
+        s("LC_ALL=C ", Style::Normal), s("awk", Style::Program), s(" ", Style::Normal),
+        s("\"{print \\\"$1\\\"}\"", Style::Quoted), s(" 2>&1", Style::Normal)
+    ]);
+    assert_eq!(styles("cp {{path/to/file}} $(pwd)", true), vec![
+        s("cp", Style::Program), s(" ", Style::Normal), s("{{path/to/file}}", Style::Placeholder),
+        s(" $(", Style::Normal), s("pwd", Style::Program), s(")", Style::Normal)
+    ]);
+    assert_eq!(url("https://grafana/d/{board}?from=now-1h&to=now", true).into_iter().map(|span| (span.text, span.style)).collect::<Vec<_>>(), vec![
+        s("https://grafana/d/", Style::Normal), s("{board}", Style::Placeholder), s("?from=now-1h&to=now", Style::Normal)
+    ]);
+}
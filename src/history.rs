@@ -0,0 +1,411 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use itertools::Itertools;
+use regex::Regex;
+
+use command::{Command, Commands, Placeholders, Selection};
+use frecency::{Context, Frecency};
+use system;
+
+use error::{Error, Result};
+
+/// An append-only log of the selected and executed commands, one JSON record per line,
+/// by default `~/.local/share/smart-runner/history.jsonl`
+pub struct History {
+    pub path: PathBuf
+}
+
+/// One line of the history
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Record {
+    /// seconds since the epoch
+    pub timestamp: u64,
+    #[serde(default)]
+    pub kind: Kind,
+    pub template: String,
+    pub cmd: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// the validated keywords that led to the command
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fill_in_ms: Option<u64>,
+    /// the exit status of the executed commands
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<i32>,
+    /// the working directory and the host, to tell the contexts apart
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>
+}
+
+/// Past that size, recording compacts the history down to half of it, dropping the oldest records
+pub const MAX_SIZE: u64 = 4 * 1024 * 1024;
+
+/// The `[history]` table of the configuration
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct HistoryConfig {
+    /// Regular expressions: the commands whose template or interpolated command match are neither
+    /// recorded nor their values remembered, e.g. `["^vault ", "(?i)token=\\S+"]`
+    #[serde(default)]
+    pub ignore: Vec<String>
+}
+
+/// What must stay out of the history, see `HistoryConfig::ignore`
+#[derive(Debug, Default)]
+pub struct IgnoreRules {
+    patterns: Vec<Regex>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    /// Picked, then printed for the shell to run it
+    #[default]
+    Selected,
+    /// Run by smart-runner itself
+    Executed
+}
+
+impl Record {
+    pub fn new(selection: &Selection, keywords: Vec<String>) -> Record {
+        let context = Context::current();
+        Record {
+            timestamp: now(),
+            kind: Kind::Selected,
+            template: selection.command.cmd.to_string(),
+            cmd: selection.interpolated(),
+            id: selection.command.id.clone(),
+            keywords,
+            fill_in_ms: selection.fill_in_time.map(|time| time.as_millis() as u64),
+            status: None,
+            cwd: context.cwd,
+            host: context.host
+        }
+    }
+
+    pub fn executed(selection: &Selection, status: i32) -> Record {
+        Record { kind: Kind::Executed, status: Some(status), ..Record::new(selection, Vec::new()) }
+    }
+}
+
+impl HistoryConfig {
+    pub fn ignore_rules(self: &HistoryConfig) -> Result<IgnoreRules> {
+        let patterns = self.ignore.iter()
+            .map(|pattern| Regex::new(pattern).map_err(|e| Error::ConfigParse {
+                path: None,
+                line: None,
+                message: format!("invalid history ignore pattern `{}`: {}", pattern, e)
+            }))
+            .collect::<Result<Vec<Regex>>>()?;
+        Ok(IgnoreRules { patterns })
+    }
+}
+
+impl IgnoreRules {
+    pub fn allows(self: &IgnoreRules, selection: &Selection) -> bool {
+        let cmd = selection.interpolated();
+        !self.patterns.iter().any(|pattern| pattern.is_match(selection.command.cmd.template()) || pattern.is_match(&cmd))
+    }
+}
+
+impl History {
+    /// Its failures are left out by the callers: the history orders and recalls the commands,
+    /// which get listed and run all the same without it
+    pub fn open() -> Result<History> {
+        ::dirs::data_dir()
+            .map(|dir| History { path: dir.join("smart-runner").join("history.jsonl") })
+            .ok_or(Error::NoDirectory("data"))
+    }
+
+    pub fn record(self: &History, record: &Record) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let _lock = self.lock()?;
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{}", ::serde_json::to_string(record)?)?;
+
+        if file.metadata()?.len() > MAX_SIZE {
+            self.compact_locked(MAX_SIZE / 2)?;
+        }
+        Ok(())
+    }
+
+    /// Drop the records older than that many seconds, returning how many got dropped
+    pub fn prune(self: &History, older_than: u64) -> Result<usize> {
+        let _lock = self.lock()?;
+        let records = self.records()?;
+        let oldest = now().saturating_sub(older_than);
+        let kept: Vec<Record> = records.iter().filter(|record| record.timestamp >= oldest).cloned().collect();
+        self.rewrite(&kept)?;
+        Ok(records.len() - kept.len())
+    }
+
+    /// Drop the oldest records until the history fits in that many bytes
+    pub fn compact(self: &History, max_size: u64) -> Result<()> {
+        let _lock = self.lock()?;
+        self.compact_locked(max_size)
+    }
+
+    /// Held while the history gets appended to or rewritten, for the records of other pickers not to get lost
+    /// between reading the history and renaming the rewritten one over it
+    fn lock(self: &History) -> Result<File> {
+        let path = self.path.with_extension("jsonl.lock");
+        system::lock(&path).map_err(|error| Error::File { path, error })
+    }
+
+    fn compact_locked(self: &History, max_size: u64) -> Result<()> {
+        let mut lines = Vec::new();
+        let mut size = 0;
+        for record in self.records()?.iter().rev() {
+            let line = ::serde_json::to_string(record)?;
+            size += line.len() as u64 + 1;
+            if size > max_size {
+                break;
+            }
+            lines.push(line);
+        }
+        lines.reverse();
+        self.write_lines(&lines)
+    }
+
+    /// The lines that cannot be parsed are dropped along the way
+    fn rewrite(self: &History, records: &[Record]) -> Result<()> {
+        let lines = records.iter().map(::serde_json::to_string).collect::<::std::result::Result<Vec<String>, _>>()?;
+        self.write_lines(&lines)
+    }
+
+    /// Through a temporary file, not to lose the history if interrupted
+    fn write_lines(self: &History, lines: &[String]) -> Result<()> {
+        let tmp = self.path.with_extension("jsonl.tmp");
+        let mut file = File::create(&tmp).map_err(|error| Error::File { path: tmp.clone(), error })?;
+        for line in lines {
+            writeln!(file, "{}", line)?;
+        }
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    /// Oldest first, skipping the lines that cannot be parsed
+    pub fn records(self: &History) -> Result<Vec<Record>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut records = Vec::new();
+        let file = File::open(&self.path).map_err(|error| Error::File { path: self.path.clone(), error })?;
+        for line in BufReader::new(file).lines() {
+            if let Ok(record) = ::serde_json::from_str(&line?) {
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+}
+
+/// How many executions of each command are looked at, the older failures having likely been fixed since
+pub const RECENT_RUNS: usize = 10;
+
+/// How the last executions of a command went, see `outcomes`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Outcomes {
+    pub runs: usize,
+    pub failures: usize,
+    /// the exit status of the latest failure
+    pub last_failure: Option<i32>
+}
+
+/// Those of the last `RECENT_RUNS` executions of each template, interrupted ones (status 130, e.g. Ctrl-C
+/// stopping `kubectl logs -f`) not counting
+pub fn outcomes(records: &[Record]) -> HashMap<String, Outcomes> {
+    let mut statuses: HashMap<&str, Vec<i32>> = HashMap::new();
+    for record in records {
+        if let Some(status) = record.status.filter(|&status| status != INTERRUPTED) {
+            statuses.entry(&record.template).or_default().push(status);
+        }
+    }
+    statuses.into_iter().map(|(template, statuses)| {
+        let recent = &statuses[statuses.len().saturating_sub(RECENT_RUNS)..];
+        (template.to_owned(), Outcomes {
+            runs: recent.len(),
+            failures: recent.iter().filter(|&&status| status != 0).count(),
+            last_failure: recent.iter().rev().find(|&&status| status != 0).cloned()
+        })
+    }).collect()
+}
+
+/// 128 + SIGINT
+const INTERRUPTED: i32 = 130;
+
+/// The commands as they were interpolated, once each, the most recent first, the words being their keywords
+pub fn commands(records: &[Record]) -> Commands {
+    let mut last_used: HashMap<&str, u64> = HashMap::new();
+    let mut uses: HashMap<String, f64> = HashMap::new();
+    for record in records {
+        let timestamp = last_used.entry(&record.cmd).or_insert(0);
+        *timestamp = (*timestamp).max(record.timestamp);
+        *uses.entry(record.cmd.clone()).or_insert(0.0) += 1.0;
+    }
+
+    let commands = last_used.keys().map(|cmd| Command {
+        cmd: Placeholders::literal(cmd),
+        keywords: cmd.split_whitespace().unique().map(str::to_owned).collect(),
+        ..Default::default()
+    }).collect();
+    let mut commands = Commands::new(commands);
+    commands.frecency = Frecency::from_scores(last_used.into_iter()
+        .map(|(cmd, timestamp)| (cmd.to_owned(), timestamp as f64))
+        .collect());
+    commands.recency = commands.frecency.clone();
+    commands.frequency = Frecency::from_scores(uses);
+    commands
+}
+
+/// An age such as `90d`, in seconds: `s`, `m`, `h`, `d` or `w`
+pub fn parse_age(age: &str) -> Result<u64> {
+    let invalid = || Error::InvalidInput(format!("Expecting an age such as `90d` or `12h`, got `{}`", age));
+    let (count, unit) = age.split_at(age.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?);
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 24 * 3600,
+        "w" => 7 * 24 * 3600,
+        _ => return Err(invalid())
+    };
+    count.parse::<u64>().ok().and_then(|count| count.checked_mul(seconds)).ok_or_else(invalid)
+}
+
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// The seconds since the epoch, whether selected or executed, the exit status if run, and the command,
+/// tab separated
+pub fn print<W: Write>(out: &mut W, records: &[Record]) -> Result<()> {
+    for record in records {
+        let kind = match record.kind {
+            Kind::Selected => "selected",
+            Kind::Executed => "executed"
+        };
+        let status = record.status.map_or(String::new(), |status| status.to_string());
+        writeln!(out, "{}\t{}\t{}\t{}", record.timestamp, kind, status, record.cmd)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn ignoring_commands() {
+    let rules = HistoryConfig { ignore: vec!["^vault ".to_owned(), "(?i)token=\\S+".to_owned()] }.ignore_rules().unwrap();
+    let selection = |cmd: &str, values: &[&str]| Selection {
+        values: values.iter().map(|value| value.to_string()).collect(),
+        ..Selection::new(::std::sync::Arc::new(Command { cmd: Placeholders::parse(cmd).unwrap(), ..Default::default() }))
+    };
+
+    assert!(rules.allows(&selection("curl {url}", &["example.com"])));
+    assert!(!rules.allows(&selection("vault read {path}", &["secret/db"])));
+    assert!(!rules.allows(&selection("curl {url}", &["example.com?TOKEN=abc"])));
+    assert!(HistoryConfig { ignore: vec!["(".to_owned()] }.ignore_rules().is_err());
+}
+
+#[test]
+fn parsing_ages() {
+    assert_eq!(parse_age("90d").unwrap(), 90 * 24 * 3600);
+    assert_eq!(parse_age("30s").unwrap(), 30);
+    assert!(parse_age("90").is_err());
+    assert!(parse_age("d").is_err());
+    assert!(parse_age("3y").is_err());
+    assert!(parse_age(&format!("{}w", u64::MAX / 2)).is_err());
+}
+
+#[test]
+fn pruning_and_compacting() {
+    let history = History { path: ::std::env::temp_dir().join(format!("smart-runner-prune-{}.jsonl", ::std::process::id())) };
+    let record = |timestamp: u64| Record { timestamp, template: "ls".to_owned(), cmd: "ls".to_owned(), ..Default::default() };
+    for timestamp in &[1, now() - 10, now()] {
+        history.record(&record(*timestamp)).unwrap();
+    }
+
+    let pruned = history.prune(3600);
+    let size = fs::metadata(&history.path).unwrap().len();
+    let compacted = history.compact(size - 1).and_then(|_| history.records());
+    fs::remove_file(&history.path).unwrap();
+    fs::remove_file(history.path.with_extension("jsonl.lock")).unwrap();
+
+    assert_eq!(pruned.unwrap(), 1);
+    assert_eq!(compacted.unwrap().len(), 1);
+}
+
+#[test]
+fn recording_while_compacting() {
+    let path = ::std::env::temp_dir().join(format!("smart-runner-concurrent-{}.jsonl", ::std::process::id()));
+    let record = Record { timestamp: 1, template: "ls".to_owned(), cmd: "ls".to_owned(), ..Default::default() };
+    let threads: Vec<_> = (0..4).map(|i| {
+        let (path, record) = (path.clone(), record.clone());
+        ::std::thread::spawn(move || for _ in 0..50 {
+            let history = History { path: path.clone() };
+            if i == 0 {
+                history.compact(u64::MAX).unwrap();
+            } else {
+                history.record(&record).unwrap();
+            }
+        })
+    }).collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    let records = History { path: path.clone() }.records();
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(path.with_extension("jsonl.lock")).unwrap();
+    assert_eq!(records.unwrap().len(), 150);
+}
+
+#[test]
+fn recording_then_reading() {
+    let history = History { path: ::std::env::temp_dir().join(format!("smart-runner-history-{}.jsonl", ::std::process::id())) };
+    fs::write(&history.path, "{\"timestamp\":1,\"template\":\"ls\",\"cmd\":\"ls\"}\nnot json\n").unwrap();
+
+    history.record(&Record { timestamp: 2, kind: Kind::Executed, template: "ls".to_owned(), cmd: "ls".to_owned(),
+                             status: Some(0), ..Default::default() }).unwrap();
+    let records = history.records();
+    fs::remove_file(&history.path).unwrap();
+    fs::remove_file(history.path.with_extension("jsonl.lock")).unwrap();
+
+    let records = records.unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].kind, Kind::Selected);
+    assert_eq!(records[1].status, Some(0));
+}
+
+#[test]
+fn tracking_the_outcomes() {
+    let executed = |template: &str, status: i32| Record {
+        kind: Kind::Executed, template: template.to_owned(), cmd: template.to_owned(), status: Some(status), ..Default::default()
+    };
+    let mut records = vec![executed("ssh old-host", 255), Record { template: "ls".to_owned(), ..Default::default() }];
+    records.extend((0..RECENT_RUNS).map(|i| executed("ssh old-host", if i % 2 == 0 { 0 } else { 255 })));
+    records.extend(vec![executed("make", 2), executed("make", 0), executed("kubectl logs -f web", 130)]);
+
+    let outcomes = outcomes(&records);
+    assert_eq!(outcomes["ssh old-host"], Outcomes { runs: RECENT_RUNS, failures: RECENT_RUNS / 2, last_failure: Some(255) });
+    assert_eq!(outcomes["make"], Outcomes { runs: 2, failures: 1, last_failure: Some(2) });
+    assert!(!outcomes.contains_key("ls") && !outcomes.contains_key("kubectl logs -f web"));
+}
+
+#[test]
+fn printing_records() {
+    let records = vec![
+        Record { timestamp: 1, template: "ls {dir}".to_owned(), cmd: "ls /tmp".to_owned(), ..Default::default() },
+        Record { timestamp: 2, kind: Kind::Executed, status: Some(1), template: "false".to_owned(), cmd: "false".to_owned(), ..Default::default() }
+    ];
+    let mut out = Vec::new();
+    print(&mut out, &records).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "1\tselected\t\tls /tmp\n2\texecuted\t1\tfalse\n");
+}
@@ -0,0 +1,134 @@
+use std::path::PathBuf;
+
+use command::{Command, Placeholders};
+use source::{self, CommandSource};
+
+use error::Result;
+
+const NAMES: &[&str] = &["justfile", "Justfile", ".justfile"];
+
+/// The public recipes of the justfile of a directory, as `just <recipe> {param}...`,
+/// described by the comment right above them
+pub struct Justfile {
+    pub dir: PathBuf
+}
+
+#[derive(Debug, PartialEq)]
+struct Recipe {
+    name: String,
+    params: Vec<String>,
+    doc: Option<String>
+}
+
+impl CommandSource for Justfile {
+    /// Nothing without a justfile
+    fn load(self: &Justfile) -> Result<Vec<Command>> {
+        let content = match source::read_first(&self.dir, NAMES)? {
+            Some((_, content)) => content,
+            None => return Ok(Vec::new())
+        };
+        recipes(&content).into_iter()
+            .map(|recipe| {
+                let params: String = recipe.params.iter().map(|param| format!(" {{{}}}", param)).collect();
+                Ok(Command {
+                    cmd: Placeholders::parse(&format!("just {}{}", recipe.name, params))?,
+                    description: recipe.doc,
+                    keywords: vec!["just".to_owned(), recipe.name],
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+}
+
+fn recipes(content: &str) -> Vec<Recipe> {
+    let mut recipes = Vec::new();
+    let mut doc: Option<String> = None;
+
+    for line in content.lines() {
+        if line.starts_with([' ', '\t']) || line.trim().is_empty() {
+            doc = None; // a recipe body, or a blank line between a comment and a recipe
+            continue;
+        }
+        if let Some(text) = line.strip_prefix('#') {
+            doc = Some(text.trim().to_owned()).filter(|text| !text.is_empty() && !text.starts_with('!'));
+            continue;
+        }
+        if line.starts_with('[') {
+            continue; // an attribute, e.g. `[private]`, the comment above still applies
+        }
+
+        if let Some(recipe) = header(line) {
+            if !recipe.0.starts_with('_') {
+                recipes.push(Recipe { name: recipe.0, params: recipe.1, doc: doc.take() });
+            }
+        }
+        doc = None;
+    }
+    recipes
+}
+
+/// The name and parameter names of `name param='default' +rest: dependencies`, unless it's a setting,
+/// an alias or an assignment
+fn header(line: &str) -> Option<(String, Vec<String>)> {
+    let line = line.strip_prefix('@').unwrap_or(line);
+    let colon = unquoted_colon(line)?;
+    if line[colon + 1..].starts_with('=') {
+        return None; // `name := value`
+    }
+
+    let mut words = line[..colon].split_whitespace();
+    let name = words.next()?;
+    if ["set", "alias", "export", "import", "mod"].contains(&name)
+        || !name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+        return None;
+    }
+
+    let params = words
+        .map(|word| word.split('=').next().unwrap_or(word).trim_start_matches(['+', '*', '$']).to_owned())
+        .filter(|param| !param.is_empty() && param.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-'))
+        .collect();
+    Some((name.to_owned(), params))
+}
+
+/// The first colon outside the quoted default values
+fn unquoted_colon(line: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, ':') => return Some(i),
+            _ => ()
+        }
+    }
+    None
+}
+
+#[test]
+fn parsing_recipes() {
+    let recipes = recipes(concat!(
+        "set shell := [\"bash\", \"-c\"]\n",
+        "version := \"1.0\"\n",
+        "alias b := build\n",
+        "\n",
+        "# Build the project\n",
+        "build:\n",
+        "    cargo build\n",
+        "\n",
+        "# Deploy somewhere\n",
+        "[confirm]\n",
+        "@deploy env target='a:b' +flags: build\n",
+        "    ./deploy {{env}}\n",
+        "_helper:\n",
+        "    true\n"));
+
+    assert_eq!(recipes, vec![
+        Recipe { name: "build".to_owned(), params: Vec::new(), doc: Some("Build the project".to_owned()) },
+        Recipe {
+            name: "deploy".to_owned(),
+            params: vec!["env".to_owned(), "target".to_owned(), "flags".to_owned()],
+            doc: Some("Deploy somewhere".to_owned())
+        }
+    ]);
+}
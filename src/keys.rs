@@ -0,0 +1,105 @@
+use termion::event::Key;
+
+use error::{Error, Result};
+use runner::Keybindings;
+
+/// Chars stand for themselves, special keys are named within angle brackets, see `parse_key`
+pub fn parse_keys(script: &str) -> Result<Vec<Key>> {
+    let mut keys = Vec::new();
+    let mut chars = script.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '<' {
+            keys.push(Key::Char(c));
+            continue;
+        }
+
+        let name: String = chars.by_ref().take_while(|&c| c != '>').collect();
+        keys.push(parse_key(&name)?);
+    }
+    Ok(keys)
+}
+
+/// `Enter`, `Tab`, `Esc`, `Backspace`, `Up`, `Down`, `Left`, `Right`, `Delete`, `Home`, `End`,
+/// `PageUp`, `PageDown`, `F1` to `F12`, `lt` for `<`, `C-x` for Control+x and `M-x` for Alt+x
+pub fn parse_key(name: &str) -> Result<Key> {
+    Ok(match name {
+        "Enter"     => Key::Char('\n'),
+        "Tab"       => Key::Char('\t'),
+        "Esc"       => Key::Esc,
+        "Backspace" => Key::Backspace,
+        "Up"        => Key::Up,
+        "Down"      => Key::Down,
+        "Left"      => Key::Left,
+        "Right"     => Key::Right,
+        "Delete"    => Key::Delete,
+        "Home"      => Key::Home,
+        "End"       => Key::End,
+        "PageUp"    => Key::PageUp,
+        "PageDown"  => Key::PageDown,
+        "lt"        => Key::Char('<'),
+        name if name.starts_with("C-") && name.chars().count() == 3 => Key::Ctrl(name.chars().nth(2).unwrap()),
+        name if name.starts_with("M-") && name.chars().count() == 3 => Key::Alt(name.chars().nth(2).unwrap()),
+        name => match name.strip_prefix('F').and_then(|n| n.parse::<u8>().ok()) {
+            Some(n) if (1..=12).contains(&n) => Key::F(n),
+            _ => return Err(Error::InvalidInput(format!("Unknown key `<{}>`", name)))
+        }
+    })
+}
+
+/// A key for a command to be accepted right away, see `Command::hotkey`: none the picker binds by default
+/// or moves around with, which the hotkey would take over, e.g. `Enter` or `Up`
+pub fn parse_hotkey(name: &str) -> Result<Key> {
+    let key = parse_key(name)?;
+    let reserved = match key {
+        Key::Char(_) | Key::Esc | Key::Alt('1'..='9') => true,
+        key => Keybindings::default().action(key).is_some()
+    };
+    if reserved {
+        return Err(Error::InvalidInput(format!("The picker already binds `{}`, pick e.g. a function key as the hotkey", name)));
+    }
+    Ok(key)
+}
+
+/// As typed in a script (see `parse_keys`), e.g. `a` or `<Tab>`, unless `parse_key` has no name for it
+pub fn key_name(key: Key) -> Option<String> {
+    let name = match key {
+        Key::Char('\n') => "Enter",
+        Key::Char('\t') => "Tab",
+        Key::Char('<')  => "lt",
+        Key::Char(c)    => return Some(c.to_string()),
+        Key::Esc        => "Esc",
+        Key::Backspace  => "Backspace",
+        Key::Up         => "Up",
+        Key::Down       => "Down",
+        Key::Left       => "Left",
+        Key::Right      => "Right",
+        Key::Delete     => "Delete",
+        Key::Home       => "Home",
+        Key::End        => "End",
+        Key::PageUp     => "PageUp",
+        Key::PageDown   => "PageDown",
+        Key::Ctrl(c)    => return Some(format!("<C-{}>", c)),
+        Key::Alt(c)     => return Some(format!("<M-{}>", c)),
+        Key::F(n) if (1..=12).contains(&n) => return Some(format!("<F{}>", n)),
+        _ => return None
+    };
+    Some(format!("<{}>", name))
+}
+
+#[test]
+fn parsing_keys() {
+    assert_eq!(parse_keys("a <Tab><C-r><lt>").unwrap(),
+               vec![Key::Char('a'), Key::Char(' '), Key::Char('\t'), Key::Ctrl('r'), Key::Char('<')]);
+    assert!(parse_keys("<Nope>").is_err());
+    assert_eq!(parse_key("F2").unwrap(), Key::F(2));
+    assert!(parse_key("F13").is_err());
+    assert_eq!(parse_hotkey("C-g").unwrap(), Key::Ctrl('g'));
+    for taken in ["Enter", "Tab", "Esc", "Up", "End", "C-r", "F5", "M-1", "lt"] {
+        assert!(parse_hotkey(taken).is_err(), "{}", taken);
+    }
+    for key in [Key::Char('a'), Key::Char('<'), Key::Char('\n'), Key::Ctrl('r'), Key::Alt('2'), Key::F(5), Key::PageDown] {
+        assert_eq!(parse_keys(&key_name(key).unwrap()).unwrap(), vec![key]);
+    }
+    assert_eq!(key_name(Key::Null), None);
+}
@@ -0,0 +1,192 @@
+use std::time::Duration;
+
+use command::{Command, WRAPPERS};
+use error::{Error, Result};
+use source::CommandSource;
+
+/// The words of the descriptions which tell nothing about a command
+const STOP_WORDS: &[&str] = &[
+    "the", "and", "for", "from", "into", "with", "without", "all", "its", "this", "that", "these", "those",
+    "one", "some", "any", "each", "every", "then", "than", "but", "not", "are", "was", "has", "have",
+    "can", "get", "out", "over", "onto", "upon", "via", "per", "given", "current"];
+
+/// At most that many keywords are suggested
+const MAX_KEYWORDS: usize = 6;
+
+/// The `[derive_keywords]` table of the configuration: whether the commands without keywords,
+/// e.g. those imported from a shell history, get keywords derived from them as they are loaded,
+/// e.g. `[derive_keywords] enabled = true` and `stop_words = ["grep", "head"]`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct DeriveKeywords {
+    #[serde(default)]
+    pub enabled: bool,
+    /// never keywords, be they programs, flags or words of the descriptions
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop_words: Vec<String>
+}
+
+/// A source whose commands get derived keywords, see `DeriveKeywords`
+pub struct Deriving<S> {
+    pub source: S,
+    pub derive: DeriveKeywords
+}
+
+impl DeriveKeywords {
+    pub fn apply(self: &DeriveKeywords, commands: &mut [Command]) {
+        if !self.enabled {
+            return;
+        }
+        for command in commands.iter_mut().filter(|command| command.keywords.is_empty()) {
+            command.keywords = derive(command.cmd.template(), command.description.as_deref(), &self.stop_words);
+        }
+    }
+}
+
+impl<S: CommandSource> CommandSource for Deriving<S> {
+    fn load(self: &Deriving<S>) -> Result<Vec<Command>> {
+        let mut commands = self.source.load()?;
+        self.derive.apply(&mut commands);
+        Ok(commands)
+    }
+
+    fn ttl(self: &Deriving<S>) -> Option<Duration> {
+        self.source.ttl()
+    }
+
+    fn runs_commands(self: &Deriving<S>) -> bool {
+        self.source.runs_commands()
+    }
+}
+
+/// Keywords to add to several commands at once and others to remove from them, e.g. `+k8s -kube`,
+/// a bare word being added
+#[derive(Debug, Default, PartialEq)]
+pub struct Retag {
+    pub add: Vec<String>,
+    pub remove: Vec<String>
+}
+
+impl Retag {
+    pub fn parse(edits: &str) -> Result<Retag> {
+        let mut retag = Retag::default();
+        for word in edits.split_whitespace() {
+            let (keywords, keyword) = match word.strip_prefix('-') {
+                Some(keyword) => (&mut retag.remove, keyword),
+                None => (&mut retag.add, word.strip_prefix('+').unwrap_or(word))
+            };
+            if keyword.is_empty() {
+                return Err(Error::InvalidInput(format!("`{}` is no keyword to add or remove", word)));
+            }
+            keywords.push(keyword.to_owned());
+        }
+        if retag.add.is_empty() && retag.remove.is_empty() {
+            return Err(Error::InvalidInput("No keyword to add or remove".to_owned()));
+        }
+        Ok(retag)
+    }
+
+    /// Whether that changed them
+    pub fn apply(self: &Retag, keywords: &mut Vec<String>) -> bool {
+        let before = keywords.clone();
+        keywords.retain(|kw| !self.remove.contains(kw));
+        for kw in &self.add {
+            if !keywords.contains(kw) {
+                keywords.push(kw.clone());
+            }
+        }
+        *keywords != before
+    }
+}
+
+/// Keywords for a command lacking them, see `derive`
+pub fn suggest(cmd: &str, description: Option<&str>) -> Vec<String> {
+    derive(cmd, description, &[])
+}
+
+/// The programs the command runs, their subcommands (the words which follow, up to the first flag)
+/// and long flags, then the significant words of the description, lowercased, but the `stop_words`
+pub fn derive(cmd: &str, description: Option<&str>, stop_words: &[String]) -> Vec<String> {
+    let mut keywords: Vec<String> = Vec::new();
+    let mut add = |word: &str| {
+        let word = word.to_lowercase();
+        if !keywords.contains(&word) && !stop_words.iter().any(|stop_word| stop_word.to_lowercase() == word) {
+            keywords.push(word);
+        }
+    };
+
+    for segment in cmd.split(['|', ';', '&']) {
+        let mut words = segment.split_whitespace()
+            .skip_while(|word| WRAPPERS.contains(word) || word.find('=').is_some_and(|idx| idx > 0));
+        if let Some(program) = words.next().filter(|word| is_word(word.rsplit('/').next().unwrap_or(word))) {
+            add(program.rsplit('/').next().unwrap_or(program));
+            let mut words = words.peekable();
+            let mut subcommands = 0;
+            while let Some(subcommand) = words.next_if(|word| !word.starts_with('-')) {
+                if subcommands < 2 && is_word(subcommand) {
+                    add(subcommand);
+                }
+                subcommands += 1;
+            }
+            // short flags are too terse to tell anything
+            for flag in words.filter_map(|word| word.strip_prefix("--")) {
+                let flag = flag.split('=').next().unwrap_or(flag);
+                if flag.len() >= 3 && is_word(flag) {
+                    add(flag);
+                }
+            }
+        }
+    }
+
+    for word in description.unwrap_or("").split(|c: char| !c.is_alphanumeric()) {
+        if word.chars().count() >= 3 && word.chars().all(char::is_alphabetic) && !STOP_WORDS.contains(&word.to_lowercase().as_str()) {
+            add(word);
+        }
+    }
+    keywords.truncate(MAX_KEYWORDS);
+    keywords
+}
+
+/// A plain word, such as a program or a subcommand, rather than a path, a placeholder or a value
+fn is_word(word: &str) -> bool {
+    word.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+        && word.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[test]
+fn suggesting_keywords() {
+    assert_eq!(suggest("git log --oneline {file}", Some("Show the history of a file")),
+               vec!["git", "log", "oneline", "show", "history", "file"]);
+    assert_eq!(suggest("sudo LANG=C /usr/bin/systemctl restart {unit} && journalctl -fu {unit}", None),
+               vec!["systemctl", "restart", "journalctl"]);
+    assert_eq!(suggest("kubectl get pods -n {namespace} | grep Running", Some("List the pods, e.g. the running ones")),
+               vec!["kubectl", "get", "pods", "grep", "running", "list"]);
+    assert!(suggest("{cmd} ./x.sh", Some("Do it")).is_empty());
+}
+
+#[test]
+fn retagging() {
+    let retag = Retag::parse("+k8s  -kube pods").unwrap();
+    assert_eq!(retag, Retag { add: vec!["k8s".to_owned(), "pods".to_owned()], remove: vec!["kube".to_owned()] });
+
+    let mut keywords = vec!["kube".to_owned(), "logs".to_owned()];
+    assert!(retag.apply(&mut keywords));
+    assert_eq!(keywords, vec!["logs", "k8s", "pods"]);
+    assert!(!retag.apply(&mut keywords));
+
+    assert!(Retag::parse(" ").is_err());
+    assert!(Retag::parse("+k8s -").is_err());
+}
+
+#[test]
+fn deriving_keywords() {
+    use command::command;
+
+    let mut commands = vec![command("rsync -a --delete --exclude=.git src/ {host}:src/ | tail -n 3", &[]), command("ls", &["files"])];
+
+    DeriveKeywords::default().apply(&mut commands);
+    assert!(commands[0].keywords.is_empty());
+
+    DeriveKeywords { enabled: true, stop_words: vec!["tail".to_owned()] }.apply(&mut commands);
+    assert_eq!(commands[0].keywords, vec!["rsync", "delete", "exclude"]);
+    assert_eq!(commands[1].keywords, vec!["files"]);
+}
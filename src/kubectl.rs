@@ -0,0 +1,141 @@
+use std::time::Duration;
+
+use command::{Command, Placeholders};
+use source::{self, CommandSource};
+use system;
+
+use error::Result;
+
+/// What can be done against each context: the verb (a keyword), the arguments and the description
+const TEMPLATES: &[(&str, &str, &str)] = &[
+    ("pods", "get pods", "List the pods"),
+    ("logs", "logs -f {pod}", "Follow the logs of a pod"),
+    ("exec", "exec -it {pod} -- sh", "Open a shell in a pod"),
+    ("describe", "describe {resource}", "Describe a resource"),
+    ("events", "get events --sort-by=.lastTimestamp", "List the recent events")
+];
+
+/// How long the contexts are listed for before listing them again
+const TTL: Duration = Duration::from_secs(60);
+
+/// When kubectl is installed, commands against each of its contexts, in the context namespace if any
+pub struct Kubectl;
+
+#[derive(Debug, PartialEq)]
+struct Context {
+    name: String,
+    namespace: Option<String>
+}
+
+impl CommandSource for Kubectl {
+    /// Nothing without kubectl
+    fn load(self: &Kubectl) -> Result<Vec<Command>> {
+        if system::find_executable("kubectl").is_none() {
+            return Ok(Vec::new());
+        }
+        let table = source::program_output("kubectl", &["config", "get-contexts"], None)?;
+        Ok(commands(&contexts(&table)))
+    }
+
+    /// The contexts come and go, e.g. as clusters get created
+    fn ttl(self: &Kubectl) -> Option<Duration> {
+        Some(TTL)
+    }
+
+    fn runs_commands(self: &Kubectl) -> bool {
+        true
+    }
+}
+
+/// Skipping a context whose commands cannot be parsed, e.g. a name with an unclosed brace, rather than all of them
+fn commands(contexts: &[Context]) -> Vec<Command> {
+    contexts.iter()
+        .filter_map(|context| context_commands(context).ok())
+        .flatten()
+        .collect()
+}
+
+fn context_commands(context: &Context) -> Result<Vec<Command>> {
+    let keywords = |verb: &str| vec!["k8s".to_owned(), "kubectl".to_owned(), context.name.clone(), verb.to_owned()];
+    let namespace = context.namespace.as_ref().map_or("{namespace}".to_owned(), |ns| ns.clone());
+
+    let mut commands = vec![Command {
+        cmd: Placeholders::parse(&format!("kubectl config use-context {}", context.name))?,
+        description: Some(format!("Switch to the {} context", context.name)),
+        keywords: keywords("use-context"),
+        ..Default::default()
+    }];
+    for &(verb, args, description) in TEMPLATES {
+        commands.push(Command {
+            cmd: Placeholders::parse(&format!("kubectl --context {} -n {} {}", context.name, namespace, args))?,
+            description: Some(format!("{} ({})", description, context.name)),
+            keywords: keywords(verb),
+            ..Default::default()
+        });
+    }
+    Ok(commands)
+}
+
+/// The rows of `kubectl config get-contexts`, whose columns are aligned on the header:
+///
+/// ```text
+/// CURRENT   NAME   CLUSTER   AUTHINFO   NAMESPACE
+/// *         prod   prod      admin      web
+/// ```
+fn contexts(table: &str) -> Vec<Context> {
+    let mut lines = table.lines();
+    let header = match lines.next() {
+        Some(header) => header,
+        None => return Vec::new()
+    };
+    let column = |name: &str| header.find(name);
+    let (name_at, cluster_at, namespace_at) = match (column("NAME"), column("CLUSTER"), column("NAMESPACE")) {
+        (Some(name), Some(cluster), namespace) => (name, cluster, namespace),
+        _ => return Vec::new()
+    };
+
+    let field = |line: &str, from: usize, to: Option<usize>| -> Option<String> {
+        let end = to.unwrap_or(line.len()).min(line.len());
+        line.get(from.min(end)..end).map(str::trim).filter(|field| !field.is_empty()).map(str::to_owned)
+    };
+    lines
+        .filter_map(|line| field(line, name_at, Some(cluster_at)).map(|name| Context {
+            name,
+            namespace: namespace_at.and_then(|at| field(line, at, None))
+        }))
+        .collect()
+}
+
+#[test]
+fn listing_contexts() {
+    let contexts = contexts(concat!(
+        "CURRENT   NAME   CLUSTER   AUTHINFO   NAMESPACE\n",
+        "*         prod   prod      admin      web\n",
+        "          dev    dev       dev\n"));
+
+    assert_eq!(contexts, vec![
+        Context { name: "prod".to_owned(), namespace: Some("web".to_owned()) },
+        Context { name: "dev".to_owned(), namespace: None }
+    ]);
+
+    let commands = context_commands(&contexts[1]).unwrap();
+    assert_eq!(source::listed(&commands[..3]), vec![
+        "kubectl config use-context dev # Switch to the dev context",
+        "kubectl --context dev -n {namespace} get pods # List the pods (dev)",
+        "kubectl --context dev -n {namespace} logs -f {pod} # Follow the logs of a pod (dev)"
+    ]);
+    assert_eq!(commands[2].keywords, vec!["k8s", "kubectl", "dev", "logs"]);
+}
+
+#[test]
+fn skipping_a_bad_context() {
+    let contexts = contexts(concat!(
+        "CURRENT   NAME   CLUSTER   AUTHINFO   NAMESPACE\n",
+        "*         pr{d   prod      admin      web\n",
+        "          dev    dev       dev\n"));
+
+    assert!(context_commands(&contexts[0]).is_err());
+    let commands = commands(&contexts);
+    assert_eq!(commands.len(), 1 + TEMPLATES.len());
+    assert!(commands.iter().all(|command| command.keywords.contains(&"dev".to_owned())));
+}
@@ -0,0 +1,63 @@
+extern crate termion;
+extern crate itertools;
+extern crate regex;
+extern crate libc;
+extern crate dirs;
+extern crate toml;
+extern crate serde;
+#[macro_use] extern crate serde_derive;
+#[macro_use] extern crate serde_json;
+extern crate clap;
+extern crate rayon;
+extern crate unicode_width;
+
+#[cfg(test)] #[macro_use] extern crate hamcrest;
+#[cfg(test)] #[macro_use] extern crate maplit; // provide `hashset!`
+
+pub mod error;
+pub mod screen;
+pub mod render;
+pub mod command;
+pub mod suggestion;
+pub mod runner;
+pub mod state;
+pub mod harness;
+pub mod keys;
+pub mod config;
+pub mod source;
+pub mod makefile;
+pub mod npm;
+pub mod justfile;
+pub mod cargo;
+pub mod kubectl;
+pub mod ssh;
+pub mod systemd;
+pub mod path;
+pub mod git;
+pub mod flake;
+pub mod stdin;
+pub mod system;
+pub mod doctor;
+pub mod history;
+pub mod frecency;
+pub mod values;
+pub mod session;
+pub mod stats;
+pub mod search;
+pub mod exec;
+pub mod cheatsheet;
+pub mod bench;
+pub mod collate;
+pub mod danger;
+pub mod highlight;
+pub mod daemon;
+pub mod rpc;
+pub mod tutorial;
+pub mod keywords;
+pub mod macros;
+pub mod recording;
+pub mod pack;
+pub mod cache;
+pub mod pane;
+pub mod badge;
+pub mod function;
@@ -0,0 +1,75 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::PathBuf;
+
+use error::{Error, Result};
+
+/// Where the saved filters are kept, by default `~/.local/share/smart-runner/macros.json`
+pub struct MacroStore {
+    pub path: PathBuf
+}
+
+/// A filter saved under a name, brought back with `@name` in the picker or `--query-macro name`
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct QueryMacro {
+    pub keywords: Vec<String>,
+    pub input: String
+}
+
+pub type QueryMacros = BTreeMap<String, QueryMacro>;
+
+impl MacroStore {
+    pub fn open() -> Result<MacroStore> {
+        ::dirs::data_dir()
+            .map(|dir| MacroStore { path: dir.join("smart-runner").join("macros.json") })
+            .ok_or(Error::NoDirectory("data"))
+    }
+
+    pub fn load(self: &MacroStore) -> Result<QueryMacros> {
+        if !self.path.exists() {
+            return Ok(QueryMacros::new());
+        }
+        let file = File::open(&self.path).map_err(|error| Error::File { path: self.path.clone(), error })?;
+        Ok(::serde_json::from_reader(file)?)
+    }
+
+    pub fn get(self: &MacroStore, name: &str) -> Result<QueryMacro> {
+        self.load()?.remove(name).ok_or_else(|| Error::InvalidInput(format!("No query macro is named `{}`", name)))
+    }
+
+    /// Replacing the one of that name, if any
+    pub fn save(self: &MacroStore, name: &str, query_macro: QueryMacro) -> Result<()> {
+        if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == '@') {
+            return Err(Error::InvalidInput(format!("`{}` cannot name a query macro: it is to be typed in as `@name`", name)));
+        }
+        let mut macros = self.load()?;
+        macros.insert(name.to_owned(), query_macro);
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, ::serde_json::to_string(&macros)?)?;
+        Ok(())
+    }
+}
+
+/// The name of the macro a word recalls, e.g. `triage` for `@triage`
+pub fn name(word: &str) -> Option<&str> {
+    word.strip_prefix('@').filter(|name| !name.is_empty())
+}
+
+#[test]
+fn saving_macros() {
+    let store = MacroStore { path: ::std::env::temp_dir().join(format!("smart-runner-macros-{}.json", ::std::process::id())) };
+    assert!(store.load().unwrap().is_empty());
+
+    let triage = QueryMacro { keywords: vec!["k8s".to_owned(), "logs".to_owned()], input: "err".to_owned() };
+    store.save("triage", triage.clone()).unwrap();
+    assert!(store.save("two words", QueryMacro::default()).is_err());
+    assert_eq!(store.get("triage").unwrap(), triage);
+    assert!(store.get("other").is_err());
+    fs::remove_file(&store.path).unwrap();
+
+    assert_eq!(name("@triage"), Some("triage"));
+    assert_eq!(name("@"), None);
+    assert_eq!(name("triage"), None);
+}
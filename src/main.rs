@@ -1,190 +1,423 @@
-extern crate termion;
+extern crate smart_runner;
 extern crate itertools;
-extern crate regex;
-
-#[cfg(test)] #[macro_use] extern crate hamcrest;
-#[cfg(test)] #[macro_use] extern crate maplit; // provide `hashset!`
-
-mod screen;
-mod command;
-mod suggestion;
-
-use termion::event::Key;
-use termion::input::TermRead;
-use termion::raw::{IntoRawMode, RawTerminal};
-use std::io::{Write, stdin, stderr, Stderr};
-use std::collections::HashSet;
-
-use command::{Command, Commands, Placeholders};
-use screen::{Screen, ValidatedKeyword};
-use suggestion::Suggestion;
-
-type Result<T> = std::result::Result<T, Box<std::error::Error>>;
+#[macro_use] extern crate clap;
+extern crate toml;
+extern crate libc;
+#[macro_use] extern crate serde_json;
+
+mod cli;
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::path::Path;
+use std::slice;
+
+use clap::ArgMatches;
+use itertools::Itertools;
+
+use smart_runner::{doctor, exec, stdin, source};
+use smart_runner::history::{self, History, IgnoreRules, Record};
+use smart_runner::frecency::{Context, Frecency};
+use smart_runner::command::{Commands, Selection};
+use smart_runner::badge::Badges;
+use smart_runner::cache::CompletionCache;
+use smart_runner::config::{Location, StartupConfig};
+use smart_runner::daemon::Library;
+use smart_runner::danger::DangerRules;
+use smart_runner::keywords::Deriving;
+use smart_runner::macros::{MacroStore, QueryMacro};
+use smart_runner::recording::Recording;
+use smart_runner::runner::{self, RunnerBuilder};
+use smart_runner::render::Theme;
+use smart_runner::source::{CommandSource, Provided};
+use smart_runner::suggestion::Suggestion;
+use smart_runner::values::ValueStore;
+use smart_runner::session::{DirectoryStore, SessionStore};
+
+use smart_runner::error::{Error, Result};
+
+// Exit codes, mirroring fzf's
+const EXIT_SUCCESS: i32 = 0;
+const EXIT_NO_MATCH: i32 = 1;
+const EXIT_ERROR: i32 = 2;
 
 fn main() {
+    let matches = cli::build_cli().get_matches();
 
-    match run_runner() {
-        Ok(Some(cmd)) => println!("{}", cmd),
-        Ok(None)      => println!(), // needed when piped with read cmd
-        Err(e)        => eprintln!("Error: {}", e)
+    match dispatch(&matches) {
+        Ok(status) => std::process::exit(status),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(EXIT_ERROR);
+        }
     }
 }
 
-
-fn run_runner() -> Result<Option<String>> {
-
-    // TODO have the commands stored externally
-    let mut runner = Runner::new(
-        vec![
-            Command {
-                cmd: Placeholders::parse("nix-env -q '.*{name}.*'")?,
-                description: Some("Search a Nix package by name".to_owned()),
-                keywords: vec!["nix".to_owned(), "search".to_owned(), "package".to_owned()]
-            },
-            Command {
-                cmd: Placeholders::parse("du -sh /nix/store")?,
-                description: Some("Show the size of the Nix store".to_owned()),
-                keywords: vec!["nix".to_owned(), "store".to_owned(), "size".to_owned()]
-            },
-            Command {
-                cmd: Placeholders::parse("sudo shutdown -h now")?,
-                description: Some("Shut the system down".to_owned()),
-                keywords: vec!["hardware".to_owned(), "shutdown".to_owned()]
-            }
-        ])?;
-
-    runner.run()
-}
-
-
-struct Runner {
-    commands: Commands,
-    screen: Screen,
-    terminal: RawTerminal<Stderr> // we use stderr to not pollute stdout
-}
-
-enum InputLoopAction {
-    Continue, Cancel, Success(String)
+fn dispatch(matches: &ArgMatches) -> Result<i32> {
+    let location = Location::new(
+        cli::global_value(matches, "config"),
+        cli::global_value(matches, "profile"))?;
+
+    let theme = if cli::global_flag(matches, "no-color") { Theme::monochrome() } else { Theme::default() };
+    let safe = cli::global_flag(matches, "safe");
+
+    match matches.subcommand() {
+        (name @ "exec", _) | (name @ "tutorial", _) | (name @ "doctor", _) | (name @ "daemon", _) | (name @ "rpc", _)
+        | (name @ "sync", _) | (name @ "pack", _) if safe =>
+            return Err(Error::InvalidInput(format!("`{}` runs commands, which --safe prevents", name))),
+        ("add", Some(m))         => cli::add(&location, m)?,
+        ("list", _)              => cli::list(&location)?,
+        ("import", Some(m))      => cli::import(&location, m)?,
+        ("export", Some(m))      => cli::export(&location, m)?,
+        ("sync", _)              => cli::sync(&location)?,
+        ("completions", Some(m)) => cli::completions(&location, m)?,
+        ("doctor", _)            => return cli::doctor(&location),
+        ("daemon", Some(m))      => cli::daemon(&location, m)?,
+        ("rpc", _)               => cli::rpc(&location)?,
+        ("tutorial", _)          => cli::tutorial(&location, theme)?,
+        ("retag", Some(m))       => cli::retag(&location, m, theme, safe)?,
+        ("stats", Some(m))       => cli::stats(&location, m)?,
+        ("search", Some(m))      => return cli::search(&location, m),
+        ("exec", Some(m))        => return cli::exec(&location, m, theme),
+        ("preset", Some(m))      => cli::preset(&location, m)?,
+        ("macro", Some(m))       => cli::save_macro(m)?,
+        ("history", Some(m))     => cli::history(m)?,
+        ("pack", Some(m))        => cli::pack(&location, m)?,
+        ("bench", Some(m))       => cli::bench(m)?,
+        ("run", Some(m))         => return run(&location, m, theme, safe),
+        _                        => return run(&location, matches, theme, safe)
+    }
+    Ok(EXIT_SUCCESS)
 }
 
-
-impl Runner {
-    fn new(vec_commands: Vec<Command>) -> Result<Runner> {
-        let mut terminal = stderr().into_raw_mode()?;
-        let screen = Screen::new(&mut terminal)?;
-        let commands = Commands::new(vec_commands);
-
-        Ok(Runner { commands, screen, terminal })
+fn run(location: &Location, matches: &ArgMatches, theme: Theme, safe: bool) -> Result<i32> {
+    let query_macro = match matches.value_of("query-macro") {
+        Some(name) => MacroStore::open()?.get(name)?,
+        None => QueryMacro::default()
+    };
+    let query = Some(query_macro.input.as_str()).filter(|input| !input.is_empty()).into_iter()
+        .chain(matches.values_of("query").map_or(Vec::new(), |words| words.collect()))
+        .join(" ");
+    let keywords: Vec<&str> = query_macro.keywords.iter().map(String::as_str)
+        .chain(matches.values_of("keywords").map_or(Vec::new(), |kws| kws.collect()))
+        .collect();
+    // given after `--`, quoted again for the shell which runs the command
+    let passthrough = matches.values_of("passthrough").map(|args| args.map(exec::quote).join(" "));
+    let from_stdin = matches.is_present("stdin");
+    let mut issues = 0;
+    let mut sources: Vec<Box<dyn CommandSource + Send>> = Vec::new();
+    // a running daemon has them loaded already, along with those of the providers
+    let library = if from_stdin || safe { None } else { Library::fetch(location).ok() };
+    let (mut commands, config) = match library {
+        Some(library) => {
+            issues = library.issues;
+            (Commands::new(library.commands()?), Some(library.config))
+        },
+        None if from_stdin => (Commands::new(stdin::read_commands(matches.value_of("delimiter"))?), None),
+        None => {
+            let mut config = location.load()?;
+            let commands = source::ConfigFile { location: location.clone() }.commands(&config)?;
+            issues = doctor::lint(&config.commands, &doctor::locations(location, config.commands.len())).len();
+            for provider in config.providers.drain(..) {
+                let provided = Provided { provider, unavailable: config.unavailable };
+                sources.push(Box::new(Deriving { source: provided, derive: config.derive_keywords.clone() }));
+            }
+            (Commands::new(commands), Some(config))
+        }
+    };
+    let mut records = Vec::new();
+    let mut ignore = IgnoreRules::default();
+    let mut bundles = BTreeMap::new();
+    let mut completion_cache = None;
+    let mut badges = Badges::default();
+    let mut startup = StartupConfig::default();
+    let mut danger = DangerRules::default();
+    let value_store = if from_stdin { None } else { ValueStore::open().ok() };
+    if let Some(config) = config {
+        ignore = config.history.ignore_rules()?;
+        bundles = config.bundles;
+        let (compiled, invalid) = Badges::compile(&config.badges);
+        for e in invalid {
+            eprintln!("Skipping the pattern of a badge: {}", e);
+        }
+        badges = compiled;
+        startup = config.startup;
+        danger = config.danger.rules();
+        for e in config.danger.errors() {
+            eprintln!("Skipping a danger pattern: {}", e);
+        }
+        // the completion commands merely run every time without it
+        completion_cache = CompletionCache::open(&config.completion_cache).ok().flatten();
+        if let Ok(dir) = std::env::current_dir() {
+            sources.extend(source::builtin(&config.sources, &dir));
+        }
+        // without the records, the commands are merely sorted
+        records = History::open().and_then(|history| history.records()).unwrap_or_default();
+        let context = Context::current();
+        commands.frecency = Frecency::compute(&records, &config.frecency, &context, history::now());
+        commands.relevance_weight = config.frecency.relevance_weight;
+        commands.recency = Frecency::last_uses(&records, &config.frecency, &context);
+        commands.frequency = Frecency::uses(&records, &config.frecency, &context);
+        commands.outcomes = history::outcomes(&records);
+    }
+    if safe {
+        sources.retain(|source| !source.runs_commands());
     }
 
-    /// Return a command to execute or None if the user canceled
-    fn run(self: &mut Runner) -> Result<Option<String>> {
-        self.refresh_screen()?;
-        let stdin = stdin();
-
-        self.terminal.flush()?;
-        for c in stdin.keys() {
-            match self.process_key(c?) {
-                InputLoopAction::Success(cmd) => {
-                    self.cleanup()?;
-                    return Ok(Some(cmd));
+    let printer = cli::Printer::from_matches(matches)?;
+    // under CI, cron, or with both stdout and stderr redirected
+    let interactive = runner::has_terminal(from_stdin);
+
+    // the picker does not wait for the other sources, unlike the options deciding upon all the commands
+    if !interactive || matches.is_present("filter") || matches.is_present("select-1") || matches.is_present("exit-0") {
+        for source in sources.drain(..) {
+            match source.load() {
+                Ok(mut loaded) => {
+                    danger.flag(&mut loaded);
+                    commands.extend(loaded)
                 },
-
-                InputLoopAction::Cancel => {
-                    self.cleanup()?;
-                    return Ok(None);
-                }
-
-                InputLoopAction::Continue => {
-                    self.refresh_screen()?;
-                }
-            };
+                Err(e) => eprintln!("Cannot load commands: {}", e)
+            }
         }
-
-        unreachable!()
     }
-
-    fn cleanup(self: &mut Runner) -> Result<()> {
-        self.screen.cleanup(&mut self.terminal)
+    if safe {
+        commands.retain(|command| command.danger.is_none());
     }
 
-    fn process_key(self: &mut Runner, key: Key) -> InputLoopAction {
-
-        fn cont<F: FnOnce() -> ()>(f: F) -> InputLoopAction {
-            f();
-            InputLoopAction::Continue
+    if matches.is_present("filter") {
+        // rather than matching more commands than asked for
+        let full_query = full_query(&keywords, &query);
+        let unknown = unknown_keywords(&commands, &full_query);
+        if !unknown.is_empty() {
+            return Err(Error::InvalidInput(format!("No command has the keywords {}", unknown.iter().map(|kw| format!("`{}`", kw)).join(", "))));
         }
+        let suggestion = Suggestion::from_query(&commands, &full_query);
+        printer.print_commands(&suggestion.commands)?;
+        return Ok(EXIT_SUCCESS);
+    }
 
-        match key {
-            Key::Char('q') => InputLoopAction::Cancel,
+    let mut single_match = None;
 
-            Key::Char('\n') => {
-                if let Some(cmd) = self.screen.selected_command() {
-                    InputLoopAction::Success(cmd)
-                } else {
-                    InputLoopAction::Continue
+    if matches.is_present("select-1") || matches.is_present("exit-0") {
+        let mut suggestion = Suggestion::from_query(&commands, &full_query(&keywords, &query));
+        match suggestion.commands.len() {
+            0 if matches.is_present("exit-0") => {
+                printer.print_nothing()?;
+                return Ok(EXIT_NO_MATCH);
+            },
+            1 if matches.is_present("select-1") => {
+                let command = suggestion.commands.remove(0);
+                // unless the arguments fill its only placeholder in
+                let filled_in = command.cmd.names().len() == 1 && command.cmd.ends_with_placeholder();
+                if command.cmd.names().is_empty() || (filled_in && passthrough.is_some()) {
+                    let mut selection = Selection::new(command);
+                    if let Some(ref arguments) = passthrough {
+                        if filled_in {
+                            selection.values.push(arguments.clone());
+                            selection.passed_through = true;
+                        }
+                        selection.pass_through(arguments);
+                    }
+                    emit(&printer, slice::from_ref(&selection), safe)?;
+                    if !from_stdin && ignore.allows(&selection) {
+                        record(&selection, query_keywords(&commands, &full_query(&keywords, &query)));
+                    }
+                    return Ok(EXIT_SUCCESS);
                 }
+                single_match = Some(command);
             },
-
-            Key::Char('\t') => cont(|| self.auto_complete()),
-            Key::Char(' ')  => cont(|| self.validate_keyword()),
-            Key::Char(c)    => cont(|| self.add_key(c)),
-            Key::Backspace  => cont(|| self.remove_last_char()),
-
-            Key::Right      => cont(|| self.screen.next_suggestion()),
-
-            Key::Left       => cont(|| self.screen.previous_suggestion()),
-            Key::Up         => cont(|| self.screen.previous_command()),
-            Key::Down       => cont(|| self.screen.next_command()),
-            _               => cont(|| ())
+            _ => ()
         }
     }
 
-    fn auto_complete(self: &mut Runner) {
-        self.screen.complete();
-        self.filter_commands();
+    if !interactive {
+        eprintln!("No terminal to show the picker on: printing the matching commands, as with --filter");
+        let suggestion = Suggestion::from_query(&commands, &full_query(&keywords, &query));
+        printer.print_commands(&suggestion.commands)?;
+        return Ok(EXIT_SUCCESS);
     }
 
-    fn add_key(self: &mut Runner, c: char) {
-        self.screen.add(c);
-        self.filter_commands();
+    if from_stdin {
+        stdin::reattach_to_tty()?;
     }
-
-    fn remove_last_char(self: &mut Runner) {
-        self.screen.remove_last_char();
-        self.filter_commands();
+    let mut builder = RunnerBuilder::new(commands)
+        .theme(theme)
+        .keywords(&keywords)
+        .query(&query)
+        .history(history::commands(&records))
+        .bundles(bundles)
+        .badges(badges)
+        .danger(danger)
+        .startup(startup.mode)
+        .macros(MacroStore::open().and_then(|store| store.load()).unwrap_or_default())
+        .multi(matches.is_present("multi"))
+        .values(value_store.as_ref().and_then(|store| store.rings().ok()).unwrap_or_default())
+        .presets(value_store.as_ref().and_then(|store| store.presets().ok()).unwrap_or_default());
+    if let Some(list) = startup.list_on_empty {
+        builder = builder.list_on_empty(list);
+    }
+    let session_store = if from_stdin { None } else { SessionStore::open().ok() };
+    if matches.is_present("resume") {
+        if let Some(session) = session_store.as_ref().and_then(|store| store.load().ok().flatten()) {
+            builder = builder.resume(session);
+        }
+    }
+    let directory = std::env::current_dir().ok();
+    let directory_store = if from_stdin { None } else { DirectoryStore::open().ok() };
+    if let (Some(store), Some(dir), false) = (&directory_store, &directory, matches.is_present("resume") || !keywords.is_empty()) {
+        builder = builder.restorable(store.keywords(dir).unwrap_or_default());
+    }
+    if let Some(ref arguments) = passthrough {
+        builder = builder.passthrough(arguments);
+    }
+    if let Some(path) = matches.value_of("replay") {
+        builder = builder.replay(Recording::load(Path::new(path))?);
+    }
+    let record_file = matches.value_of("record").map(|path| Recording::create(Path::new(path))).transpose()?;
+    builder = builder.record(record_file.is_some());
+    if let Some(command) = single_match {
+        // still need the values of its placeholders
+        builder = builder.fill_in(command);
+    }
+    if matches.is_present("announce-fd") {
+        let mut announces = announce_file(value_t!(matches, "announce-fd", RawFd)?)?;
+        builder = builder.observe(move |event| if let Some(announcement) = event.announcement() {
+            // the listener may have exited
+            let _ = writeln!(announces, "{}", announcement);
+        });
+    }
+    if let Some(cache) = completion_cache {
+        builder = builder.completion_cache(cache);
+    }
+    for source in sources {
+        builder = builder.source(source);
+    }
+    builder = builder.safe(safe);
+    let mut runner = builder.build()?;
+
+    let picked = runner.run()?;
+    let chained = runner.picked().to_vec();
+    let from_history = runner.browsing_history();
+    let session = runner.session();
+    let valid_keywords = runner.valid_keywords();
+    let errors: Vec<String> = runner.errors().iter().map(ToString::to_string).collect();
+    let recorded = record_file.and_then(|file| runner.recording().map(|recording| recording.save(file)));
+    drop(runner); // out of raw mode
+    if let Some(Err(e)) = recorded {
+        eprintln!("Cannot save the recording: {}", e);
+    }
+    for e in errors {
+        eprintln!("Cannot load commands: {}", e);
+    }
+    if issues > 0 {
+        eprintln!("{} issue(s) with the commands of {}, see `smart-runner doctor`", issues, location.path.display());
     }
 
-    fn filter_commands(self: &mut Runner) {
+    if let (Some(store), Some(dir), false) = (&directory_store, &directory, valid_keywords.is_empty()) {
+        if let Err(e) = store.save(dir, &valid_keywords) {
+            eprintln!("Cannot remember the keywords used in {}: {}", dir.display(), e);
+        }
+    }
+    if let Some(ref store) = session_store {
+        // unsaved, the next picker starts afresh
+        let saved = if picked.is_some() { store.clear() } else { store.save(&session) };
+        if let Err(e) = saved {
+            eprintln!("Cannot save the session: {}", e);
+        }
+    }
 
-        let suggestion = {
-            // nest `validated_keywords` as it borrows self immutably
-            let validated_keywords: HashSet<&String> = self.screen.validated_keywords.iter()
-                .filter_map(|v| match v {
-                    &ValidatedKeyword::Valid(ref kw) => Some(kw),
-                    _ => None
-                }).collect();
+    match picked {
+        Some(mut selection) => {
+            if let Some(ref arguments) = passthrough {
+                selection.pass_through(arguments);
+            }
+            let selections: Vec<Selection> = chained.into_iter().chain(Some(selection)).collect();
+            emit(&printer, &selections, safe)?;
+            // picking a command from the history again (Ctrl-R) doesn't count as a use of its own
+            for selection in selections.iter().filter(|selection| !from_stdin && !from_history && ignore.allows(selection)) {
+                record(selection, valid_keywords.clone());
+                if let Some(ref store) = value_store {
+                    remember_values(store, selection);
+                }
+            }
+        },
+        None => printer.print_nothing()?
+    }
+    Ok(EXIT_SUCCESS)
+}
 
-            Suggestion::from_input(
-                &self.commands,
-                self.screen.input().as_ref(),
-                validated_keywords)
-        };
+/// Open the URLs in the browser, unless `--safe`, and print the other commands
+fn emit(printer: &cli::Printer, selections: &[Selection], safe: bool) -> Result<()> {
+    let opened = |selection: &&Selection| selection.command.is_url() && !safe;
+    for selection in selections.iter().filter(opened) {
+        exec::open_url(&selection.url())?;
+    }
+    let commands: Vec<Selection> = selections.iter().filter(|selection| !opened(selection)).cloned().collect();
+    match commands.len() {
+        0 => printer.print_nothing(),
+        1 => printer.print_selection(&commands[0]),
+        _ => printer.print_selections(&commands)
+    }
+}
 
-        self.screen.set_suggestion(suggestion);
+/// A duplicate of the file descriptor opened by the shell, e.g. with `3>/tmp/announces`, which stays open:
+/// not stdin, stdout nor stderr, which the picker and the selection need
+fn announce_file(fd: RawFd) -> Result<File> {
+    if fd <= 2 {
+        return Err(Error::InvalidInput(format!("Cannot announce the selection to the file descriptor {}: \
+                                                stdin, stdout and stderr are taken, open another one, e.g. 3", fd)));
     }
+    let duplicate = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 3) };
+    if duplicate < 0 {
+        return Err(Error::InvalidInput(format!("Cannot announce the selection to the file descriptor {}: {}",
+                                               fd, io::Error::last_os_error())));
+    }
+    Ok(unsafe { File::from_raw_fd(duplicate) })
+}
 
-    fn validate_keyword(self: &mut Runner) {
-        let input = self.screen.reset_input();
-        let validated_kw = if self.commands.kwd2cmd.contains_key(&input) {
-            ValidatedKeyword::Valid(input)
-        } else {
-            ValidatedKeyword::Invalid(input)
-        };
-        self.screen.add_validated_keyword(validated_kw);
+/// Failing to record it must not prevent the command from running
+fn record(selection: &Selection, keywords: Vec<String>) {
+    if let Err(e) = History::open().and_then(|history| history.record(&Record::new(selection, keywords))) {
+        eprintln!("Cannot record the selection in the history: {}", e);
     }
+}
 
-    fn refresh_screen(self: &mut Runner) -> Result<()> {
-        self.screen.print(&mut self.terminal)
+/// Unsaved, the values typed in merely cannot be recalled the next time
+fn remember_values(store: &ValueStore, selection: &Selection) {
+    if selection.values.is_empty() {
+        return;
+    }
+    let remembered = store.rings().and_then(|mut rings| {
+        rings.remember(selection.command.cmd.template(), &selection.values);
+        store.save(&rings)
+    });
+    if let Err(e) = remembered {
+        eprintln!("Cannot remember the values of the placeholders: {}", e);
     }
-}
\ No newline at end of file
+}
+
+/// The known keywords validated by the query
+fn query_keywords(commands: &Commands, query: &str) -> Vec<String> {
+    let mut words: Vec<&str> = query.split(' ').collect();
+    words.pop();
+    words.into_iter()
+        .filter(|word| commands.kwd2cmd.contains_key(*word))
+        .map(str::to_owned)
+        .collect()
+}
+
+/// The words of the query followed by a space which no command has as keyword
+fn unknown_keywords<'a>(commands: &Commands, query: &'a str) -> Vec<&'a str> {
+    let mut words: Vec<&str> = query.split(' ').collect();
+    words.pop();
+    words.into_iter()
+        .filter(|word| !word.is_empty() && !commands.kwd2cmd.contains_key(*word))
+        .collect()
+}
+
+/// The pre-validated keywords followed by the query, as if everything had been typed in
+fn full_query(keywords: &[&str], query: &str) -> String {
+    keywords.iter().map(|kw| format!("{} ", kw)).collect::<String>() + query
+}
@@ -0,0 +1,81 @@
+use std::path::PathBuf;
+
+use command::Command;
+use source::{self, CommandSource};
+
+use error::Result;
+
+const NAMES: &[&str] = &["GNUmakefile", "makefile", "Makefile"];
+
+/// The targets of the Makefile of a directory, as `make <target>`, described by the comment
+/// that follows them (`target: ## description`) or the one right above them
+pub struct Makefile {
+    pub dir: PathBuf
+}
+
+impl CommandSource for Makefile {
+    /// Nothing without a Makefile
+    fn load(self: &Makefile) -> Result<Vec<Command>> {
+        Ok(match source::read_first(&self.dir, NAMES)? {
+            Some((_, content)) => source::named_commands("make", targets(&content)),
+            None => Vec::new()
+        })
+    }
+}
+
+/// The explicit targets, in order, with their description
+fn targets(content: &str) -> Vec<(String, Option<String>)> {
+    let mut targets: Vec<(String, Option<String>)> = Vec::new();
+    let mut comment: Option<String> = None;
+
+    for line in content.lines() {
+        if line.starts_with('\t') {
+            continue; // a recipe
+        }
+        if let Some(text) = line.trim_start().strip_prefix('#') {
+            comment = Some(text.trim_start_matches('#').trim().to_owned()).filter(|text| !text.is_empty());
+            continue;
+        }
+
+        if let Some(idx) = line.find(':') {
+            let (names, rest) = (&line[..idx], &line[idx + 1..]);
+            let is_rule = !rest.starts_with('=') && !rest.starts_with(":=")
+                && !names.contains(['=', '%', '$']);
+            if is_rule {
+                let description = rest.find("##")
+                    .map(|idx| rest[idx + 2..].trim().to_owned())
+                    .or_else(|| comment.clone());
+                for name in names.split_whitespace().filter(|name| !name.starts_with('.')) {
+                    if !targets.iter().any(|t| t.0 == name) {
+                        targets.push((name.to_owned(), description.clone()));
+                    }
+                }
+            }
+        }
+        comment = None;
+    }
+    targets
+}
+
+#[test]
+fn parsing_targets() {
+    let targets = targets(concat!(
+        "CC := gcc\n",
+        "FLAGS = -o a:b\n",
+        ".PHONY: build test\n",
+        "\n",
+        "# Compile everything\n",
+        "build: main.o\n",
+        "\t$(CC) -c main.c # not a target: really\n",
+        "test: build ## Run the tests\n",
+        "%.o: %.c\n",
+        "clean install:\n",
+        "build:\n"));
+
+    assert_eq!(source::listed(&source::named_commands("make", targets)), vec![
+        "make build # Compile everything",
+        "make test # Run the tests",
+        "make clean",
+        "make install"
+    ]);
+}
@@ -0,0 +1,72 @@
+use std::path::{Path, PathBuf};
+
+use command::Command;
+use source::{self, CommandSource};
+
+use error::{Error, Result};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PackageManager { Npm, Yarn, Pnpm }
+
+/// The scripts of the `package.json` of a directory, as `npm run <script>`, described by their code
+pub struct PackageJson {
+    pub dir: PathBuf,
+    /// Guessed from the lock file when not given
+    pub manager: Option<PackageManager>
+}
+
+impl PackageManager {
+    /// After the lock file of the directory, npm by default
+    pub fn guess(dir: &Path) -> PackageManager {
+        if dir.join("yarn.lock").exists() {
+            PackageManager::Yarn
+        } else if dir.join("pnpm-lock.yaml").exists() {
+            PackageManager::Pnpm
+        } else {
+            PackageManager::Npm
+        }
+    }
+
+    pub fn name(self: PackageManager) -> &'static str {
+        match self {
+            PackageManager::Npm  => "npm",
+            PackageManager::Yarn => "yarn",
+            PackageManager::Pnpm => "pnpm"
+        }
+    }
+}
+
+impl CommandSource for PackageJson {
+    /// Nothing without a `package.json`
+    fn load(self: &PackageJson) -> Result<Vec<Command>> {
+        let (path, content) = match source::read_first(&self.dir, &["package.json"])? {
+            Some(found) => found,
+            None => return Ok(Vec::new())
+        };
+        let manager = self.manager.unwrap_or_else(|| PackageManager::guess(&self.dir));
+        scripts(&content, manager).map_err(|e| Error::ConfigParse { path: Some(path), line: None, message: e.to_string() })
+    }
+}
+
+fn scripts(content: &str, manager: PackageManager) -> Result<Vec<Command>> {
+    let package: ::serde_json::Value = ::serde_json::from_str(content)?;
+    let scripts = match package.get("scripts").and_then(|scripts| scripts.as_object()) {
+        Some(scripts) => scripts,
+        None => return Ok(Vec::new())
+    };
+
+    Ok(source::named_commands(&format!("{} run", manager.name()), scripts.iter()
+        .map(|(name, code)| (name.clone(), code.as_str().map(str::to_owned)))))
+}
+
+#[test]
+fn listing_scripts() {
+    let commands = scripts(r#"{"name": "app", "scripts": {"test": "jest", "build": "tsc -p ."}}"#, PackageManager::Yarn).unwrap();
+
+    assert_eq!(source::listed(&commands), vec!["yarn run build # tsc -p .", "yarn run test # jest"]);
+    assert_eq!(commands[1].keywords, vec!["yarn", "test"]);
+
+    assert!(scripts(r#"{"name": "app"}"#, PackageManager::Npm).unwrap().is_empty());
+    assert!(scripts("{", PackageManager::Npm).is_err());
+}
@@ -0,0 +1,272 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process;
+
+use collate::natural_cmp;
+use command::Command;
+use config::{CommandEntry, Config, Location, Machine, Unavailable};
+use danger::DangerRules;
+use source::CommandSource;
+
+use error::{Error, Result};
+
+/// The `[packs]` table of the configuration, e.g. `[packs] registry = "https://example.com/packs/index.json"`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct PacksConfig {
+    /// the index listing the packs, fetched with curl (so a `file://` URL works too)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registry: Option<String>
+}
+
+/// What a registry lists: every version of every pack
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct Index {
+    pub packs: Vec<Release>
+}
+
+/// A version of a pack: a configuration file whose commands get listed under the name of the pack
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Release {
+    pub name: String,
+    pub version: String,
+    pub url: String,
+    /// of the file, in hexadecimal
+    pub sha256: String
+}
+
+/// A pack as installed, see `PackDir::installed`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Installed {
+    pub version: String,
+    pub sha256: String,
+    /// installed at a given version, which `update` leaves as it is
+    #[serde(default)]
+    pub pinned: bool
+}
+
+/// The `commands.d` directory next to the configuration file, a `<name>.toml` file per pack
+/// along with `packs.json`, which tells their versions
+pub struct PackDir {
+    pub path: PathBuf
+}
+
+/// The commands of the installed packs, flagged by the rules of the user rather than by those of the packs
+pub struct Packs {
+    pub dir: PackDir,
+    pub danger: DangerRules,
+    /// what becomes of those which cannot run here, as the user's configuration says
+    pub unavailable: Unavailable
+}
+
+impl PacksConfig {
+    pub fn index(self: &PacksConfig) -> Result<Index> {
+        let url = self.registry.as_ref()
+            .ok_or_else(|| Error::InvalidInput("No registry to fetch the packs from: set `[packs] registry = \"<URL>\"`".to_owned()))?;
+        Ok(::serde_json::from_slice(&fetch(url)?)?)
+    }
+}
+
+impl Index {
+    /// That version, or else the latest one
+    pub fn release(self: &Index, name: &str, version: Option<&str>) -> Result<&Release> {
+        let releases = self.packs.iter().filter(|release| release.name == name);
+        match version {
+            Some(version) => releases.into_iter().find(|release| release.version == version)
+                .ok_or_else(|| Error::InvalidInput(format!("The registry has no version {} of the pack `{}`", version, name))),
+            None => releases.max_by(|a, b| natural_cmp(&a.version, &b.version))
+                .ok_or_else(|| Error::InvalidInput(format!("The registry has no pack named `{}`", name)))
+        }
+    }
+}
+
+impl PackDir {
+    pub fn of(location: &Location) -> PackDir {
+        PackDir { path: location.dir().join("commands.d") }
+    }
+
+    pub fn installed(self: &PackDir) -> Result<BTreeMap<String, Installed>> {
+        let path = self.path.join("packs.json");
+        if !path.exists() {
+            return Ok(BTreeMap::new());
+        }
+        let file = File::open(&path).map_err(|error| Error::File { path: path.clone(), error })?;
+        Ok(::serde_json::from_reader(file)?)
+    }
+
+    /// Replacing the version installed, if any, once the checksum matches and the commands parse
+    pub fn install(self: &PackDir, release: &Release, pinned: bool) -> Result<()> {
+        if release.name.is_empty() || release.name.contains(|c: char| c == '/' || c == '.' || c.is_whitespace()) {
+            return Err(Error::InvalidInput(format!("`{}` cannot name a pack: it names its file", release.name)));
+        }
+        let content = fetch(&release.url)?;
+        let sha256 = sha256(&content)?;
+        if !sha256.eq_ignore_ascii_case(&release.sha256) {
+            return Err(Error::InvalidInput(format!("The checksum of {} is {}, not {} as the registry says",
+                                                   release.url, sha256, release.sha256)));
+        }
+        let config = Config::parse(&String::from_utf8_lossy(&content))?;
+        config.commands()?;
+
+        fs::create_dir_all(&self.path)?;
+        let mut installed = self.installed()?;
+        installed.insert(release.name.clone(), Installed { version: release.version.clone(), sha256, pinned });
+        // both written in full before either replaces what is installed, the versions last
+        let commands = self.write_temporary(&format!("{}.toml", release.name), &content)?;
+        let versions = self.write_temporary("packs.json", ::serde_json::to_string_pretty(&installed)?.as_bytes())?;
+        fs::rename(&commands, self.path.join(format!("{}.toml", release.name)))?;
+        fs::rename(&versions, self.path.join("packs.json"))?;
+        Ok(())
+    }
+
+    fn write_temporary(self: &PackDir, name: &str, content: &[u8]) -> Result<PathBuf> {
+        let path = self.path.join(format!(".{}.{}", name, process::id()));
+        fs::write(&path, content).map_err(|error| Error::File { path: path.clone(), error })?;
+        Ok(path)
+    }
+
+    /// The packs to bring to the latest version of the index, but the pinned ones and those it no longer lists
+    pub fn outdated<'a>(self: &PackDir, index: &'a Index) -> Result<Vec<&'a Release>> {
+        let mut outdated = Vec::new();
+        for (name, installed) in self.installed()?.iter().filter(|&(_, installed)| !installed.pinned) {
+            if let Ok(latest) = index.release(name, None) {
+                if natural_cmp(&latest.version, &installed.version).is_gt() {
+                    outdated.push(latest);
+                }
+            }
+        }
+        Ok(outdated)
+    }
+
+    /// The installed packs the index no longer lists, which `update` leaves as they are
+    pub fn unlisted(self: &PackDir, index: &Index) -> Result<Vec<String>> {
+        Ok(self.installed()?.into_keys().filter(|name| index.release(name, None).is_err()).collect())
+    }
+}
+
+impl Packs {
+    /// Those of every pack along with their entries, whatever machine they are listed on (see `Config::entries`),
+    /// the failing packs being skipped, as are all of them when their versions cannot be read
+    pub fn entries(self: &Packs) -> Vec<(CommandEntry, Command)> {
+        let installed = self.dir.installed().unwrap_or_else(|e| {
+            eprintln!("Cannot list the packs installed in {}: {}", self.dir.path.display(), e);
+            BTreeMap::new()
+        });
+        let mut entries = Vec::new();
+        for name in installed.keys() {
+            match self.pack_entries(name) {
+                Ok(loaded) => entries.extend(loaded),
+                Err(e) => eprintln!("Cannot load the pack `{}`: {}", name, e)
+            }
+        }
+        entries
+    }
+
+    /// Those of a pack, whose own `[danger]` table and `allow_danger` do not count
+    fn pack_entries(self: &Packs, name: &str) -> Result<Vec<(CommandEntry, Command)>> {
+        let path = self.dir.path.join(format!("{}.toml", name));
+        let config = Config::read(&path)?;
+        Ok(config.entries()?.into_iter().map(|(entry, mut command)| {
+            command.group = command.group.take().or_else(|| Some(name.to_owned()));
+            command.danger = self.danger.check(command.cmd.template()).map(str::to_owned);
+            (entry.clone(), command)
+        }).collect())
+    }
+}
+
+/// Those listed on this machine
+impl CommandSource for Packs {
+    fn load(self: &Packs) -> Result<Vec<Command>> {
+        let machine = Machine::current();
+        Ok(self.entries().into_iter()
+            .filter_map(|(entry, command)| entry.on(&machine, self.unavailable, command))
+            .collect())
+    }
+}
+
+/// The content at that URL, with curl
+fn fetch(url: &str) -> Result<Vec<u8>> {
+    let output = process::Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location", url])
+        .stdin(process::Stdio::null())
+        .output()
+        .map_err(|e| Error::Process { program: "curl".to_owned(), message: format!("cannot run it: {}", e) })?;
+    if !output.status.success() {
+        return Err(Error::Process { program: "curl".to_owned(), message: String::from_utf8_lossy(&output.stderr).trim().to_owned() });
+    }
+    Ok(output.stdout)
+}
+
+/// With `sha256sum`, or `shasum` as on macOS
+fn sha256(content: &[u8]) -> Result<String> {
+    let error = |message: String| Error::Process { program: "sha256sum".to_owned(), message };
+    let mut child = process::Command::new("sha256sum")
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .spawn()
+        .or_else(|_| process::Command::new("shasum").args(["-a", "256"])
+            .stdin(process::Stdio::piped())
+            .stdout(process::Stdio::piped())
+            .stderr(process::Stdio::piped())
+            .spawn())
+        .map_err(|e| error(format!("cannot run it: {}", e)))?;
+    child.stdin.take().expect("the input is piped").write_all(content)?;
+    let output = child.wait_with_output()?;
+
+    String::from_utf8_lossy(&output.stdout).split_whitespace().next()
+        .filter(|_| output.status.success())
+        .map(str::to_owned)
+        .ok_or_else(|| error(String::from_utf8_lossy(&output.stderr).trim().to_owned()))
+}
+
+#[test]
+fn installing_packs() {
+    let dir = ::std::env::temp_dir().join(format!("smart-runner-packs-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let release = |version: &str, content: &str| {
+        let path = dir.join(format!("docker-{}.toml", version));
+        fs::write(&path, content).unwrap();
+        Release { name: "docker".to_owned(), version: version.to_owned(), url: format!("file://{}", path.display()), sha256: sha256(content.as_bytes()).unwrap() }
+    };
+    let index = Index { packs: vec![
+        release("1.9", "[[commands]]\ncmd = \"docker ps\"\nkeywords = [\"docker\"]\n"),
+        release("1.10", "[[commands]]\ncmd = \"docker ps -a\"\nkeywords = [\"docker\"]\n")
+    ] };
+    assert_eq!(index.release("docker", None).unwrap().version, "1.10");
+    assert!(index.release("docker", Some("2.0")).is_err());
+    assert!(index.release("git", None).is_err());
+
+    let danger = ::danger::DangerConfig { patterns: vec!["^docker rm".to_owned()], ..Default::default() }.rules();
+    let packs = Packs { dir: PackDir { path: dir.join("commands.d") }, danger, unavailable: Unavailable::Hide };
+    packs.dir.install(index.release("docker", Some("1.9")).unwrap(), false).unwrap();
+    let commands = packs.load().unwrap();
+    assert_eq!(commands.iter().map(|command| command.cmd.template()).collect::<Vec<_>>(), vec!["docker ps"]);
+    assert_eq!(commands[0].group, Some("docker".to_owned()));
+    assert_eq!(packs.dir.outdated(&index).unwrap(), vec![&index.packs[1]]);
+
+    packs.dir.install(index.release("docker", Some("1.9")).unwrap(), true).unwrap();
+    assert!(packs.dir.outdated(&index).unwrap().is_empty());
+
+    let tampered = Release { sha256: "0".repeat(64), ..index.packs[1].clone() };
+    assert!(packs.dir.install(&tampered, false).is_err());
+    assert_eq!(packs.dir.installed().unwrap()["docker"].version, "1.9");
+
+    // flagged by the rules of the user, whatever the pack says
+    let content = "[danger]\nbuiltin = false\n[[commands]]\ncmd = \"docker rm -f {id}\"\nkeywords = [\"docker\"]\nallow_danger = true\n";
+    let path = dir.join("prune.toml");
+    fs::write(&path, content).unwrap();
+    let prune = Release { name: "prune".to_owned(), version: "1.0".to_owned(), url: format!("file://{}", path.display()), sha256: sha256(content.as_bytes()).unwrap() };
+    packs.dir.install(&prune, false).unwrap();
+    let commands = packs.load().unwrap();
+    assert_eq!(commands.iter().map(|command| command.danger.as_deref()).collect::<Vec<_>>(), vec![None, Some("matches `^docker rm`")]);
+
+    // a broken pack leaves the others be, and one gone from the registry is not updated
+    fs::write(packs.dir.path.join("prune.toml"), "[[commands]\n").unwrap();
+    assert_eq!(packs.load().unwrap().len(), 1);
+    assert_eq!(packs.dir.unlisted(&index).unwrap(), vec!["prune"]);
+    assert!(packs.dir.outdated(&index).unwrap().is_empty());
+    assert!(fs::read_dir(&packs.dir.path).unwrap().all(|entry| !entry.unwrap().file_name().to_string_lossy().starts_with('.')));
+    fs::remove_dir_all(&dir).unwrap();
+}
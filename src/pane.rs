@@ -0,0 +1,324 @@
+use termion::event::Key;
+use termion::input::TermRead;
+use termion::raw::IntoRawMode;
+use std::collections::VecDeque;
+use std::io::{stderr, BufRead, BufReader, Read};
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::thread;
+
+use exec;
+use system::{self, StoppableStdin};
+use render::{self, Frame, Line, Renderer, Span, Style, TerminalRenderer, Theme};
+
+use error::{Error, Result};
+
+/// How many lines the pane keeps, the oldest being dropped past that
+pub const MAX_LINES: usize = 100_000;
+
+/// How many lines PageUp and PageDown scroll by
+const PAGE: usize = 20;
+
+/// How many lines around the current one get drawn, no terminal being that tall
+const WINDOW: usize = 500;
+
+/// The output of a running command, e.g. `kubectl logs -f {pod}`, as it comes: Up and Down scroll it,
+/// `f` follows the last line or stops following it, `/` searches for a text (`n` and `N` going to the next
+/// and the previous line containing it) and `q` closes the pane, killing the command if still running
+#[derive(Debug, Default)]
+pub struct Pane {
+    /// The command line running
+    pub title: String,
+    pub lines: VecDeque<String>,
+    /// The line scrolled to, at the bottom of the screen
+    pub at: usize,
+    pub follow: bool,
+    /// What's typed in after `/`, until Enter
+    pub searching: Option<String>,
+    /// The text searched for last
+    pub pattern: Option<String>,
+    /// Once the command is done
+    pub status: Option<i32>
+}
+
+/// What the pane waits for
+enum Input {
+    Key(Key),
+    Line(String),
+    /// The end of the output or of the errors
+    Closed,
+    /// The terminal got resized
+    Resize
+}
+
+impl Pane {
+    pub fn new(title: &str) -> Pane {
+        Pane { title: title.to_owned(), follow: true, ..Default::default() }
+    }
+
+    pub fn push(self: &mut Pane, line: String) {
+        self.lines.push_back(line);
+        if self.lines.len() > MAX_LINES {
+            self.lines.pop_front();
+            self.at = self.at.saturating_sub(1);
+        }
+        if self.follow {
+            self.at = self.lines.len() - 1;
+        }
+    }
+
+    /// Whether the pane is to be closed
+    pub fn key(self: &mut Pane, key: Key) -> bool {
+        if let Some(ref mut searching) = self.searching {
+            match key {
+                Key::Char('\n') => {
+                    let pattern = self.searching.take().unwrap_or_default();
+                    if !pattern.is_empty() {
+                        self.pattern = Some(pattern);
+                        self.search(true, true);
+                    }
+                },
+                Key::Esc | Key::Ctrl('c') => self.searching = None,
+                Key::Backspace => {
+                    searching.pop();
+                },
+                Key::Char(c) if !c.is_control() => searching.push(c),
+                _ => ()
+            }
+            return false;
+        }
+
+        let last = self.lines.len().saturating_sub(1);
+        match key {
+            Key::Char('q') | Key::Esc | Key::Ctrl('c') => return true,
+            Key::Up | Key::Char('k')   => self.scroll_to(self.at.saturating_sub(1)),
+            Key::Down | Key::Char('j') => self.scroll_to(self.at + 1),
+            Key::PageUp                => self.scroll_to(self.at.saturating_sub(PAGE)),
+            Key::PageDown              => self.scroll_to(self.at + PAGE),
+            Key::Home | Key::Char('g') => self.scroll_to(0),
+            Key::End | Key::Char('G')  => self.scroll_to(last),
+            Key::Char('f') => {
+                self.follow = !self.follow;
+                if self.follow {
+                    self.at = last;
+                }
+            },
+            Key::Char('/') => self.searching = Some(String::new()),
+            Key::Char('n') => self.search(true, false),
+            Key::Char('N') => self.search(false, false),
+            _ => ()
+        }
+        false
+    }
+
+    /// Following again once scrolled to the last line, as `tail -f` would
+    fn scroll_to(self: &mut Pane, at: usize) {
+        let last = self.lines.len().saturating_sub(1);
+        self.at = at.min(last);
+        self.follow = self.at == last;
+    }
+
+    /// Go to the next (or previous) line containing the pattern, if any, starting with the current one or not,
+    /// wrapping around the ends as `less` does
+    fn search(self: &mut Pane, forward: bool, inclusive: bool) {
+        let (pattern, len) = match self.pattern {
+            Some(ref pattern) if !self.lines.is_empty() => (pattern, self.lines.len()),
+            _ => return
+        };
+        let skip = if inclusive { 0 } else { 1 };
+        let found = (0..len)
+            .map(|i| if forward { (self.at + skip + i) % len } else { (self.at + 2 * len - skip - i) % len })
+            .find(|&idx| self.lines[idx].contains(pattern.as_str()));
+        if let Some(idx) = found {
+            self.at = idx;
+            self.follow = false;
+        }
+    }
+
+    /// The command or the search on the prompt line, what's going on on the info line, then the lines
+    /// the renderer scrolls along the current one, the occurrences of the pattern being highlighted
+    pub fn view(self: &Pane) -> Frame {
+        let (prompt, cursor) = match self.searching {
+            Some(ref searching) => (Line::new(vec![Span::normal(format!("/{}", searching))]), render::width(searching) + 1),
+            None => (Line::new(vec![Span::new(self.title.clone(), Style::Program)]), 0)
+        };
+
+        let mut info = vec![match self.status {
+            Some(status) => format!("exited with status {}", status),
+            None => "running".to_owned()
+        }];
+        info.push(format!("{} lines", self.lines.len()));
+        if self.follow {
+            info.push("following".to_owned());
+        }
+        if let Some(ref pattern) = self.pattern {
+            let matches = self.lines.iter().filter(|line| line.contains(pattern.as_str())).count();
+            info.push(format!("{} lines match `{}` (n/N)", matches, pattern));
+        }
+        info.push("/ search, f follow, q quit".to_owned());
+
+        let start = self.at.saturating_sub(WINDOW);
+        let body = self.lines.range(start..self.lines.len().min(self.at + WINDOW)).enumerate().map(|(idx, line)| Line {
+            spans: highlighted(line, self.pattern.as_deref()),
+            selected: start + idx == self.at
+        }).collect();
+        Frame { prompt, cursor, info: Line::new(vec![Span::new(info.join(" · "), Style::Description)]), body, preview: Vec::new() }
+    }
+}
+
+fn highlighted(line: &str, pattern: Option<&str>) -> Vec<Span> {
+    let pattern = match pattern {
+        Some(pattern) if !pattern.is_empty() => pattern,
+        _ => return vec![Span::normal(line)]
+    };
+    let mut spans = Vec::new();
+    let mut rest = line;
+    while let Some(idx) = rest.find(pattern) {
+        if idx > 0 {
+            spans.push(Span::normal(&rest[..idx]));
+        }
+        spans.push(Span::new(pattern, Style::Highlight));
+        rest = &rest[idx + pattern.len()..];
+    }
+    if !rest.is_empty() || spans.is_empty() {
+        spans.push(Span::normal(rest));
+    }
+    spans
+}
+
+/// Without the escape sequences, e.g. the colors, which would mess the screen up, the tabs expanded
+fn plain(line: &str) -> String {
+    let mut plain = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => {
+                // a CSI sequence ends with a char in `@`..`~`, the others with the next char
+                if chars.next_if_eq(&'[').is_some() {
+                    while chars.next().is_some_and(|c| !('@'..='~').contains(&c)) {}
+                } else {
+                    chars.next();
+                }
+            },
+            '\t' => plain.push_str("    "),
+            c if c.is_control() => (),
+            c => plain.push(c)
+        }
+    }
+    plain
+}
+
+/// Run the command line with the user's shell, streaming its output and errors into a pane drawn on stderr,
+/// until closed, returning its exit status and the lines it printed
+pub fn stream(cmd: &str, theme: Theme) -> Result<(i32, Vec<String>)> {
+    let mut child = exec::spawn(cmd)?;
+    let (sender, receiver) = mpsc::channel();
+    let outputs: Vec<Box<dyn Read + Send>> = vec![
+        Box::new(child.stdout.take().expect("the output is piped")),
+        Box::new(child.stderr.take().expect("the errors are piped"))];
+    for output in outputs {
+        let sender = sender.clone();
+        thread::spawn(move || {
+            // whatever its encoding, up to the end rather than the first line which isn't UTF-8
+            let mut reader = BufReader::new(output);
+            let mut line = Vec::new();
+            while reader.read_until(b'\n', &mut line).is_ok_and(|read| read > 0) {
+                let text = String::from_utf8_lossy(&line);
+                if sender.send(Input::Line(plain(text.trim_end_matches('\n')))).is_err() {
+                    return;
+                }
+                line.clear();
+            }
+            let _ = sender.send(Input::Closed);
+        });
+    }
+    let keyboard = StoppableStdin::default();
+    let stop = keyboard.stop.clone();
+    let resize_sender = sender.clone();
+    let resizes = system::watch_resizes(stop.clone(), move || resize_sender.send(Input::Resize).is_ok());
+    let keys = thread::spawn(move || {
+        for key in keyboard.keys().map_while(|key| key.ok()) {
+            if sender.send(Input::Key(key)).is_err() {
+                break;
+            }
+        }
+    });
+
+    let terminal = stderr().into_raw_mode().map_err(Error::Terminal)?;
+    let mut renderer = TerminalRenderer::new(terminal, theme);
+    let mut pane = Pane::new(cmd);
+    let mut open = 2;
+    renderer.render(&pane.view())?;
+    'running: loop {
+        let mut next = receiver.recv().ok();
+        while let Some(input) = next {
+            match input {
+                Input::Line(line) => pane.push(line),
+                Input::Closed => {
+                    open -= 1;
+                    if open == 0 {
+                        pane.status = Some(exec::wait(&mut child)?);
+                    }
+                },
+                Input::Key(key) => if pane.key(key) {
+                    break 'running;
+                },
+                Input::Resize => renderer.resize()?
+            }
+            // drawn once the lines pending are all in
+            next = receiver.try_recv().ok();
+        }
+        renderer.render(&pane.view())?;
+    }
+    stop.store(true, Ordering::SeqCst);
+    let _ = keys.join();
+    let _ = resizes.join();
+    renderer.clear()?;
+
+    let status = match pane.status {
+        Some(status) => status,
+        None => {
+            // closed while running
+            exec::kill(&child);
+            exec::wait(&mut child)?
+        }
+    };
+    Ok((status, pane.lines.into()))
+}
+
+#[test]
+fn scrolling_and_searching() {
+    let mut pane = Pane::new("kubectl logs -f web");
+    for line in &["GET /", "GET /health", "POST /login", "GET /health"] {
+        pane.push(plain(line));
+    }
+    assert_eq!(pane.view().text(), "kubectl logs -f web\nrunning · 4 lines · following · / search, f follow, q quit\n  GET /\n  GET /health\n  POST /login\n* GET /health");
+
+    pane.key(Key::Up);
+    pane.push("GET /".to_owned());
+    assert_eq!((pane.at, pane.follow), (2, false));
+
+    for key in "/health\n".chars() {
+        pane.key(Key::Char(key));
+    }
+    assert_eq!((pane.at, pane.pattern.as_deref()), (3, Some("health")));
+    pane.key(Key::Char('N'));
+    assert_eq!(pane.at, 1);
+    assert_eq!(pane.view().body[1].spans, vec![Span::normal("GET /"), Span::new("health", Style::Highlight)]);
+    pane.key(Key::Char('N'));
+    assert_eq!(pane.at, 3);
+
+    pane.key(Key::Char('f'));
+    pane.status = Some(0);
+    assert_eq!(pane.at, 4);
+    assert_eq!(pane.view().info.text(), "exited with status 0 · 5 lines · following · 2 lines match `health` (n/N) · / search, f follow, q quit");
+    assert!(pane.key(Key::Char('q')));
+
+    assert_eq!(plain("\x1b[1;31mERROR\x1b[0m\tdown\r"), "ERROR    down");
+
+    let mut full = Pane::new("yes");
+    for idx in 0..MAX_LINES + 2 {
+        full.push(idx.to_string());
+    }
+    assert_eq!((full.lines.len(), full.lines[0].as_str(), full.at), (MAX_LINES, "2", MAX_LINES - 1));
+}
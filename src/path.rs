@@ -0,0 +1,127 @@
+use std::collections::{BTreeSet, HashMap};
+use std::env;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::process;
+
+use command::{Command, Placeholders};
+use source::CommandSource;
+use system;
+
+use error::{Error, Result};
+
+/// How many names are given to each `whatis` call
+const BATCH: usize = 200;
+
+/// The executables of the `PATH`, described by their `whatis` summary. Those summaries are cached
+/// (by default in `~/.cache/smart-runner/whatis.json`), only the new executables being looked up.
+pub struct PathExecutables {
+    pub cache: PathBuf
+}
+
+impl PathExecutables {
+    pub fn user() -> Option<PathExecutables> {
+        ::dirs::cache_dir().map(|dir| PathExecutables { cache: dir.join("smart-runner").join("whatis.json") })
+    }
+
+    /// The names without a summary are cached too, as empty, not to be looked up again
+    fn summaries(self: &PathExecutables, names: &BTreeSet<String>) -> Result<HashMap<String, String>> {
+        let mut summaries: HashMap<String, String> = if self.cache.is_file() {
+            let file = File::open(&self.cache).map_err(|error| Error::File { path: self.cache.clone(), error })?;
+            ::serde_json::from_reader(file).unwrap_or_default()
+        } else {
+            HashMap::new()
+        };
+
+        let missing: Vec<&String> = names.iter().filter(|name| !summaries.contains_key(*name)).collect();
+        if missing.is_empty() {
+            return Ok(summaries);
+        }
+        if system::find_executable("whatis").is_some() {
+            for batch in missing.chunks(BATCH) {
+                summaries.extend(whatis(batch));
+            }
+        }
+        for name in missing {
+            summaries.entry(name.clone()).or_default();
+        }
+
+        if let Some(dir) = self.cache.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.cache, ::serde_json::to_string(&summaries)?)?;
+        Ok(summaries)
+    }
+}
+
+impl CommandSource for PathExecutables {
+    fn load(self: &PathExecutables) -> Result<Vec<Command>> {
+        let names = executables();
+        let summaries = self.summaries(&names)?;
+        Ok(names.into_iter()
+            .map(|name| Command {
+                cmd: Placeholders::literal(&name),
+                description: summaries.get(&name).filter(|summary| !summary.is_empty()).cloned(),
+                keywords: vec![name],
+                ..Default::default()
+            })
+            .collect())
+    }
+
+    fn runs_commands(self: &PathExecutables) -> bool {
+        true
+    }
+}
+
+/// The names of the executables of the `PATH` directories
+fn executables() -> BTreeSet<String> {
+    let mut names = BTreeSet::new();
+    let dirs = env::var_os("PATH").map_or(Vec::new(), |paths| env::split_paths(&paths).collect());
+    for dir in dirs {
+        if let Ok(entries) = fs::read_dir(&dir) {
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if !name.starts_with('.') && system::find_executable(&dir.join(&name).to_string_lossy()).is_some() {
+                    names.insert(name);
+                }
+            }
+        }
+    }
+    names
+}
+
+/// Nothing for the names whatis knows nothing about
+fn whatis(names: &[&String]) -> HashMap<String, String> {
+    process::Command::new("whatis")
+        .args(names)
+        .stdin(process::Stdio::null())
+        .stderr(process::Stdio::null())
+        .output()
+        .map(|output| parse_whatis(&String::from_utf8_lossy(&output.stdout)))
+        .unwrap_or_default()
+}
+
+/// Lines such as `ls (1)   - list directory contents`, the first section being kept
+fn parse_whatis(output: &str) -> HashMap<String, String> {
+    let mut summaries = HashMap::new();
+    for line in output.lines() {
+        if let Some(idx) = line.find(" - ") {
+            if let Some(name) = line[..idx].split_whitespace().next() {
+                summaries.entry(name.to_owned()).or_insert_with(|| line[idx + 3..].trim().to_owned());
+            }
+        }
+    }
+    summaries
+}
+
+#[test]
+fn parsing_whatis() {
+    let summaries = parse_whatis(concat!(
+        "ls (1)               - list directory contents\n",
+        "ls (1p)              - list directory contents (POSIX)\n",
+        "git-log (1)          - Show commit logs\n"));
+
+    assert_eq!(summaries.len(), 2);
+    assert_eq!(summaries["ls"], "list directory contents");
+    assert_eq!(summaries["git-log"], "Show commit logs");
+}
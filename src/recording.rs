@@ -0,0 +1,118 @@
+use termion::event::Key;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use error::{Error, Result};
+use keys::{key_name, parse_keys};
+
+/// The keys typed in the picker, with the time elapsed before each, as written by `--record`
+/// and played back by `--replay`: one JSON object per line, e.g. `{"after_ms":180,"key":"<Tab>"}`
+#[derive(Debug, Default, PartialEq)]
+pub struct Recording {
+    pub inputs: Vec<Recorded>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Recorded {
+    /// since the previous input, or since the picker showed up
+    pub after_ms: u64,
+    #[serde(flatten)]
+    pub input: Typed
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Typed {
+    /// As named by `keys::parse_key`, a char standing for itself
+    Key(String),
+    /// Text pasted as a whole
+    Paste(String)
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> Result<Recording> {
+        let file = File::open(path).map_err(|error| Error::File { path: path.to_owned(), error })?;
+        let mut inputs = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let recorded: Recorded = ::serde_json::from_str(&line)?;
+            // rather than once replaying
+            if let Typed::Key(ref name) = recorded.input {
+                key(name)?;
+            }
+            inputs.push(recorded);
+        }
+        Ok(Recording { inputs })
+    }
+
+    /// The file to `save` the recording into, created up front: the session is not to be lost
+    /// because the file cannot be written once it's over
+    pub fn create(path: &Path) -> Result<File> {
+        File::create(path).map_err(|error| Error::File { path: path.to_owned(), error })
+    }
+
+    pub fn save<W: Write>(self: &Recording, mut out: W) -> Result<()> {
+        let mut content = String::new();
+        for recorded in &self.inputs {
+            content.push_str(&::serde_json::to_string(recorded)?);
+            content.push('\n');
+        }
+        out.write_all(content.as_bytes())?;
+        Ok(())
+    }
+
+    /// Unless no name stands for it, see `keys::key_name`
+    pub fn push_key(self: &mut Recording, after: Duration, key: Key) {
+        if let Some(name) = key_name(key) {
+            self.push(after, Typed::Key(name));
+        }
+    }
+
+    pub fn push_paste(self: &mut Recording, after: Duration, text: String) {
+        self.push(after, Typed::Paste(text));
+    }
+
+    fn push(self: &mut Recording, after: Duration, input: Typed) {
+        self.inputs.push(Recorded { after_ms: after.as_millis() as u64, input });
+    }
+}
+
+/// The key of a recorded name, e.g. `a` or `<C-r>`
+pub fn key(name: &str) -> Result<Key> {
+    let mut keys = parse_keys(name)?;
+    match keys.len() {
+        1 => Ok(keys.remove(0)),
+        _ => Err(Error::InvalidInput(format!("`{}` names no key", name)))
+    }
+}
+
+#[test]
+fn recording_keys() {
+    let path = ::std::env::temp_dir().join(format!("smart-runner-recording-{}.log", ::std::process::id()));
+    let mut recording = Recording::default();
+    recording.push_key(Duration::from_millis(120), Key::Char('n'));
+    recording.push_key(Duration::from_millis(80), Key::Char('\t'));
+    recording.push_key(Duration::from_millis(10), Key::Null);
+    recording.push_paste(Duration::from_millis(300), "a b".to_owned());
+    recording.push_key(Duration::from_millis(5), Key::Alt('2'));
+    recording.save(Recording::create(&path).unwrap()).unwrap();
+
+    assert_eq!(::std::fs::read_to_string(&path).unwrap().lines().take(2).collect::<Vec<_>>(),
+               vec![r#"{"after_ms":120,"key":"n"}"#, r#"{"after_ms":80,"key":"<Tab>"}"#]);
+    let loaded = Recording::load(&path).unwrap();
+    assert_eq!(loaded, recording);
+    assert_eq!(loaded.inputs.iter().filter_map(|recorded| match recorded.input {
+        Typed::Key(ref name) => Some(key(name).unwrap()),
+        Typed::Paste(_) => None
+    }).collect::<Vec<_>>(), vec![Key::Char('n'), Key::Char('\t'), Key::Alt('2')]);
+
+    ::std::fs::write(&path, "{\"after_ms\":1,\"key\":\"<Nope>\"}\n").unwrap();
+    assert!(Recording::load(&path).is_err());
+    ::std::fs::remove_file(&path).unwrap();
+    assert!(Recording::create(&path.join("nowhere")).is_err());
+}
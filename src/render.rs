@@ -0,0 +1,532 @@
+use termion::{clear, color, cursor, style};
+use std::io::Write;
+use std::ops::Range;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use badge::BadgeColor;
+use system;
+
+use error::Result;
+
+/// Pasted text is then sent between markers, see `runner::PasteReader`
+pub const BRACKETED_PASTE_ON: &str = "\x1b[?2004h";
+pub const BRACKETED_PASTE_OFF: &str = "\x1b[?2004l";
+
+/// Below that many lines, only the prompt and the top commands are drawn: no info line nor rulers
+const MIN_FULL_HEIGHT: u16 = 6;
+
+// see below for an explanation of why this isn't a mere function
+// Note: it's here because it has to be above it's application point
+macro_rules! write_highlighted {
+    ($dst:expr, $msg:expr, $bg_color:expr, $colored:expr) =>
+        (if $colored {
+            write!($dst, "{}{}{}{}{}",
+                   color::Bg($bg_color),
+                   color::Fg(color::Black),
+                   $msg,
+                   color::Bg(color::Reset),
+                   color::Fg(color::Reset))
+        } else {
+            write!($dst, "{}{}{}", style::Invert, $msg, style::NoInvert)
+        })
+}
+
+/// Draws the frames of the screen
+pub trait Renderer {
+    fn render(&mut self, frame: &Frame) -> Result<()>;
+
+    /// Erase what got drawn, once done
+    fn clear(&mut self) -> Result<()>;
+
+    /// The terminal got resized, the next frame being drawn to its new size
+    fn resize(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Everything the screen shows: the prompt line, the info line (auto-completions or the command
+/// being filled in), the commands and the documentation of the selected one, when asked for
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Frame {
+    pub prompt: Line,
+    /// Where the chars get typed in, as a column of the prompt line starting at 0
+    pub cursor: usize,
+    pub info: Line,
+    pub body: Vec<Line>,
+    /// Drawn below the body, taking at most half of its rows
+    pub preview: Vec<Line>
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Line {
+    pub spans: Vec<Span>,
+    pub selected: bool
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub style: Style
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Style {
+    Normal,
+    ValidKeyword,
+    InvalidKeyword,
+    /// The selected auto-completion and the placeholder being filled in
+    Highlight,
+    Description,
+    /// Why a command is dangerous
+    Danger,
+    /// The parts of a command line, see `highlight`
+    Program,
+    Flag,
+    Quoted,
+    Placeholder,
+    /// A command which cannot run here
+    Unavailable,
+    /// The header of a group of commands
+    Header,
+    /// A command replaced by another one, struck through
+    Deprecated,
+    /// A tag of a command, see `badge::Badges`
+    Badge(BadgeColor)
+}
+
+/// The colors of the screen, as 256-color palette indexes
+#[derive(Clone, Copy, Debug)]
+pub struct Theme {
+    /// Without colors, the keywords and the selected suggestion are in reverse video
+    pub color: bool,
+    pub valid_keyword: color::AnsiValue,
+    pub invalid_keyword: color::AnsiValue,
+    pub highlight: color::AnsiValue,
+    pub description: color::AnsiValue,
+    pub danger: color::AnsiValue,
+    pub program: color::AnsiValue,
+    pub flag: color::AnsiValue,
+    pub quoted: color::AnsiValue,
+    /// Without colors, the placeholders are underlined
+    pub placeholder: color::AnsiValue,
+    /// Without colors, the unavailable commands are faint
+    pub unavailable: color::AnsiValue
+}
+
+/// Draws below the cursor with escape sequences
+pub struct TerminalRenderer<W: Write> {
+    terminal: W,
+    theme: Theme,
+    x: u16,
+    y: u16,
+    width: u16,
+    height: u16,
+    /// Bracketed paste is turned on with the first frame, and off once cleared
+    pasting: bool
+}
+
+/// Where the parts of a frame go on a screen of some height
+struct Layout {
+    /// With the info line between rulers below the prompt, unless the screen is too short
+    full: bool,
+    /// The rows of the commands
+    rows: usize,
+    /// The commands fitting in them, scrolled down to the selected one
+    visible: Range<usize>,
+    preview_rows: usize
+}
+
+/// Keeps the last frame, e.g. to test the screen
+#[derive(Default)]
+pub struct HeadlessRenderer {
+    pub frame: Frame
+}
+
+
+impl Line {
+    pub fn new(spans: Vec<Span>) -> Line {
+        Line { spans, selected: false }
+    }
+
+    pub fn text(self: &Line) -> String {
+        self.spans.iter().map(|span| span.text.as_str()).collect()
+    }
+}
+
+impl Span {
+    pub fn new<S: Into<String>>(text: S, style: Style) -> Span {
+        Span { text: text.into(), style }
+    }
+
+    pub fn normal<S: Into<String>>(text: S) -> Span {
+        Span::new(text, Style::Normal)
+    }
+}
+
+impl Frame {
+    /// One line after the other, the selected command being marked with a `*`
+    /// and the preview following a `--` line
+    pub fn text(self: &Frame) -> String {
+        let mut lines = vec![self.prompt.text(), self.info.text()];
+        for line in &self.body {
+            lines.push(format!("{}{}", if line.selected { "* " } else { "  " }, line.text()));
+        }
+        if !self.preview.is_empty() {
+            lines.push("--".to_owned());
+            lines.extend(self.preview.iter().map(Line::text));
+        }
+        lines.join("\n")
+    }
+
+    /// What a terminal of that size shows, without escape sequences, e.g. for golden files:
+    /// each row padded to the width and closed with a `|`, the selected command being followed by a `*`
+    pub fn snapshot(self: &Frame, width: u16, height: u16) -> String {
+        let layout = Layout::new(self, height);
+        let width = width as usize;
+        let row = |line: &Line| {
+            let text = line.text();
+            let (text, columns) = fitting(&text, width);
+            format!("{}{}|{}", text, " ".repeat(width - columns), if line.selected { "*" } else { "" })
+        };
+        let ruler = format!("{}|", "─".repeat(width));
+
+        let mut rows = vec![row(&self.prompt)];
+        if layout.full {
+            rows.extend(vec![ruler.clone(), row(&self.info), ruler.clone()]);
+        }
+        rows.extend(self.body[layout.visible.clone()].iter().map(row));
+        if layout.preview_rows > 0 {
+            rows.resize(rows.len() + layout.rows - layout.visible.len(), row(&Line::default()));
+            rows.push(ruler);
+            rows.extend(self.preview.iter().take(layout.preview_rows).map(row));
+        }
+        rows.join("\n")
+    }
+}
+
+impl Layout {
+    fn new(frame: &Frame, height: u16) -> Layout {
+        let full = height >= MIN_FULL_HEIGHT;
+        let mut rows = height.saturating_sub(if full { 4 } else { 1 }) as usize;
+        let preview_rows = if full { frame.preview.len().min(rows / 2) } else { 0 };
+        if preview_rows > 0 {
+            rows -= preview_rows + 1;
+        }
+        let selected = frame.body.iter().position(|line| line.selected);
+        Layout { full, rows, visible: visible(frame.body.len(), rows, selected), preview_rows }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            color: true,
+            valid_keyword: color::AnsiValue(2), // green
+            invalid_keyword: color::AnsiValue(1), // red
+            highlight: color::AnsiValue(3), // yellow
+            description: color::AnsiValue(2),
+            danger: color::AnsiValue(1), // red
+            program: color::AnsiValue(4), // blue
+            flag: color::AnsiValue(6), // cyan
+            quoted: color::AnsiValue(5), // magenta
+            placeholder: color::AnsiValue(3),
+            unavailable: color::AnsiValue(8) // grey
+        }
+    }
+}
+
+impl Theme {
+    pub fn monochrome() -> Theme {
+        Theme { color: false, ..Theme::default() }
+    }
+}
+
+impl<W: Write> TerminalRenderer<W> {
+    pub fn new(terminal: W, theme: Theme) -> TerminalRenderer<W> {
+        //let vertical_size: u16 = 6;
+        //write!(stdout, "{}", "\n".repeat(vertical_size as usize))?;
+
+        // Cannot get the cursor position without eating off one char
+        // See https://github.com/ticki/termion/issues/136
+        // let (_,y) = stdout.cursor_pos()?;
+
+        let (width, height) = system::terminal_size().unwrap_or((80, 10));
+        TerminalRenderer {
+            terminal,
+            theme,
+            x: 1,
+            y: 1, // y - vertical_size,
+            width,
+            height,
+            pasting: false
+        }
+    }
+
+    /// Cut to the width of the terminal, as a wrapped line would scroll what's below out of the screen
+    fn write_line(self: &mut TerminalRenderer<W>, line: &Line) -> Result<()> {
+        if line.selected {
+            write!(self.terminal, "{}", style::Bold)?;
+        }
+        let mut room = self.width as usize;
+        for span in &line.spans {
+            let (text, columns) = fitting(&span.text, room);
+            room -= columns;
+            self.write_span(text, span.style)?;
+        }
+        if line.selected {
+            write!(self.terminal, "{}", style::Reset)?;
+        }
+        Ok(())
+    }
+
+    fn write_span(self: &mut TerminalRenderer<W>, text: &str, style: Style) -> Result<()> {
+        let theme = self.theme;
+        match style {
+            Style::Normal =>
+                write!(self.terminal, "{}", text)?,
+
+            Style::ValidKeyword =>
+                write_highlighted!(self.terminal, text, theme.valid_keyword, theme.color)?,
+
+            Style::InvalidKeyword if !theme.color =>
+                write!(self.terminal, "{}{}{}", style::CrossedOut, text, style::NoCrossedOut)?,
+
+            Style::InvalidKeyword =>
+                write_highlighted!(self.terminal, text, theme.invalid_keyword, theme.color)?,
+
+            Style::Highlight =>
+                write_highlighted!(self.terminal, text, theme.highlight, theme.color)?,
+
+            Style::Description if theme.color =>
+                write!(self.terminal, "{}{}{}", color::Fg(theme.description), text, color::Fg(color::Reset))?,
+
+            Style::Description =>
+                write!(self.terminal, "{}", text)?,
+
+            Style::Danger if theme.color =>
+                write!(self.terminal, "{}{}{}", color::Fg(theme.danger), text, color::Fg(color::Reset))?,
+
+            Style::Danger =>
+                write!(self.terminal, "{}{}{}", style::Bold, text, style::NoBold)?,
+
+            Style::Placeholder if !theme.color =>
+                write!(self.terminal, "{}{}{}", style::Underline, text, style::NoUnderline)?,
+
+            Style::Unavailable if theme.color =>
+                write!(self.terminal, "{}{}{}", color::Fg(theme.unavailable), text, color::Fg(color::Reset))?,
+
+            Style::Unavailable =>
+                write!(self.terminal, "{}{}{}", style::Faint, text, style::NoFaint)?,
+
+            Style::Header =>
+                write!(self.terminal, "{}{}{}", style::Bold, text, style::NoBold)?,
+
+            Style::Deprecated =>
+                write!(self.terminal, "{}{}{}", style::CrossedOut, text, style::NoCrossedOut)?,
+
+            Style::Badge(badge_color) =>
+                write_highlighted!(self.terminal, text, ansi(badge_color), theme.color)?,
+
+            Style::Program | Style::Flag | Style::Quoted if !theme.color =>
+                write!(self.terminal, "{}", text)?,
+
+            Style::Program | Style::Flag | Style::Quoted | Style::Placeholder => {
+                let fg = match style {
+                    Style::Program => theme.program,
+                    Style::Flag => theme.flag,
+                    Style::Quoted => theme.quoted,
+                    _ => theme.placeholder
+                };
+                write!(self.terminal, "{}{}{}", color::Fg(fg), text, color::Fg(color::Reset))?
+            }
+        };
+        Ok(())
+    }
+}
+
+impl<W: Write> Renderer for TerminalRenderer<W> {
+    fn render(self: &mut TerminalRenderer<W>, frame: &Frame) -> Result<()> {
+        if !self.pasting {
+            write!(self.terminal, "{}", BRACKETED_PASTE_ON)?;
+            self.pasting = true;
+        }
+        let layout = Layout::new(frame, self.height);
+        let body_y = if layout.full { self.y + 4 } else { self.y + 1 };
+
+        if layout.full {
+            let ruler = "─".repeat(self.width as usize);
+
+            write!(self.terminal, "{}{}", cursor::Goto(1, self.y + 1), ruler)?;
+            write!(self.terminal, "{}{}", cursor::Goto(1, self.y + 3), ruler)?;
+
+            write!(self.terminal, "{}{}", cursor::Goto(1, self.y + 2), clear::CurrentLine)?;
+            self.write_line(&frame.info)?;
+        }
+
+        // print commands, as many as fit without scrolling
+        write!(self.terminal, "{}{}", cursor::Goto(1, body_y), clear::AfterCursor)?;
+        for (i, line) in frame.body[layout.visible].iter().enumerate() {
+            if i > 0 {
+                write!(self.terminal, "\r\n")?;
+            }
+            self.write_line(line)?;
+        }
+
+        if layout.preview_rows > 0 {
+            let preview_y = body_y + layout.rows as u16;
+            write!(self.terminal, "{}{}", cursor::Goto(1, preview_y), "─".repeat(self.width as usize))?;
+            for (i, line) in frame.preview.iter().take(layout.preview_rows).enumerate() {
+                write!(self.terminal, "{}", cursor::Goto(1, preview_y + 1 + i as u16))?;
+                self.write_line(line)?;
+            }
+        }
+
+        write!(self.terminal, "{}{}", cursor::Goto(self.x, self.y), clear::CurrentLine)?;
+        self.write_line(&frame.prompt)?;
+        let column = frame.cursor.min(self.width.saturating_sub(1) as usize) as u16;
+        write!(self.terminal, "{}", cursor::Goto(self.x + column, self.y))?;
+
+        self.terminal.flush()?;
+        Ok(())
+    }
+
+    fn clear(self: &mut TerminalRenderer<W>) -> Result<()> {
+        write!(self.terminal, "{}{}",
+               cursor::Goto(self.x, self.y),
+               clear::AfterCursor)?;
+        if self.pasting {
+            write!(self.terminal, "{}", BRACKETED_PASTE_OFF)?;
+            self.pasting = false;
+        }
+        self.terminal.flush()?;
+        Ok(())
+    }
+
+    /// What got drawn is erased, the terminal having maybe wrapped it
+    fn resize(self: &mut TerminalRenderer<W>) -> Result<()> {
+        if let Some((width, height)) = system::terminal_size() {
+            self.width = width;
+            self.height = height;
+        }
+        write!(self.terminal, "{}{}", cursor::Goto(self.x, self.y), clear::AfterCursor)?;
+        Ok(())
+    }
+}
+
+/// The columns the text takes on a terminal, the wide chars (e.g. CJK or emoji) taking two
+pub fn width(text: &str) -> usize {
+    text.width()
+}
+
+/// The longest start of the text fitting in that many columns, along with the columns it takes
+fn fitting(text: &str, columns: usize) -> (&str, usize) {
+    let mut taken = 0;
+    for (idx, c) in text.char_indices() {
+        let width = c.width().unwrap_or(0);
+        if taken + width > columns {
+            return (&text[..idx], taken);
+        }
+        taken += width;
+    }
+    (text, taken)
+}
+
+/// In the 16 colors of the palette, which the terminal themes keep readable
+fn ansi(badge_color: BadgeColor) -> color::AnsiValue {
+    color::AnsiValue(match badge_color {
+        BadgeColor::Red     => 1,
+        BadgeColor::Green   => 2,
+        BadgeColor::Yellow  => 3,
+        BadgeColor::Blue    => 4,
+        BadgeColor::Magenta => 5,
+        BadgeColor::Cyan    => 6,
+        BadgeColor::Grey    => 8
+    })
+}
+
+/// The lines fitting in that many rows, scrolled down to the selected one
+fn visible(len: usize, rows: usize, selected: Option<usize>) -> Range<usize> {
+    let start = selected.map_or(0, |i| (i + 1).saturating_sub(rows));
+    start..len.min(start + rows)
+}
+
+impl Renderer for HeadlessRenderer {
+    fn render(self: &mut HeadlessRenderer, frame: &Frame) -> Result<()> {
+        self.frame = frame.clone();
+        Ok(())
+    }
+
+    /// The last frame is kept, to be looked at once the runner is done
+    fn clear(self: &mut HeadlessRenderer) -> Result<()> {
+        Ok(())
+    }
+}
+
+// Note on the `write_highlighted` macro
+//
+// There's currently no way of providing `write_highlighted` through traits as
+// it is impossible to provide an implementation for both Write and FmtWrite.
+// Although the 2 traits share a lot of similarities, FmtWrite takes UTF-8 formatted
+// Strings and discards errors, whereas Write takes [u8] and reports errors.
+// Those differences result in a missing "bridge" between the two: FmtWrite does
+// not have a Write implementation, and neither has Write an FmtWrite implementation.
+// This is probably the reason why write! and writeln! are macros.
+//
+//pub trait WriteExt {
+//    fn write_highlighted<C: color::Color>(self: &mut Self,
+//                                          msg: &str,
+//                                          bg_color: C) -> Result<()>;
+//}
+//
+//impl<W: Write> WriteExt for W {
+//    fn write_highlighted<C: color::Color>(self: &mut W,
+//                                          msg: &str,
+//                                          bg_color: C) -> Result<()> {
+//        write!(self, "{}{}{}{}{}",
+//               color::Bg(bg_color),
+//               color::Fg(color::Black),
+//               msg,
+//               color::Bg(color::Reset),
+//               color::Fg(color::Reset))
+//    }
+//}
+//
+//
+// Another option would have been to specialize for String, knowing
+// that the `write_highlighted` implementation for Write would produce
+// correctly formatted UTF-8 Vec<u8>.
+// BUT specialization hasn't landed yet...
+//
+//impl WriteExt for String {
+//    fn write_highlighted<C: color::Color>(self: &mut String,
+//                                          msg: &str,
+//                                          bg_color: C) -> Result<()> {
+//        let v: Vec<u8> = Vec::new(); // Vec<u8> is Write
+//        v.write_highlighted(msg, bg_color)?;
+//        // write_highlighted produces UTF-8
+//        unsafe {
+//            write!(self, "{}", String::from_utf8_unchecked(v))
+//        }
+//        Ok(())
+//    }
+//}
+
+#[test]
+fn scrolling_to_the_selected_line() {
+    assert_eq!(visible(10, 3, None), 0..3);
+    assert_eq!(visible(10, 3, Some(2)), 0..3);
+    assert_eq!(visible(10, 3, Some(5)), 3..6);
+    assert_eq!(visible(2, 3, Some(1)), 0..2);
+    assert_eq!(visible(10, 0, Some(5)), 6..6);
+}
+
+#[test]
+fn truncating_to_the_display_width() {
+    assert_eq!(fitting("ls -la", 4), ("ls -", 4));
+    assert_eq!(fitting("日本語", 4), ("日本", 4));
+    assert_eq!(fitting("日本語", 5), ("日本", 4));
+    assert_eq!(fitting("🚀 go", 10), ("🚀 go", 5));
+
+    let frame = Frame { prompt: Line::new(vec![Span::normal("echo 日本語".to_string())]), ..Frame::default() };
+    assert_eq!(frame.snapshot(8, 1).lines().next(), Some("echo 日 |"));
+}
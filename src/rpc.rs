@@ -0,0 +1,122 @@
+use serde_json::{self, Value};
+
+use command::{Command, Commands};
+use suggestion::Suggestion;
+
+/// The error codes of the JSON-RPC 2.0 specification
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    /// None for a notification, which gets no response, unlike `"id": null`
+    #[serde(default, deserialize_with = "present")]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value
+}
+
+/// The params of `query`, e.g. `{"query": "git che", "limit": 10}`
+#[derive(Deserialize)]
+struct QueryParams {
+    query: String,
+    #[serde(default = "default_limit")]
+    limit: usize
+}
+
+fn default_limit() -> usize {
+    50
+}
+
+/// Some even when null, `default` telling it apart from a missing value
+fn present<'de, D: ::serde::Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+    use serde::Deserialize;
+
+    Value::deserialize(deserializer).map(Some)
+}
+
+/// The response to a line of JSON-RPC 2.0, for editors to match the commands as the picker does.
+/// The one method is `query`, the words of the query followed by a space being keywords,
+/// its result being the auto-completions of the last word and the matching commands, best first,
+/// along with their placeholders:
+///
+/// ```json
+/// {"jsonrpc": "2.0", "id": 1, "method": "query", "params": {"query": "git che"}}
+/// {"jsonrpc": "2.0", "id": 1, "result": {"keywords": ["checkout"], "commands": [{"cmd": "git checkout {branch}", "placeholders": [{"name": "branch", "completion": "git branch"}], ...}]}}
+/// ```
+pub fn answer(commands: &Commands, line: &str) -> Option<String> {
+    let request: Request = match serde_json::from_str::<Value>(line) {
+        Err(e) => return Some(error(Value::Null, PARSE_ERROR, e.to_string())),
+        Ok(value) => match serde_json::from_value(value) {
+            Ok(request) => request,
+            Err(e) => return Some(error(Value::Null, INVALID_REQUEST, e.to_string()))
+        }
+    };
+    if request.jsonrpc != "2.0" {
+        return Some(error(request.id.unwrap_or(Value::Null), INVALID_REQUEST, "Not JSON-RPC 2.0".to_owned()));
+    }
+
+    let result = match request.method.as_str() {
+        "query" => serde_json::from_value(request.params)
+            .map(|params| query(commands, &params))
+            .map_err(|e| (INVALID_PARAMS, e.to_string())),
+        method => Err((METHOD_NOT_FOUND, format!("Unknown method `{}`", method)))
+    };
+    let id = request.id?;
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }).to_string(),
+        Err((code, message)) => error(id, code, message)
+    })
+}
+
+fn error(id: Value, code: i64, message: String) -> String {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } }).to_string()
+}
+
+fn query(commands: &Commands, params: &QueryParams) -> Value {
+    let suggestion = Suggestion::from_query(commands, &params.query);
+    let matching: Vec<Value> = suggestion.commands.iter().take(params.limit).map(|cmd| command_json(cmd)).collect();
+    json!({ "keywords": suggestion.keywords, "commands": matching })
+}
+
+fn command_json(cmd: &Command) -> Value {
+    let placeholders: Vec<_> = cmd.cmd.names().iter()
+        .map(|name| json!({ "name": name, "completion": cmd.completions.get(name) }))
+        .collect();
+
+    json!({
+        "id": cmd.id,
+        "cmd": cmd.cmd.to_string(),
+        "description": cmd.description,
+        "keywords": cmd.keywords,
+        "placeholders": placeholders,
+        "danger": cmd.danger
+    })
+}
+
+#[test]
+fn answering_queries() {
+    use command::Placeholders;
+
+    let commands = Commands::new(vec![
+        Command { cmd: Placeholders::parse("git checkout {branch}").unwrap(), keywords: vec!["git".to_owned(), "checkout".to_owned()], ..Default::default() },
+        Command { cmd: Placeholders::parse("ls").unwrap(), keywords: vec!["files".to_owned()], ..Default::default() }
+    ]);
+    let response = |line: &str| answer(&commands, line).map(|response| serde_json::from_str::<Value>(&response).unwrap());
+
+    let queried = response(r#"{"jsonrpc": "2.0", "id": 1, "method": "query", "params": {"query": "git che"}}"#).unwrap();
+    assert_eq!(queried["id"], 1);
+    assert_eq!(queried["result"]["keywords"], json!(["checkout"]));
+    assert_eq!(queried["result"]["commands"][0]["cmd"], "git checkout {branch}");
+    assert_eq!(queried["result"]["commands"][0]["placeholders"], json!([{ "name": "branch", "completion": null }]));
+
+    assert_eq!(response(r#"{"jsonrpc": "2.0", "id": 2, "method": "run"}"#).unwrap()["error"]["code"], METHOD_NOT_FOUND);
+    assert_eq!(response(r#"{"jsonrpc": "2.0", "id": 3, "method": "query"}"#).unwrap()["error"]["code"], INVALID_PARAMS);
+    assert_eq!(response("{").unwrap()["error"]["code"], PARSE_ERROR);
+    assert_eq!(response(r#"{"jsonrpc": "2.0", "method": "query", "params": {"query": ""}}"#), None);
+    assert_eq!(response(r#"{"jsonrpc": "2.0", "id": null, "method": "query", "params": {"query": ""}}"#).unwrap()["id"], Value::Null);
+}
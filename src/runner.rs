@@ -0,0 +1,1061 @@
+use termion::event::Key;
+use termion::input::TermRead;
+use termion::raw::{IntoRawMode, RawTerminal};
+use termion::{clear, cursor};
+use std::io::{self, Read, Write, stdin, stderr, Stderr};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::mem;
+use std::panic;
+use std::sync::{Arc, Mutex, Once};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+
+use badge::Badges;
+use cache::CompletionCache;
+use command::{Command, Commands, Selection};
+use danger::DangerRules;
+use exec;
+use macros::QueryMacros;
+use recording::{self, Recording, Typed};
+use render::{self, Renderer, TerminalRenderer, Theme};
+use screen::ValidatedKeyword;
+use session::{Session, OutputStore, LAST_OUTPUT};
+use source::CommandSource;
+use state::{self, Effect, Msg, StartupMode, State};
+use system::{self, StoppableStdin};
+use values::{ValuePresets, ValueRings};
+
+use error::{Error, Result};
+
+/// Whether the picker can be shown: it is drawn on stderr, the keys being read from stdin
+/// (unless stdin gets reattached to the terminal)
+pub fn has_terminal(reattaching_stdin: bool) -> bool {
+    ::termion::is_tty(&stderr()) && (reattaching_stdin || ::termion::is_tty(&stdin()))
+}
+
+/// The thread showing the picker, and the terminal mode to restore should it panic
+static PICKER: Mutex<Option<(ThreadId, ::libc::termios)>> = Mutex::new(None);
+static PANIC_HOOK: Once = Once::new();
+
+/// Out of raw mode and with the picker erased, before the message of a panic of this thread
+/// gets printed: the terminal would otherwise be left unusable. The hook is set once, however many
+/// pickers get built, only the latest one being restored
+fn restore_terminal_on_panic() {
+    let mut cooked: ::libc::termios = unsafe { mem::zeroed() };
+    if unsafe { ::libc::tcgetattr(::libc::STDERR_FILENO, &mut cooked) } != 0 {
+        return;
+    }
+    *PICKER.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some((thread::current().id(), cooked));
+    PANIC_HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let picker = PICKER.try_lock().ok().and_then(|picker| *picker);
+            if let Some((_, cooked)) = picker.filter(|&(id, _)| id == thread::current().id()) {
+                unsafe { ::libc::tcsetattr(::libc::STDERR_FILENO, ::libc::TCSANOW, &cooked) };
+                eprint!("{}{}{}", cursor::Goto(1, 1), clear::AfterCursor, render::BRACKETED_PASTE_OFF);
+            }
+            previous(info);
+        }));
+    });
+}
+
+pub struct Runner<R: Renderer> {
+    state: State,
+    keybindings: Keybindings,
+    observers: Vec<Observer>,
+    sources: Vec<Slot>,
+    /// Those of the completion commands, the sources keeping their last one
+    errors: Vec<Error>,
+    /// The completion commands already started
+    completions: HashSet<String>,
+    /// Whether the sources were loaded again, the completion commands then running again rather than reading the cache
+    refreshed: bool,
+    /// Where the completion commands keep their values for a while, if anywhere
+    cache: Option<Arc<CompletionCache>>,
+    /// Whether to run no command at all, see `RunnerBuilder::safe`
+    safe: bool,
+    /// The programs whose documentation is already being looked up
+    docs: HashSet<String>,
+    /// The keys typed in so far, when recorded
+    recording: Option<Recording>,
+    /// The keys to type in before those read from stdin
+    replay: Option<Recording>,
+    renderer: R
+}
+
+type Observer = Box<dyn FnMut(&Event)>;
+
+/// A source of the runner, away while it loads on a thread of its own
+struct Slot {
+    source: Option<Box<dyn CommandSource + Send>>,
+    loaded: Option<Instant>,
+    /// Why it failed to load last time, if it did
+    error: Option<Error>
+}
+
+/// What observers get notified of, see `RunnerBuilder::observe`
+#[derive(Debug)]
+pub enum Event<'a> {
+    /// The highlighted command, if any
+    SelectionChanged(Option<&'a Command>),
+    KeywordValidated(&'a ValidatedKeyword),
+    /// The command got picked and its placeholders filled in
+    Accepted(&'a Selection),
+    /// Following `Runner::execute`, with the exit status
+    Executed(&'a Selection, i32)
+}
+
+impl<'a> Event<'a> {
+    /// The command selected and its description on a plain line, for e.g. a screen reader to read out
+    /// (`--announce-fd`), the other events not being announced
+    pub fn announcement(self: &Event<'a>) -> Option<String> {
+        match *self {
+            Event::SelectionChanged(Some(command)) => Some(match command.description {
+                Some(ref description) => format!("{}: {}", command.cmd, description),
+                None => command.cmd.to_string()
+            }),
+            Event::SelectionChanged(None) => Some("no matching command".to_owned()),
+            _ => None
+        }
+    }
+}
+
+/// What the keys do while picking a command. Filling the placeholders in, every char is typed in,
+/// Enter validates the value and Escape goes back to the picker.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Cancel,
+    Accept,
+    Complete,
+    ValidateKeyword,
+    DeleteChar,
+    /// Delete the char under the cursor
+    DeleteNextChar,
+    StartOfLine,
+    EndOfLine,
+    NextSuggestion,
+    PreviousSuggestion,
+    NextCommand,
+    PreviousCommand,
+    /// Browse the commands used before, see `RunnerBuilder::history`
+    History,
+    /// Show the documentation of the selected command, see `exec::documentation`
+    Docs,
+    /// List the commands in another order, see `suggestion::Order`
+    Order,
+    /// Back to the input and the keywords before their last change
+    Undo,
+    /// Validate the keywords last validated in the directory, see `RunnerBuilder::restorable`
+    Restore,
+    /// Load the sources again, without waiting for them to expire (see `CommandSource::ttl`)
+    Refresh
+}
+
+#[derive(Clone, Debug)]
+pub struct Keybindings {
+    bindings: HashMap<Key, Action>
+}
+
+/// ```no_run
+/// # use smart_runner::command::Commands;
+/// # use smart_runner::runner::RunnerBuilder;
+/// let mut runner = RunnerBuilder::new(Commands::new(Vec::new()))
+///     .query("git ")
+///     .build()
+///     .unwrap();
+/// let selection = runner.run().unwrap();
+/// ```
+pub struct RunnerBuilder {
+    commands: Commands,
+    theme: Theme,
+    keybindings: Keybindings,
+    keywords: Vec<String>,
+    query: String,
+    /// The template of the command to highlight once listed, see `State::resumed`
+    selected: Option<String>,
+    fill_in: Option<Arc<Command>>,
+    startup: StartupMode,
+    list_on_empty: Option<bool>,
+    history: Commands,
+    values: ValueRings,
+    presets: ValuePresets,
+    bundles: BTreeMap<String, Vec<String>>,
+    macros: QueryMacros,
+    restorable: Vec<String>,
+    multi: bool,
+    keep_placeholders: bool,
+    passthrough: Option<String>,
+    record: bool,
+    replay: Option<Recording>,
+    cache: Option<CompletionCache>,
+    safe: bool,
+    danger: DangerRules,
+    badges: Badges,
+    hint: Vec<String>,
+    observers: Vec<Observer>,
+    sources: Vec<Box<dyn CommandSource + Send>>
+}
+
+/// What the input loop waits for
+enum Input {
+    Key(io::Result<Key>),
+    /// Text pasted as a whole, not to be taken for keys such as Space or Enter
+    Paste(String),
+    /// The commands of the source of that index, along with the source to put back
+    Loaded(usize, Box<dyn CommandSource + Send>, Result<Vec<Command>>),
+    /// The values listed by a completion command
+    Completions(String, Result<Vec<String>>),
+    Docs(String, Vec<String>),
+    /// The terminal got resized
+    Resize
+}
+
+
+impl Default for Keybindings {
+    fn default() -> Keybindings {
+        let mut bindings = HashMap::new();
+        bindings.insert(Key::Char('q'), Action::Cancel);
+        bindings.insert(Key::Char('\n'), Action::Accept);
+        bindings.insert(Key::Char('\t'), Action::Complete);
+        bindings.insert(Key::Char(' '), Action::ValidateKeyword);
+        bindings.insert(Key::Backspace, Action::DeleteChar);
+        bindings.insert(Key::Delete, Action::DeleteNextChar);
+        bindings.insert(Key::Home, Action::StartOfLine);
+        bindings.insert(Key::End, Action::EndOfLine);
+        bindings.insert(Key::Right, Action::NextSuggestion);
+        bindings.insert(Key::Left, Action::PreviousSuggestion);
+        bindings.insert(Key::Down, Action::NextCommand);
+        bindings.insert(Key::Up, Action::PreviousCommand);
+        bindings.insert(Key::Ctrl('r'), Action::History);
+        bindings.insert(Key::Ctrl('o'), Action::Docs);
+        bindings.insert(Key::Ctrl('s'), Action::Order);
+        bindings.insert(Key::Ctrl('z'), Action::Undo);
+        bindings.insert(Key::Ctrl('t'), Action::Restore);
+        bindings.insert(Key::F(5), Action::Refresh);
+        Keybindings { bindings }
+    }
+}
+
+impl Keybindings {
+    /// Replace what the key does, if anything
+    pub fn bind(mut self: Keybindings, key: Key, action: Action) -> Keybindings {
+        self.bindings.insert(key, action);
+        self
+    }
+
+    /// Let the key be typed in
+    pub fn unbind(mut self: Keybindings, key: Key) -> Keybindings {
+        self.bindings.remove(&key);
+        self
+    }
+
+    pub fn action(self: &Keybindings, key: Key) -> Option<Action> {
+        self.bindings.get(&key).cloned()
+    }
+}
+
+impl RunnerBuilder {
+    pub fn new(commands: Commands) -> RunnerBuilder {
+        RunnerBuilder {
+            commands,
+            theme: Theme::default(),
+            keybindings: Keybindings::default(),
+            keywords: Vec::new(),
+            query: String::new(),
+            selected: None,
+            fill_in: None,
+            startup: StartupMode::default(),
+            list_on_empty: None,
+            history: Commands::default(),
+            values: ValueRings::default(),
+            presets: ValuePresets::default(),
+            bundles: BTreeMap::new(),
+            macros: QueryMacros::new(),
+            restorable: Vec::new(),
+            multi: false,
+            keep_placeholders: false,
+            passthrough: None,
+            record: false,
+            replay: None,
+            cache: None,
+            safe: false,
+            danger: DangerRules::default(),
+            badges: Badges::default(),
+            hint: Vec::new(),
+            observers: Vec::new(),
+            sources: Vec::new()
+        }
+    }
+
+    pub fn theme(mut self: RunnerBuilder, theme: Theme) -> RunnerBuilder {
+        self.theme = theme;
+        self
+    }
+
+    pub fn keybindings(mut self: RunnerBuilder, keybindings: Keybindings) -> RunnerBuilder {
+        self.keybindings = keybindings;
+        self
+    }
+
+    /// Validated right away, even the unknown ones
+    pub fn keywords(mut self: RunnerBuilder, keywords: &[&str]) -> RunnerBuilder {
+        self.keywords = keywords.iter().map(|kw| kw.to_string()).collect();
+        self
+    }
+
+    /// Behave as if the query had been typed in
+    pub fn query(mut self: RunnerBuilder, query: &str) -> RunnerBuilder {
+        self.query = query.to_owned();
+        self
+    }
+
+    /// Reopen the picker where it was left, see `Runner::session`
+    pub fn resume(mut self: RunnerBuilder, session: Session) -> RunnerBuilder {
+        self.keywords = session.keywords;
+        self.query = session.input;
+        self.selected = session.selected;
+        self
+    }
+
+    /// Start by asking for the values of this command placeholders
+    pub fn fill_in(mut self: RunnerBuilder, command: Arc<Command>) -> RunnerBuilder {
+        self.fill_in = Some(command);
+        self
+    }
+
+    /// What gets listed before anything is typed in, e.g. the history
+    pub fn startup(mut self: RunnerBuilder, mode: StartupMode) -> RunnerBuilder {
+        self.startup = mode;
+        self
+    }
+
+    /// List all the commands while nothing is typed in, right from the start, or never, e.g. with thousands of them
+    pub fn list_on_empty(mut self: RunnerBuilder, list: bool) -> RunnerBuilder {
+        self.list_on_empty = Some(list);
+        self
+    }
+
+    /// What Ctrl-R lists, e.g. `history::commands(&records)`
+    pub fn history(mut self: RunnerBuilder, history: Commands) -> RunnerBuilder {
+        self.history = history;
+        self
+    }
+
+    /// The values typed in before for the placeholders, recalled with Up/Down while filling them in
+    pub fn values(mut self: RunnerBuilder, values: ValueRings) -> RunnerBuilder {
+        self.values = values;
+        self
+    }
+
+    /// The named value sets of the placeholders, picked with Alt-1 to Alt-9 while filling them in
+    pub fn presets(mut self: RunnerBuilder, presets: ValuePresets) -> RunnerBuilder {
+        self.presets = presets;
+        self
+    }
+
+    /// The keywords validating their members instead, e.g. `Config::bundles`
+    pub fn bundles(mut self: RunnerBuilder, bundles: BTreeMap<String, Vec<String>>) -> RunnerBuilder {
+        self.bundles = bundles;
+        self
+    }
+
+    /// The saved filters, brought back by typing `@name` then Space, see `macros::MacroStore`
+    pub fn macros(mut self: RunnerBuilder, macros: QueryMacros) -> RunnerBuilder {
+        self.macros = macros;
+        self
+    }
+
+    /// Keywords to offer, brought back with Ctrl-T, e.g. those last validated in the directory
+    pub fn restorable(mut self: RunnerBuilder, keywords: Vec<String>) -> RunnerBuilder {
+        self.restorable = keywords;
+        self
+    }
+
+    /// Let Tab mark several commands to chain rather than complete the keywords, see `Runner::picked`
+    pub fn multi(mut self: RunnerBuilder, multi: bool) -> RunnerBuilder {
+        self.multi = multi;
+        self
+    }
+
+    /// Accept the commands without filling their placeholders in, e.g. to edit them
+    pub fn keep_placeholders(mut self: RunnerBuilder, keep_placeholders: bool) -> RunnerBuilder {
+        self.keep_placeholders = keep_placeholders;
+        self
+    }
+
+    /// Arguments for the trailing placeholder of the commands, e.g. `{args}` in `kubectl get pods {args}`,
+    /// which is then not asked for, e.g. those given after `--`: see `Selection::pass_through` for the others
+    pub fn passthrough(mut self: RunnerBuilder, arguments: &str) -> RunnerBuilder {
+        self.passthrough = Some(arguments.to_owned());
+        self
+    }
+
+    /// Record the keys typed in, with their timing, see `Runner::recording`
+    pub fn record(mut self: RunnerBuilder, record: bool) -> RunnerBuilder {
+        self.record = record;
+        self
+    }
+
+    /// Type these keys in as they were recorded, with the same delays, e.g. to reproduce a bug or a demo,
+    /// those read from stdin following
+    pub fn replay(mut self: RunnerBuilder, recording: Recording) -> RunnerBuilder {
+        self.replay = Some(recording);
+        self
+    }
+
+    /// Run the completion commands through the cache, e.g. `CompletionCache::open`
+    pub fn completion_cache(mut self: RunnerBuilder, cache: CompletionCache) -> RunnerBuilder {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Run no command at all: the completion commands aren't, leaving the placeholders to be typed in,
+    /// nor those looking the documentation up, the dangerous commands of the sources are left out
+    /// and `Runner::execute` refuses to run any, e.g. for a demo or on a production machine
+    pub fn safe(mut self: RunnerBuilder, safe: bool) -> RunnerBuilder {
+        self.safe = safe;
+        self
+    }
+
+    /// What flags the commands of the sources as they get loaded, e.g. `config.danger.rules()`,
+    /// those of `safe` mode being left out
+    pub fn danger(mut self: RunnerBuilder, rules: DangerRules) -> RunnerBuilder {
+        self.danger = rules;
+        self
+    }
+
+    /// The colored tags to show before the commands, e.g. `Badges::compile(&config.badges).0`
+    pub fn badges(mut self: RunnerBuilder, badges: Badges) -> RunnerBuilder {
+        self.badges = badges;
+        self
+    }
+
+    /// Lines to show below the commands, e.g. instructions
+    pub fn hint(mut self: RunnerBuilder, hint: Vec<String>) -> RunnerBuilder {
+        self.hint = hint;
+        self
+    }
+
+    /// Load the source in the background, once running, its commands being added as soon as they are
+    /// available: a slow source does not delay the first keys
+    pub fn source<S: CommandSource + Send + 'static>(mut self: RunnerBuilder, source: S) -> RunnerBuilder {
+        self.sources.push(Box::new(source));
+        self
+    }
+
+    /// Get notified of the events, in the order the observers got added
+    pub fn observe<F: FnMut(&Event) + 'static>(mut self: RunnerBuilder, observer: F) -> RunnerBuilder {
+        self.observers.push(Box::new(observer));
+        self
+    }
+
+    /// Draw on stderr, in raw mode, so that stdout is left for the selection
+    pub fn build(self: RunnerBuilder) -> Result<Runner<TerminalRenderer<RawTerminal<Stderr>>>> {
+        if !::termion::is_tty(&stderr()) {
+            return Err(Error::Terminal(io::Error::other("stderr is not a terminal")));
+        }
+        restore_terminal_on_panic();
+        let terminal = stderr().into_raw_mode().map_err(Error::Terminal)?;
+        self.build_with(terminal)
+    }
+
+    /// Draw on a terminal already set up, e.g. in raw mode
+    pub fn build_with<W: Write>(self: RunnerBuilder, terminal: W) -> Result<Runner<TerminalRenderer<W>>> {
+        let renderer = TerminalRenderer::new(terminal, self.theme);
+        self.build_with_renderer(renderer)
+    }
+
+    /// Draw with a custom front-end, the theme being left to it
+    pub fn build_with_renderer<R: Renderer>(self: RunnerBuilder, renderer: R) -> Result<Runner<R>> {
+        let mut runner = Runner {
+            state: State {
+                history: self.history,
+                values: self.values,
+                presets: self.presets,
+                bundles: self.bundles,
+                macros: self.macros,
+                restorable: self.restorable,
+                multi: self.multi,
+                keep_placeholders: self.keep_placeholders,
+                passthrough: self.passthrough,
+                list_on_empty: self.list_on_empty,
+                danger: self.danger,
+                safe: self.safe,
+                ..State::new(self.commands)
+            },
+            keybindings: self.keybindings,
+            observers: self.observers,
+            sources: self.sources.into_iter().map(|source| Slot { source: Some(source), loaded: None, error: None }).collect(),
+            errors: Vec::new(),
+            completions: HashSet::new(),
+            refreshed: false,
+            cache: self.cache.map(Arc::new),
+            safe: self.safe,
+            docs: HashSet::new(),
+            recording: if self.record { Some(Recording::default()) } else { None },
+            replay: self.replay,
+            renderer
+        };
+
+        runner.state.screen.badges = self.badges;
+        let mut hint = self.hint;
+        if hint.is_empty() && !runner.state.restorable.is_empty() {
+            hint.push(format!("Ctrl-T brings back the keywords last used here: {}", runner.state.restorable.join(" ")));
+        }
+        runner.state.screen.set_hint(hint);
+        runner.state.start(self.startup);
+        for kw in &self.keywords {
+            runner.type_in(kw);
+            runner.dispatch(Msg::ValidateKeyword);
+        }
+        runner.type_in(&self.query);
+        runner.state.resumed = self.selected;
+        runner.state.select_resumed();
+        if let Some(command) = self.fill_in {
+            runner.dispatch(Msg::FillIn(command));
+        }
+        Ok(runner)
+    }
+}
+
+impl<R: Renderer> Runner<R> {
+    /// Return a command to execute or None if the user canceled.
+    /// The keys are read on a thread of their own, stopped before returning so that whatever reads stdin next,
+    /// e.g. another runner, gets the keys typed in afterwards.
+    pub fn run(self: &mut Runner<R>) -> Result<Option<Selection>> {
+        self.refresh_screen()?;
+
+        let (sender, receiver) = mpsc::channel();
+        self.start_loading(&sender, false);
+        self.start_completions(&sender);
+        self.start_docs(&sender);
+        let key_sender = sender.clone();
+        let keyboard = StoppableStdin::default();
+        let stop = keyboard.stop.clone();
+        let stdin = PasteReader { inner: keyboard, pending: Vec::new(), sender: sender.clone() };
+        let replay = self.replay.take();
+        let stopped = stop.clone();
+        let keys = thread::spawn(move || {
+            for recorded in replay.map_or(Vec::new(), |replay| replay.inputs) {
+                if !pause(&stopped, Duration::from_millis(recorded.after_ms)) {
+                    return;
+                }
+                let input = match recorded.input {
+                    Typed::Key(name) => Input::Key(recording::key(&name).map_err(|e| io::Error::other(e.to_string()))),
+                    Typed::Paste(text) => Input::Paste(text)
+                };
+                if key_sender.send(input).is_err() {
+                    return;
+                }
+            }
+            for key in stdin.keys() {
+                if key_sender.send(Input::Key(key)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let resize_sender = sender.clone();
+        let resizes = system::watch_resizes(stop.clone(), move || resize_sender.send(Input::Resize).is_ok());
+
+        let selection = self.process_inputs(&sender, &receiver);
+        stop.store(true, Ordering::SeqCst);
+        let _ = keys.join();
+        let _ = resizes.join();
+        selection
+    }
+
+    /// Until a command gets picked or the user cancels
+    fn process_inputs(self: &mut Runner<R>, sender: &mpsc::Sender<Input>, receiver: &mpsc::Receiver<Input>) -> Result<Option<Selection>> {
+        let mut last_input = Instant::now();
+        loop {
+            let input = match self.next_expiry() {
+                Some(timeout) => receiver.recv_timeout(timeout).ok(),
+                None => receiver.recv().ok()
+            };
+            let input = match input {
+                Some(input) => input,
+                // a source expired
+                None => {
+                    self.start_loading(sender, false);
+                    continue;
+                }
+            };
+            if let Some(ref mut recording) = self.recording {
+                match input {
+                    Input::Key(Ok(key)) => recording.push_key(last_input.elapsed(), key),
+                    Input::Paste(ref text) => recording.push_paste(last_input.elapsed(), text.clone()),
+                    _ => ()
+                }
+                if let Input::Key(Ok(_)) | Input::Paste(_) = input {
+                    last_input = Instant::now();
+                }
+            }
+            let effect = match input {
+                Input::Key(key) => self.process_key(key?),
+                Input::Paste(text) => self.dispatch(Msg::Paste(text)),
+                Input::Loaded(idx, source, loaded) => {
+                    let (commands, error) = match loaded {
+                        Ok(commands) => (Some(commands), None),
+                        Err(e) => (None, Some(e))
+                    };
+                    self.sources[idx] = Slot { source: Some(source), loaded: Some(Instant::now()), error };
+                    match commands {
+                        Some(commands) => self.dispatch(Msg::Loaded(idx, commands)),
+                        None => Effect::Continue
+                    }
+                },
+                Input::Completions(provider, Ok(values)) => self.dispatch(Msg::Completions(provider, values)),
+                Input::Docs(program, docs) => self.dispatch(Msg::Docs(program, docs)),
+                Input::Completions(_, Err(e)) => {
+                    self.errors.push(e);
+                    Effect::Continue
+                },
+                Input::Resize => {
+                    self.renderer.resize()?;
+                    Effect::Continue
+                }
+            };
+
+            if let Effect::Done(selection) = self.apply(effect)? {
+                return Ok(selection);
+            }
+            if mem::take(&mut self.state.refreshing) {
+                self.refresh_completions();
+                self.start_loading(sender, true);
+            }
+            self.start_completions(sender);
+            self.start_docs(sender);
+        }
+    }
+
+    /// Process the keys as if typed in, e.g. to test the runner with a `HeadlessRenderer`,
+    /// the sources being loaded beforehand. Continue means that the keys ran out first.
+    pub fn simulate<I: IntoIterator<Item = Key>>(self: &mut Runner<R>, keys: I) -> Result<Effect> {
+        self.load_now()?;
+        self.refresh_screen()?;
+
+        self.complete_now()?;
+
+        for key in keys {
+            let effect = self.process_key(key);
+            if let Effect::Done(selection) = self.apply(effect)? {
+                return Ok(Effect::Done(selection));
+            }
+            if mem::take(&mut self.state.refreshing) {
+                self.refresh_completions();
+                self.load_now()?;
+                self.refresh_screen()?;
+            }
+            self.complete_now()?;
+            self.document_now()?;
+        }
+        Ok(Effect::Continue)
+    }
+
+    pub fn renderer(self: &Runner<R>) -> &R {
+        &self.renderer
+    }
+
+    /// Run the selected command with the user's shell, returning its exit status
+    pub fn execute(self: &mut Runner<R>, selection: &Selection) -> Result<i32> {
+        if self.safe {
+            return Err(Error::InvalidInput(format!("Not running `{}`: safe mode", selection.interpolated())));
+        }
+        let status = selection.execute()?;
+        self.notify(&Event::Executed(selection, status));
+        Ok(status)
+    }
+
+    /// The keys typed in so far, with their timing, when recorded, see `RunnerBuilder::record`
+    pub fn recording(self: &Runner<R>) -> Option<&Recording> {
+        self.recording.as_ref()
+    }
+
+    /// Whether the commands returned by `run` were picked among those used before, see `RunnerBuilder::history`:
+    /// they are not new uses
+    pub fn browsing_history(self: &Runner<R>) -> bool {
+        self.state.browsing_history
+    }
+
+    /// The chained commands filled in before the one returned by `run`, in the order they were marked
+    pub fn picked(self: &Runner<R>) -> &[Selection] {
+        &self.state.picked
+    }
+
+    /// Where the picker was left, to resume it with `RunnerBuilder::resume`
+    pub fn session(self: &Runner<R>) -> Session {
+        let screen = &self.state.screen;
+        Session {
+            keywords: screen.validated_keywords.iter().map(|kw| kw.text().to_owned()).collect(),
+            input: screen.input(),
+            selected: screen.selected_command().map(|command| command.cmd.template().to_owned())
+        }
+    }
+
+    /// What went wrong the last time each source got loaded, and running the completion commands
+    pub fn errors(self: &Runner<R>) -> Vec<&Error> {
+        self.sources.iter().filter_map(|slot| slot.error.as_ref()).chain(&self.errors).collect()
+    }
+
+    pub fn valid_keywords(self: &Runner<R>) -> Vec<String> {
+        self.state.valid_keywords()
+    }
+
+    /// Draw the screen, or clean it up once done
+    fn apply(self: &mut Runner<R>, effect: Effect) -> Result<Effect> {
+        match effect {
+            Effect::Done(selection) => {
+                self.cleanup()?;
+                if let Some(ref selection) = selection {
+                    self.notify(&Event::Accepted(selection));
+                }
+                Ok(Effect::Done(selection))
+            },
+
+            Effect::Continue => {
+                self.refresh_screen()?;
+                Ok(Effect::Continue)
+            }
+        }
+    }
+
+    /// Load the sources in the background: those never loaded and the expired ones, or else all of them,
+    /// but those still loading
+    fn start_loading(self: &mut Runner<R>, sender: &mpsc::Sender<Input>, all: bool) {
+        for (idx, slot) in self.sources.iter_mut().enumerate() {
+            let expired = match (slot.loaded, slot.source.as_ref().and_then(|source| source.ttl())) {
+                (None, _) => true,
+                (Some(loaded), Some(ttl)) => loaded.elapsed() >= ttl,
+                (Some(_), None) => false
+            };
+            if let Some(source) = slot.source.take_if(|_| all || expired) {
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    let loaded = source.load();
+                    sender.send(Input::Loaded(idx, source, loaded))
+                });
+            }
+        }
+    }
+
+    /// Until the next source expires, if any
+    fn next_expiry(self: &Runner<R>) -> Option<Duration> {
+        self.sources.iter()
+            .filter_map(|slot| match (slot.loaded, slot.source.as_ref().and_then(|source| source.ttl())) {
+                (Some(loaded), Some(ttl)) => Some(ttl.saturating_sub(loaded.elapsed())),
+                _ => None
+            })
+            .min()
+    }
+
+    /// Same as `start_loading`, all the sources, waiting for them
+    fn load_now(self: &mut Runner<R>) -> Result<()> {
+        for idx in 0..self.sources.len() {
+            if let Some(commands) = self.sources[idx].source.as_ref().map(|source| source.load()).transpose()? {
+                self.sources[idx].loaded = Some(Instant::now());
+                self.dispatch(Msg::Loaded(idx, commands));
+            }
+        }
+        Ok(())
+    }
+
+    /// For the completion commands to run again, see `State::refreshing`
+    fn refresh_completions(self: &mut Runner<R>) {
+        self.completions.clear();
+        self.refreshed = true;
+    }
+
+    /// That of the placeholder being filled in, the output captured last aside (it runs nothing) unless `--safe`
+    fn pending_completions(self: &Runner<R>) -> Option<String> {
+        self.state.pending_completions().filter(|provider| !self.safe || provider == LAST_OUTPUT)
+    }
+
+    /// Run the completion command of the placeholder being filled in, in the background
+    fn start_completions(self: &mut Runner<R>, sender: &mpsc::Sender<Input>) {
+        if let Some(provider) = self.pending_completions() {
+            if self.completions.insert(provider.clone()) {
+                let sender = sender.clone();
+                let (cache, refreshed) = (self.cache.clone(), self.refreshed);
+                thread::spawn(move || {
+                    let values = completions(&provider, cache.as_deref(), refreshed);
+                    sender.send(Input::Completions(provider, values))
+                });
+            }
+        }
+    }
+
+    /// Same as `start_completions`, waiting for the values
+    fn complete_now(self: &mut Runner<R>) -> Result<()> {
+        if let Some(provider) = self.pending_completions() {
+            if self.completions.insert(provider.clone()) {
+                let values = completions(&provider, self.cache.as_deref(), self.refreshed)?;
+                self.dispatch(Msg::Completions(provider, values));
+                self.refresh_screen()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Look the documentation of the selected command up in the background, failing which it says so
+    fn start_docs(self: &mut Runner<R>, sender: &mpsc::Sender<Input>) {
+        if let Some(program) = self.state.pending_docs() {
+            if self.docs.insert(program.clone()) {
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    let docs = documentation(&program);
+                    sender.send(Input::Docs(program, docs))
+                });
+            }
+        }
+    }
+
+    /// Same as `start_docs`, waiting for the documentation
+    fn document_now(self: &mut Runner<R>) -> Result<()> {
+        if let Some(program) = self.state.pending_docs() {
+            if self.docs.insert(program.clone()) {
+                let docs = documentation(&program);
+                self.dispatch(Msg::Docs(program, docs));
+                self.refresh_screen()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn process_key(self: &mut Runner<R>, key: Key) -> Effect {
+        match state::msg(&self.state, &self.keybindings, key) {
+            Some(msg) => self.dispatch(msg),
+            None => Effect::Continue
+        }
+    }
+
+    /// Update the state, notifying the observers of what changed
+    fn dispatch(self: &mut Runner<R>, msg: Msg) -> Effect {
+        let selected = self.state.screen.selected_command();
+        let validated = self.state.screen.validated_keywords.len();
+
+        let (state, effect) = state::update(mem::take(&mut self.state), msg);
+        self.state = state;
+
+        if self.observers.is_empty() {
+            return effect;
+        }
+
+        let validated_keywords = mem::take(&mut self.state.screen.validated_keywords);
+        // a bundle validates several at once
+        for kw in validated_keywords.iter().skip(validated) {
+            self.notify(&Event::KeywordValidated(kw));
+        }
+        self.state.screen.validated_keywords = validated_keywords;
+
+        let now_selected = self.state.screen.selected_command();
+        let changed = match (&selected, &now_selected) {
+            (Some(before), Some(after)) => !Arc::ptr_eq(before, after),
+            (before, after) => before.is_some() != after.is_some()
+        };
+        if changed {
+            self.notify(&Event::SelectionChanged(now_selected.as_deref()));
+        }
+        effect
+    }
+
+    /// Behave as if the text had been typed in, spaces validating the keywords
+    fn type_in(self: &mut Runner<R>, text: &str) {
+        for c in text.chars() {
+            self.dispatch(if c == ' ' { Msg::ValidateKeyword } else { Msg::Type(c) });
+        }
+    }
+
+    fn cleanup(self: &mut Runner<R>) -> Result<()> {
+        self.renderer.clear()
+    }
+
+    fn notify(self: &mut Runner<R>, event: &Event) {
+        for observer in &mut self.observers {
+            observer(event);
+        }
+    }
+
+    fn refresh_screen(self: &mut Runner<R>) -> Result<()> {
+        self.renderer.render(&state::view(&self.state))
+    }
+}
+
+fn documentation(program: &str) -> Vec<String> {
+    exec::documentation(program).unwrap_or_else(|_| vec![format!("No documentation found for {}", program)])
+}
+
+/// What terminals send around the pasted text, once bracketed paste is on
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// Hands the keys over to termion, which knows nothing about bracketed paste, the pasted text
+/// being sent on its own
+struct PasteReader<R: Read> {
+    inner: R,
+    pending: Vec<u8>,
+    sender: mpsc::Sender<Input>
+}
+
+impl<R: Read> PasteReader<R> {
+    fn fill(self: &mut PasteReader<R>) -> io::Result<bool> {
+        let mut buf = [0; 1024];
+        let read = self.inner.read(&mut buf)?;
+        self.pending.extend_from_slice(&buf[..read]);
+        Ok(read > 0)
+    }
+}
+
+impl<R: Read> Read for PasteReader<R> {
+    /// Up to the next pasted text, so that termion parses the keys typed before it first
+    fn read(self: &mut PasteReader<R>, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.pending.is_empty() && !self.fill()? {
+                return Ok(0);
+            }
+            if !self.pending.starts_with(PASTE_START) {
+                break;
+            }
+            let end = loop {
+                match find(&self.pending[PASTE_START.len()..], PASTE_END) {
+                    Some(idx) => break PASTE_START.len() + idx,
+                    None if self.fill()? => (),
+                    None => break self.pending.len()
+                }
+            };
+            let text = String::from_utf8_lossy(&self.pending[PASTE_START.len()..end]).into_owned();
+            self.pending.drain(..self.pending.len().min(end + PASTE_END.len()));
+            // fails once the runner is done
+            let _ = self.sender.send(Input::Paste(text));
+        }
+
+        let len = find(&self.pending[1..], PASTE_START).map_or(self.pending.len(), |idx| idx + 1).min(buf.len());
+        buf[..len].copy_from_slice(&self.pending[..len]);
+        self.pending.drain(..len);
+        Ok(len)
+    }
+}
+
+/// The values the completion command lists, or the lines of the output captured last for `@output`.
+/// Once refreshed, not those of the cache.
+fn completions(provider: &str, cache: Option<&CompletionCache>, refreshed: bool) -> Result<Vec<String>> {
+    if provider == LAST_OUTPUT {
+        return OutputStore::open()?.lines();
+    }
+    match cache {
+        Some(cache) if refreshed => cache.refresh(provider),
+        Some(cache) => cache.lines(provider),
+        None => exec::lines(provider)
+    }
+}
+
+/// Whether the delay went by without getting stopped
+fn pause(stop: &AtomicBool, delay: Duration) -> bool {
+    let until = Instant::now() + delay;
+    while !stop.load(Ordering::SeqCst) {
+        let now = Instant::now();
+        if now >= until {
+            return true;
+        }
+        thread::sleep((until - now).min(Duration::from_millis(50)));
+    }
+    false
+}
+
+fn find(bytes: &[u8], marker: &[u8]) -> Option<usize> {
+    bytes.windows(marker.len()).position(|window| window == marker)
+}
+
+#[test]
+fn reading_pasted_text() {
+    let (sender, receiver) = mpsc::channel();
+    let reader = PasteReader {
+        inner: io::Cursor::new(b"ab\x1b[200~git log\n\x1b[201~c\x1b[200~d".to_vec()),
+        pending: Vec::new(),
+        sender
+    };
+
+    let keys: Vec<Key> = reader.keys().map(|key| key.unwrap()).collect();
+    assert_eq!(keys, vec![Key::Char('a'), Key::Char('b'), Key::Char('c')]);
+    let pasted: Vec<String> = receiver.try_iter()
+        .filter_map(|input| match input { Input::Paste(text) => Some(text), _ => None })
+        .collect();
+    assert_eq!(pasted, vec!["git log\n", "d"]);
+}
+
+#[test]
+fn notifying_observers() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use command::command;
+    use render::HeadlessRenderer;
+
+    let commands = Commands::new(vec![command("ls", &["ls", "files"]), command("du -sh", &["du", "files"])]);
+
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let recorded = events.clone();
+    let mut runner = RunnerBuilder::new(commands)
+        .observe(move |event| recorded.borrow_mut().push(match *event {
+            Event::SelectionChanged(cmd) => format!("selected {}", cmd.map_or(String::new(), |cmd| cmd.cmd.to_string())),
+            Event::KeywordValidated(kw) => format!("validated {:?}", kw),
+            Event::Accepted(selection) | Event::Executed(selection, _) => selection.interpolated()
+        }))
+        .build_with_renderer(HeadlessRenderer::default())
+        .unwrap();
+
+    for key in "files ".chars().chain(vec!['l', '\t']) {
+        runner.process_key(Key::Char(key));
+    }
+    runner.process_key(Key::Down);
+
+    assert_eq!(*events.borrow(), vec![
+        "selected du -sh",
+        "validated Valid(\"files\")",
+        "selected ls",
+        "validated Valid(\"ls\")"
+    ]);
+
+    runner.refresh_screen().unwrap();
+    assert_eq!(runner.renderer.frame.text(), "> ¹files ²ls \n\n* ls ");
+
+    let described = Command { description: Some("Disk usage".to_owned()), ..command("du -sh", &[]) };
+    assert_eq!(Event::SelectionChanged(Some(&described)).announcement().as_deref(), Some("du -sh: Disk usage"));
+    assert_eq!(Event::SelectionChanged(None).announcement().as_deref(), Some("no matching command"));
+}
+
+#[test]
+fn picking_from_the_history() {
+    use command::command;
+    use keys::parse_keys;
+    use render::HeadlessRenderer;
+
+    let runner = |keys: &str| {
+        let mut runner = RunnerBuilder::new(Commands::new(vec![command("ls {dir}", &["ls"])]))
+            .history(Commands::new(vec![command("ls /tmp", &["ls", "/tmp"])]))
+            .build_with_renderer(HeadlessRenderer::default())
+            .unwrap();
+        let picked = match runner.simulate(parse_keys(keys).unwrap()).unwrap() {
+            Effect::Done(selection) => selection.map(|selection| selection.interpolated()),
+            Effect::Continue => None
+        };
+        (picked, runner.browsing_history())
+    };
+
+    assert_eq!(runner("<C-r>ls<Enter>"), (Some("ls /tmp".to_owned()), true));
+    assert_eq!(runner("ls<Enter>/var<Enter>"), (Some("ls /var".to_owned()), false));
+}
+
+#[test]
+fn placing_the_cursor_after_wide_chars() {
+    use command::command;
+    use keys::parse_keys;
+    use render::HeadlessRenderer;
+
+    let commands = Commands::new(vec![command("echo 日本", &["日本"])]);
+    let mut runner = RunnerBuilder::new(commands).build_with_renderer(HeadlessRenderer::default()).unwrap();
+
+    runner.simulate(parse_keys("日本").unwrap()).unwrap();
+    assert_eq!(runner.renderer.frame.cursor, "> ".len() + 4);
+}
@@ -1,95 +1,259 @@
-extern crate termion;
-
-use termion::{clear, color, cursor, style};
-use termion::raw::RawTerminal;
-use std::rc::Rc;
-use std::io::Write;
-use command::Command;
-use std::fmt::Write as FmtWrite;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Instant;
 use itertools::Itertools;
+use badge::Badges;
+use command::{Command, Selection};
 
+use highlight;
+use render::{self, Frame, Line, Span, Style};
 use suggestion::Suggestion;
 
-type Result<T> = ::std::result::Result<T, Box<::std::error::Error>>;
+/// The indexes of the first keywords, as drawn before them
+const SUPERSCRIPTS: [char; 9] = ['¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'];
 
-
-// see below for an explanation of why this isn't a mere function
-// Note: it's here because it has to be above it's application point
-macro_rules! write_highlighted {
-    ($dst:expr, $msg:expr, $bg_color:expr) =>
-        (write!($dst, "{}{}{}{}{}",
-               color::Bg($bg_color),
-               color::Fg(color::Black),
-               $msg,
-               color::Bg(color::Reset),
-               color::Fg(color::Reset)))
-}
+/// The commands past the selected one drawn at most: more than a terminal shows, but not
+/// all the thousands of a large library
+const MAX_LINES_BELOW: usize = 200;
 
 #[derive(Default)]
 pub struct Screen {
-    x: u16,
-    y: u16,
     prompt: String,
     current_line: Vec<char>,
+    /// Where the chars get typed in, as a char index of `current_line`
+    cursor: usize,
     pub validated_keywords: Vec<ValidatedKeyword>,
     auto_complete: Vec<String>,
     selected_auto_complete_index: Option<usize>,
-    commands: Vec<Rc<Command>>,
+    /// Those which can be highlighted: the collapsed sections aside
+    commands: Vec<Arc<Command>>,
     selected_command_index: Option<usize>,
-    term_size: (u16,u16)
+    /// The commands listed under headers, when they come from several groups, see `Command::group`
+    sections: Vec<Section>,
+    /// The groups whose commands are hidden below their header
+    collapsed: HashSet<Option<String>>,
+    fill_in: Option<FillIn>,
+    /// The documentation of the selected command, when asked for
+    docs: Option<Vec<String>>,
+    /// The commands to chain, in the order they were marked
+    marked: Vec<Arc<Command>>,
+    /// How the commands are listed, unless by relevance
+    order: Option<&'static str>,
+    /// The input and the keywords before their last changes, typing aside, see `undo`
+    undo: Vec<(Vec<char>, Vec<ValidatedKeyword>)>,
+    /// Shown below the commands unless the documentation is, e.g. the instructions of the tutorial
+    hint: Vec<String>,
+    pub badges: Badges
 }
 
+/// The commands of a group, in the order they are listed
+struct Section {
+    group: Option<String>,
+    commands: Vec<Arc<Command>>
+}
+
+/// Typing in the values of the selected command placeholders
+struct FillIn {
+    command: Arc<Command>,
+    values: Vec<String>,
+    started: Instant,
+    picker_line: Vec<char>, // restored when the fill-in is canceled
+    /// The value sets typed in before, the most recent first, and the one recalled with Up/Down
+    ring: Vec<Vec<String>>,
+    recalled: Option<usize>,
+    /// The values of the current placeholder to pick among, see `Command::completions`
+    candidates: Vec<String>,
+    selected_candidate: usize,
+    /// The named value sets, picked with `apply_preset`
+    presets: Vec<(String, Vec<String>)>,
+    /// The value of the last placeholder, not asked for, see `Selection::pass_through`
+    trailing: Option<String>
+}
 
+#[derive(Clone, Debug)]
 pub enum ValidatedKeyword {
     Valid(String),
     Invalid(String)
 }
 
 impl Screen {
-    pub fn new<T: Write>(stdout: &mut RawTerminal<T>) -> Result<Screen> {
-        //let vertical_size: u16 = 6;
-        //write!(stdout, "{}", "\n".repeat(vertical_size as usize))?;
-
-        // Cannot get the cursor position without eating off one char
-        // See https://github.com/ticki/termion/issues/136
-        // let (_,y) = stdout.cursor_pos()?;
-
-        let term_size = termion::terminal_size().unwrap_or((80, 10));
-        let screen = Screen {
+    pub fn new() -> Screen {
+        Screen {
             prompt: "> ".to_owned(),
-            x: 1,
-            y: 1, // y - vertical_size,
-            term_size,
             ..Default::default()
-        };
-        screen.print(stdout)?;
-
-        Ok(screen)
+        }
     }
 
-    pub fn cleanup<T: Write>(self: &mut Screen, terminal: &mut RawTerminal<T>) -> Result<()> {
-        write!(terminal, "{}{}",
-               cursor::Goto(self.x, self.y),
-               clear::AfterCursor)?;
-        terminal.flush()?;
-        Ok(())
+    pub fn set_prompt(self: &mut Screen, prompt: &str) {
+        self.prompt = prompt.to_owned();
     }
 
     pub fn complete(self: &mut Screen) {
         if let Some(idx) = self.selected_auto_complete_index {
-            self.validated_keywords.push(ValidatedKeyword::Valid(
-                self.auto_complete.get(idx).unwrap().clone()));
-            self.current_line = Vec::new();
+            self.save_for_undo();
+            let kw = self.auto_complete.get(idx).unwrap().clone();
+            self.add_validated_keyword(ValidatedKeyword::Valid(kw));
+            self.set_input(Vec::new());
         }
     }
 
+    /// Unless validated already, e.g. as a member of a bundle, each keyword getting a single chip
     pub fn add_validated_keyword(self: &mut Screen, vkw: ValidatedKeyword) {
-        self.validated_keywords.push(vkw);
+        if !self.validated_keywords.iter().any(|validated| validated.text() == vkw.text()) {
+            self.validated_keywords.push(vkw);
+        }
+    }
+
+    pub fn selected_command(self: &Screen) -> Option<Arc<Command>> {
+        self.selected_command_index.and_then(|idx| self.commands.get(idx).cloned())
+    }
+
+    pub fn selected_command_index(self: &Screen) -> Option<usize> {
+        self.selected_command_index
+    }
+
+    /// Highlight the command of that index, or the last one
+    pub fn select_command_index(self: &mut Screen, idx: usize) {
+        if !self.commands.is_empty() {
+            self.selected_command_index = Some(idx.min(self.commands.len() - 1));
+        }
     }
 
-    pub fn selected_command(self: &Screen) -> Option<String> {
-        self.selected_command_index.and_then(|idx|
-            self.commands.get(idx).map(|cmd|cmd.cmd.interpolate(vec!["".to_owned()])))
+    pub fn is_filling_in(self: &Screen) -> bool {
+        self.fill_in.is_some()
+    }
+
+    /// Ask for the values of the command placeholders, one after the other,
+    /// those of the ring being recalled with `recall_values` and the presets picked with `apply_preset`
+    pub fn start_fill_in(self: &mut Screen, command: Arc<Command>, ring: Vec<Vec<String>>, presets: Vec<(String, Vec<String>)>,
+                         trailing: Option<String>) {
+        let picker_line = self.take_input();
+        self.fill_in = Some(FillIn {
+            command,
+            values: Vec::new(),
+            started: Instant::now(),
+            picker_line,
+            ring,
+            recalled: None,
+            candidates: Vec::new(),
+            selected_candidate: 0,
+            presets,
+            trailing
+        });
+    }
+
+    /// All the values of the preset of that index, the ones typed so far being replaced,
+    /// returning the selection
+    pub fn apply_preset(self: &mut Screen, idx: usize) -> Option<Selection> {
+        let values = self.fill_in.as_ref()
+            .and_then(|fill_in| fill_in.presets.get(idx))
+            .map(|(_, values)| values.clone())?;
+        self.reset_input();
+        self.fill_in.take().map(|fill_in| Selection {
+            values,
+            fill_in_time: Some(fill_in.started.elapsed()),
+            ..Selection::new(fill_in.command)
+        })
+    }
+
+    /// Replace the value being typed in with the one of an older or newer value set,
+    /// the following placeholders getting theirs as they come
+    pub fn recall_values(self: &mut Screen, older: bool) {
+        if let Some(ref mut fill_in) = self.fill_in {
+            fill_in.recalled = match (fill_in.recalled, older) {
+                (None, true) if !fill_in.ring.is_empty()         => Some(0),
+                (Some(i), true) if i + 1 < fill_in.ring.len()    => Some(i + 1),
+                (Some(i), false) if i > 0                        => Some(i - 1),
+                (Some(_), false)                                 => None,
+                _                                                => return
+            };
+            let value = fill_in.recalled_value();
+            self.set_input(value);
+        }
+    }
+
+    /// The command being filled in and the name of its current placeholder
+    pub fn current_placeholder(self: &Screen) -> Option<(&Command, &str)> {
+        self.fill_in.as_ref().and_then(|fill_in| fill_in.command.cmd.names().get(fill_in.values.len())
+            .map(|name| (&*fill_in.command, name.as_str())))
+    }
+
+    pub fn set_candidates(self: &mut Screen, candidates: Vec<String>) {
+        if let Some(ref mut fill_in) = self.fill_in {
+            fill_in.candidates = candidates;
+            fill_in.selected_candidate = 0;
+        }
+    }
+
+    /// The candidates containing the value typed so far
+    pub fn matching_candidates(self: &Screen) -> Vec<&String> {
+        let input = self.input();
+        self.fill_in.as_ref()
+            .map_or(Vec::new(), |fill_in| fill_in.candidates.iter().filter(|c| c.contains(&input)).collect())
+    }
+
+    /// Replace the value typed so far with the highlighted candidate
+    pub fn complete_value(self: &mut Screen) {
+        let selected = self.fill_in.as_ref().map_or(0, |fill_in| fill_in.selected_candidate);
+        let candidate = self.matching_candidates().get(selected).map(|c| c.chars().collect());
+        if let Some(candidate) = candidate {
+            self.set_input(candidate);
+            self.set_selected_candidate(0);
+        }
+    }
+
+    pub fn next_candidate(self: &mut Screen) {
+        let count = self.matching_candidates().len();
+        if count > 0 {
+            let selected = self.fill_in.as_ref().map_or(0, |fill_in| fill_in.selected_candidate);
+            self.set_selected_candidate((selected + 1) % count);
+        }
+    }
+
+    pub fn previous_candidate(self: &mut Screen) {
+        let count = self.matching_candidates().len();
+        if count > 0 {
+            let selected = self.fill_in.as_ref().map_or(0, |fill_in| fill_in.selected_candidate);
+            self.set_selected_candidate(if selected == 0 { count - 1 } else { selected - 1 });
+        }
+    }
+
+    fn set_selected_candidate(self: &mut Screen, selected: usize) {
+        if let Some(ref mut fill_in) = self.fill_in {
+            fill_in.selected_candidate = selected;
+        }
+    }
+
+    pub fn cancel_fill_in(self: &mut Screen) {
+        if let Some(fill_in) = self.fill_in.take() {
+            self.set_input(fill_in.picker_line);
+        }
+    }
+
+    /// Validate the value being typed in, returning the selection once all the values are known
+    pub fn next_placeholder(self: &mut Screen) -> Option<Selection> {
+        let value = self.reset_input();
+        let complete = match self.fill_in {
+            Some(ref mut fill_in) => {
+                fill_in.values.push(value);
+                fill_in.candidates.clear();
+                self.current_line = fill_in.recalled_value();
+                self.cursor = self.current_line.len();
+                fill_in.values.len() + fill_in.trailing.iter().len() >= fill_in.command.cmd.names().len()
+            },
+            None => false
+        };
+
+        if complete {
+            self.fill_in.take().map(|fill_in| Selection {
+                passed_through: fill_in.trailing.is_some(),
+                values: fill_in.values.into_iter().chain(fill_in.trailing).collect(),
+                fill_in_time: Some(fill_in.started.elapsed()),
+                ..Selection::new(fill_in.command)
+            })
+        } else {
+            None
+        }
     }
 
     pub fn next_suggestion(self: &mut Screen) {
@@ -130,8 +294,22 @@ impl Screen {
         }
     }
 
-    pub fn set_commands(self: &mut Screen, commands: Vec<Rc<Command>>) {
-        self.commands = commands;
+    /// Those of a group are listed together, the groups in the order of their first command
+    pub fn set_commands(self: &mut Screen, commands: Vec<Arc<Command>>) {
+        let mut sections: Vec<Section> = Vec::new();
+        for command in commands {
+            match sections.iter_mut().find(|section| section.group == command.group) {
+                Some(section) => section.commands.push(command),
+                None => sections.push(Section { group: command.group.clone(), commands: vec![command] })
+            }
+        }
+        if sections.len() > 1 {
+            self.sections = sections;
+            self.commands = self.expanded_commands();
+        } else {
+            self.sections.clear();
+            self.commands = sections.pop().map_or(Vec::new(), |section| section.commands);
+        }
         if self.commands.is_empty() {
             self.selected_command_index = None;
         } else {
@@ -139,155 +317,356 @@ impl Screen {
         }
     }
 
+    pub fn is_grouped(self: &Screen) -> bool {
+        !self.sections.is_empty()
+    }
+
+    fn expanded_commands(self: &Screen) -> Vec<Arc<Command>> {
+        self.sections.iter()
+            .filter(|section| !self.collapsed.contains(&section.group))
+            .flat_map(|section| section.commands.iter().cloned())
+            .collect()
+    }
+
+    /// Hide the commands of the group of the highlighted one, the next one listed (or else the previous one)
+    /// being highlighted instead
+    pub fn collapse_section(self: &mut Screen) {
+        let (idx, group) = match self.selected_command() {
+            Some(command) if self.is_grouped() => (self.selected_command_index.unwrap_or(0), command.group.clone()),
+            _ => return
+        };
+        let next = self.commands[idx..].iter().find(|cmd| cmd.group != group)
+            .or_else(|| self.commands[..idx].iter().rev().find(|cmd| cmd.group != group))
+            .cloned();
+        self.collapsed.insert(group);
+        self.commands = self.expanded_commands();
+        self.selected_command_index = None;
+        if let Some(command) = next {
+            self.select_command(&command);
+        }
+    }
+
+    /// Show the commands of every group again
+    pub fn expand_sections(self: &mut Screen) {
+        let selected = self.selected_command();
+        self.collapsed.clear();
+        if !self.is_grouped() {
+            return;
+        }
+        self.commands = self.expanded_commands();
+        self.selected_command_index = Some(0).filter(|_| !self.commands.is_empty());
+        if let Some(command) = selected {
+            self.select_command(&command);
+        }
+    }
+
+    pub fn set_order(self: &mut Screen, order: Option<&'static str>) {
+        self.order = order;
+    }
+
+    pub fn set_docs(self: &mut Screen, docs: Option<Vec<String>>) {
+        self.docs = docs;
+    }
+
+    pub fn set_hint(self: &mut Screen, hint: Vec<String>) {
+        self.hint = hint;
+    }
+
+    /// Mark the selected command, or unmark it, moving on to the next one
+    pub fn toggle_mark(self: &mut Screen) {
+        if let Some(command) = self.selected_command() {
+            match self.marked.iter().position(|cmd| Arc::ptr_eq(cmd, &command)) {
+                Some(idx) => { self.marked.remove(idx); },
+                None => self.marked.push(command)
+            }
+            self.next_command();
+        }
+    }
+
+    pub fn marked(self: &Screen) -> &[Arc<Command>] {
+        &self.marked
+    }
+
+    /// Highlight this command, if listed
+    pub fn select_command(self: &mut Screen, command: &Arc<Command>) {
+        if let Some(idx) = self.commands.iter().position(|cmd| Arc::ptr_eq(cmd, command)) {
+            self.selected_command_index = Some(idx);
+        }
+    }
+
+    /// Highlight the listed command of that template, if any, telling whether there is one
+    pub fn select_template(self: &mut Screen, template: &str) -> bool {
+        let idx = self.commands.iter().position(|cmd| cmd.cmd.template() == template);
+        if idx.is_some() {
+            self.selected_command_index = idx;
+        }
+        idx.is_some()
+    }
+
     pub fn input(self: &Screen) -> String {
         self.current_line.iter().cloned().collect()
     }
 
     /// return the previous input
     pub fn reset_input(self: &mut Screen) -> String {
-        ::std::mem::replace(&mut self.current_line, Vec::new()).into_iter().collect()
+        self.take_input().into_iter().collect()
+    }
+
+    fn take_input(self: &mut Screen) -> Vec<char> {
+        self.cursor = 0;
+        ::std::mem::take(&mut self.current_line)
+    }
+
+    /// The cursor goes at the end
+    fn set_input(self: &mut Screen, line: Vec<char>) {
+        self.cursor = line.len();
+        self.current_line = line;
     }
 
     pub fn add(self: &mut Screen, key: char) {
-        self.current_line.push(key);
+        self.current_line.insert(self.cursor, key);
+        self.cursor += 1;
+        self.set_selected_candidate(0);
     }
 
+    /// The char before the cursor, or on an empty line the previous value or keyword
     pub fn remove_last_char(self: &mut Screen) {
-        if self.current_line.is_empty() {
-            self.validated_keywords.pop();
-        } else {
-            self.current_line.pop();
+        self.set_selected_candidate(0);
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.current_line.remove(self.cursor);
+        } else if !self.current_line.is_empty() {
+            // at the start of the line: nothing to remove
+        } else if let Some(ref mut fill_in) = self.fill_in {
+            // back to the previous value
+            if let Some(value) = fill_in.values.pop() {
+                self.set_input(value.chars().collect());
+            }
+        } else if !self.validated_keywords.is_empty() {
+            // back to editing the keyword, to fix a typo
+            self.save_for_undo();
+            let vkw = self.validated_keywords.pop().unwrap();
+            let mut line: Vec<char> = vkw.text().chars().collect();
+            line.pop();
+            self.set_input(line);
         }
     }
 
-    pub fn print<T: Write>(self: &Screen, terminal: &mut RawTerminal<T>) -> Result<()> {
+    /// Before changing the keywords or the input as a whole, e.g. pasting
+    pub fn save_for_undo(self: &mut Screen) {
+        self.undo.push((self.current_line.clone(), self.validated_keywords.clone()));
+    }
 
-        let auto_complete_string = if let Some(selection) = self.selected_auto_complete_index {
-            let mut new_item = String::new();
-            let mut ac: Vec<&String> = self.auto_complete.iter().collect();
+    /// The keyword of that index, e.g. with Alt-1 for the first one
+    pub fn remove_validated_keyword(self: &mut Screen, idx: usize) {
+        if idx < self.validated_keywords.len() {
+            self.save_for_undo();
+            self.validated_keywords.remove(idx);
+        }
+    }
+
+    /// Back to the input and the keywords before their last change, if any
+    pub fn undo(self: &mut Screen) {
+        if let Some((line, keywords)) = self.undo.pop() {
+            self.set_input(line);
+            self.validated_keywords = keywords;
+        }
+    }
 
-            let replace_selection = ac.get(selection).map(|item| {
-                write_highlighted!(new_item, item, color::Yellow) // TODO we're not doing anything with the Result
-            }).is_some();
+    /// The char under the cursor, if any
+    pub fn remove_next_char(self: &mut Screen) {
+        if self.cursor < self.current_line.len() {
+            self.current_line.remove(self.cursor);
+            self.set_selected_candidate(0);
+        }
+    }
 
-            if replace_selection {
-                ac.remove(selection);
-                ac.insert(selection, &new_item)
-            }
+    pub fn move_to_start(self: &mut Screen) {
+        self.cursor = 0;
+    }
+
+    pub fn move_to_end(self: &mut Screen) {
+        self.cursor = self.current_line.len();
+    }
 
-            ac.iter().join(" ")
-        } else { "".to_owned() };
-
-
-        write!(terminal, "{}{}",
-               cursor::Goto(1, self.y + 1),
-               "─".repeat(self.term_size.0 as usize))?;
-
-        write!(terminal, "{}{}",
-               cursor::Goto(1, self.y + 3),
-               "─".repeat(self.term_size.0 as usize))?;
-
-        write!(terminal, "{}{}{}",
-               cursor::Goto(1, self.y + 2),
-               clear::CurrentLine,
-               auto_complete_string
-        )?;
-
-        // print commands
-        write!(terminal, "{}{}",
-               termion::cursor::Goto(1, self.y + 4),
-               termion::clear::AfterCursor)?;
-
-        for (i,cmd) in self.commands.iter().enumerate() {
-            let description = colorize_fg(cmd.some_description(), color::Green);
-
-            match self.selected_command_index {
-                Some(sel) if i == sel =>
-                    writeln!(terminal, "{}{} {}{}\r",
-                             style::Bold,
-                             cmd.cmd.interpolate(vec!["".to_owned()]),
-                             description,
-                             style::Reset)?,
-                _ =>
-                    writeln!(terminal, "{} {}\r", cmd.cmd.interpolate(vec!["".to_owned()]), description)?
+    /// What to draw, see `render`
+    pub fn frame(self: &Screen) -> Frame {
+        let mut prompt = Vec::new();
+        let info;
+        let body;
+        let mut preview = Vec::new();
+
+        if let Some(ref fill_in) = self.fill_in {
+            prompt.push(Span::normal(format!("{}> ", fill_in.current_label())));
+            info = self.fill_in_preview(fill_in);
+            let mut lines = vec![Line::new(vec![Span::new(fill_in.command.some_description(), Style::Description)])];
+            if !fill_in.presets.is_empty() {
+                let presets = fill_in.presets.iter().enumerate().take(9)
+                    .map(|(i, (name, _))| format!("Alt-{} {}", i + 1, name))
+                    .join("  ");
+                lines.push(Line::new(vec![Span::new(presets, Style::Description)]));
+            }
+            lines.extend(self.matching_candidates().into_iter().enumerate().map(|(i, candidate)| Line {
+                spans: vec![Span::normal(candidate.clone())],
+                selected: i == fill_in.selected_candidate
+            }));
+            body = lines;
+        } else {
+            prompt.push(Span::normal(self.prompt.clone()));
+            for (i, vk) in self.validated_keywords.iter().enumerate() {
+                // removed with Alt-<index>
+                if let Some(index) = SUPERSCRIPTS.get(i) {
+                    prompt.push(Span::new(index.to_string(), Style::Description));
+                }
+                prompt.push(match *vk {
+                    ValidatedKeyword::Valid(ref kw) => Span::new(kw.clone(), Style::ValidKeyword),
+                    ValidatedKeyword::Invalid(ref kw) => Span::new(kw.clone(), Style::InvalidKeyword)
+                });
+                prompt.push(Span::normal(" "));
+            }
+            info = self.auto_complete_line();
+            body = if self.is_grouped() {
+                self.section_lines()
+            } else {
+                let shown = self.selected_command_index.unwrap_or(0) + MAX_LINES_BELOW;
+                self.commands.iter().take(shown).enumerate()
+                    .map(|(i, cmd)| self.command_line(cmd, self.selected_command_index == Some(i)))
+                    .collect()
             };
+            if let Some(ref docs) = self.docs {
+                // the examples of the tldr pages are indented the most
+                preview = docs.iter().map(|line| Line::new(if line.starts_with("    ") {
+                    highlight::spans(line, true)
+                } else {
+                    vec![Span::normal(line.clone())]
+                })).collect();
+            }
         }
+        if preview.is_empty() {
+            preview = self.hint.iter().map(|line| Line::new(vec![Span::new(line.clone(), Style::Description)])).collect();
+        }
+        let cursor = prompt.iter().map(|span| render::width(&span.text)).sum::<usize>()
+            + render::width(&self.current_line[..self.cursor].iter().collect::<String>());
+        prompt.push(Span::normal(self.input()));
 
-        write!(terminal, "{}{}{}",
-               termion::cursor::Goto(self.x, self.y),
-               clear::CurrentLine,
-               self.prompt)?;
-
-        for vk in &self.validated_keywords {
-            match vk {
-                &ValidatedKeyword::Valid(ref kw) =>
-                    write_highlighted!(terminal, kw, color::Green)?,
+        Frame { prompt: Line::new(prompt), cursor, info, body, preview }
+    }
 
-                &ValidatedKeyword::Invalid(ref kw) =>
-                    write_highlighted!(terminal, kw, color::Red)?
+    fn command_line(self: &Screen, cmd: &Arc<Command>, selected: bool) -> Line {
+        let mut spans = Vec::new();
+        if !self.marked.is_empty() {
+            let marked = self.marked.iter().any(|marked| Arc::ptr_eq(marked, cmd));
+            spans.push(Span::normal(if marked { "+ " } else { "  " }));
+        }
+        for (text, color) in self.badges.of(cmd) {
+            spans.push(Span::new(format!(" {} ", text), Style::Badge(color)));
+            spans.push(Span::normal(" "));
+        }
+        let placeholders = cmd.cmd.chunks().len() > 1;
+        if cmd.unavailable.is_some() {
+            spans.push(Span::new(cmd.cmd.template(), Style::Unavailable));
+        } else if cmd.replaced_by.is_some() {
+            spans.push(Span::new(cmd.cmd.template(), Style::Deprecated));
+        } else if cmd.is_url() {
+            spans.push(Span::new("↗ ", Style::Description));
+            spans.extend(highlight::url(cmd.cmd.template(), placeholders));
+        } else {
+            spans.extend(highlight::spans(cmd.cmd.template(), placeholders));
+        }
+        spans.push(Span::normal(" "));
+        spans.push(Span::new(cmd.some_description(), Style::Description));
+        if let Some(ref danger) = cmd.danger {
+            spans.push(Span::new(format!("  ! {}", danger), Style::Danger));
+        }
+        if let Some(ref unavailable) = cmd.unavailable {
+            spans.push(Span::new(format!("  ({})", unavailable), Style::Unavailable));
+        }
+        Line { spans, selected }
+    }
 
-            };
-            write!(terminal, " ")?;
+    /// A header per group, e.g. `▾ Makefile (3)`, followed by its commands unless collapsed
+    fn section_lines(self: &Screen) -> Vec<Line> {
+        let selected = self.selected_command();
+        let mut lines = Vec::new();
+        let mut shown = MAX_LINES_BELOW;
+        'sections: for section in &self.sections {
+            let collapsed = self.collapsed.contains(&section.group);
+            let header = format!("{} {} ({})", if collapsed { "▸" } else { "▾" },
+                                 section.group.as_deref().unwrap_or("commands"), section.commands.len());
+            lines.push(Line::new(vec![Span::new(header, Style::Header)]));
+            if collapsed {
+                continue;
+            }
+            for cmd in &section.commands {
+                if lines.len() >= shown {
+                    break 'sections;
+                }
+                let is_selected = selected.as_ref().is_some_and(|selected| Arc::ptr_eq(selected, cmd));
+                if is_selected {
+                    shown = lines.len() + MAX_LINES_BELOW;
+                }
+                lines.push(self.command_line(cmd, is_selected));
+            }
         }
+        lines
+    }
 
-        write!(terminal, "{}", self.input())?;
+    fn auto_complete_line(self: &Screen) -> Line {
+        let mut spans = Vec::new();
+        for (i, item) in self.auto_complete.iter().enumerate() {
+            if i > 0 {
+                spans.push(Span::normal(" "));
+            }
+            let style = if self.selected_auto_complete_index == Some(i) { Style::Highlight } else { Style::Normal };
+            spans.push(Span::new(item.clone(), style));
+        }
+        if let Some(order) = self.order {
+            spans.push(Span::new(format!("  [{}]", order), Style::Description));
+        }
+        Line::new(spans)
+    }
 
-        terminal.flush()?;
-        Ok(())
+    /// The command with the values typed so far, the current one being highlighted
+    fn fill_in_preview(self: &Screen, fill_in: &FillIn) -> Line {
+        let placeholders = &fill_in.command.cmd;
+        let mut spans = Vec::new();
+
+        for (i, chunk) in placeholders.chunks().iter().enumerate() {
+            spans.push(Span::normal(chunk.clone()));
+            if let Some(value) = fill_in.values.get(i) {
+                spans.push(Span::normal(value.clone()));
+            } else if i == fill_in.values.len() && i < placeholders.names().len() {
+                let input = self.input();
+                let current = if input.is_empty() { format!("{{{}}}", placeholders.names()[i]) } else { input };
+                spans.push(Span::new(current, Style::Highlight));
+            } else if let Some(name) = placeholders.names().get(i) {
+                spans.push(Span::normal(format!("{{{}}}", name)));
+            }
+        }
+        Line::new(spans)
     }
 }
 
-fn colorize_fg<C: color::Color>(msg: &str, color: C) -> String {
-    let mut colorized = String::new();
-    write!(colorized, "{}{}{}", color::Fg(color), msg, color::Fg(color::Reset));
-    colorized
+impl ValidatedKeyword {
+    pub fn text(self: &ValidatedKeyword) -> &str {
+        match *self {
+            ValidatedKeyword::Valid(ref kw) | ValidatedKeyword::Invalid(ref kw) => kw
+        }
+    }
 }
 
-// Note on the `write_highlighted` macro
-//
-// There's currently no way of providing `write_highlighted` through traits as
-// it is impossible to provide an implementation for both Write and FmtWrite.
-// Although the 2 traits share a lot of similarities, FmtWrite takes UTF-8 formatted
-// Strings and discards errors, whereas Write takes [u8] and reports errors.
-// Those differences result in a missing "bridge" between the two: FmtWrite does
-// not have a Write implementation, and neither has Write an FmtWrite implementation.
-// This is probably the reason why write! and writeln! are macros.
-//
-//pub trait WriteExt {
-//    fn write_highlighted<C: color::Color>(self: &mut Self,
-//                                          msg: &str,
-//                                          bg_color: C) -> Result<()>;
-//}
-//
-//impl<W: Write> WriteExt for W {
-//    fn write_highlighted<C: color::Color>(self: &mut W,
-//                                          msg: &str,
-//                                          bg_color: C) -> Result<()> {
-//        write!(self, "{}{}{}{}{}",
-//               color::Bg(bg_color),
-//               color::Fg(color::Black),
-//               msg,
-//               color::Bg(color::Reset),
-//               color::Fg(color::Reset))
-//    }
-//}
-//
-//
-// Another option would have been to specialize for String, knowing
-// that the `write_highlighted` implementation for Write would produce
-// correctly formatted UTF-8 Vec<u8>.
-// BUT specialization hasn't landed yet...
-//
-//impl WriteExt for String {
-//    fn write_highlighted<C: color::Color>(self: &mut String,
-//                                          msg: &str,
-//                                          bg_color: C) -> Result<()> {
-//        let v: Vec<u8> = Vec::new(); // Vec<u8> is Write
-//        v.write_highlighted(msg, bg_color)?;
-//        // write_highlighted produces UTF-8
-//        unsafe {
-//            write!(self, "{}", String::from_utf8_unchecked(v))
-//        }
-//        Ok(())
-//    }
-//}
\ No newline at end of file
+impl FillIn {
+    /// The value of the recalled set for the current placeholder, if any
+    fn recalled_value(self: &FillIn) -> Vec<char> {
+        self.recalled
+            .and_then(|i| self.ring[i].get(self.values.len()))
+            .map_or(Vec::new(), |value| value.chars().collect())
+    }
+
+    fn current_label(self: &FillIn) -> &str {
+        match self.command.cmd.names().get(self.values.len()) {
+            Some(name) if !name.is_empty() => name,
+            _ => "value"
+        }
+    }
+}
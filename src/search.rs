@@ -0,0 +1,85 @@
+use std::sync::Arc;
+use std::io::Write;
+use command::{Command, Commands};
+use suggestion::PARALLEL_THRESHOLD;
+use rayon::prelude::*;
+
+use error::Result;
+
+/// Rank the commands by relevance, the most relevant first, leaving out the irrelevant ones
+pub fn rank(commands: &Commands, query: &str) -> Vec<(f64, Arc<Command>)> {
+    let terms = words(query);
+    let scored = |cmd: &Arc<Command>| (score(cmd, &terms), cmd.clone());
+    let relevant = |&(score, _): &(f64, Arc<Command>)| score > 0.0;
+    let mut ranked: Vec<(f64, Arc<Command>)> = if commands.commands.len() < PARALLEL_THRESHOLD {
+        commands.commands.iter().map(scored).filter(relevant).collect()
+    } else {
+        commands.commands.par_iter().map(scored).filter(relevant).collect()
+    };
+
+    ranked.par_sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap().then_with(|| a.1.cmp(&b.1)));
+    ranked
+}
+
+/// Each term counts for its best match: a keyword (exactly or as a prefix),
+/// then a word of the description, then any part of the command itself
+pub fn score(cmd: &Command, terms: &[String]) -> f64 {
+    let keywords: Vec<String> = cmd.keywords.iter().map(|kw| kw.to_lowercase()).collect();
+    let description = words(cmd.some_description());
+    let text = cmd.cmd.to_string().to_lowercase();
+
+    terms.iter().map(|term| {
+        if keywords.contains(term) { 3.0 }
+        else if keywords.iter().any(|kw| kw.starts_with(term.as_str())) { 2.0 }
+        else if description.contains(term) { 1.5 }
+        else if description.iter().any(|word| word.starts_with(term.as_str())) { 1.0 }
+        else if text.contains(term.as_str()) { 0.5 }
+        else { 0.0 }
+    }).sum()
+}
+
+pub fn print<W: Write>(out: &mut W, ranked: &[(f64, Arc<Command>)]) -> Result<()> {
+    for &(score, ref cmd) in ranked {
+        writeln!(out, "{:>5.1}  {}  {}", score, cmd.cmd, cmd.some_description())?;
+    }
+    Ok(())
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use command;
+
+    fn command(cmd: &str, description: &str, keywords: &[&str]) -> Command {
+        Command { description: Some(description.to_owned()), ..command::command(cmd, keywords) }
+    }
+
+    #[test]
+    fn ranking_by_relevance() {
+        let commands = Commands::new(vec![
+            command("du -sh {dir}", "Show the disk usage of a directory", &["disk", "du"]),
+            command("df -h", "Free space on the mounted file systems", &["disk", "df"]),
+            command("ls -la", "List all the files", &["ls"])]);
+
+        let ranked: Vec<(f64, String)> = rank(&commands, "Disk usage").into_iter()
+            .map(|(score, cmd)| (score, cmd.cmd.to_string()))
+            .collect();
+
+        assert_eq!(ranked, vec![(4.5, "du -sh {dir}".to_owned()), (3.0, "df -h".to_owned())]);
+    }
+
+    #[test]
+    fn scoring_the_command_text() {
+        let cmd = command("git log --oneline", "Compact history", &["git"]);
+        assert_eq!(score(&cmd, &words("oneline")), 0.5);
+        assert_eq!(score(&cmd, &words("hist")), 1.0);
+        assert_eq!(score(&cmd, &words("gi")), 2.0);
+    }
+}
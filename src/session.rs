@@ -0,0 +1,185 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use error::{Error, Result};
+
+/// Where the picker is left when canceled, to be reopened with `--resume`,
+/// by default `~/.local/share/smart-runner/session.json`
+pub struct SessionStore {
+    pub path: PathBuf
+}
+
+/// What the picker showed: the validated keywords, the input and the highlighted command
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Session {
+    pub keywords: Vec<String>,
+    pub input: String,
+    /// The template of the highlighted command, which the picker scrolls to once a source lists it
+    #[serde(default)]
+    pub selected: Option<String>
+}
+
+impl SessionStore {
+    pub fn open() -> Result<SessionStore> {
+        ::dirs::data_dir()
+            .map(|dir| SessionStore { path: dir.join("smart-runner").join("session.json") })
+            .ok_or(Error::NoDirectory("data"))
+    }
+
+    /// None when there is nothing to resume
+    pub fn load(self: &SessionStore) -> Result<Option<Session>> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        let file = File::open(&self.path).map_err(|error| Error::File { path: self.path.clone(), error })?;
+        Ok(Some(::serde_json::from_reader(file)?))
+    }
+
+    pub fn save(self: &SessionStore, session: &Session) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, ::serde_json::to_string(session)?)?;
+        Ok(())
+    }
+
+    /// Once a command got picked, there is nothing left to resume
+    pub fn clear(self: &SessionStore) -> Result<()> {
+        if self.path.exists() {
+            fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+}
+
+/// The keywords last validated in each directory, offered back when picking there again,
+/// by default `~/.local/share/smart-runner/directories.json`
+pub struct DirectoryStore {
+    pub path: PathBuf
+}
+
+impl DirectoryStore {
+    pub fn open() -> Result<DirectoryStore> {
+        ::dirs::data_dir()
+            .map(|dir| DirectoryStore { path: dir.join("smart-runner").join("directories.json") })
+            .ok_or(Error::NoDirectory("data"))
+    }
+
+    fn load(self: &DirectoryStore) -> Result<BTreeMap<PathBuf, Vec<String>>> {
+        if !self.path.exists() {
+            return Ok(BTreeMap::new());
+        }
+        let file = File::open(&self.path).map_err(|error| Error::File { path: self.path.clone(), error })?;
+        Ok(::serde_json::from_reader(file)?)
+    }
+
+    /// Empty when nothing got validated there yet
+    pub fn keywords(self: &DirectoryStore, dir: &Path) -> Result<Vec<String>> {
+        Ok(self.load()?.remove(dir).unwrap_or_default())
+    }
+
+    pub fn save(self: &DirectoryStore, dir: &Path, keywords: &[String]) -> Result<()> {
+        let mut directories = self.load()?;
+        directories.insert(dir.to_owned(), keywords.to_vec());
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, ::serde_json::to_string(&directories)?)?;
+        Ok(())
+    }
+}
+
+/// The completion command standing for the lines of the output captured last, e.g. `completions = { pod = "@output" }`
+pub const LAST_OUTPUT: &str = "@output";
+
+/// How many lines of the output are kept, the last ones
+const MAX_OUTPUT_LINES: usize = 10_000;
+
+/// The output of the command `exec` ran last with `--capture` or `--pane`, for the placeholders of the next one
+/// to pick among its lines (`LAST_OUTPUT`), by default `~/.local/share/smart-runner/output.json`
+pub struct OutputStore {
+    pub path: PathBuf
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Captured {
+    /// The command line which printed it
+    pub cmd: String,
+    pub lines: Vec<String>
+}
+
+impl OutputStore {
+    pub fn open() -> Result<OutputStore> {
+        ::dirs::data_dir()
+            .map(|dir| OutputStore { path: dir.join("smart-runner").join("output.json") })
+            .ok_or(Error::NoDirectory("data"))
+    }
+
+    /// None until some output gets captured
+    pub fn load(self: &OutputStore) -> Result<Option<Captured>> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        let file = File::open(&self.path).map_err(|error| Error::File { path: self.path.clone(), error })?;
+        Ok(Some(::serde_json::from_reader(file)?))
+    }
+
+    pub fn save(self: &OutputStore, output: &Captured) -> Result<()> {
+        let start = output.lines.len().saturating_sub(MAX_OUTPUT_LINES);
+        let output = Captured { cmd: output.cmd.clone(), lines: output.lines[start..].to_vec() };
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, ::serde_json::to_string(&output)?)?;
+        Ok(())
+    }
+
+    /// The non-empty ones, as a completion command would list them
+    pub fn lines(self: &OutputStore) -> Result<Vec<String>> {
+        let output = self.load()?
+            .ok_or_else(|| Error::InvalidInput("No output captured yet, see `exec --capture`".to_owned()))?;
+        Ok(output.lines.iter()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .map(str::to_owned)
+            .collect())
+    }
+}
+
+#[test]
+fn remembering_the_keywords_of_each_directory() {
+    let store = DirectoryStore { path: ::std::env::temp_dir().join(format!("smart-runner-directories-{}.json", ::std::process::id())) };
+    assert!(store.keywords(Path::new("/src/app")).unwrap().is_empty());
+
+    store.save(Path::new("/src/app"), &["git".to_owned(), "deploy".to_owned()]).unwrap();
+    store.save(Path::new("/src/lib"), &["cargo".to_owned()]).unwrap();
+    assert_eq!(store.keywords(Path::new("/src/app")).unwrap(), vec!["git", "deploy"]);
+    assert_eq!(store.keywords(Path::new("/src/lib")).unwrap(), vec!["cargo"]);
+    fs::remove_file(&store.path).unwrap();
+}
+
+#[test]
+fn saving_and_clearing_the_session() {
+    let store = SessionStore { path: ::std::env::temp_dir().join(format!("smart-runner-session-{}.json", ::std::process::id())) };
+    assert_eq!(store.load().unwrap(), None);
+
+    let session = Session { keywords: vec!["git".to_owned()], input: "lo".to_owned(), selected: Some("git log".to_owned()) };
+    store.save(&session).unwrap();
+    assert_eq!(store.load().unwrap(), Some(session));
+
+    store.clear().unwrap();
+    assert_eq!(store.load().unwrap(), None);
+}
+
+#[test]
+fn capturing_the_output() {
+    let store = OutputStore { path: ::std::env::temp_dir().join(format!("smart-runner-output-{}.json", ::std::process::id())) };
+    assert!(store.lines().is_err());
+
+    let lines = vec!["pod/web-1".to_owned(), "".to_owned(), " pod/web-2 ".to_owned()];
+    store.save(&Captured { cmd: "kubectl get pods -o name".to_owned(), lines }).unwrap();
+    assert_eq!(store.lines().unwrap(), vec!["pod/web-1", "pod/web-2"]);
+    assert_eq!(store.load().unwrap().unwrap().cmd, "kubectl get pods -o name");
+    fs::remove_file(&store.path).unwrap();
+}
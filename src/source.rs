@@ -0,0 +1,418 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
+use command::{Command, Placeholders};
+use config::{CommandEntry, Config, Location, Machine, Unavailable};
+use cargo::Cargo;
+use flake::Flake;
+use git::GitAliases;
+use justfile::Justfile;
+use kubectl::Kubectl;
+use path::PathExecutables;
+use ssh::SshConfig;
+use systemd::Systemd;
+use makefile::Makefile;
+use npm::{PackageJson, PackageManager};
+use pack::{PackDir, Packs};
+use stdin;
+
+use error::{Error, Result};
+
+/// Where commands come from, see `Commands::from_sources` to pick among several of them
+pub trait CommandSource {
+    fn load(&self) -> Result<Vec<Command>>;
+
+    /// How long its commands stay current, after which the picker loads them again, if ever
+    fn ttl(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Whether loading it runs some program, e.g. `kubectl`, rather than merely reading files: not with `--safe`
+    fn runs_commands(&self) -> bool {
+        false
+    }
+}
+
+/// The configuration file, or the sample commands until the default one gets created
+pub struct ConfigFile {
+    pub location: Location
+}
+
+/// One command per line of the standard input, see `stdin::read_commands`
+pub struct Stdin {
+    pub delimiter: Option<String>
+}
+
+/// An executable contributing commands, a plugin written in any language. It is run without
+/// any input and prints its commands on stdout as a JSON object, exiting with status 0:
+///
+/// ```json
+/// {"commands": [{"cmd": "kubectl logs {pod}", "description": "Print the logs", "keywords": ["k8s", "logs"], "id": "logs"}]}
+/// ```
+///
+/// Only `cmd` is required, as in the configuration. Declared in the configuration as:
+///
+/// ```toml
+/// [[providers]]
+/// program = "~/bin/my-commands"
+/// args = ["--json"]
+/// ttl_secs = 300
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Provider {
+    pub program: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// how often the picker runs it again while open, see `CommandSource::ttl`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_secs: Option<u64>
+}
+
+/// The commands of a provider listed on this machine, as those of the configuration are, see `CommandEntry::on`
+pub struct Provided {
+    pub provider: Provider,
+    /// what becomes of those which cannot run here, as the user's configuration says
+    pub unavailable: Unavailable
+}
+
+/// A source whose commands are listed under a header of their own, see `Command::group`
+pub struct Grouped<S> {
+    pub group: &'static str,
+    pub source: S
+}
+
+/// The `[sources]` table of the configuration: which of the built-in sources are on,
+/// e.g. `[sources] makefile = false`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SourcesConfig {
+    /// the targets of the Makefile of the current directory
+    #[serde(default = "enabled")]
+    pub makefile: bool,
+    /// the scripts of the `package.json` of the current directory
+    #[serde(default = "enabled")]
+    pub package_json: bool,
+    /// the recipes of the justfile of the current directory
+    #[serde(default = "enabled")]
+    pub justfile: bool,
+    /// the common cargo tasks and aliases, within a Rust project
+    #[serde(default = "enabled")]
+    pub cargo: bool,
+    /// commands against each kubectl context, when it's installed
+    #[serde(default = "enabled")]
+    pub kubectl: bool,
+    /// the hosts of `~/.ssh/config`
+    #[serde(default = "enabled")]
+    pub ssh: bool,
+    /// the usual systemctl and journalctl commands, when systemd is there
+    #[serde(default = "enabled")]
+    pub systemd: bool,
+    /// the apps and development shells of the `flake.nix` of the current directory
+    #[serde(default = "enabled")]
+    pub flake: bool,
+    /// the git aliases
+    #[serde(default = "enabled")]
+    pub git_aliases: bool,
+    /// the executables of the PATH, off by default as there are plenty of them
+    #[serde(default)]
+    pub path: bool,
+    /// how they are run, guessed from the lock file when not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package_manager: Option<PackageManager>
+}
+
+/// What providers print
+#[derive(Deserialize)]
+struct ProviderOutput {
+    commands: Vec<CommandEntry>
+}
+
+impl Default for SourcesConfig {
+    fn default() -> SourcesConfig {
+        SourcesConfig {
+            makefile: true,
+            package_json: true,
+            justfile: true,
+            cargo: true,
+            kubectl: true,
+            ssh: true,
+            systemd: true,
+            flake: true,
+            git_aliases: true,
+            path: false,
+            package_manager: None
+        }
+    }
+}
+
+fn enabled() -> bool {
+    true
+}
+
+/// The built-in sources turned on, for that directory
+pub fn builtin(config: &SourcesConfig, dir: &Path) -> Vec<Box<dyn CommandSource + Send>> {
+    vec![
+        config.makefile.then(|| grouped("Makefile", Makefile { dir: dir.to_owned() })),
+        config.package_json.then(|| grouped("package.json", PackageJson { dir: dir.to_owned(), manager: config.package_manager })),
+        config.justfile.then(|| grouped("justfile", Justfile { dir: dir.to_owned() })),
+        config.cargo.then(|| grouped("cargo", Cargo { dir: dir.to_owned() })),
+        config.kubectl.then(|| grouped("kubectl", Kubectl)),
+        SshConfig::user().filter(|_| config.ssh).map(|ssh| grouped("ssh", ssh)),
+        config.systemd.then(|| grouped("systemd", Systemd)),
+        config.flake.then(|| grouped("flake.nix", Flake { dir: dir.to_owned() })),
+        config.git_aliases.then(|| grouped("git aliases", GitAliases)),
+        PathExecutables::user().filter(|_| config.path).map(|path| grouped("PATH", path))
+    ].into_iter().flatten().collect()
+}
+
+/// Its commands listed under that header, see `Grouped`
+fn grouped<S: CommandSource + Send + 'static>(group: &'static str, source: S) -> Box<dyn CommandSource + Send> {
+    Box::new(Grouped { group, source })
+}
+
+/// The first of those files of the directory there is, along with its content, for the built-in sources
+pub fn read_first(dir: &Path, names: &[&str]) -> Result<Option<(PathBuf, String)>> {
+    let path = match names.iter().map(|name| dir.join(name)).find(|path| path.is_file()) {
+        Some(path) => path,
+        None => return Ok(None)
+    };
+    match fs::read_to_string(&path) {
+        Ok(content) => Ok(Some((path, content))),
+        Err(error) => Err(Error::File { path, error })
+    }
+}
+
+/// The standard output of the program, run without any input, failing with its error output
+/// unless it succeeds, for the built-in sources
+pub fn program_output(program: &str, args: &[&str], dir: Option<&Path>) -> Result<String> {
+    let error = |message: String| Error::Process { program: program.to_owned(), message };
+    let mut command = process::Command::new(program);
+    command.args(args).stdin(process::Stdio::null());
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    let output = command.output().map_err(|e| error(e.to_string()))?;
+    if !output.status.success() {
+        return Err(error(String::from_utf8_lossy(&output.stderr).trim().to_owned()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// `<program> <name>` for each name, e.g. `make build` or `npm run build`, with the program (its first word) and the name
+/// as keywords
+pub fn named_commands<I: IntoIterator<Item = (String, Option<String>)>>(program: &str, named: I) -> Vec<Command> {
+    let keyword = program.split_whitespace().next().unwrap_or(program);
+    named.into_iter()
+        .map(|(name, description)| Command {
+            cmd: Placeholders::literal(&format!("{} {}", program, name)),
+            description,
+            keywords: vec![keyword.to_owned(), name],
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// The commands of a source as `<cmd> # <description>`, for its tests to compare them at a glance
+#[cfg(test)]
+pub fn listed(commands: &[Command]) -> Vec<String> {
+    commands.iter()
+        .map(|command| match command.description {
+            Some(ref description) => format!("{} # {}", command.cmd, description),
+            None => command.cmd.to_string()
+        })
+        .collect()
+}
+
+impl<S: CommandSource + ?Sized> CommandSource for Box<S> {
+    fn load(self: &Box<S>) -> Result<Vec<Command>> {
+        (**self).load()
+    }
+
+    fn ttl(self: &Box<S>) -> Option<Duration> {
+        (**self).ttl()
+    }
+
+    fn runs_commands(self: &Box<S>) -> bool {
+        (**self).runs_commands()
+    }
+}
+
+impl<S: CommandSource> CommandSource for Grouped<S> {
+    fn load(self: &Grouped<S>) -> Result<Vec<Command>> {
+        let mut commands = self.source.load()?;
+        for command in commands.iter_mut().filter(|command| command.group.is_none()) {
+            command.group = Some(self.group.to_owned());
+        }
+        Ok(commands)
+    }
+
+    fn ttl(self: &Grouped<S>) -> Option<Duration> {
+        self.source.ttl()
+    }
+
+    fn runs_commands(self: &Grouped<S>) -> bool {
+        self.source.runs_commands()
+    }
+}
+
+/// Along with the commands of the installed packs
+impl ConfigFile {
+    /// Those of the configuration as loaded already, along with the packs next to it
+    pub fn commands(self: &ConfigFile, config: &Config) -> Result<Vec<Command>> {
+        let mut commands = config.commands()?;
+        commands.extend(Packs { dir: PackDir::of(&self.location), danger: config.danger.rules(), unavailable: config.unavailable }.load()?);
+        unique_ids(&commands)?;
+        Ok(commands)
+    }
+}
+
+impl CommandSource for ConfigFile {
+    fn load(self: &ConfigFile) -> Result<Vec<Command>> {
+        self.commands(&self.location.load()?)
+    }
+}
+
+impl CommandSource for Stdin {
+    fn load(self: &Stdin) -> Result<Vec<Command>> {
+        stdin::read_commands(self.delimiter.as_deref())
+    }
+}
+
+impl Provider {
+    /// With `~/` standing for the home directory
+    pub fn program_path(self: &Provider) -> PathBuf {
+        match (self.program.strip_prefix("~/"), ::dirs::home_dir()) {
+            (Some(rest), Some(home)) => home.join(rest),
+            _ => PathBuf::from(&self.program)
+        }
+    }
+
+    /// Those it prints along with their entry, whatever their conditions, e.g. for the picker to check them
+    pub fn entries(self: &Provider) -> Result<Vec<(CommandEntry, Command)>> {
+        let error = |message: String| Error::Process { program: self.program.clone(), message };
+        // stdin is left to the runner
+        let output = process::Command::new(self.program_path())
+            .args(&self.args)
+            .stdin(process::Stdio::null())
+            .output()
+            .map_err(|e| error(format!("cannot run it: {}", e)))?;
+
+        if !output.status.success() {
+            return Err(error(format!("{}: {}", output.status, String::from_utf8_lossy(&output.stderr).trim())));
+        }
+        let output: ProviderOutput = ::serde_json::from_slice(&output.stdout)
+            .map_err(|e| error(format!("unexpected output: {}", e)))?;
+        output.commands.into_iter()
+            .map(|entry| entry.to_command().map(|command| (entry, command)))
+            .collect()
+    }
+}
+
+impl CommandSource for Provided {
+    fn load(self: &Provided) -> Result<Vec<Command>> {
+        let machine = Machine::current();
+        Ok(self.provider.entries()?.into_iter()
+            .filter_map(|(entry, command)| entry.on(&machine, self.unavailable, command))
+            .collect())
+    }
+
+    fn ttl(self: &Provided) -> Option<Duration> {
+        self.provider.ttl_secs.map(Duration::from_secs)
+    }
+
+    fn runs_commands(self: &Provided) -> bool {
+        true
+    }
+}
+
+/// Each id names a single command, be it in the configuration file or in a pack,
+/// for `exec <ID>`, `replaced_by` and the history to refer to it
+pub fn unique_ids(commands: &[Command]) -> Result<()> {
+    let mut named: HashMap<&str, &Command> = HashMap::new();
+    for command in commands {
+        if let Some(ref id) = command.id {
+            if let Some(other) = named.insert(id, command) {
+                return Err(Error::InvalidInput(format!("The id `{}` names both `{}` and `{}`: ids must be unique, rename all but one",
+                                                       id, other.cmd, command.cmd)));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn running_providers() {
+    let provider = |script: &str| Provider { program: "sh".to_owned(), args: vec!["-c".to_owned(), script.to_owned()], ttl_secs: None };
+
+    let entries = provider(r#"echo '{"commands": [{"cmd": "ls {dir}", "keywords": ["ls"]}]}'"#).entries().unwrap();
+    assert_eq!(entries[0].1.cmd.names(), &["dir"]);
+    assert_eq!(entries[0].1.keywords, vec!["ls"]);
+
+    assert!(provider("echo '[]'").entries().is_err());
+    assert!(provider("echo oops >&2; exit 3").entries().is_err());
+}
+
+#[test]
+fn checking_the_programs_provided_commands_require() {
+    let provider = Provider {
+        program: "sh".to_owned(),
+        args: vec!["-c".to_owned(), r#"echo '{"commands": [{"cmd": "ls"}, {"cmd": "kubectl get pods", "requires_bin": ["smart-runner-no-such-program"]}]}'"#.to_owned()],
+        ttl_secs: None
+    };
+    let provided = |unavailable: Unavailable| Provided { provider: provider.clone(), unavailable }.load().unwrap().into_iter()
+        .map(|command| (command.cmd.to_string(), command.unavailable))
+        .collect::<Vec<_>>();
+
+    assert_eq!(provided(Unavailable::Hide), vec![("ls".to_owned(), None)]);
+    assert_eq!(provided(Unavailable::Grey), vec![
+        ("ls".to_owned(), None),
+        ("kubectl get pods".to_owned(), Some("needs smart-runner-no-such-program".to_owned()))
+    ]);
+}
+
+#[test]
+fn listing_provided_commands_on_their_platforms() {
+    let script = format!(r#"echo '{{"commands": [{{"cmd": "open .", "platform": ["plan9"]}}, {{"cmd": "ls", "platform": ["{}"]}}]}}'"#,
+                         ::std::env::consts::OS);
+    let provider = Provider { program: "sh".to_owned(), args: vec!["-c".to_owned(), script], ttl_secs: None };
+
+    let commands = Provided { provider, unavailable: Unavailable::Grey }.load().unwrap();
+    assert_eq!(commands.iter().map(|command| command.cmd.to_string()).collect::<Vec<_>>(), vec!["ls"]);
+}
+
+#[test]
+fn listing_provided_commands_on_their_hosts_and_for_their_users() {
+    let script = r#"echo '{"commands": [{"cmd": "ls"}, {"cmd": "reboot", "hosts": ["smart-runner-no-such-host"]}, {"cmd": "apt upgrade", "users": ["smart-runner-no-such-user"]}]}'"#;
+    let provider = Provider { program: "sh".to_owned(), args: vec!["-c".to_owned(), script.to_owned()], ttl_secs: None };
+
+    let commands = Provided { provider, unavailable: Unavailable::Grey }.load().unwrap();
+    assert_eq!(commands.iter().map(|command| command.cmd.to_string()).collect::<Vec<_>>(), vec!["ls"]);
+}
+
+#[test]
+fn aggregating_sources() {
+    use command::Commands;
+
+    struct Fixed(&'static str);
+    impl CommandSource for Fixed {
+        fn load(&self) -> Result<Vec<Command>> {
+            Ok(vec![Command { cmd: Placeholders::parse(self.0)?, keywords: vec!["ls".to_owned()], ..Default::default() }])
+        }
+    }
+
+    let sources: Vec<Box<dyn CommandSource>> = vec![Box::new(Fixed("ls")), Box::new(Fixed("ls -la"))];
+    let commands = Commands::from_sources(&sources).unwrap();
+
+    assert_eq!(commands.commands.len(), 2);
+    assert_eq!(commands.kwd2cmd["ls"].len(), 2);
+}
+
+#[test]
+fn detecting_id_collisions() {
+    let command = |cmd: &str, id: Option<&str>| Command { cmd: Placeholders::parse(cmd).unwrap(), id: id.map(str::to_owned), ..Default::default() };
+    assert!(unique_ids(&[command("ls", Some("list")), command("du", None), command("df", None)]).is_ok());
+
+    let error = unique_ids(&[command("ls", Some("list")), command("du", Some("usage")), command("docker ps", Some("list"))]).unwrap_err();
+    assert_eq!(error.to_string(), "The id `list` names both `ls` and `docker ps`: ids must be unique, rename all but one");
+}
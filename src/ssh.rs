@@ -0,0 +1,169 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use command::Command;
+use source::{self, CommandSource};
+use system;
+
+use error::{Error, Result};
+
+/// The hosts of an ssh configuration, e.g. `~/.ssh/config`, as `ssh <host>`
+/// described by their user and host name. The patterns (`*`, `?`, `!`) are left out.
+/// The files of its `Include` directives are read too, relative to its directory, e.g. `~/.ssh`.
+pub struct SshConfig {
+    pub path: PathBuf
+}
+
+/// How deep the `Include` directives get followed, as with ssh, one file including itself aside
+const MAX_DEPTH: usize = 16;
+
+#[derive(Debug, PartialEq, Default)]
+struct Host {
+    alias: String,
+    host_name: Option<String>,
+    user: Option<String>
+}
+
+impl SshConfig {
+    pub fn user() -> Option<SshConfig> {
+        ::dirs::home_dir().map(|home| SshConfig { path: home.join(".ssh").join("config") })
+    }
+}
+
+impl CommandSource for SshConfig {
+    /// Nothing without that file
+    fn load(self: &SshConfig) -> Result<Vec<Command>> {
+        if !self.path.is_file() {
+            return Ok(Vec::new());
+        }
+        let content = fs::read_to_string(&self.path).map_err(|error| Error::File { path: self.path.clone(), error })?;
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        Ok(source::named_commands("ssh", hosts(&content, dir, 0).into_iter()
+            .map(|host| {
+                let description = host.description();
+                (host.alias, description)
+            })))
+    }
+}
+
+impl Host {
+    fn description(self: &Host) -> Option<String> {
+        match (&self.user, &self.host_name) {
+            (Some(user), Some(host_name)) => Some(format!("{}@{}", user, host_name)),
+            (Some(user), None) => Some(format!("{}@{}", user, self.alias)),
+            (None, host_name) => host_name.clone()
+        }
+    }
+}
+
+fn hosts(content: &str, dir: &Path, depth: usize) -> Vec<Host> {
+    let mut hosts: Vec<Host> = Vec::new();
+    // the hosts the following options apply to
+    let mut current = 0..0;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = match line.find([' ', '\t', '=']) {
+            Some(idx) => (&line[..idx], line[idx + 1..].trim_start_matches([' ', '\t', '=']).trim()),
+            None => continue
+        };
+
+        match key.to_lowercase().as_str() {
+            "host" => {
+                let start = hosts.len();
+                hosts.extend(value.split_whitespace()
+                    .filter(|alias| !alias.contains(['*', '?', '!']))
+                    .map(|alias| Host { alias: alias.to_owned(), ..Default::default() }));
+                current = start..hosts.len();
+            },
+            "match" => current = hosts.len()..hosts.len(),
+            // the files that cannot be read are skipped, as with ssh
+            "include" if depth < MAX_DEPTH => for path in value.split_whitespace().flat_map(|pattern| included(pattern, dir)) {
+                if let Ok(content) = fs::read_to_string(&path) {
+                    hosts.extend(self::hosts(&content, dir, depth + 1));
+                }
+            },
+            // the first value obtained is used, like with ssh
+            "hostname" => for host in &mut hosts[current.clone()] {
+                host.host_name.get_or_insert_with(|| value.to_owned());
+            },
+            "user" => for host in &mut hosts[current.clone()] {
+                host.user.get_or_insert_with(|| value.to_owned());
+            },
+            _ => ()
+        }
+    }
+    hosts
+}
+
+/// The files of an `Include` directive, in order: `~/` stands for the home directory, a relative path is
+/// relative to `dir`, and `*` and `?` match within each component of the path
+fn included(pattern: &str, dir: &Path) -> Vec<PathBuf> {
+    let path = match (pattern.strip_prefix("~/"), ::dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => dir.join(pattern)
+    };
+    let mut paths = vec![PathBuf::new()];
+    for component in path.components() {
+        let name = component.as_os_str().to_string_lossy();
+        if !name.contains(['*', '?']) {
+            for path in &mut paths {
+                path.push(component);
+            }
+            continue;
+        }
+        paths = paths.iter()
+            .flat_map(|parent| fs::read_dir(parent).into_iter().flatten().flatten().map(move |entry| parent.join(entry.file_name())))
+            .filter(|path| path.file_name().map(|file| file.to_string_lossy())
+                .is_some_and(|file| (!file.starts_with('.') || name.starts_with('.')) && system::matches_glob(&name, &file)))
+            .collect();
+        paths.sort();
+    }
+    paths.into_iter().filter(|path| path.is_file()).collect()
+}
+
+#[test]
+fn parsing_hosts() {
+    let hosts = hosts(concat!(
+        "# my machines\n",
+        "Host web db\n",
+        "    HostName 10.0.0.1\n",
+        "    User admin\n",
+        "\n",
+        "Host *.internal !bastion\n",
+        "    User nobody\n",
+        "Host=nas\n",
+        "  hostname=nas.local\n",
+        "Host *\n",
+        "    HostName ignored\n"), Path::new("/nowhere"), 0);
+
+    assert_eq!(hosts.len(), 3);
+    assert_eq!(hosts[0].description(), Some("admin@10.0.0.1".to_owned()));
+    assert_eq!(hosts[1].alias, "db");
+    assert_eq!(hosts[2], Host { alias: "nas".to_owned(), host_name: Some("nas.local".to_owned()), user: None });
+}
+
+#[test]
+fn following_includes() {
+    let dir = ::std::env::temp_dir().join(format!("smart-runner-ssh-{}", ::std::process::id()));
+    fs::create_dir_all(dir.join("config.d")).unwrap();
+    fs::write(dir.join("config.d").join("work"), "Host build\n    User ci\n").unwrap();
+    fs::write(dir.join("config.d").join("home"), "Host nas\n").unwrap();
+    fs::write(dir.join("other"), "Host backup\n").unwrap();
+    fs::write(dir.join("config"), format!("Host web\nInclude config.d/* {}\n    User admin\n", dir.join("oth?r").display())).unwrap();
+    fs::write(dir.join("loop"), "Host loop\nInclude loop\n").unwrap();
+
+    let commands = SshConfig { path: dir.join("config") }.load();
+    let looping = SshConfig { path: dir.join("loop") }.load();
+    fs::remove_dir_all(&dir).unwrap();
+
+    let commands = commands.unwrap();
+    let aliases: Vec<&str> = commands.iter().map(|command| command.keywords[1].as_str()).collect();
+    assert_eq!(aliases, vec!["web", "nas", "build", "backup"]);
+    assert_eq!(commands[0].description, Some("admin@web".to_owned()));
+    assert_eq!(commands[2].description, Some("ci@build".to_owned()));
+    assert_eq!(looping.unwrap().len(), MAX_DEPTH + 1);
+}
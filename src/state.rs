@@ -0,0 +1,1156 @@
+use termion::event::Key;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+
+use command::{Command, Commands, Selection};
+use danger::DangerRules;
+use macros::{self, QueryMacros};
+use render::{Frame, Line, Span, Style};
+use runner::{Action, Keybindings};
+use screen::{Screen, ValidatedKeyword};
+use suggestion::{Order, Suggestion};
+use values::{ValuePresets, ValueRings};
+
+/// What the runner acts upon, see `update`
+#[derive(Default)]
+pub struct State {
+    pub commands: Commands,
+    pub screen: Screen,
+    /// The commands used before, swapped with `commands` while browsing them
+    pub history: Commands,
+    pub browsing_history: bool,
+    /// Whether the commands used before are listed while nothing is typed in, see `StartupMode::Favorites`
+    pub favorites: bool,
+    /// Whether all the commands are listed while nothing is typed in, from the start (true) or never (false):
+    /// by default they are once something got typed in and erased
+    pub list_on_empty: Option<bool>,
+    /// The values typed in before for the placeholders of each command
+    pub values: ValueRings,
+    /// The named value sets of the placeholders of each command
+    pub presets: ValuePresets,
+    /// The values listed by the completion commands run so far, see `Command::completions`
+    pub completions: HashMap<String, Vec<String>>,
+    /// The keywords validating their members instead, see `Config::bundles`
+    pub bundles: BTreeMap<String, Vec<String>>,
+    /// The saved filters, brought back with `@name`
+    pub macros: QueryMacros,
+    /// The keywords last validated in the directory, until brought back
+    pub restorable: Vec<String>,
+    /// Whether the documentation of the selected command is shown
+    pub showing_docs: bool,
+    /// The documentation of the programs looked up so far, see `exec::documentation`
+    pub docs: HashMap<String, Vec<String>>,
+    /// How the commands are listed
+    pub order: Order,
+    /// Whether Tab marks commands to chain rather than completing the keywords
+    pub multi: bool,
+    /// Whether the commands are accepted as they are, their placeholders left unfilled
+    pub keep_placeholders: bool,
+    /// The marked commands yet to be filled in, once accepted
+    pub chain: Vec<Arc<Command>>,
+    /// The commands of the chain filled in so far
+    pub picked: Vec<Selection>,
+    /// The arguments filling the trailing placeholder of the commands in, see `RunnerBuilder::passthrough`
+    pub passthrough: Option<String>,
+    /// A deprecated command just accepted, and the one replacing it, offered instead
+    pub offer: Option<(Arc<Command>, Arc<Command>)>,
+    /// Whether the sources are to be loaded again, which the runner takes care of
+    pub refreshing: bool,
+    /// What flags the commands of the sources as they get loaded
+    pub danger: DangerRules,
+    /// Whether nothing runs, not even to look the documentation up, the dangerous commands being left out
+    pub safe: bool,
+    /// The template of the command to highlight once listed, e.g. as a source loads it, until anything else happens
+    pub resumed: Option<String>
+}
+
+/// What the picker lists before anything is typed in, e.g. `[startup] mode = "history"`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StartupMode {
+    /// Nothing until something is typed in
+    #[default]
+    Filter,
+    /// The commands used before, as with Ctrl-R
+    History,
+    /// The commands used before among those configured, the most frequent first
+    Favorites
+}
+
+#[derive(Debug)]
+pub enum Msg {
+    Type(char),
+    /// Text pasted as a whole: typed in verbatim, its spaces validating nothing
+    Paste(String),
+    DeleteChar,
+    /// Delete the char under the cursor
+    DeleteNextChar,
+    /// Move the cursor to the start of the input
+    StartOfLine,
+    EndOfLine,
+    ValidateKeyword,
+    Complete,
+    NextSuggestion,
+    PreviousSuggestion,
+    NextCommand,
+    PreviousCommand,
+    /// Pick the highlighted command, or validate the value of its placeholder
+    Accept,
+    /// Quit, or go back to the picker when filling the placeholders in
+    Cancel,
+    /// Start filling the placeholders of this command in
+    FillIn(Arc<Command>),
+    /// Accept this command, whether listed or not, e.g. with its hotkey
+    Pick(Arc<Command>),
+    /// Recall the values typed in before for the placeholders, older (true) or newer
+    RecallValues(bool),
+    /// Fill all the placeholders in with the values of the preset of that index
+    Preset(usize),
+    /// The values listed by a completion command
+    Completions(String, Vec<String>),
+    /// The commands of the source of that index, loaded in the background, maybe once again
+    Loaded(usize, Vec<Command>),
+    /// Load the sources again, see `State::refreshing`, and run the completion commands again
+    Refresh,
+    /// Browse the commands used before rather than the configured ones, or the other way around
+    ToggleHistory,
+    /// Show the documentation of the selected command, or hide it
+    ToggleDocs,
+    /// Mark the selected command to chain it, or unmark it
+    ToggleMark,
+    /// Back to the input and the keywords before their last change
+    Undo,
+    /// Remove the validated keyword of that index
+    RemoveKeyword(usize),
+    /// Validate the keywords last validated in the directory, see `State::restorable`
+    RestoreKeywords,
+    /// List the commands in the next order, see `Order::next`
+    CycleOrder,
+    /// Hide the commands of the group of the highlighted one under their header
+    CollapseSection,
+    ExpandSections,
+    /// The documentation of a program
+    Docs(String, Vec<String>)
+}
+
+#[derive(Debug)]
+pub enum Effect {
+    Continue,
+    /// With the selection, unless canceled
+    Done(Option<Selection>)
+}
+
+impl State {
+    pub fn new(commands: Commands) -> State {
+        State { commands, screen: Screen::new(), ..Default::default() }
+    }
+
+    /// The known keywords validated so far
+    pub fn valid_keywords(self: &State) -> Vec<String> {
+        self.screen.validated_keywords.iter()
+            .filter_map(|v| match *v {
+                ValidatedKeyword::Valid(ref kw) => Some(kw.clone()),
+                _ => None
+            }).collect()
+    }
+
+    /// Listing what the mode does right away
+    pub fn start(self: &mut State, mode: StartupMode) {
+        match mode {
+            StartupMode::Filter if self.list_on_empty == Some(true) => self.filter_commands(),
+            StartupMode::Filter => (),
+            StartupMode::History => self.toggle_history(),
+            StartupMode::Favorites => {
+                self.favorites = true;
+                self.order = Order::MostFrequent;
+                self.screen.set_order(Some(self.order.name()));
+                self.filter_commands();
+            }
+        }
+    }
+
+    /// Whether neither a keyword is validated nor anything typed in
+    fn nothing_typed_in(self: &State) -> bool {
+        self.screen.input().is_empty() && self.screen.validated_keywords.is_empty()
+    }
+
+    fn filter_commands(self: &mut State) {
+        if self.list_on_empty == Some(false) && self.nothing_typed_in() {
+            self.screen.set_suggestion(Suggestion::default());
+            return;
+        }
+
+        let mut suggestion = {
+            // nest `validated_keywords` as it borrows self immutably
+            let validated_keywords: HashSet<&String> = self.screen.validated_keywords.iter()
+                .filter_map(|v| match *v {
+                    ValidatedKeyword::Valid(ref kw) => Some(kw),
+                    _ => None
+                }).collect();
+
+            Suggestion::from_input_in_order(
+                &self.commands,
+                self.screen.input().as_ref(),
+                validated_keywords,
+                self.order)
+        };
+        if self.favorites && !self.browsing_history && self.nothing_typed_in() {
+            let frequency = &self.commands.frequency;
+            suggestion.commands.retain(|command| frequency.score(command.cmd.template()) > 0.0);
+        }
+
+        self.screen.set_suggestion(suggestion);
+    }
+
+    /// On a single line: a trailing newline must not accept anything
+    fn paste(self: &mut State, text: &str) {
+        self.screen.save_for_undo();
+        for c in text.trim_end_matches(['\r', '\n']).chars() {
+            match c {
+                '\r' | '\n' | '\t' => self.screen.add(' '),
+                c if c.is_control() => (),
+                c => self.screen.add(c)
+            }
+        }
+    }
+
+    /// A bundle validates each of its members, listing what they match right away,
+    /// and a query macro, e.g. `@triage`, brings its keywords and input back.
+    /// A keyword validated already merely goes away from the input
+    fn validate_keyword(self: &mut State) {
+        self.screen.save_for_undo();
+        let kw = self.screen.reset_input();
+        if let Some(query_macro) = macros::name(&kw).and_then(|name| self.macros.get(name)).cloned() {
+            self.add_keywords(query_macro.keywords);
+            for c in query_macro.input.chars() {
+                self.screen.add(c);
+            }
+            self.filter_commands();
+            return;
+        }
+        let bundle = self.bundles.get(&kw).cloned();
+        let expanded = bundle.is_some();
+        let again = self.screen.validated_keywords.iter().any(|validated| validated.text() == kw);
+        self.add_keywords(bundle.unwrap_or_else(|| vec![kw]));
+        if expanded || again {
+            self.filter_commands();
+        }
+    }
+
+    /// Validated as they are, be they unknown
+    fn add_keywords(self: &mut State, keywords: Vec<String>) {
+        for kw in keywords {
+            self.screen.add_validated_keyword(match self.commands.kwd2cmd.contains_key(&kw) {
+                true => ValidatedKeyword::Valid(kw),
+                false => ValidatedKeyword::Invalid(kw)
+            });
+        }
+    }
+
+    /// The offer to do so goes away
+    fn restore_keywords(self: &mut State) {
+        if self.restorable.is_empty() {
+            return;
+        }
+        self.screen.save_for_undo();
+        let keywords = ::std::mem::take(&mut self.restorable);
+        self.add_keywords(keywords);
+        self.screen.set_hint(Vec::new());
+        self.filter_commands();
+    }
+
+    fn start_fill_in(self: &mut State, command: Arc<Command>) {
+        let ring = self.values.get(command.cmd.template()).to_vec();
+        let presets = self.presets.get(command.cmd.template());
+        // the last placeholder is asked for all the same when it's the only one
+        let trailing = self.trailing(&command).filter(|_| command.cmd.names().len() > 1);
+        self.screen.start_fill_in(command, ring, presets, trailing);
+        self.show_candidates();
+    }
+
+    /// The value of its trailing placeholder, if passed through
+    fn trailing(self: &State, command: &Command) -> Option<String> {
+        self.passthrough.clone().filter(|_| command.cmd.ends_with_placeholder())
+    }
+
+    /// Fill its placeholders in, but the trailing one when passed through,
+    /// done right away if it's the only one
+    fn fill_in(self: &mut State, command: Arc<Command>) -> Effect {
+        match self.trailing(&command) {
+            Some(arguments) if command.cmd.names().len() == 1 =>
+                self.filled_in(Selection { values: vec![arguments], passed_through: true, ..Selection::new(command) }),
+            _ => {
+                self.start_fill_in(command);
+                Effect::Continue
+            }
+        }
+    }
+
+    /// The completion command of the placeholder being filled in, when yet to be run
+    pub fn pending_completions(self: &State) -> Option<String> {
+        self.screen.current_placeholder()
+            .and_then(|(command, name)| command.completions.get(name))
+            .filter(|provider| !self.completions.contains_key(*provider))
+            .cloned()
+    }
+
+    /// List the values of the placeholder being filled in, once its completion command has run
+    fn show_candidates(self: &mut State) {
+        let candidates = self.screen.current_placeholder()
+            .and_then(|(command, name)| command.completions.get(name))
+            .and_then(|provider| self.completions.get(provider))
+            .cloned()
+            .unwrap_or_default();
+        self.screen.set_candidates(candidates);
+    }
+
+    /// Fill the next command of the chain in, done once none is left: the last one is the selection,
+    /// following the `picked` ones
+    fn pick_next(self: &mut State) -> Effect {
+        while !self.chain.is_empty() {
+            let command = self.chain.remove(0);
+            if !command.cmd.names().is_empty() && !self.keep_placeholders {
+                return self.fill_in(command);
+            }
+            self.picked.push(Selection::new(command));
+        }
+        Effect::Done(self.picked.pop())
+    }
+
+    /// Done, unless the command is part of a chain with more to fill in
+    fn filled_in(self: &mut State, selection: Selection) -> Effect {
+        if self.chain.is_empty() && self.picked.is_empty() {
+            return Effect::Done(Some(selection));
+        }
+        self.picked.push(selection);
+        self.pick_next()
+    }
+
+    /// The command replacing a deprecated one, when listed
+    fn replacement(self: &State, command: &Command) -> Option<Arc<Command>> {
+        command.replaced_by.as_ref().and_then(|id| self.commands.commands.iter()
+            .find(|replacement| replacement.id.as_ref() == Some(id))
+            .cloned())
+    }
+
+    /// Offering its replacement instead when it's deprecated, see `State::offer`
+    fn accept_or_offer(self: &mut State, command: Arc<Command>) -> Effect {
+        match self.replacement(&command) {
+            Some(replacement) => {
+                self.offer = Some((command, replacement));
+                Effect::Continue
+            },
+            None => self.accept(command)
+        }
+    }
+
+    /// Done unless its placeholders are yet to be filled in
+    fn accept(self: &mut State, command: Arc<Command>) -> Effect {
+        if command.cmd.names().is_empty() || self.keep_placeholders {
+            return Effect::Done(Some(Selection::new(command)));
+        }
+        self.fill_in(command)
+    }
+
+    /// The program the selected command runs, whose documentation is shown
+    fn documented_program(self: &State) -> Option<String> {
+        self.screen.selected_command()
+            .and_then(|command| command.cmd.template().split_whitespace().next().map(str::to_owned))
+    }
+
+    /// The program to look the documentation of up, when shown and not known yet
+    pub fn pending_docs(self: &State) -> Option<String> {
+        if !self.showing_docs || self.safe || self.screen.is_filling_in() {
+            return None;
+        }
+        self.documented_program().filter(|program| !self.docs.contains_key(program))
+    }
+
+    fn show_docs(self: &mut State) {
+        let docs = if self.showing_docs {
+            self.documented_program().map(|program| match self.docs.get(&program) {
+                Some(docs) => docs.clone(),
+                None if self.safe => vec![format!("Not looking the documentation of {} up: safe mode", program)],
+                None => vec![format!("Looking the documentation of {} up...", program)]
+            })
+        } else {
+            None
+        };
+        self.screen.set_docs(docs);
+    }
+
+    /// Keep the input, listing what it matches right away
+    fn toggle_history(self: &mut State) {
+        ::std::mem::swap(&mut self.commands, &mut self.history);
+        self.browsing_history = !self.browsing_history;
+        self.screen.set_prompt(if self.browsing_history { "history> " } else { "> " });
+        self.filter_commands();
+    }
+
+    /// Keep the highlighted command, if it still matches
+    fn add_commands(self: &mut State, source: usize, mut commands: Vec<Command>) {
+        self.danger.flag(&mut commands);
+        if self.safe {
+            commands.retain(|command| command.danger.is_none());
+        }
+        if self.browsing_history {
+            self.history.replace(source, commands);
+            return;
+        }
+        let selected = self.screen.selected_command();
+        self.commands.replace(source, commands);
+
+        self.refilter_commands();
+        // loaded again, or merged with one loaded, it is now another command of the same template
+        if let Some(selected) = selected.filter(|selected| !self.commands.commands.iter().any(|cmd| Arc::ptr_eq(cmd, selected))) {
+            if let Some(same) = self.commands.commands.iter().find(|cmd| cmd.cmd.template() == selected.cmd.template()).cloned() {
+                self.screen.select_command(&same);
+            }
+        }
+        self.select_resumed();
+    }
+
+    /// Highlight the command of the resumed session, if listed by now, see `State::resumed`
+    pub fn select_resumed(self: &mut State) {
+        let screen = &mut self.screen;
+        if self.resumed.as_ref().is_some_and(|template| screen.select_template(template)) {
+            self.resumed = None;
+        }
+    }
+
+    /// The order is shown unless it is the default one
+    fn cycle_order(self: &mut State) {
+        self.order = self.order.next();
+        self.screen.set_order(Some(self.order.name()).filter(|_| self.order != Order::default()));
+        self.refilter_commands();
+    }
+
+    /// Keep the highlighted command, if it still matches
+    fn refilter_commands(self: &mut State) {
+        // nothing is listed until something is typed in, unless listed from the start
+        if !self.nothing_typed_in() || self.favorites || self.list_on_empty == Some(true) {
+            let selected = self.screen.selected_command();
+            self.filter_commands();
+            if let Some(command) = selected {
+                self.screen.select_command(&command);
+            }
+        }
+    }
+}
+
+/// What the key stands for: while filling the placeholders in, every char is typed in,
+/// Enter validates the value and Escape goes back to the picker. The hotkeys of the commands
+/// come before the keybindings.
+pub fn msg(state: &State, keybindings: &Keybindings, key: Key) -> Option<Msg> {
+    // the replacement of a deprecated command is offered
+    if state.offer.is_some() {
+        return match key {
+            Key::Char('\n') => Some(Msg::Accept),
+            Key::Char('\t') => Some(Msg::Complete),
+            Key::Esc        => Some(Msg::Cancel),
+            _               => None
+        };
+    }
+    if state.screen.is_filling_in() {
+        return match key {
+            Key::Char('\n') => Some(Msg::Accept),
+            Key::Esc        => Some(Msg::Cancel),
+            Key::Char('\t') => Some(Msg::Complete),
+            Key::Char(c)    => Some(Msg::Type(c)),
+            Key::Backspace  => Some(Msg::DeleteChar),
+            Key::Delete     => Some(Msg::DeleteNextChar),
+            Key::Home       => Some(Msg::StartOfLine),
+            Key::End        => Some(Msg::EndOfLine),
+            Key::Up         => Some(Msg::RecallValues(true)),
+            Key::Down       => Some(Msg::RecallValues(false)),
+            Key::Right      => Some(Msg::NextSuggestion),
+            Key::Left       => Some(Msg::PreviousSuggestion),
+            Key::Alt(c @ '1'..='9') => Some(Msg::Preset(c as usize - '1' as usize)),
+            _               => None
+        };
+    }
+
+    if let Some(&idx) = state.commands.hotkeys.get(&key) {
+        return Some(Msg::Pick(state.commands.commands[idx].clone()));
+    }
+
+    let sections = state.screen.is_grouped() && state.screen.input().is_empty();
+    match (keybindings.action(key), key) {
+        (Some(Action::Cancel), _)             => Some(Msg::Cancel),
+        (Some(Action::Accept), _)             => Some(Msg::Accept),
+        (Some(Action::Complete), _) if state.multi => Some(Msg::ToggleMark),
+        (Some(Action::Complete), _)           => Some(Msg::Complete),
+        (Some(Action::ValidateKeyword), _)    => Some(Msg::ValidateKeyword),
+        (Some(Action::DeleteChar), _)         => Some(Msg::DeleteChar),
+        (Some(Action::DeleteNextChar), _)     => Some(Msg::DeleteNextChar),
+        (Some(Action::StartOfLine), _)        => Some(Msg::StartOfLine),
+        (Some(Action::EndOfLine), _)          => Some(Msg::EndOfLine),
+        // the groups of commands, until a word is typed in: with keys typing nothing, e.g. `-` starting `--force`
+        (Some(Action::PreviousSuggestion), _) if sections => Some(Msg::CollapseSection),
+        (Some(Action::NextSuggestion), _) if sections     => Some(Msg::ExpandSections),
+        (Some(Action::NextSuggestion), _)     => Some(Msg::NextSuggestion),
+        (Some(Action::PreviousSuggestion), _) => Some(Msg::PreviousSuggestion),
+        (Some(Action::NextCommand), _)        => Some(Msg::NextCommand),
+        (Some(Action::PreviousCommand), _)    => Some(Msg::PreviousCommand),
+        (Some(Action::History), _)            => Some(Msg::ToggleHistory),
+        (Some(Action::Docs), _)               => Some(Msg::ToggleDocs),
+        (Some(Action::Order), _)              => Some(Msg::CycleOrder),
+        (Some(Action::Undo), _)               => Some(Msg::Undo),
+        (Some(Action::Restore), _)            => Some(Msg::RestoreKeywords),
+        (Some(Action::Refresh), _)            => Some(Msg::Refresh),
+        (None, Key::Alt(c @ '1'..='9'))       => Some(Msg::RemoveKeyword(c as usize - '1' as usize)),
+        (None, Key::Char(c))                  => Some(Msg::Type(c)),
+        (None, _)                             => None
+    }
+}
+
+pub fn update(mut state: State, msg: Msg) -> (State, Effect) {
+    let mut effect = Effect::Continue;
+    if !matches!(msg, Msg::Loaded(..) | Msg::Completions(..)) {
+        state.resumed = None;
+    }
+
+    if state.screen.is_filling_in() {
+        match msg {
+            Msg::Accept => match state.screen.next_placeholder() {
+                Some(selection) => effect = state.filled_in(selection),
+                None => state.show_candidates()
+            },
+            Msg::Preset(idx) => if let Some(selection) = state.screen.apply_preset(idx) {
+                effect = state.filled_in(selection);
+            },
+            Msg::Cancel => {
+                // back to the marked commands
+                state.chain.clear();
+                state.picked.clear();
+                state.screen.cancel_fill_in();
+            },
+            Msg::Type(c)             => state.screen.add(c),
+            Msg::Paste(text)         => state.paste(&text),
+            Msg::DeleteChar          => {
+                state.screen.remove_last_char();
+                state.show_candidates();
+            },
+            Msg::DeleteNextChar      => state.screen.remove_next_char(),
+            Msg::StartOfLine         => state.screen.move_to_start(),
+            Msg::EndOfLine           => state.screen.move_to_end(),
+            Msg::RecallValues(older) => state.screen.recall_values(older),
+            Msg::Complete            => state.screen.complete_value(),
+            Msg::NextSuggestion      => state.screen.next_candidate(),
+            Msg::PreviousSuggestion  => state.screen.previous_candidate(),
+            Msg::Completions(provider, values) => {
+                state.completions.insert(provider, values);
+                state.show_candidates();
+            },
+            Msg::Loaded(source, commands) => state.add_commands(source, commands),
+            Msg::Docs(program, docs) => {
+                state.docs.insert(program, docs);
+            },
+            _ => ()
+        }
+        return (state, effect);
+    }
+
+    // the other messages are e.g. the commands of a source, loaded meanwhile
+    if let (Some(_), Msg::Accept | Msg::Complete | Msg::Cancel) = (&state.offer, &msg) {
+        let (deprecated, replacement) = state.offer.take().unwrap();
+        match msg {
+            Msg::Accept   => effect = state.accept(replacement),
+            Msg::Complete => effect = state.accept(deprecated),
+            _             => ()
+        }
+        return (state, effect);
+    }
+
+    match msg {
+        Msg::Type(c) => {
+            state.screen.add(c);
+            state.filter_commands();
+        },
+        Msg::Paste(text) => {
+            state.paste(&text);
+            state.filter_commands();
+        },
+        Msg::DeleteChar => {
+            state.screen.remove_last_char();
+            state.filter_commands();
+        },
+        Msg::DeleteNextChar => {
+            state.screen.remove_next_char();
+            state.filter_commands();
+        },
+        Msg::StartOfLine        => state.screen.move_to_start(),
+        Msg::EndOfLine          => state.screen.move_to_end(),
+        Msg::ValidateKeyword    => state.validate_keyword(),
+        Msg::Complete => {
+            state.screen.complete();
+            state.filter_commands();
+        },
+        Msg::NextSuggestion     => state.screen.next_suggestion(),
+        Msg::PreviousSuggestion => state.screen.previous_suggestion(),
+        Msg::NextCommand        => state.screen.next_command(),
+        Msg::PreviousCommand    => state.screen.previous_command(),
+
+        Msg::Accept if !state.screen.marked().is_empty() => {
+            state.chain = state.screen.marked().to_vec();
+            effect = state.pick_next();
+        },
+        Msg::Accept => if let Some(command) = state.screen.selected_command() {
+            effect = state.accept_or_offer(command);
+        },
+        Msg::Pick(command)    => effect = state.accept_or_offer(command),
+        Msg::Cancel => effect = Effect::Done(None),
+
+        Msg::FillIn(command)  => state.start_fill_in(command),
+        Msg::Loaded(source, commands) => state.add_commands(source, commands),
+        Msg::Refresh          => {
+            state.refreshing = true;
+            state.completions.clear();
+        },
+        Msg::ToggleHistory    => state.toggle_history(),
+        Msg::Completions(provider, values) => {
+            state.completions.insert(provider, values);
+        },
+        Msg::ToggleDocs       => state.showing_docs = !state.showing_docs,
+        Msg::ToggleMark       => state.screen.toggle_mark(),
+        Msg::CycleOrder       => state.cycle_order(),
+        Msg::CollapseSection  => state.screen.collapse_section(),
+        Msg::ExpandSections   => state.screen.expand_sections(),
+        Msg::Undo => {
+            state.screen.undo();
+            state.filter_commands();
+        },
+        Msg::RestoreKeywords => state.restore_keywords(),
+        Msg::RemoveKeyword(idx) => {
+            state.screen.remove_validated_keyword(idx);
+            state.filter_commands();
+        },
+        Msg::Docs(program, docs) => {
+            state.docs.insert(program, docs);
+        },
+        Msg::RecallValues(_) | Msg::Preset(_) => ()
+    }
+    state.show_docs();
+    (state, effect)
+}
+
+pub fn view(state: &State) -> Frame {
+    let mut frame = state.screen.frame();
+    let deprecation = match state.offer {
+        Some((ref deprecated, ref replacement)) => vec![
+            format!("`{}` is deprecated in favor of `{}`", deprecated.cmd, replacement.cmd),
+            "Enter picks the latter, Tab the former anyway, Escape goes back".to_owned()
+        ],
+        None => state.screen.selected_command()
+            .filter(|_| !state.screen.is_filling_in() && !state.showing_docs)
+            .and_then(|command| command.replaced_by.as_ref().map(|id| vec![match state.replacement(&command) {
+                Some(replacement) => format!("Deprecated in favor of `{}`", replacement.cmd),
+                None => format!("Deprecated in favor of the command `{}`", id)
+            }]))
+            .unwrap_or_default()
+    };
+    if !deprecation.is_empty() {
+        frame.preview = deprecation.into_iter().map(|line| Line::new(vec![Span::new(line, Style::Description)])).collect();
+    }
+    // above the docs, which may not fit in
+    let failing = state.screen.selected_command()
+        .filter(|_| !state.screen.is_filling_in() && state.offer.is_none())
+        .and_then(|command| state.commands.outcomes.get(&command.cmd.to_string()).cloned())
+        .filter(|outcomes| outcomes.failures > 0);
+    if let Some(outcomes) = failing {
+        let last = outcomes.last_failure.map_or(String::new(), |status| format!(", lately with status {}", status));
+        let line = format!("Failed {} of its last {} runs{}", outcomes.failures, outcomes.runs, last);
+        frame.preview.insert(0, Line::new(vec![Span::new(line, Style::Description)]));
+    }
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use command::command;
+
+    fn update_all(state: State, msgs: Vec<Msg>) -> (State, Effect) {
+        msgs.into_iter().fold((state, Effect::Continue), |(state, _), msg| update(state, msg))
+    }
+
+    #[test]
+    fn filling_placeholders_in() {
+        let state = State::new(Commands::new(vec![command("cp {src} {dst}", &["cp"])]));
+
+        let (state, effect) = update_all(state, vec![Msg::Type('c'), Msg::Type('p'), Msg::Accept]);
+        assert!(state.screen.is_filling_in());
+        assert!(matches!(effect, Effect::Continue));
+
+        let (_, effect) = update_all(state, vec![Msg::Type('a'), Msg::Accept, Msg::Type('b'), Msg::Accept]);
+        match effect {
+            Effect::Done(Some(selection)) => assert_eq!(selection.interpolated(), "cp a b"),
+            other => panic!("unexpected {:?}", other)
+        }
+    }
+
+    #[test]
+    fn canceling_the_fill_in() {
+        let state = State::new(Commands::new(vec![command("cp {src} {dst}", &["cp"])]));
+
+        let (state, _) = update_all(state, vec![Msg::Type('c'), Msg::Type('p'), Msg::Accept, Msg::Type('a'), Msg::Cancel]);
+        assert!(!state.screen.is_filling_in());
+        assert_eq!(state.screen.input(), "cp");
+
+        let (_, effect) = update(state, Msg::Cancel);
+        assert!(matches!(effect, Effect::Done(None)));
+    }
+
+    #[test]
+    fn adding_the_commands_of_late_sources() {
+        let state = State::new(Commands::new(vec![command("ls", &["files"]), command("du", &["files"])]));
+
+        let (state, _) = update_all(state, vec![
+            Msg::Type('f'), Msg::Type('i'), Msg::Type('l'), Msg::Type('e'), Msg::Type('s'), Msg::ValidateKeyword,
+            Msg::NextCommand,
+            Msg::Loaded(0, vec![command("df", &["files"]), command("tree", &["files"])])
+        ]);
+        assert_eq!(view(&state).text(), "> ¹files \n\n  df \n  du \n* ls \n  tree ");
+    }
+
+    #[test]
+    fn loading_a_source_again() {
+        let state = State::new(Commands::new(vec![command("ls", &["files"])]));
+
+        let (state, _) = update_all(state, vec![
+            Msg::Loaded(1, vec![command("kubectl --context dev get pods", &["k8s"]), command("kubectl --context prod get pods", &["k8s"])]),
+            Msg::Type('k'), Msg::Type('8'), Msg::Type('s'), Msg::NextCommand,
+            Msg::Refresh
+        ]);
+        assert!(state.refreshing);
+        assert_eq!(view(&state).text(), "> k8s\nk8s\n  kubectl --context dev get pods \n* kubectl --context prod get pods ");
+
+        let (state, _) = update(state, Msg::Loaded(1, vec![
+            command("kubectl --context prod get pods", &["k8s"]), command("kubectl --context staging get pods", &["k8s"])]));
+        assert_eq!(view(&state).text(), "> k8s\nk8s\n* kubectl --context prod get pods \n  kubectl --context staging get pods ");
+        assert_eq!(state.commands.commands.len(), 3);
+    }
+
+    #[test]
+    fn resuming_on_a_command_of_a_late_source() {
+        let (mut state, _) = update_all(State::new(Commands::new(vec![command("ls", &["files"])])), vec![
+            Msg::Type('f'), Msg::Type('i'), Msg::Type('l'), Msg::Type('e'), Msg::Type('s'), Msg::ValidateKeyword]);
+        state.resumed = Some("tree".to_owned());
+        state.select_resumed();
+        assert_eq!(view(&state).text(), "> ¹files \nfiles\n* ls ");
+
+        let (state, _) = update(state, Msg::Loaded(0, vec![command("df", &["files"]), command("tree", &["files"])]));
+        assert_eq!(view(&state).text(), "> ¹files \n\n  df \n  ls \n* tree ");
+        assert_eq!(state.resumed, None);
+    }
+
+    #[test]
+    fn validating_a_bundle() {
+        let mut state = State::new(Commands::new(vec![
+            command("kubectl logs web", &["k8s", "pods", "logs"]), command("kubectl get pods", &["k8s", "pods"])]));
+        state.bundles.insert("dbg".to_owned(), vec!["k8s".to_owned(), "logs".to_owned(), "typo".to_owned()]);
+
+        let (state, _) = update_all(state, vec![Msg::Type('d'), Msg::Type('b'), Msg::Type('g'), Msg::ValidateKeyword]);
+        assert_eq!(view(&state).text(), "> ¹k8s ²logs ³typo \n\n* kubectl logs web ");
+    }
+
+    #[test]
+    fn validating_a_keyword_once() {
+        let mut state = State::new(Commands::new(vec![
+            command("kubectl logs web", &["k8s", "pods", "logs"]), command("kubectl get pods", &["k8s", "pods"])]));
+        state.bundles.insert("dbg".to_owned(), vec!["k8s".to_owned(), "logs".to_owned()]);
+
+        let typed = |text: &str| text.chars().map(Msg::Type).chain(vec![Msg::ValidateKeyword]).collect::<Vec<_>>();
+        let (state, _) = update_all(state, vec![typed("logs"), typed("dbg"), typed("k8s")].into_iter().flatten().collect());
+        assert_eq!(view(&state).text(), "> ¹logs ²k8s \n\n* kubectl logs web ");
+    }
+
+    #[test]
+    fn recalling_a_query_macro() {
+        use macros::QueryMacro;
+
+        let mut state = State::new(Commands::new(vec![
+            command("kubectl logs web", &["k8s", "logs", "web"]), command("kubectl logs api", &["k8s", "logs", "api"])]));
+        state.macros.insert("web".to_owned(), QueryMacro { keywords: vec!["k8s".to_owned(), "logs".to_owned()], input: "we".to_owned() });
+
+        let (state, _) = update_all(state, vec![Msg::Type('@'), Msg::Type('w'), Msg::Type('e'), Msg::Type('b'), Msg::ValidateKeyword]);
+        assert_eq!(view(&state).text(), "> ¹k8s ²logs we\nweb\n* kubectl logs web ");
+    }
+
+    #[test]
+    fn collapsing_groups() {
+        let grouped = |cmd: &str, group: Option<&str>| Command { group: group.map(str::to_owned), ..command(cmd, &["build"]) };
+        let state = State::new(Commands::new(vec![
+            grouped("make all", Some("Makefile")), grouped("cargo build", None), grouped("make test", Some("Makefile"))]));
+        let keybindings = Keybindings::default();
+
+        let (state, _) = update_all(state, vec![
+            Msg::Type('b'), Msg::Type('u'), Msg::Type('i'), Msg::Type('l'), Msg::Type('d'), Msg::ValidateKeyword]);
+        assert_eq!(view(&state).text(), "> ¹build \nbuild\n  ▾ commands (1)\n* cargo build \n  ▾ Makefile (2)\n  make all \n  make test ");
+
+        let collapse = msg(&state, &keybindings, Key::Left).unwrap();
+        let (state, _) = update(state, collapse);
+        assert_eq!(view(&state).text(), "> ¹build \nbuild\n  ▸ commands (1)\n  ▾ Makefile (2)\n* make all \n  make test ");
+
+        assert!(matches!(msg(&state, &keybindings, Key::Char('-')), Some(Msg::Type('-'))));
+        let expand = msg(&state, &keybindings, Key::Right).unwrap();
+        let (state, _) = update(state, expand);
+        assert_eq!(state.screen.selected_command().unwrap().cmd.template(), "make all");
+        assert_eq!(view(&state).text().lines().count(), 7);
+    }
+
+    #[test]
+    fn editing_a_validated_keyword() {
+        let state = State::new(Commands::new(vec![command("ls", &["files"]), command("du", &["disk"])]));
+
+        let (state, _) = update_all(state, vec![
+            Msg::Type('f'), Msg::Type('i'), Msg::Type('l'), Msg::Type('s'), Msg::ValidateKeyword, Msg::DeleteChar
+        ]);
+        assert!(state.screen.validated_keywords.is_empty());
+        assert_eq!(state.screen.input(), "fil");
+
+        let (state, _) = update_all(state, vec![Msg::Type('e'), Msg::Type('s'), Msg::ValidateKeyword]);
+        assert_eq!(state.valid_keywords(), vec!["files".to_owned()]);
+    }
+
+    #[test]
+    fn editing_mid_line() {
+        let state = State::new(Commands::new(vec![command("ls", &["files"])]));
+
+        let (state, _) = update_all(state, vec![
+            Msg::Type('i'), Msg::Type('l'), Msg::Type('x'), Msg::StartOfLine, Msg::DeleteNextChar,
+            Msg::Type('f'), Msg::Type('i'), Msg::EndOfLine, Msg::DeleteChar, Msg::Type('e')
+        ]);
+        assert_eq!(state.screen.input(), "file");
+        assert_eq!(view(&state).cursor, 6);
+
+        let (state, _) = update_all(state, vec![Msg::StartOfLine, Msg::DeleteChar]);
+        assert_eq!(state.screen.input(), "file");
+        assert_eq!(view(&state).cursor, 2);
+    }
+
+    #[test]
+    fn showing_the_docs() {
+        let state = State::new(Commands::new(vec![command("ls -la", &["ls"]), command("du -sh", &["du"])]));
+
+        let (state, _) = update_all(state, vec![Msg::Type('l'), Msg::ToggleDocs]);
+        assert_eq!(state.pending_docs(), Some("ls".to_owned()));
+        assert_eq!(view(&state).text(), "> l\nls\n* ls -la \n--\nLooking the documentation of ls up...");
+
+        let (state, _) = update(state, Msg::Docs("ls".to_owned(), vec!["ls - list directory contents".to_owned()]));
+        assert_eq!(state.pending_docs(), None);
+        assert_eq!(view(&state).text(), "> l\nls\n* ls -la \n--\nls - list directory contents");
+
+        let (state, _) = update(state, Msg::ToggleDocs);
+        assert_eq!(view(&state).text(), "> l\nls\n* ls -la ");
+    }
+
+    #[test]
+    fn chaining_marked_commands() {
+        let commands = Commands::new(vec![
+            command("git add {path}", &["git"]), command("git commit", &["git"]), command("git push", &["git"])]);
+        let state = State { multi: true, ..State::new(commands) };
+        let keybindings = Keybindings::default();
+        assert!(matches!(msg(&state, &keybindings, Key::Char('\t')), Some(Msg::ToggleMark)));
+
+        let (state, _) = update_all(state, vec![
+            Msg::Type('g'), Msg::ToggleMark, Msg::ToggleMark, Msg::ToggleMark, Msg::PreviousCommand, Msg::ToggleMark,
+            Msg::NextCommand
+        ]);
+        assert_eq!(view(&state).text(), "> g\ngit\n  + git add {path} \n* + git commit \n    git push ");
+
+        let (state, effect) = update_all(state, vec![Msg::Accept, Msg::Type('a'), Msg::Accept]);
+        match effect {
+            Effect::Done(Some(selection)) => assert_eq!(selection.interpolated(), "git commit"),
+            other => panic!("unexpected {:?}", other)
+        }
+        let picked: Vec<String> = state.picked.iter().map(Selection::interpolated).collect();
+        assert_eq!(picked, vec!["git add a"]);
+    }
+
+    #[test]
+    fn leaving_the_dangerous_commands_of_the_sources_out() {
+        use danger::DangerConfig;
+
+        let danger = DangerConfig { patterns: vec!["systemctl stop".to_owned()], ..Default::default() }.rules();
+        let state = State { danger: danger.clone(), ..State::new(Commands::new(vec![command("ls", &["unit"])])) };
+        let loaded = || Msg::Loaded(0, vec![command("sudo systemctl stop {unit}", &["unit"]), command("systemctl status {unit}", &["unit"])]);
+
+        let (state, _) = update_all(state, vec![loaded(), Msg::Type('u')]);
+        assert_eq!(state.commands.commands[1].danger, Some("matches `systemctl stop`".to_owned()));
+
+        let state = State { danger, safe: true, ..State::new(Commands::new(vec![command("ls", &["unit"])])) };
+        let (state, _) = update_all(state, vec![loaded(), Msg::Type('u'), Msg::ToggleDocs]);
+        assert_eq!(view(&state).text(), "> u\nunit\n* ls \n  systemctl status {unit} \n--\nNot looking the documentation of ls up: safe mode");
+        assert_eq!(state.pending_docs(), None);
+    }
+
+    #[test]
+    fn starting_in_a_mode() {
+        let commands = || {
+            let mut commands = Commands::new(vec![command("ls", &["files"]), command("du", &["files"]), command("df", &["disk"])]);
+            commands.frequency = ::frecency::Frecency::from_scores(hashmap!("ls".to_owned() => 1.0, "df".to_owned() => 3.0));
+            commands
+        };
+        let started = |mode: StartupMode, list_on_empty: Option<bool>| {
+            let mut state = State { list_on_empty, ..State::new(commands()) };
+            state.history = Commands::new(vec![command("ls /tmp", &["ls", "/tmp"])]);
+            state.start(mode);
+            state
+        };
+
+        assert_eq!(view(&started(StartupMode::Filter, None)).text(), "> \n");
+        assert_eq!(view(&started(StartupMode::Filter, Some(true))).text(), "> \n\n* df \n  du \n  ls ");
+        assert_eq!(view(&started(StartupMode::History, None)).text(), "history> \n\n* ls /tmp ");
+        let (state, _) = update(started(StartupMode::Favorites, None), Msg::NextCommand);
+        assert_eq!(view(&state).text(), "> \n  [most frequent]\n  df \n* ls ");
+        let (state, _) = update(state, Msg::Type('f'));
+        assert_eq!(view(&state).text(), "> f\nfiles  [most frequent]\n* ls \n  du ");
+
+        let (state, _) = update_all(started(StartupMode::Filter, Some(false)), vec![Msg::Type('f'), Msg::DeleteChar]);
+        assert_eq!(view(&state).text(), "> \n");
+        let (state, _) = update(started(StartupMode::Filter, None), Msg::Loaded(0, vec![command("tree", &["files"])]));
+        assert_eq!(view(&state).text(), "> \n");
+    }
+
+    #[test]
+    fn cycling_the_order() {
+        let mut state = State::new(Commands::new(vec![command("ls", &["files"]), command("du", &["files"])]));
+        state.commands.frequency = ::frecency::Frecency::from_scores(hashmap!("ls".to_owned() => 3.0));
+
+        let (state, _) = update_all(state, vec![Msg::Type('f'), Msg::CycleOrder]);
+        assert_eq!(view(&state).text(), "> f\nfiles  [alphabetical]\n* du \n  ls ");
+
+        let (state, _) = update_all(state, vec![Msg::CycleOrder, Msg::CycleOrder]);
+        assert_eq!(view(&state).text(), "> f\nfiles  [most frequent]\n  ls \n* du ");
+
+        let (state, _) = update(state, Msg::Type('i'));
+        assert_eq!(view(&state).text(), "> fi\nfiles  [most frequent]\n* ls \n  du ");
+    }
+
+    #[test]
+    fn telling_the_failing_commands() {
+        let mut state = State::new(Commands::new(vec![command("ssh old-host", &["ssh"]), command("ssh new-host", &["ssh"])]));
+        state.commands.outcomes = hashmap!("ssh old-host".to_owned() =>
+            ::history::Outcomes { runs: 4, failures: 3, last_failure: Some(255) });
+
+        let (state, _) = update(state, Msg::Type('s'));
+        assert_eq!(view(&state).preview.len(), 0);
+        let (state, _) = update(state, Msg::NextCommand);
+        assert_eq!(view(&state).preview[0].text(), "Failed 3 of its last 4 runs, lately with status 255");
+    }
+
+    #[test]
+    fn undoing() {
+        let state = State::new(Commands::new(vec![command("ls", &["files"]), command("du", &["disk"])]));
+
+        let (state, _) = update_all(state, vec![
+            Msg::Type('f'), Msg::Type('i'), Msg::ValidateKeyword, Msg::Type('d'), Msg::Complete
+        ]);
+        assert_eq!(view(&state).text(), "> ¹fi ²disk \n\n* du ");
+
+        let (state, _) = update(state, Msg::Undo);
+        assert_eq!(view(&state).text(), "> ¹fi d\ndisk\n* du ");
+
+        let (state, _) = update_all(state, vec![Msg::Undo, Msg::Undo]);
+        assert_eq!(view(&state).text(), "> fi\nfiles\n* ls ");
+    }
+
+    #[test]
+    fn removing_a_keyword() {
+        let state = State::new(Commands::new(vec![command("ls", &["files", "list"]), command("du", &["files", "disk"])]));
+        let keybindings = Keybindings::default();
+
+        let (state, _) = update_all(state, vec![
+            Msg::Type('f'), Msg::Type('i'), Msg::Type('l'), Msg::Type('e'), Msg::Type('s'), Msg::ValidateKeyword,
+            Msg::Type('d'), Msg::Type('i'), Msg::Type('s'), Msg::Type('k'), Msg::ValidateKeyword
+        ]);
+        let remove = msg(&state, &keybindings, Key::Alt('2')).unwrap();
+        let (state, _) = update(state, remove);
+        assert_eq!(state.valid_keywords(), vec!["files".to_owned()]);
+        assert_eq!(view(&state).text().lines().skip(2).count(), 2);
+
+        let (state, _) = update(state, Msg::RemoveKeyword(5));
+        assert_eq!(state.valid_keywords().len(), 1);
+        let (state, _) = update(state, Msg::Undo);
+        assert_eq!(state.valid_keywords(), vec!["files".to_owned(), "disk".to_owned()]);
+    }
+
+    #[test]
+    fn restoring_the_keywords_of_the_directory() {
+        let mut state = State::new(Commands::new(vec![command("ls", &["files"]), command("du", &["disk"])]));
+        state.restorable = vec!["files".to_owned(), "gone".to_owned()];
+
+        let restore = msg(&state, &Keybindings::default(), Key::Ctrl('t')).unwrap();
+        let (state, _) = update(state, restore);
+        assert_eq!(view(&state).text(), "> ¹files ²gone \n\n* ls ");
+        assert!(state.restorable.is_empty());
+    }
+
+    #[test]
+    fn accepting_with_a_hotkey() {
+        let mut du = command("du -sh {dir}", &["du"]);
+        du.hotkey = Some("F2".to_owned());
+        let state = State::new(Commands::new(vec![command("ls", &["files"]), du]));
+
+        let (state, _) = update(state, Msg::Type('f'));
+        let pick = msg(&state, &Keybindings::default(), Key::F(2));
+        assert!(matches!(pick, Some(Msg::Pick(ref command)) if command.cmd.template() == "du -sh {dir}"));
+
+        let (state, _) = update(state, pick.unwrap());
+        assert!(state.screen.is_filling_in());
+    }
+
+    #[test]
+    fn pasting_text() {
+        let commands = || Commands::new(vec![command("git commit -m {message}", &["git", "commit"])]);
+
+        let (state, _) = update(State::new(commands()), Msg::Paste("git commit".to_owned()));
+        assert!(state.screen.validated_keywords.is_empty());
+        assert_eq!(state.screen.input(), "git commit");
+
+        let (state, _) = update_all(State::new(commands()), vec![
+            Msg::Type('g'), Msg::Accept, Msg::Paste("fix\tthe build\n".to_owned())]);
+        assert!(state.screen.is_filling_in());
+        assert_eq!(state.screen.input(), "fix the build");
+    }
+
+    #[test]
+    fn recalling_values() {
+        let mut state = State::new(Commands::new(vec![command("cp {src} {dst}", &["cp"])]));
+        state.values.remember("cp {src} {dst}", &["a".to_owned(), "b".to_owned()]);
+        state.values.remember("cp {src} {dst}", &["c".to_owned(), "d".to_owned()]);
+
+        let (state, _) = update_all(state, vec![
+            Msg::Type('c'), Msg::Type('p'), Msg::Accept,
+            Msg::RecallValues(true), Msg::RecallValues(true), Msg::RecallValues(true)
+        ]);
+        assert_eq!(state.screen.input(), "a");
+
+        let (state, _) = update_all(state, vec![Msg::RecallValues(false), Msg::Accept]);
+        assert_eq!(state.screen.input(), "d");
+
+        let (_, effect) = update_all(state, vec![Msg::DeleteChar, Msg::Type('e'), Msg::Accept]);
+        match effect {
+            Effect::Done(Some(selection)) => assert_eq!(selection.interpolated(), "cp c e"),
+            other => panic!("unexpected {:?}", other)
+        }
+    }
+
+    #[test]
+    fn applying_presets() {
+        let mut state = State::new(Commands::new(vec![command("deploy {env} {region}", &["deploy"])]));
+        state.presets.set("deploy {env} {region}", "prod", &["prd".to_owned(), "eu".to_owned()]);
+        state.presets.set("deploy {env} {region}", "staging", &["stg".to_owned(), "us".to_owned()]);
+
+        let (state, _) = update_all(state, vec![Msg::Type('d'), Msg::Accept, Msg::Type('x')]);
+        assert_eq!(view(&state).text(), "env> x\ndeploy x {region}\n  \n  Alt-1 prod  Alt-2 staging");
+        assert!(matches!(msg(&state, &Keybindings::default(), Key::Alt('2')), Some(Msg::Preset(1))));
+
+        let (_, effect) = update(state, Msg::Preset(1));
+        match effect {
+            Effect::Done(Some(selection)) => assert_eq!(selection.interpolated(), "deploy stg us"),
+            other => panic!("unexpected {:?}", other)
+        }
+    }
+
+    #[test]
+    fn completing_values() {
+        let mut git = command("git checkout {branch}", &["git"]);
+        git.completions.insert("branch".to_owned(), "git branch".to_owned());
+        let state = State::new(Commands::new(vec![git]));
+
+        let (state, _) = update_all(state, vec![Msg::Type('g'), Msg::Accept]);
+        assert_eq!(state.pending_completions(), Some("git branch".to_owned()));
+
+        let (state, _) = update_all(state, vec![
+            Msg::Completions("git branch".to_owned(), vec!["main".to_owned(), "fix-a".to_owned(), "fix-b".to_owned()]),
+            Msg::Type('f'), Msg::NextSuggestion
+        ]);
+        assert_eq!(state.pending_completions(), None);
+        assert_eq!(view(&state).text(), "branch> f\ngit checkout f\n  \n  fix-a\n* fix-b");
+
+        let (_, effect) = update_all(state, vec![Msg::Complete, Msg::Accept]);
+        match effect {
+            Effect::Done(Some(selection)) => assert_eq!(selection.interpolated(), "git checkout fix-b"),
+            other => panic!("unexpected {:?}", other)
+        }
+    }
+
+    #[test]
+    fn browsing_the_history() {
+        let mut state = State::new(Commands::new(vec![command("ls {dir}", &["ls"])]));
+        state.history = Commands::new(vec![command("ls /tmp", &["ls", "/tmp"]), command("du -sh", &["du", "-sh"])]);
+
+        let (state, _) = update_all(state, vec![Msg::Type('l'), Msg::ToggleHistory]);
+        assert_eq!(view(&state).text(), "history> l\nls\n* ls /tmp ");
+
+        let (state, _) = update_all(state, vec![Msg::DeleteChar, Msg::ToggleHistory, Msg::ToggleHistory]);
+        assert_eq!(view(&state).text(), "history> \n\n* du -sh \n  ls /tmp ");
+
+        let (_, effect) = update(state, Msg::Accept);
+        match effect {
+            Effect::Done(Some(selection)) => assert_eq!(selection.interpolated(), "du -sh"),
+            other => panic!("unexpected {:?}", other)
+        }
+    }
+
+    #[test]
+    fn passing_arguments_through() {
+        let commands = || Commands::new(vec![
+            command("kubectl get pods {args}", &["pods"]), command("kubectl logs {pod} {args}", &["logs"]), command("ls", &["ls"])]);
+        let passing = |msgs: Vec<Msg>| {
+            let state = State { passthrough: Some("-n kube-system".to_owned()), ..State::new(commands()) };
+            match update_all(state, msgs) {
+                (state, Effect::Continue) => (Some(state), None),
+                (_, Effect::Done(selection)) => (None, selection.map(|mut selection| {
+                    selection.pass_through("-n kube-system");
+                    selection.interpolated()
+                }))
+            }
+        };
+
+        let (_, picked) = passing(vec![Msg::Type('p'), Msg::Type('o'), Msg::Accept]);
+        assert_eq!(picked.unwrap(), "kubectl get pods -n kube-system");
+
+        let (state, _) = passing(vec![Msg::Type('l'), Msg::Type('o'), Msg::Accept]);
+        assert_eq!(view(&state.unwrap()).text().lines().next(), Some("pod> "));
+        let (_, picked) = passing(vec![Msg::Type('l'), Msg::Type('o'), Msg::Accept, Msg::Type('w'), Msg::Accept]);
+        assert_eq!(picked.unwrap(), "kubectl logs w -n kube-system");
+
+        let (_, picked) = passing(vec![Msg::Type('l'), Msg::Type('s'), Msg::ValidateKeyword, Msg::Accept]);
+        assert_eq!(picked.unwrap(), "ls -n kube-system");
+    }
+
+    #[test]
+    fn offering_the_replacement_of_a_deprecated_command() {
+        let with_id = |id: &str, cmd: &str, replaced_by: Option<&str>| Command {
+            id: Some(id.to_owned()),
+            replaced_by: replaced_by.map(str::to_owned),
+            ..command(cmd, &[id])
+        };
+        let state = State::new(Commands::new(vec![
+            with_id("legacy", "docker-compose up", Some("compose")), with_id("compose", "docker compose up", None)]));
+        let keybindings = Keybindings::default();
+
+        let (state, _) = update_all(state, vec![Msg::Type('l'), Msg::Type('e'), Msg::Type('g')]);
+        assert_eq!(view(&state).text().lines().last(), Some("Deprecated in favor of `docker compose up`"));
+
+        let (state, effect) = update(state, Msg::Accept);
+        assert!(matches!(effect, Effect::Continue));
+        assert_eq!(view(&state).text().lines().rev().nth(1), Some("`docker-compose up` is deprecated in favor of `docker compose up`"));
+        assert!(msg(&state, &keybindings, Key::Char('x')).is_none());
+
+        let cancel = msg(&state, &keybindings, Key::Esc).unwrap();
+        let (state, effect) = update(state, cancel);
+        assert!(matches!(effect, Effect::Continue));
+        assert!(state.offer.is_none());
+
+        let (state, _) = update(state, Msg::Accept);
+        let accept = msg(&state, &keybindings, Key::Char('\n')).unwrap();
+        match update(state, accept) {
+            (_, Effect::Done(Some(selection))) => assert_eq!(selection.interpolated(), "docker compose up"),
+            (_, other) => panic!("unexpected {:?}", other)
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::io::Write;
+use itertools::Itertools;
+
+use config::CommandEntry;
+use frecency::Frecency;
+use history::Record;
+
+use error::Result;
+
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// most used first
+    pub most_used: Vec<(String, usize)>,
+    pub never_used: Vec<String>,
+    /// most used first
+    pub keywords: Vec<(String, usize)>,
+    pub average_fill_in_ms: Option<u64>,
+    pub fill_ins: usize,
+    /// most frecent first
+    pub frecent: Vec<(String, f64)>,
+    /// the keywords of the commands, most used first
+    pub heat: Vec<KeywordHeat>,
+    /// the commands whose keywords are all never used, hence likely picked some other way or not at all
+    pub only_via_unused: Vec<String>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeywordHeat {
+    pub keyword: String,
+    /// how many times it was validated to pick a command
+    pub uses: usize,
+    /// how many commands declare it
+    pub commands: usize
+}
+
+impl Stats {
+    pub fn compute(records: &[Record], commands: &[CommandEntry], frecency: &Frecency) -> Stats {
+        let mut uses: HashMap<&str, usize> = HashMap::new();
+        let mut keywords: HashMap<&str, usize> = HashMap::new();
+        for record in records {
+            *uses.entry(&record.template).or_insert(0) += 1;
+            for kw in &record.keywords {
+                *keywords.entry(kw).or_insert(0) += 1;
+            }
+        }
+
+        let fill_in_times: Vec<u64> = records.iter().filter_map(|r| r.fill_in_ms).collect();
+
+        let mut declaring: HashMap<&str, usize> = HashMap::new();
+        for kw in commands.iter().flat_map(|cmd| cmd.keywords.iter().unique()) {
+            *declaring.entry(kw).or_insert(0) += 1;
+        }
+        let heat = declaring.into_iter()
+            .map(|(kw, commands)| KeywordHeat { keyword: kw.to_owned(), uses: keywords.get(kw).cloned().unwrap_or(0), commands })
+            .sorted_by(|a, b| b.uses.cmp(&a.uses).then_with(|| a.keyword.cmp(&b.keyword)));
+        let only_via_unused = commands.iter()
+            .filter(|cmd| !cmd.keywords.is_empty() && cmd.keywords.iter().all(|kw| !keywords.contains_key(kw.as_str())))
+            .map(|cmd| cmd.cmd.clone())
+            .collect();
+
+        Stats {
+            never_used: commands.iter()
+                .filter(|cmd| !uses.contains_key(cmd.cmd.as_str()))
+                .map(|cmd| cmd.cmd.clone())
+                .collect(),
+            most_used: by_count(uses),
+            keywords: by_count(keywords),
+            average_fill_in_ms: if fill_in_times.is_empty() {
+                None
+            } else {
+                Some(fill_in_times.iter().sum::<u64>() / fill_in_times.len() as u64)
+            },
+            fill_ins: fill_in_times.len(),
+            frecent: frecency.ranked(),
+            heat,
+            only_via_unused
+        }
+    }
+
+    pub fn print<W: Write>(self: &Stats, out: &mut W, top: usize) -> Result<()> {
+        writeln!(out, "Most used commands:")?;
+        for &(ref cmd, count) in self.most_used.iter().take(top) {
+            writeln!(out, "{:>6}  {}", count, cmd)?;
+        }
+
+        writeln!(out, "\nMost frecent commands:")?;
+        for &(ref cmd, score) in self.frecent.iter().take(top) {
+            writeln!(out, "{:>6.2}  {}", score, cmd)?;
+        }
+
+        writeln!(out, "\nNever used commands:")?;
+        for cmd in &self.never_used {
+            writeln!(out, "        {}", cmd)?;
+        }
+
+        writeln!(out, "\nKeywords used to filter:")?;
+        for &(ref kw, count) in self.keywords.iter().take(top) {
+            writeln!(out, "{:>6}  {}", count, kw)?;
+        }
+
+        if let Some(ms) = self.average_fill_in_ms {
+            writeln!(out, "\nAverage fill-in time: {:.1}s ({} fill-in(s))", ms as f64 / 1000.0, self.fill_ins)?;
+        }
+        Ok(())
+    }
+
+    /// How each keyword of the commands is used, to tell which ones to drop or rename
+    pub fn print_keywords<W: Write>(self: &Stats, out: &mut W, top: usize) -> Result<()> {
+        writeln!(out, "Most used keywords (uses, commands declaring them):")?;
+        for heat in self.heat.iter().filter(|heat| heat.uses > 0).take(top) {
+            writeln!(out, "{:>6} {:>6}  {}", heat.uses, heat.commands, heat.keyword)?;
+        }
+
+        writeln!(out, "\nNever used keywords (commands declaring them):")?;
+        for heat in self.heat.iter().filter(|heat| heat.uses == 0) {
+            writeln!(out, "       {:>6}  {}", heat.commands, heat.keyword)?;
+        }
+
+        writeln!(out, "\nCommands only reachable via never used keywords:")?;
+        for cmd in &self.only_via_unused {
+            writeln!(out, "        {}", cmd)?;
+        }
+        Ok(())
+    }
+}
+
+fn by_count(counts: HashMap<&str, usize>) -> Vec<(String, usize)> {
+    counts.into_iter()
+        .map(|(item, count)| (item.to_owned(), count))
+        .sorted_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(template: &str, keywords: &[&str], fill_in_ms: Option<u64>) -> Record {
+        Record {
+            template: template.to_owned(),
+            cmd: template.to_owned(),
+            keywords: keywords.iter().map(|kw| kw.to_string()).collect(),
+            fill_in_ms,
+            ..Default::default()
+        }
+    }
+
+    fn entry(cmd: &str) -> CommandEntry {
+        CommandEntry { cmd: cmd.to_owned(), ..Default::default() }
+    }
+
+    fn with_keywords(cmd: &str, keywords: &[&str]) -> CommandEntry {
+        CommandEntry { keywords: keywords.iter().map(|kw| kw.to_string()).collect(), ..entry(cmd) }
+    }
+
+    #[test]
+    fn computing_stats() {
+        let stats = Stats::compute(
+            &[record("ls", &["ls"], None),
+              record("du -sh {dir}", &["du", "disk"], Some(1000)),
+              record("du -sh {dir}", &["disk"], Some(3000))],
+            &[entry("ls"), entry("du -sh {dir}"), entry("df -h")],
+            &Frecency::default());
+
+        assert_eq!(stats.most_used, vec![("du -sh {dir}".to_owned(), 2), ("ls".to_owned(), 1)]);
+        assert_eq!(stats.never_used, vec!["df -h"]);
+        assert_eq!(stats.keywords, vec![("disk".to_owned(), 2), ("du".to_owned(), 1), ("ls".to_owned(), 1)]);
+        assert_eq!(stats.average_fill_in_ms, Some(2000));
+    }
+
+    #[test]
+    fn reporting_keywords() {
+        let stats = Stats::compute(
+            &[record("du -sh {dir}", &["du", "disk"], None),
+              record("df -h", &["disk"], None)],
+            &[with_keywords("du -sh {dir}", &["du", "disk"]),
+              with_keywords("df -h", &["df", "disk", "free"]),
+              with_keywords("ncdu", &["ncdu", "tui"]),
+              entry("ls")],
+            &Frecency::default());
+
+        let heat: Vec<(&str, usize, usize)> = stats.heat.iter().map(|heat| (heat.keyword.as_str(), heat.uses, heat.commands)).collect();
+        assert_eq!(heat, vec![("disk", 2, 2), ("du", 1, 1), ("df", 0, 1), ("free", 0, 1), ("ncdu", 0, 1), ("tui", 0, 1)]);
+        assert_eq!(stats.only_via_unused, vec!["ncdu"]);
+
+        let mut out = Vec::new();
+        stats.print_keywords(&mut out, 1).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "Most used keywords (uses, commands declaring them):\n     2      2  disk\n\n\
+            Never used keywords (commands declaring them):\n            1  df\n            1  free\n            1  ncdu\n            1  tui\n\n\
+            Commands only reachable via never used keywords:\n        ncdu\n");
+    }
+}
@@ -0,0 +1,76 @@
+use std::io::{self, BufRead};
+use std::os::unix::io::AsRawFd;
+use command::{Command, Placeholders};
+
+use error::{Error, Result};
+
+/// Read one command per line. Without a delimiter, the words of a line are its keywords,
+/// otherwise lines are formatted as `keyword,keyword<delimiter>description<delimiter>command`.
+pub fn read_commands(delimiter: Option<&str>) -> Result<Vec<Command>> {
+    let stdin = io::stdin();
+    let mut commands = Vec::new();
+    for line in stdin.lock().lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            commands.push(parse_line(&line, delimiter)?);
+        }
+    }
+    Ok(commands)
+}
+
+fn parse_line(line: &str, delimiter: Option<&str>) -> Result<Command> {
+    let mut keywords = Vec::new();
+    let mut description = None;
+    let mut cmd = line;
+
+    if let Some(delimiter) = delimiter {
+        let fields: Vec<&str> = line.splitn(3, delimiter).collect();
+        if fields.len() != 3 {
+            return Err(Error::InvalidInput(
+                format!("Expecting `keywords{0}description{0}command`, got: {1}", delimiter, line)));
+        }
+        keywords = fields[0].split(',').map(str::trim).filter(|kw| !kw.is_empty()).map(str::to_owned).collect();
+        description = Some(fields[1].to_owned()).filter(|d| !d.is_empty());
+        cmd = fields[2];
+    } else {
+        for word in line.split_whitespace() {
+            if !keywords.iter().any(|kw| kw == word) {
+                keywords.push(word.to_owned());
+            }
+        }
+    }
+
+    Ok(Command {
+        cmd: Placeholders::parse(cmd)?,
+        description,
+        keywords,
+        ..Default::default()
+    })
+}
+
+/// Keys are read from stdin: once the commands are read, point it to the terminal instead
+pub fn reattach_to_tty() -> Result<()> {
+    let tty = ::termion::get_tty()?;
+    if unsafe { ::libc::dup2(tty.as_raw_fd(), 0) } < 0 {
+        return Err(Error::Terminal(io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+#[test]
+fn parsing_plain_line() {
+    let cmd = parse_line("git log --oneline git", None).unwrap();
+    assert_eq!(cmd.cmd.to_string(), "git log --oneline git");
+    assert_eq!(cmd.keywords, vec!["git", "log", "--oneline"]);
+    assert_eq!(cmd.description, None);
+}
+
+#[test]
+fn parsing_delimited_line() {
+    let cmd = parse_line("git, log:Compact history:git log --format='%h:%s'", Some(":")).unwrap();
+    assert_eq!(cmd.cmd.to_string(), "git log --format='%h:%s'");
+    assert_eq!(cmd.keywords, vec!["git", "log"]);
+    assert_eq!(cmd.description, Some("Compact history".to_owned()));
+
+    assert!(parse_line("git log", Some(":")).is_err());
+}
@@ -1,60 +1,175 @@
 
-use std::rc::Rc;
-use command::{Command, Commands, Placeholders};
+use std::sync::Arc;
+use std::cmp::Ordering;
+use command::{Command, CommandSet, Commands};
 use std::collections::HashSet;
+use rayon::prelude::*;
+
+/// From that many commands on, they are scored and sorted on all the cores
+pub const PARALLEL_THRESHOLD: usize = 5000;
+
+/// How the commands get listed, cycled through with `Order::next`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Order {
+    /// The most frecent first, see `Commands::frecency`
+    #[default]
+    Relevance,
+    /// In natural order, see `Ord for Command`
+    Alphabetical,
+    MostRecent,
+    MostFrequent
+}
 
 #[derive(Default, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub struct Suggestion {
     pub keywords: Vec<String>,
-    pub commands: Vec<Rc<Command>>,
+    pub commands: Vec<Arc<Command>>,
 }
 
 
+impl Order {
+    pub fn next(self: Order) -> Order {
+        match self {
+            Order::Relevance    => Order::Alphabetical,
+            Order::Alphabetical => Order::MostRecent,
+            Order::MostRecent   => Order::MostFrequent,
+            Order::MostFrequent => Order::Relevance
+        }
+    }
+
+    pub fn name(self: Order) -> &'static str {
+        match self {
+            Order::Relevance    => "relevance",
+            Order::Alphabetical => "alphabetical",
+            Order::MostRecent   => "most recent",
+            Order::MostFrequent => "most frequent"
+        }
+    }
+}
+
 impl Suggestion {
 
     pub fn from_input(commands: &Commands,
                       input: &str,
                       validated_keywords: HashSet<&String>) -> Suggestion {
+        Suggestion::from_input_in_order(commands, input, validated_keywords, Order::Relevance)
+    }
+
+    /// The ties are broken in natural order. By relevance, the frecency gets blended with how well
+    /// the input matches, see `Commands::relevance_weight`
+    pub fn from_input_in_order(commands: &Commands,
+                               input: &str,
+                               validated_keywords: HashSet<&String>,
+                               order: Order) -> Suggestion {
+        Suggestion::scored_past(commands, input, validated_keywords, order, PARALLEL_THRESHOLD)
+    }
+
+    /// Scored and sorted on all the cores from that many matching commands on, in the same order either way
+    fn scored_past(commands: &Commands,
+                   input: &str,
+                   validated_keywords: HashSet<&String>,
+                   order: Order,
+                   parallel_threshold: usize) -> Suggestion {
         let mut suggestion: Suggestion = Default::default();
 
-        let validated_commands: HashSet<Rc<Command>> = if validated_keywords.is_empty() {
-            commands.commands.iter().cloned().collect()
-        } else {
-            commands.commands.iter().cloned()
-                .filter(|cmd| validated_keywords.iter()
-                    .all(|kw| cmd.keywords.contains(kw)))
-                .collect()
-        };
+        let mut validated_commands = CommandSet::full(commands.commands.len());
+        for kw in &validated_keywords {
+            match commands.kwd2cmd.get(*kw) {
+                Some(cmds) => validated_commands.intersect_with(cmds),
+                None => validated_commands = CommandSet::default()
+            }
+        }
 
-        if input.is_empty() {
-            suggestion.commands.extend(validated_commands);
+        let matching_commands = if input.is_empty() {
+            validated_commands
         } else {
-            for (kw, cmds) in &commands.kwd2cmd {
-                if kw.starts_with(&input)
-                    && !validated_keywords.contains(kw) {
+            let mut matching_commands = CommandSet::default();
+            for (kw, cmds) in commands.keywords_starting_with(input) {
+                if !validated_keywords.contains(kw) {
                     suggestion.keywords.push(kw.clone());
-                    suggestion.commands.extend(cmds.intersection(&validated_commands)
-                        .cloned());
+                    matching_commands.union_with(cmds);
                 }
             }
-        }
-        suggestion.commands.sort_by(|c1, c2| c1.cmp(&c2));
+            matching_commands.intersect_with(&validated_commands);
+            matching_commands
+        };
+
+        // scored once rather than at every comparison
+        let indexes: Vec<usize> = matching_commands.iter().collect();
+        let scores = match order {
+            Order::Relevance    => Some(&commands.frecency),
+            Order::Alphabetical => None,
+            Order::MostRecent   => Some(&commands.recency),
+            Order::MostFrequent => Some(&commands.frequency)
+        };
+        let weight = if order == Order::Relevance { commands.relevance_weight.clamp(0.0, 1.0) } else { 0.0 };
+        let score = |idx: usize| (scores.map_or(0.0, |scores| scores.score(commands.commands[idx].cmd.template())), idx);
+        let order = |a: &(f64, usize), b: &(f64, usize)| -> Ordering {
+            b.0.total_cmp(&a.0).then_with(|| commands.rank(a.1).cmp(&commands.rank(b.1)))
+        };
+        let scored: Vec<(f64, usize)> = if indexes.len() < parallel_threshold {
+            let mut scored: Vec<(f64, usize)> = indexes.into_iter().map(score).collect();
+            if weight > 0.0 {
+                blend(&mut scored, commands, input, weight);
+            }
+            scored.sort_by(order);
+            scored
+        } else {
+            let mut scored: Vec<(f64, usize)> = indexes.into_par_iter().map(score).collect();
+            if weight > 0.0 {
+                blend(&mut scored, commands, input, weight);
+            }
+            scored.par_sort_by(order);
+            scored
+        };
+        suggestion.commands = scored.into_iter().map(|(_, idx)| commands.commands[idx].clone()).collect();
 
         suggestion
     }
+
+    /// Same as typing the query in: the words followed by a space become validated keywords
+    /// (unknown ones being ignored) and the last word is the input
+    pub fn from_query(commands: &Commands, query: &str) -> Suggestion {
+        let mut words: Vec<&str> = query.split(' ').collect();
+        let input = words.pop().unwrap_or("");
+
+        let validated_keywords: HashSet<&String> = words.iter()
+            .filter_map(|word| commands.kwd2cmd.get_key_value(*word).map(|(kw, _)| kw))
+            .collect();
+
+        Suggestion::from_input(commands, input, validated_keywords)
+    }
 }
 
+/// The frecencies, brought between 0 and 1, blended with the share of the best keyword the input covers,
+/// a keyword typed in full scoring 1
+fn blend(scored: &mut [(f64, usize)], commands: &Commands, input: &str, weight: f64) {
+    let top = scored.iter().map(|&(score, _)| score).fold(0.0, f64::max);
+    let typed = input.chars().count() as f64;
+    for &mut (ref mut score, idx) in scored.iter_mut() {
+        let relevance = commands.commands[idx].keywords.iter()
+            .filter(|kw| !kw.is_empty() && kw.starts_with(input))
+            .map(|kw| typed / kw.chars().count() as f64)
+            .fold(0.0, f64::max);
+        let frecency = if top > 0.0 { *score / top } else { 0.0 };
+        *score = (1.0 - weight) * frecency + weight * relevance;
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use command::Placeholders;
+    use frecency::{Context, Frecency, FrecencyConfig};
+    use history::Record;
     use hamcrest::prelude::*;
 
     struct TestData {
         commands: Commands,
-        cmd_nix_env: Rc<Command>,
-        cmd_nix_store: Rc<Command>,
-        cmd_shutdown: Rc<Command>,
+        cmd_nix_env: Arc<Command>,
+        cmd_nix_store: Arc<Command>,
+        cmd_shutdown: Arc<Command>,
         kw: Keywords
     }
 
@@ -80,24 +195,27 @@ mod tests {
         fn new() -> TestData {
             let kw = Keywords::new();
 
-            let cmd_nix_env = Rc::new(Command {
+            let cmd_nix_env = Arc::new(Command {
                 cmd: Placeholders::parse("nix-env -q '.*{}.*'").unwrap(),
                 description: Some("Search a Nix package by name".to_owned()),
-                keywords: vec_clone![kw.nix, kw.search]
+                keywords: vec_clone![kw.nix, kw.search],
+                ..Default::default()
             });
-            let cmd_nix_store = Rc::new(Command {
+            let cmd_nix_store = Arc::new(Command {
                 cmd: Placeholders::parse("du -sh /nix/store").unwrap(),
                 description: Some("Show the size of the Nix store".to_owned()),
-                keywords: vec_clone![kw.nix, kw.store]
+                keywords: vec_clone![kw.nix, kw.store],
+                ..Default::default()
             });
-            let cmd_shutdown = Rc::new(Command {
+            let cmd_shutdown = Arc::new(Command {
                 cmd: Placeholders::parse("sudo shutdown -h now").unwrap(),
                 description: Some("Shut the system down".to_owned()),
-                keywords: vec_clone![kw.shutdown]
+                keywords: vec_clone![kw.shutdown],
+                ..Default::default()
             });
 
             TestData {
-                commands: Commands::new_rc(vec![
+                commands: Commands::new_arc(vec![
                     cmd_nix_env.clone(),
                     cmd_nix_store.clone(),
                     cmd_shutdown.clone()]),
@@ -143,7 +261,7 @@ mod tests {
         let t = TestData::new();
         let s = Suggestion::from_input(&t.commands, "xy", HashSet::new());
         assert_eq!(s.keywords, empty_keywords());
-        assert_eq!(s.commands, Vec::<Rc<Command>>::new());
+        assert_eq!(s.commands, Vec::<Arc<Command>>::new());
     }
 
     #[test]
@@ -154,6 +272,82 @@ mod tests {
         assert_that!(s.commands, equal_to(vec![t.cmd_nix_store]));
     }
 
+    #[test]
+    fn query_with_validated_keywords() {
+        let t = TestData::new();
+        let s = Suggestion::from_query(&t.commands, "store unknown ni");
+        assert_that!(s.keywords, equal_to(vec![t.kw.nix]));
+        assert_that!(s.commands, equal_to(vec![t.cmd_nix_store]));
+    }
+
+    #[test]
+    fn most_frecent_first() {
+        let mut t = TestData::new();
+        let record = Record { template: t.cmd_shutdown.cmd.to_string(), ..Default::default() };
+        t.commands.frecency = Frecency::compute(&[record], &FrecencyConfig::default(), &Context::default(), 0);
+
+        let s = Suggestion::from_input(&t.commands, "", HashSet::new());
+        assert_that!(s.commands, equal_to(vec![
+            t.cmd_shutdown, t.cmd_nix_store, t.cmd_nix_env]));
+    }
+
+    #[test]
+    fn same_order_on_all_the_cores() {
+        let mut commands = Commands::new(::bench::synthetic_commands(3 * PARALLEL_THRESHOLD));
+        // ties aplenty, for the natural order to break them
+        let scores = commands.commands.iter().enumerate()
+            .map(|(i, command)| (command.cmd.to_string(), (i % 7) as f64))
+            .collect();
+        commands.frecency = Frecency::from_scores(scores);
+        commands.relevance_weight = 0.5;
+
+        for &order in &[Order::Relevance, Order::Alphabetical, Order::MostRecent, Order::MostFrequent] {
+            let sequential = Suggestion::scored_past(&commands, "git", HashSet::new(), order, usize::MAX);
+            let parallel = Suggestion::scored_past(&commands, "git", HashSet::new(), order, 0);
+            assert!(sequential.commands.len() > PARALLEL_THRESHOLD);
+            assert!(sequential.commands.iter().zip(&parallel.commands).all(|(a, b)| Arc::ptr_eq(a, b)), "{:?}", order);
+            assert_eq!(sequential.commands.len(), parallel.commands.len());
+        }
+    }
+
+    #[test]
+    fn in_other_orders() {
+        let mut t = TestData::new();
+        let record = Record { template: t.cmd_shutdown.cmd.to_string(), ..Default::default() };
+        t.commands.frecency = Frecency::compute(&[record], &FrecencyConfig::default(), &Context::default(), 0);
+        t.commands.frequency = Frecency::from_scores(hashmap!(t.cmd_nix_env.cmd.to_string() => 2.0));
+
+        let s = Suggestion::from_input_in_order(&t.commands, "", HashSet::new(), Order::Alphabetical);
+        assert_that!(s.commands, equal_to(vec![
+            t.cmd_nix_store.clone(), t.cmd_nix_env.clone(), t.cmd_shutdown.clone()]));
+
+        let s = Suggestion::from_input_in_order(&t.commands, "", HashSet::new(), Order::MostFrequent);
+        assert_that!(s.commands, equal_to(vec![
+            t.cmd_nix_env, t.cmd_nix_store, t.cmd_shutdown]));
+    }
+
+    #[test]
+    fn blending_frecency_and_relevance() {
+        let mut t = TestData::new();
+        t.commands.extend(vec![Command {
+            cmd: Placeholders::parse("sudo nixos-rebuild switch").unwrap(),
+            keywords: vec!["nixos".to_owned()],
+            ..Default::default()
+        }]);
+        let rebuild = t.commands.commands[3].clone();
+        let records: Vec<Record> = (0..3).map(|_| Record { template: rebuild.cmd.to_string(), ..Default::default() }).collect();
+        t.commands.frecency = Frecency::compute(&records, &FrecencyConfig::default(), &Context::default(), 0);
+
+        let s = Suggestion::from_input(&t.commands, "nix", HashSet::new());
+        assert_that!(s.commands, equal_to(vec![
+            rebuild.clone(), t.cmd_nix_store.clone(), t.cmd_nix_env.clone()]));
+
+        t.commands.relevance_weight = 0.8;
+        let s = Suggestion::from_input(&t.commands, "nix", HashSet::new());
+        assert_that!(s.commands, equal_to(vec![
+            t.cmd_nix_store, t.cmd_nix_env, rebuild]));
+    }
+
     fn empty_keywords() -> Vec<String> {
         Vec::<String>::new()
     }
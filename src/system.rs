@@ -0,0 +1,152 @@
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::ffi::CStr;
+use std::fs::{File, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Once};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How often a thread reading the keys checks whether it is to stop
+const POLL_MS: i32 = 50;
+
+/// Look an executable up like the shell does
+pub fn find_executable(name: &str) -> Option<PathBuf> {
+    if name.contains('/') {
+        return Some(PathBuf::from(name)).filter(|path| is_executable(path));
+    }
+
+    env::var_os("PATH").and_then(|paths| env::split_paths(&paths)
+        .map(|dir| dir.join(name))
+        .find(|path| is_executable(path)))
+}
+
+pub fn hostname() -> Option<String> {
+    let mut buf = [0 as ::libc::c_char; 256];
+    if unsafe { ::libc::gethostname(buf.as_mut_ptr(), buf.len()) } != 0 {
+        return None;
+    }
+    let name = unsafe { CStr::from_ptr(buf.as_ptr()) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+/// That of the effective user, e.g. `root` under sudo
+pub fn username() -> Option<String> {
+    let passwd = unsafe { ::libc::getpwuid(::libc::geteuid()) };
+    if passwd.is_null() {
+        return env::var("USER").ok();
+    }
+    let name = unsafe { CStr::from_ptr((*passwd).pw_name) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+/// From the kernel, else from the hashers std seeds randomly
+pub fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0; len];
+    if File::open("/dev/urandom").and_then(|mut file| file.read_exact(&mut bytes)).is_err() {
+        let state = RandomState::new();
+        for (idx, byte) in bytes.iter_mut().enumerate() {
+            let mut hasher = state.build_hasher();
+            hasher.write_usize(idx);
+            *byte = hasher.finish() as u8;
+        }
+    }
+    bytes
+}
+
+/// The columns and rows of the terminal the picker is drawn on: that of stderr, stdout being
+/// usually redirected
+pub fn terminal_size() -> Option<(u16, u16)> {
+    let mut size: ::libc::winsize = unsafe { ::std::mem::zeroed() };
+    if unsafe { ::libc::ioctl(::libc::STDERR_FILENO, ::libc::TIOCGWINSZ, &mut size) } != 0 || size.ws_col == 0 {
+        return None;
+    }
+    Some((size.ws_col, size.ws_row))
+}
+
+/// Set by the SIGWINCH handler, taken back by whoever redraws
+static RESIZED: AtomicBool = AtomicBool::new(false);
+static WATCHING_RESIZES: Once = Once::new();
+
+extern "C" fn on_sigwinch(_: ::libc::c_int) {
+    RESIZED.store(true, Ordering::SeqCst);
+}
+
+/// Call back on every resize of the terminal, on a thread of its own, until stopped or the callback
+/// returns false
+pub fn watch_resizes<F: FnMut() -> bool + Send + 'static>(stop: Arc<AtomicBool>, mut resized: F) -> JoinHandle<()> {
+    WATCHING_RESIZES.call_once(|| unsafe {
+        ::libc::signal(::libc::SIGWINCH, on_sigwinch as extern "C" fn(::libc::c_int) as ::libc::sighandler_t);
+    });
+    thread::spawn(move || {
+        while !stop.load(Ordering::SeqCst) {
+            if RESIZED.swap(false, Ordering::SeqCst) && !resized() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(POLL_MS as u64));
+        }
+    })
+}
+
+/// Stdin, read without the buffering of `io::stdin`, coming to an end once stopped rather than waiting
+/// for one more key: the keys typed in afterwards are left to whatever reads stdin next
+#[derive(Default)]
+pub struct StoppableStdin {
+    pub stop: Arc<AtomicBool>
+}
+
+impl Read for StoppableStdin {
+    fn read(self: &mut StoppableStdin, buf: &mut [u8]) -> io::Result<usize> {
+        let mut fd = ::libc::pollfd { fd: ::libc::STDIN_FILENO, events: ::libc::POLLIN, revents: 0 };
+        loop {
+            if self.stop.load(Ordering::SeqCst) {
+                return Ok(0);
+            }
+            match unsafe { ::libc::poll(&mut fd, 1, POLL_MS) } {
+                0 => continue,
+                ready if ready > 0 => break,
+                _ => {
+                    let error = io::Error::last_os_error();
+                    if error.kind() != io::ErrorKind::Interrupted {
+                        return Err(error);
+                    }
+                }
+            }
+        }
+        let read = unsafe { ::libc::read(::libc::STDIN_FILENO, buf.as_mut_ptr() as *mut ::libc::c_void, buf.len()) };
+        if read < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(read as usize)
+    }
+}
+
+/// An exclusive lock on that file, created if need be, held until the file returned gets dropped:
+/// e.g. by the processes rewriting a file next to it through a temporary one, not to lose each other's changes
+pub fn lock(path: &Path) -> io::Result<File> {
+    let file = OpenOptions::new().create(true).truncate(false).write(true).open(path)?;
+    file.lock()?;
+    Ok(file)
+}
+
+/// `*` standing for any chars, possibly none, and `?` for any char
+pub fn matches_glob(pattern: &str, text: &str) -> bool {
+    let mut chars = pattern.chars();
+    match chars.next() {
+        None => text.is_empty(),
+        Some('*') => (0..=text.len()).filter(|&i| text.is_char_boundary(i)).any(|i| matches_glob(chars.as_str(), &text[i..])),
+        Some('?') => text.chars().next().is_some_and(|c| matches_glob(chars.as_str(), &text[c.len_utf8()..])),
+        Some(c) => text.strip_prefix(c).is_some_and(|text| matches_glob(chars.as_str(), text))
+    }
+}
+
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
@@ -0,0 +1,39 @@
+use command::{Command, Placeholders};
+use source::CommandSource;
+use system;
+
+use error::Result;
+
+/// Lists the units for the `{unit}` placeholders
+const UNITS: &str = "systemctl list-units --all --plain --no-legend --no-pager | cut -d' ' -f1";
+
+/// The verb (a keyword), the command and its description
+const TEMPLATES: &[(&str, &str, &str)] = &[
+    ("status", "systemctl status {unit}", "Show the status of a unit"),
+    ("restart", "sudo systemctl restart {unit}", "Restart a unit"),
+    ("start", "sudo systemctl start {unit}", "Start a unit"),
+    ("stop", "sudo systemctl stop {unit}", "Stop a unit"),
+    ("logs", "journalctl -u {unit} -f", "Follow the logs of a unit")
+];
+
+/// When systemd is there, the usual commands about its units, the `{unit}` placeholder
+/// completing from `systemctl list-units`
+pub struct Systemd;
+
+impl CommandSource for Systemd {
+    /// Nothing without systemctl
+    fn load(self: &Systemd) -> Result<Vec<Command>> {
+        if system::find_executable("systemctl").is_none() {
+            return Ok(Vec::new());
+        }
+        TEMPLATES.iter()
+            .map(|&(verb, cmd, description)| Ok(Command {
+                cmd: Placeholders::parse(cmd)?,
+                description: Some(description.to_owned()),
+                keywords: vec!["systemd".to_owned(), verb.to_owned()],
+                completions: vec![("unit".to_owned(), UNITS.to_owned())].into_iter().collect(),
+                ..Default::default()
+            }))
+            .collect()
+    }
+}
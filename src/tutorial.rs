@@ -0,0 +1,112 @@
+use command::{Command, Placeholders, Selection};
+
+/// One thing to learn: the command to pick, and how
+pub struct Step {
+    pub instructions: &'static [&'static str],
+    /// None when the picker is to be left without picking anything
+    pub template: Option<&'static str>,
+    /// A keyword to validate on the way
+    pub keyword: Option<&'static str>,
+    /// The keys doing it, e.g. to check that the step can be done
+    pub solution: &'static str
+}
+
+pub const STEPS: &[Step] = &[
+    Step {
+        instructions: &[
+            "Type `dat`: only the commands matching what's typed in are listed.",
+            "Then press Enter to pick the highlighted one, `date`."
+        ],
+        template: Some("date"),
+        keyword: None,
+        solution: "dat<Enter>"
+    },
+    Step {
+        instructions: &[
+            "Type `fi` then press Tab: it completes the keyword `files`.",
+            "Press Space to validate it, leaving the commands about files only.",
+            "Then highlight `ls -la` with Down, and press Enter."
+        ],
+        template: Some("ls -la"),
+        keyword: Some("files"),
+        solution: "fi<Tab> <Down><Enter>"
+    },
+    Step {
+        instructions: &[
+            "Type `greet ` and press Enter to pick `echo Hello, {name}!`.",
+            "Its placeholder is filled in next: type your name and press Enter."
+        ],
+        template: Some("echo Hello, {name}!"),
+        keyword: Some("greet"),
+        solution: "greet <Enter>you<Enter>"
+    },
+    Step {
+        instructions: &[
+            "q leaves without picking anything (while filling a placeholder in, Escape goes back to the commands).",
+            "Press q."
+        ],
+        template: None,
+        keyword: None,
+        solution: "q"
+    }
+];
+
+/// Harmless commands to practice on
+pub fn sandbox() -> Vec<Command> {
+    let command = |cmd: &str, description: &str, keywords: &[&str]| Command {
+        cmd: Placeholders::parse(cmd).unwrap(),
+        description: Some(description.to_owned()),
+        keywords: keywords.iter().map(|&kw| kw.to_owned()).collect(),
+        ..Default::default()
+    };
+    vec![
+        command("ls -la", "List the files", &["files", "list"]),
+        command("du -sh .", "Size of the directory", &["files", "disk", "size"]),
+        command("df -h", "Free space of the disks", &["disk", "size"]),
+        command("date", "Print the date", &["date", "time"]),
+        command("echo Hello, {name}!", "Greet someone", &["greet", "hello"]),
+        command("uname -a", "Describe the system", &["system"])
+    ]
+}
+
+impl Step {
+    /// What went wrong, if the step wasn't done as asked
+    pub fn check(self: &Step, picked: Option<&Selection>, keywords: &[String]) -> Result<(), String> {
+        match (self.template, picked) {
+            (None, None) => return Ok(()),
+            (None, Some(selection)) => return Err(format!("That picked `{}` rather than leaving.", selection.command.cmd)),
+            (Some(template), None) => return Err(format!("That left rather than picking `{}`.", template)),
+            (Some(template), Some(selection)) if selection.command.cmd.template() != template =>
+                return Err(format!("That picked `{}` rather than `{}`.", selection.command.cmd, template)),
+            _ => ()
+        }
+        match self.keyword {
+            Some(keyword) if !keywords.iter().any(|kw| kw == keyword) =>
+                Err(format!("The keyword `{}` was not validated: type it (or complete it with Tab) then press Space.", keyword)),
+            _ => Ok(())
+        }
+    }
+}
+
+#[test]
+fn doing_the_steps() {
+    use command::Commands;
+    use keys::parse_keys;
+    use render::HeadlessRenderer;
+    use runner::RunnerBuilder;
+    use state::Effect;
+
+    for step in STEPS {
+        let mut runner = RunnerBuilder::new(Commands::new(sandbox())).build_with_renderer(HeadlessRenderer::default()).unwrap();
+        let picked = match runner.simulate(parse_keys(step.solution).unwrap()).unwrap() {
+            Effect::Done(picked) => picked,
+            Effect::Continue => panic!("`{}` does not leave the picker", step.solution)
+        };
+        assert_eq!(step.check(picked.as_ref(), &runner.valid_keywords()), Ok(()), "{}", step.solution);
+    }
+
+    let ls = Selection::new(::std::sync::Arc::new(sandbox().remove(0)));
+    assert_eq!(STEPS[0].check(Some(&ls), &[]), Err("That picked `ls -la` rather than `date`.".to_owned()));
+    assert!(STEPS[1].check(Some(&ls), &[]).is_err());
+    assert!(STEPS[1].check(None, &["files".to_owned()]).is_err());
+}
@@ -0,0 +1,131 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use error::{Error, Result};
+
+/// How many value sets are remembered for each command
+pub const RING_SIZE: usize = 10;
+
+/// Where the values typed in for the placeholders are remembered,
+/// by default `~/.local/share/smart-runner/values.json`, the presets being kept next to them
+pub struct ValueStore {
+    pub path: PathBuf
+}
+
+/// The last value sets of each template, the most recent first
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(transparent)]
+pub struct ValueRings {
+    rings: HashMap<String, Vec<Vec<String>>>
+}
+
+/// The named value sets of each template, e.g. `prod` and `staging` for a deploy command
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(transparent)]
+pub struct ValuePresets {
+    presets: HashMap<String, BTreeMap<String, Vec<String>>>
+}
+
+impl ValueStore {
+    pub fn open() -> Result<ValueStore> {
+        ::dirs::data_dir()
+            .map(|dir| ValueStore { path: dir.join("smart-runner").join("values.json") })
+            .ok_or(Error::NoDirectory("data"))
+    }
+
+    pub fn rings(self: &ValueStore) -> Result<ValueRings> {
+        read(&self.path)
+    }
+
+    pub fn save(self: &ValueStore, rings: &ValueRings) -> Result<()> {
+        write(&self.path, rings)
+    }
+
+    /// `presets.json`, next to the remembered values
+    pub fn presets(self: &ValueStore) -> Result<ValuePresets> {
+        read(&self.path.with_file_name("presets.json"))
+    }
+
+    pub fn save_presets(self: &ValueStore, presets: &ValuePresets) -> Result<()> {
+        write(&self.path.with_file_name("presets.json"), presets)
+    }
+}
+
+fn read<T: DeserializeOwned + Default>(path: &Path) -> Result<T> {
+    if !path.exists() {
+        return Ok(T::default());
+    }
+    let file = File::open(path).map_err(|error| Error::File { path: path.to_owned(), error })?;
+    Ok(::serde_json::from_reader(file)?)
+}
+
+fn write<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, ::serde_json::to_string(value)?)?;
+    Ok(())
+}
+
+impl ValueRings {
+    pub fn get(self: &ValueRings, template: &str) -> &[Vec<String>] {
+        self.rings.get(template).map_or(&[], Vec::as_slice)
+    }
+
+    /// Put the values first, once, forgetting the oldest beyond `RING_SIZE`
+    pub fn remember(self: &mut ValueRings, template: &str, values: &[String]) {
+        if values.is_empty() {
+            return;
+        }
+        let ring = self.rings.entry(template.to_owned()).or_default();
+        ring.retain(|set| set.as_slice() != values);
+        ring.insert(0, values.to_vec());
+        ring.truncate(RING_SIZE);
+    }
+}
+
+impl ValuePresets {
+    /// By name
+    pub fn get(self: &ValuePresets, template: &str) -> Vec<(String, Vec<String>)> {
+        self.presets.get(template)
+            .map_or(Vec::new(), |presets| presets.iter().map(|(name, values)| (name.clone(), values.clone())).collect())
+    }
+
+    /// Replacing the preset of that name, if any
+    pub fn set(self: &mut ValuePresets, template: &str, name: &str, values: &[String]) {
+        self.presets.entry(template.to_owned()).or_default().insert(name.to_owned(), values.to_vec());
+    }
+}
+
+#[test]
+fn remembering_values() {
+    let mut rings = ValueRings::default();
+    let values = |a: &str, b: &str| vec![a.to_owned(), b.to_owned()];
+    for i in 0..RING_SIZE + 2 {
+        rings.remember("cp {src} {dst}", &values("a", &i.to_string()));
+    }
+    rings.remember("cp {src} {dst}", &values("a", "5"));
+
+    let ring = rings.get("cp {src} {dst}");
+    assert_eq!(ring.len(), RING_SIZE);
+    assert_eq!(ring[0], values("a", "5"));
+    assert_eq!(ring[1], values("a", "11"));
+    assert!(rings.get("ls").is_empty());
+}
+
+#[test]
+fn naming_presets() {
+    let mut presets = ValuePresets::default();
+    presets.set("deploy {env}", "staging", &["stg".to_owned()]);
+    presets.set("deploy {env}", "prod", &["prd".to_owned()]);
+    presets.set("deploy {env}", "staging", &["staging".to_owned()]);
+
+    assert_eq!(presets.get("deploy {env}"), vec![
+        ("prod".to_owned(), vec!["prd".to_owned()]),
+        ("staging".to_owned(), vec!["staging".to_owned()])
+    ]);
+    assert!(presets.get("ls").is_empty());
+}